@@ -0,0 +1,310 @@
+//! Recalculation of formula cells.
+//!
+//! The calculator keeps every formula in the sheet parsed, along with a
+//! dependency graph from each cell to the formulas that read it. Results are
+//! computed on demand (for the cells on screen, or for everything when saving
+//! values) and cached; when a cell changes, only the formulas that depend on it,
+//! directly or through other formulas, lose their cached result. Row and column
+//! changes move cells around, so they rebuild the graph instead.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use crate::formula::{self, CellRef, CellSource, Formula, FormulaError, Range, Value};
+use crate::sheet::Sheet;
+
+struct Entry {
+    formula: Formula,
+    references: Vec<Range>,
+}
+
+/// Formulas of a sheet and their results, worked out when asked for and kept
+/// until the cells they read change
+#[derive(Default)]
+pub struct Calculator {
+    formulas: HashMap<CellRef, Entry>,
+    /// Formulas reading each single cell
+    dependents: HashMap<CellRef, Vec<CellRef>>,
+    /// Formulas reading multi-cell ranges, checked by containment
+    range_dependents: Vec<(Range, CellRef)>,
+    values: HashMap<CellRef, Value>,
+}
+
+impl Calculator {
+    /// Forget everything and re-read the formulas in the sheet
+    pub fn rebuild(&mut self, sheet: &Sheet) {
+        *self = Self::default();
+        for col in 0..sheet.num_cols() {
+            for (row, text) in sheet.column(col).enumerate() {
+                if formula::is_formula(text) {
+                    self.register((row, col), text);
+                }
+            }
+        }
+    }
+
+    /// Update after the text of some cells changed
+    pub fn cells_changed(&mut self, sheet: &Sheet, cells: impl IntoIterator<Item = CellRef>) {
+        for cell in cells {
+            self.invalidate(cell);
+            self.unregister(cell);
+            let text = sheet.get(cell.0, cell.1);
+            if formula::is_formula(text) {
+                self.register(cell, text);
+            }
+        }
+    }
+
+    /// Whether any cell holds a formula
+    pub fn has_formulas(&self) -> bool {
+        !self.formulas.is_empty()
+    }
+
+    /// Cells holding formulas, in no particular order
+    pub fn formula_cells(&self) -> impl Iterator<Item = CellRef> + '_ {
+        self.formulas.keys().copied()
+    }
+
+    fn register(&mut self, cell: CellRef, text: &str) {
+        let formula = Formula::parse(text);
+        let references = formula.references();
+        for range in &references {
+            if range.is_cell() {
+                self.dependents.entry(range.start).or_default().push(cell);
+            } else {
+                self.range_dependents.push((*range, cell));
+            }
+        }
+        self.formulas.insert(cell, Entry { formula, references });
+    }
+
+    fn unregister(&mut self, cell: CellRef) {
+        let Some(entry) = self.formulas.remove(&cell) else {
+            return;
+        };
+        for range in entry.references {
+            if range.is_cell() {
+                if let Some(dependents) = self.dependents.get_mut(&range.start) {
+                    dependents.retain(|&d| d != cell);
+                    if dependents.is_empty() {
+                        self.dependents.remove(&range.start);
+                    }
+                }
+            } else {
+                self.range_dependents.retain(|&(r, d)| !(r == range && d == cell));
+            }
+        }
+    }
+
+    /// Drop the cached results of a cell and everything that depends on it
+    fn invalidate(&mut self, cell: CellRef) {
+        let mut pending = vec![cell];
+        let mut seen = HashSet::from([cell]);
+        while let Some(cell) = pending.pop() {
+            self.values.remove(&cell);
+            let direct = self.dependents.get(&cell).into_iter().flatten().copied();
+            let through_ranges = self
+                .range_dependents
+                .iter()
+                .filter(|(range, _)| range.contains(cell))
+                .map(|&(_, dependent)| dependent);
+            let next: Vec<CellRef> = direct.chain(through_ranges).filter(|d| seen.insert(*d)).collect();
+            pending.extend(next);
+        }
+    }
+
+    /// Formula cells read by a formula. Large ranges are matched against the
+    /// formula list rather than walked cell by cell.
+    fn formula_precedents(&self, references: &[Range], rows: usize) -> Vec<CellRef> {
+        let mut out = Vec::new();
+        for range in references {
+            if range.cell_count(rows) <= self.formulas.len() {
+                out.extend(range.cells(rows).filter(|cell| self.formulas.contains_key(cell)));
+            } else {
+                out.extend(self.formulas.keys().filter(|cell| range.contains(**cell)));
+            }
+        }
+        out
+    }
+
+    /// Compute a formula cell and whatever uncomputed formulas it reads. Walks
+    /// the graph with an explicit stack so long chains can't overflow the call
+    /// stack; a formula found on its own path is part of a cycle.
+    fn compute(&mut self, sheet: &Sheet, root: CellRef) {
+        // (cell, whether its precedents have been pushed)
+        let mut stack = vec![(root, false)];
+        let mut on_path = HashSet::new();
+        while let Some(&(cell, expanded)) = stack.last() {
+            if self.values.contains_key(&cell) {
+                stack.pop();
+                continue;
+            }
+            let Some(entry) = self.formulas.get(&cell) else {
+                stack.pop();
+                continue;
+            };
+            if !expanded {
+                on_path.insert(cell);
+                let precedents = self.formula_precedents(&entry.references, sheet.num_rows());
+                if let Some(top) = stack.last_mut() {
+                    top.1 = true;
+                }
+                for precedent in precedents {
+                    if !on_path.contains(&precedent) && !self.values.contains_key(&precedent) {
+                        stack.push((precedent, false));
+                    }
+                }
+            } else {
+                stack.pop();
+                on_path.remove(&cell);
+                // Precedents still without a value are on a cycle through this cell
+                let value = entry.formula.evaluate(&Lookup { sheet, calc: self });
+                self.values.insert(cell, value);
+            }
+        }
+    }
+
+    /// Value of a cell: the result of its formula, worked out if needed, or
+    /// the value its text stands for
+    pub fn value(&mut self, sheet: &Sheet, cell: CellRef) -> Value {
+        if !self.formulas.contains_key(&cell) {
+            return Value::from_cell(sheet.get(cell.0, cell.1));
+        }
+        self.compute(sheet, cell);
+        self.values.get(&cell).cloned().unwrap_or(Value::Error(FormulaError::Cycle))
+    }
+
+    /// Compute the formulas in a block of cells, e.g. the ones on screen
+    pub fn prepare(&mut self, sheet: &Sheet, rows: impl IntoIterator<Item = usize>, cols: std::ops::Range<usize>) {
+        if self.formulas.is_empty() {
+            return;
+        }
+        for row in rows {
+            for col in cols.clone() {
+                if self.formulas.contains_key(&(row, col)) && !self.values.contains_key(&(row, col)) {
+                    self.compute(sheet, (row, col));
+                }
+            }
+        }
+    }
+
+    /// Text to show for a cell: the result for formulas computed by `prepare`,
+    /// the cell text otherwise
+    pub fn display<'a>(&self, sheet: &'a Sheet, row: usize, col: usize) -> Cow<'a, str> {
+        match self.values.get(&(row, col)) {
+            Some(value) if self.formulas.contains_key(&(row, col)) => Cow::Owned(value.to_string()),
+            _ => Cow::Borrowed(sheet.get(row, col)),
+        }
+    }
+
+    /// Text of a cell as shown: the result for formulas, the cell text otherwise
+    pub fn text<'a>(&mut self, sheet: &'a Sheet, row: usize, col: usize) -> Cow<'a, str> {
+        let text = sheet.get(row, col);
+        if self.formulas.contains_key(&(row, col)) {
+            Cow::Owned(self.value(sheet, (row, col)).to_string())
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Copy of the sheet with every formula replaced by its result
+    pub fn computed_sheet(&mut self, sheet: &Sheet) -> Sheet {
+        let mut values = sheet.clone();
+        let cells: Vec<CellRef> = self.formula_cells().collect();
+        for cell in cells {
+            let value = self.value(sheet, cell);
+            values.set(cell.0, cell.1, &value.to_string());
+        }
+        values
+    }
+}
+
+struct Lookup<'a> {
+    sheet: &'a Sheet,
+    calc: &'a Calculator,
+}
+
+impl CellSource for Lookup<'_> {
+    fn value(&self, cell: CellRef) -> Value {
+        if self.calc.formulas.contains_key(&cell) {
+            // Computed beforehand by `compute`, unless the cell is on a cycle
+            self.calc.values.get(&cell).cloned().unwrap_or(Value::Error(FormulaError::Cycle))
+        } else {
+            Value::from_cell(self.sheet.get(cell.0, cell.1))
+        }
+    }
+
+    fn num_rows(&self) -> usize {
+        self.sheet.num_rows()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(cells: &[&str]) -> Sheet {
+        let mut sheet = Sheet::new(0, 1);
+        for text in cells {
+            sheet.push_row([text]);
+        }
+        sheet
+    }
+
+    #[test]
+    fn recalculates_dependents_of_changed_cells() {
+        let mut sheet = sheet(&["1", "2", "=A1+A2", "=A3*10"]);
+        let mut calc = Calculator::default();
+        calc.rebuild(&sheet);
+        assert_eq!(calc.text(&sheet, 3, 0), "30");
+        sheet.set(0, 0, "5");
+        calc.cells_changed(&sheet, [(0, 0)]);
+        assert_eq!(calc.text(&sheet, 2, 0), "7");
+        assert_eq!(calc.text(&sheet, 3, 0), "70");
+        assert_eq!(calc.text(&sheet, 0, 0), "5");
+    }
+
+    #[test]
+    fn display_shows_prepared_results_only() {
+        let sheet = sheet(&["4", "=A1/2"]);
+        let mut calc = Calculator::default();
+        calc.rebuild(&sheet);
+        assert_eq!(calc.display(&sheet, 1, 0), "=A1/2");
+        calc.prepare(&sheet, 0..2, 0..1);
+        assert_eq!(calc.display(&sheet, 1, 0), "2");
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let sheet = sheet(&["=A2", "=A1", "=SUM(A1:A2)"]);
+        let mut calc = Calculator::default();
+        calc.rebuild(&sheet);
+        assert_eq!(calc.text(&sheet, 0, 0), "#CYCLE!");
+        assert_eq!(calc.text(&sheet, 2, 0), "#CYCLE!");
+    }
+
+    #[test]
+    fn breaking_a_cycle_recovers() {
+        let mut sheet = sheet(&["=A1+1", "=A2", "=A3*2"]);
+        let mut calc = Calculator::default();
+        calc.rebuild(&sheet);
+        assert_eq!(calc.text(&sheet, 0, 0), "#CYCLE!");
+        assert_eq!(calc.text(&sheet, 1, 0), "#CYCLE!");
+        sheet.set(1, 0, "4");
+        calc.cells_changed(&sheet, [(1, 0)]);
+        assert_eq!(calc.text(&sheet, 2, 0), "#CYCLE!");
+        sheet.set(2, 0, "=A2*2");
+        calc.cells_changed(&sheet, [(2, 0)]);
+        assert_eq!(calc.text(&sheet, 2, 0), "8");
+        assert_eq!(calc.text(&sheet, 0, 0), "#CYCLE!");
+    }
+
+    #[test]
+    fn computes_a_sheet_of_values() {
+        let sheet = sheet(&["3", "=A1^2"]);
+        let mut calc = Calculator::default();
+        calc.rebuild(&sheet);
+        assert!(calc.has_formulas());
+        assert_eq!(calc.computed_sheet(&sheet).column(0).collect::<Vec<_>>(), ["3", "9"]);
+    }
+}
@@ -0,0 +1,366 @@
+//! Copying and pasting blocks of cells as the tab-separated text spreadsheets
+//! exchange on the clipboard: cells separated by tabs, rows by line breaks.
+//!
+//! A block can also be copied as a Markdown, HTML or LaTeX table, JSON
+//! records or SQL `INSERT` statements, for pasting into documents and
+//! programs rather than another spreadsheet.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::formula::column_letters;
+use crate::history::{CellChange, Edit};
+use crate::json::{self, JsonLayout, JsonOptions};
+use crate::sheet::Sheet;
+use crate::types::ColumnType;
+
+/// Text of the given rows and columns, one line per row.
+pub fn copy_text(sheet: &Sheet, rows: impl IntoIterator<Item = usize>, cols: Range<usize>) -> String {
+    let lines: Vec<String> = rows
+        .into_iter()
+        .map(|row| {
+            let cells: Vec<&str> = cols.clone().map(|col| sheet.get(row, col)).collect();
+            cells.join("\t")
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Formats a block of cells can be copied in besides tab-separated text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    /// A table with a header row, aligned by column type
+    Markdown,
+    /// A `<table>` with a head and a body
+    Html,
+    /// A `tabular` environment
+    Latex,
+    /// An array of records, or of arrays without a header
+    Json,
+    /// One `INSERT` statement per row
+    Sql,
+}
+
+impl CopyFormat {
+    /// Every format, in menu order
+    pub const ALL: [CopyFormat; 5] = [CopyFormat::Markdown, CopyFormat::Html, CopyFormat::Latex, CopyFormat::Json, CopyFormat::Sql];
+
+    /// Name shown in menus
+    pub fn label(self) -> &'static str {
+        match self {
+            CopyFormat::Markdown => "Markdown Table",
+            CopyFormat::Html => "HTML Table",
+            CopyFormat::Latex => "LaTeX Tabular",
+            CopyFormat::Json => "JSON",
+            CopyFormat::Sql => "SQL INSERT",
+        }
+    }
+
+    /// Extension of a file holding text in the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            CopyFormat::Markdown => "md",
+            CopyFormat::Html => "html",
+            CopyFormat::Latex => "tex",
+            CopyFormat::Json => "json",
+            CopyFormat::Sql => "sql",
+        }
+    }
+}
+
+/// How [`format_cells`] writes a block
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    /// Format the block is written in
+    pub format: CopyFormat,
+    /// The sheet's first row names the columns
+    pub header: bool,
+    /// Table the SQL statements insert into
+    pub table: String,
+}
+
+/// The given rows and columns as text in another format, with every value
+/// escaped the way the format needs. With a header, its cells name the
+/// columns wherever the block starts, and it isn't repeated as a data row;
+/// without one, Markdown, which needs a header, uses the column letters.
+/// Numbers and booleans in typed columns are written as values in JSON and
+/// SQL, and numeric columns are right-aligned in the tables. Formulas should
+/// already have been replaced by their results.
+pub fn format_cells(
+    sheet: &Sheet,
+    column_types: &HashMap<usize, ColumnType>,
+    rows: impl IntoIterator<Item = usize>,
+    cols: Range<usize>,
+    options: &CopyOptions,
+) -> String {
+    let header = options.header && !sheet.is_empty();
+    let types: Vec<ColumnType> = cols.clone().map(|col| column_types.get(&col).copied().unwrap_or(ColumnType::Text)).collect();
+    let names: Vec<String> = if header {
+        cols.clone().map(|col| sheet.get(0, col).to_string()).collect()
+    } else {
+        cols.clone().map(column_letters).collect()
+    };
+    let mut block = Sheet::new(0, cols.len());
+    for row in rows.into_iter().filter(|&row| !(header && row == 0)) {
+        block.push_row(cols.clone().map(|col| sheet.get(row, col)));
+    }
+
+    match options.format {
+        CopyFormat::Markdown => markdown_table(&block, &names, &types),
+        CopyFormat::Html => html_table(&block, header.then_some(&names[..]), &types),
+        CopyFormat::Latex => latex_tabular(&block, header.then_some(&names[..]), &types),
+        CopyFormat::Json => {
+            let mut records = Sheet::new(0, cols.len());
+            if header {
+                records.push_row(&names);
+            }
+            for row in 0..block.num_rows() {
+                records.push_row(block.row(row));
+            }
+            let column_types = types.iter().copied().enumerate().collect();
+            let options = JsonOptions { layout: JsonLayout::Array, header, nest: false };
+            String::from_utf8_lossy(&json::write_json(&records, &column_types, &options)).into_owned()
+        }
+        CopyFormat::Sql => sql_inserts(&block, header.then_some(&names[..]), &types, &options.table),
+    }
+}
+
+fn markdown_table(block: &Sheet, names: &[String], types: &[ColumnType]) -> String {
+    // Pipes would end the cell and line breaks the row
+    let escape = |text: &str| text.replace('\\', "\\\\").replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>");
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    let mut out = line(names.iter().map(|name| escape(name)).collect());
+    out.push_str(&line(types.iter().map(|ty| if ty.is_numeric() { "---:" } else { "---" }.to_string()).collect()));
+    for row in 0..block.num_rows() {
+        out.push_str(&line(block.row(row).map(escape).collect()));
+    }
+    out
+}
+
+fn html_table(block: &Sheet, names: Option<&[String]>, types: &[ColumnType]) -> String {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+            .replace('\'', "&#39;")
+            .replace("\r\n", "<br>")
+            .replace('\n', "<br>")
+    };
+    let cell = |tag: &str, text: &str, ty: &ColumnType| {
+        let align = if ty.is_numeric() { " style=\"text-align: right\"" } else { "" };
+        format!("<{}{}>{}</{}>", tag, align, escape(text), tag)
+    };
+    let mut out = String::from("<table>\n");
+    if let Some(names) = names {
+        let cells: String = names.iter().zip(types).map(|(name, ty)| cell("th", name, ty)).collect();
+        out.push_str(&format!("  <thead>\n    <tr>{}</tr>\n  </thead>\n", cells));
+    }
+    out.push_str("  <tbody>\n");
+    for row in 0..block.num_rows() {
+        let cells: String = block.row(row).zip(types).map(|(text, ty)| cell("td", text, ty)).collect();
+        out.push_str(&format!("    <tr>{}</tr>\n", cells));
+    }
+    out.push_str("  </tbody>\n</table>\n");
+    out
+}
+
+fn latex_tabular(block: &Sheet, names: Option<&[String]>, types: &[ColumnType]) -> String {
+    let escape = |text: &str| {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '\\' => escaped.push_str("\\textbackslash{}"),
+                '~' => escaped.push_str("\\textasciitilde{}"),
+                '^' => escaped.push_str("\\textasciicircum{}"),
+                '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                    escaped.push('\\');
+                    escaped.push(c);
+                }
+                // A tabular cell is a single line
+                '\r' => {}
+                '\n' => escaped.push(' '),
+                _ => escaped.push(c),
+            }
+        }
+        escaped
+    };
+    let line = |cells: Vec<String>| format!("{} \\\\\n", cells.join(" & "));
+    let spec: String = types.iter().map(|ty| if ty.is_numeric() { 'r' } else { 'l' }).collect();
+    let mut out = format!("\\begin{{tabular}}{{{}}}\n\\hline\n", spec);
+    if let Some(names) = names {
+        out.push_str(&line(names.iter().map(|name| escape(name)).collect()));
+        out.push_str("\\hline\n");
+    }
+    for row in 0..block.num_rows() {
+        out.push_str(&line(block.row(row).map(escape).collect()));
+    }
+    out.push_str("\\hline\n\\end{tabular}\n");
+    out
+}
+
+fn sql_inserts(block: &Sheet, names: Option<&[String]>, types: &[ColumnType], table: &str) -> String {
+    let identifier = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+    let string = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    // Empty cells are NULL, and numbers and booleans that fit their column
+    // are written bare. Whole numbers keep every digit; ones past 2^53 that
+    // aren't plain integers would lose some as floats, so they stay strings.
+    let value = |text: &str, ty: ColumnType| {
+        if text.is_empty() {
+            return "NULL".to_string();
+        }
+        if let Some(integer) = ty.integer(text) {
+            return integer.to_string();
+        }
+        match (ty, ty.normalize(text)) {
+            (ColumnType::Number | ColumnType::Currency, Some(_)) if ty.exact_value(text).is_none() => string(text),
+            (ColumnType::Number | ColumnType::Currency | ColumnType::Boolean, Some(plain)) => plain,
+            (ColumnType::Date, Some(iso)) => string(&iso),
+            _ => string(text),
+        }
+    };
+    let columns = match names {
+        Some(names) => format!(" ({})", names.iter().map(|name| identifier(name)).collect::<Vec<_>>().join(", ")),
+        None => String::new(),
+    };
+    let mut out = String::new();
+    for row in 0..block.num_rows() {
+        let values: Vec<String> = block.row(row).zip(types).map(|(text, &ty)| value(text, ty)).collect();
+        out.push_str(&format!("INSERT INTO {}{} VALUES ({});\n", identifier(table), columns, values.join(", ")));
+    }
+    out
+}
+
+/// Edits that paste `text` with its first cell in column `start_col`.
+///
+/// Lines go to the rows given, in order, and once those run out to new rows
+/// added at the end, so a front-end can skip rows it has hidden. The sheet is
+/// grown if the block doesn't fit, and cells that already hold the pasted text
+/// are left out. Applying nothing is an empty list.
+pub fn paste_edits(sheet: &Sheet, rows: impl IntoIterator<Item = usize>, start_col: usize, text: &str) -> Vec<Edit> {
+    let lines: Vec<&str> = text.lines().collect();
+    let target_rows: Vec<usize> = rows.into_iter().take(lines.len()).collect();
+
+    let mut changes = Vec::new();
+    let mut rows_needed = sheet.num_rows();
+    let mut cols_needed = sheet.num_cols();
+    for (line_idx, line) in lines.iter().enumerate() {
+        let row = match target_rows.get(line_idx) {
+            Some(&row) => row,
+            None => sheet.num_rows() + line_idx - target_rows.len(),
+        };
+        let cells: Vec<&str> = line.split('\t').collect();
+
+        rows_needed = rows_needed.max(row + 1);
+        cols_needed = cols_needed.max(start_col + cells.len());
+
+        for (col_offset, cell_text) in cells.iter().enumerate() {
+            changes.push(CellChange { row, col: start_col + col_offset, text: cell_text.to_string() });
+        }
+    }
+
+    let mut edits = Vec::new();
+    if (rows_needed, cols_needed) != (sheet.num_rows(), sheet.num_cols()) {
+        edits.push(Edit::Resize(rows_needed, cols_needed, Vec::new()));
+    }
+    changes.retain(|change| sheet.get(change.row, change.col) != change.text);
+    if !changes.is_empty() {
+        edits.push(Edit::SetCells(changes));
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_a_block_as_tab_separated_lines() {
+        let sheet = Sheet::from_rows([["a", "b", "c"], ["d", "e", "f"], ["g", "h", "i"]]);
+        assert_eq!(copy_text(&sheet, 0..2, 1..3), "b\tc\ne\tf");
+        assert_eq!(copy_text(&sheet, [0, 2], 0..1), "a\ng");
+    }
+
+    #[test]
+    fn pastes_into_the_given_rows_then_appends() {
+        let mut target = Sheet::from_rows([["1", "2"], ["3", "4"], ["5", "6"]]);
+        let edits = paste_edits(&target, [0, 2], 1, "x\ty\nz\t6\nw");
+        for edit in edits {
+            edit.apply(&mut target);
+        }
+        assert_eq!(target.num_rows(), 4);
+        assert_eq!(target.num_cols(), 3);
+        assert_eq!(target.row(0).collect::<Vec<_>>(), ["1", "x", "y"]);
+        assert_eq!(target.row(1).collect::<Vec<_>>(), ["3", "4", ""]);
+        assert_eq!(target.row(2).collect::<Vec<_>>(), ["5", "z", "6"]);
+        assert_eq!(target.row(3).collect::<Vec<_>>(), ["", "w", ""]);
+    }
+
+    #[test]
+    fn pasting_the_same_text_changes_nothing() {
+        let target = Sheet::from_rows([["a", "b"]]);
+        assert!(paste_edits(&target, [0], 0, "a\tb").is_empty());
+    }
+
+    #[test]
+    fn formats_blocks_with_escaping() {
+        let sheet = Sheet::from_rows([
+            ["name", "price", "note"],
+            ["a|b", "$1,200.50", "x & <y>"],
+            ["it's", "", "50% of #1_{a}\\"],
+        ]);
+        let types = HashMap::from([(1, ColumnType::Currency)]);
+        let options = |format| CopyOptions { format, header: true, table: "my \"t\"".to_string() };
+        let copy = |format, rows: &[usize]| format_cells(&sheet, &types, rows.iter().copied(), 0..3, &options(format));
+
+        assert_eq!(
+            copy(CopyFormat::Markdown, &[0, 1, 2]),
+            "| name | price | note |\n| --- | ---: | --- |\n| a\\|b | $1,200.50 | x & <y> |\n| it's |  | 50% of #1_{a}\\\\ |\n"
+        );
+        assert_eq!(
+            copy(CopyFormat::Html, &[1]),
+            "<table>\n  <thead>\n    <tr><th>name</th><th style=\"text-align: right\">price</th><th>note</th></tr>\n  </thead>\n  \
+             <tbody>\n    <tr><td>a|b</td><td style=\"text-align: right\">$1,200.50</td><td>x &amp; &lt;y&gt;</td></tr>\n  </tbody>\n</table>\n"
+        );
+        assert_eq!(
+            copy(CopyFormat::Latex, &[2]),
+            "\\begin{tabular}{lrl}\n\\hline\nname & price & note \\\\\n\\hline\n\
+             it's &  & 50\\% of \\#1\\_\\{a\\}\\textbackslash{} \\\\\n\\hline\n\\end{tabular}\n"
+        );
+        assert_eq!(
+            copy(CopyFormat::Json, &[1]),
+            "[\n  {\"name\":\"a|b\",\"price\":1200.5,\"note\":\"x & <y>\"}\n]\n"
+        );
+        assert_eq!(
+            copy(CopyFormat::Sql, &[1, 2]),
+            "INSERT INTO \"my \"\"t\"\"\" (\"name\", \"price\", \"note\") VALUES ('a|b', 1200.5, 'x & <y>');\n\
+             INSERT INTO \"my \"\"t\"\"\" (\"name\", \"price\", \"note\") VALUES ('it''s', NULL, '50% of #1_{a}\\');\n"
+        );
+    }
+
+    #[test]
+    fn formats_blocks_without_a_header() {
+        let sheet = Sheet::from_rows([["1", "x"], ["2", "y\nz"]]);
+        let types = HashMap::from([(0, ColumnType::Number)]);
+        let copy = |format| {
+            let options = CopyOptions { format, header: false, table: "t".to_string() };
+            format_cells(&sheet, &types, 0..2, 0..2, &options)
+        };
+        assert_eq!(copy(CopyFormat::Markdown), "| A | B |\n| ---: | --- |\n| 1 | x |\n| 2 | y<br>z |\n");
+        assert_eq!(copy(CopyFormat::Json), "[\n  [1,\"x\"],\n  [2,\"y\\nz\"]\n]\n");
+        assert_eq!(copy(CopyFormat::Sql), "INSERT INTO \"t\" VALUES (1, 'x');\nINSERT INTO \"t\" VALUES (2, 'y\nz');\n");
+        assert!(copy(CopyFormat::Html).starts_with("<table>\n  <tbody>\n    <tr><td style=\"text-align: right\">1</td>"));
+    }
+
+    #[test]
+    fn copies_large_integers_and_zero_padded_codes_as_shown() {
+        let sheet = Sheet::from_rows([["id", "zip"], ["1234567890123456789", "02134"]]);
+        let types = HashMap::from([(0, ColumnType::Number), (1, ColumnType::Number)]);
+        let copy = |format| {
+            let options = CopyOptions { format, header: true, table: "t".to_string() };
+            format_cells(&sheet, &types, 0..2, 0..2, &options)
+        };
+        assert_eq!(copy(CopyFormat::Json), "[\n  {\"id\":1234567890123456789,\"zip\":\"02134\"}\n]\n");
+        assert_eq!(copy(CopyFormat::Sql), "INSERT INTO \"t\" (\"id\", \"zip\") VALUES (1234567890123456789, '02134');\n");
+    }
+}
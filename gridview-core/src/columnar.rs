@@ -0,0 +1,368 @@
+//! Columnar files: Apache Parquet and Arrow IPC (`.arrow`, also known as
+//! Feather).
+//!
+//! Reading takes every record batch into a [`Sheet`] of text under a header
+//! row of the field names, formatting values the way Arrow displays them:
+//! plain decimals, ISO dates and timestamps, `true`/`false`, and lists and
+//! structs in brackets. Nulls become empty cells. Column types come from the
+//! schema rather than being guessed from the cells, so an integer column is a
+//! number column even when its first values look like text.
+//!
+//! Writing does the reverse from the column types: numbers become 64-bit
+//! integers when every value is whole and floats otherwise, amounts floats,
+//! dates Arrow dates (or timestamps when they have a time), booleans booleans
+//! and everything else strings. A typed column with a cell that doesn't fit
+//! its type, a zero-padded code or a number a float can't hold exactly is
+//! written as strings, so nothing is lost. Empty cells are nulls.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Date32Array, Float64Array, Int64Array, RecordBatch, RecordBatchReader, StringArray,
+    TimestampMillisecondArray,
+};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_ipc::reader::{FileReader, StreamReader};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::formula::{self, column_letters};
+use crate::sheet::Sheet;
+use crate::types::ColumnType;
+
+/// Why a columnar file couldn't be read or written
+pub type Error = parquet::errors::ParquetError;
+
+/// The columnar file formats that can be read and written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnarFormat {
+    /// Apache Parquet
+    Parquet,
+    /// The Arrow IPC file format; streams are read too
+    Arrow,
+}
+
+impl ColumnarFormat {
+    /// Format going by a file's extension: `.parquet`, or `.arrow` and
+    /// `.feather`
+    pub fn for_path(path: impl AsRef<Path>) -> Option<ColumnarFormat> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "parquet" => Some(ColumnarFormat::Parquet),
+            "arrow" | "feather" => Some(ColumnarFormat::Arrow),
+            _ => None,
+        }
+    }
+
+    /// Extension a written file gets
+    pub fn extension(self) -> &'static str {
+        match self {
+            ColumnarFormat::Parquet => "parquet",
+            ColumnarFormat::Arrow => "arrow",
+        }
+    }
+}
+
+/// File extensions read as columnar files
+pub const EXTENSIONS: &[&str] = &["parquet", "arrow", "feather"];
+
+/// Whether a file name has a Parquet or Arrow extension, ignoring case
+pub fn is_columnar_path(path: impl AsRef<Path>) -> bool {
+    ColumnarFormat::for_path(path).is_some()
+}
+
+/// Cells read from a columnar file
+pub struct ColumnarTable {
+    /// A header row of field names, then the records
+    pub sheet: Sheet,
+    /// Type of each column going by its field's data type; columns not listed
+    /// are text
+    pub column_types: HashMap<usize, ColumnType>,
+}
+
+/// Read every record of a Parquet or Arrow file
+pub fn read_columnar(bytes: Vec<u8>, format: ColumnarFormat) -> Result<ColumnarTable, Error> {
+    let (schema, batches) = match format {
+        ColumnarFormat::Parquet => {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(bytes))?.build()?;
+            read_batches(reader)?
+        }
+        ColumnarFormat::Arrow => match FileReader::try_new(Cursor::new(&bytes), None) {
+            Ok(reader) => read_batches(reader)?,
+            // Not a file with a footer, so perhaps a stream
+            Err(_) => read_batches(StreamReader::try_new(Cursor::new(&bytes), None)?)?,
+        },
+    };
+
+    let mut sheet = Sheet::new(0, schema.fields().len());
+    sheet.push_row(schema.fields().iter().map(|field| field.name().as_str()));
+    let options = FormatOptions::new()
+        .with_null("")
+        .with_datetime_format(Some("%Y-%m-%d %H:%M:%S%.f"))
+        .with_timestamp_format(Some("%Y-%m-%d %H:%M:%S%.f"));
+    for batch in &batches {
+        let formatters = batch
+            .columns()
+            .iter()
+            .map(|array| ArrayFormatter::try_new(array.as_ref(), &options))
+            .collect::<Result<Vec<_>, ArrowError>>()?;
+        // Arrow writes whole floats as "3.0"; other files would say "3"
+        let floats: Vec<bool> = batch.columns().iter().map(|array| array.data_type().is_floating()).collect();
+        for row in 0..batch.num_rows() {
+            sheet.push_row(formatters.iter().zip(&floats).map(|(formatter, &float)| {
+                let mut text = formatter.value(row).to_string();
+                if float && text.ends_with(".0") {
+                    text.truncate(text.len() - 2);
+                }
+                text
+            }));
+        }
+    }
+
+    let column_types = schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(col, field)| (col, column_type(field.data_type())))
+        .filter(|&(_, column_type)| column_type != ColumnType::Text)
+        .collect();
+    Ok(ColumnarTable { sheet, column_types })
+}
+
+fn read_batches(reader: impl RecordBatchReader) -> Result<(SchemaRef, Vec<RecordBatch>), ArrowError> {
+    let schema = reader.schema();
+    let batches = reader.collect::<Result<Vec<_>, ArrowError>>()?;
+    Ok((schema, batches))
+}
+
+/// The column type that shows a field's values best
+pub fn column_type(data_type: &DataType) -> ColumnType {
+    match data_type {
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Float16
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Decimal128(..)
+        | DataType::Decimal256(..) => ColumnType::Number,
+        DataType::Boolean => ColumnType::Boolean,
+        // Timestamps in a time zone are shown with their offset, which dates don't take
+        DataType::Date32 | DataType::Date64 | DataType::Timestamp(_, None) => ColumnType::Date,
+        DataType::Dictionary(_, values) => column_type(values),
+        _ => ColumnType::Text,
+    }
+}
+
+/// How [`write_columnar`] lays out the file
+#[derive(Debug, Clone)]
+pub struct ColumnarOptions {
+    /// Format the file is written in
+    pub format: ColumnarFormat,
+    /// The first row is a header naming the fields; without one the fields
+    /// are named by column letter
+    pub header: bool,
+}
+
+/// Rows as a Parquet or Arrow file with a field for each column. Formulas
+/// should already have been replaced by their results.
+pub fn write_columnar(
+    sheet: &Sheet,
+    column_types: &HashMap<usize, ColumnType>,
+    options: &ColumnarOptions,
+) -> Result<Vec<u8>, Error> {
+    let header = options.header && !sheet.is_empty();
+    let first = usize::from(header);
+    let names = if header {
+        crate::json::object_keys(sheet)
+    } else {
+        (0..sheet.num_cols()).map(column_letters).collect()
+    };
+
+    let mut fields = Vec::with_capacity(names.len());
+    let mut arrays = Vec::with_capacity(names.len());
+    for (col, name) in names.into_iter().enumerate() {
+        let column_type = column_types.get(&col).copied().unwrap_or(ColumnType::Text);
+        let cells: Vec<&str> = (first..sheet.num_rows()).map(|row| sheet.get(row, col)).collect();
+        let array = typed_array(&cells, column_type).unwrap_or_else(|| string_array(&cells));
+        fields.push(Field::new(name, array.data_type().clone(), true));
+        arrays.push(array);
+    }
+    let schema = Arc::new(Schema::new(fields));
+    let batch = if arrays.is_empty() {
+        RecordBatch::new_empty(schema.clone())
+    } else {
+        RecordBatch::try_new(schema.clone(), arrays)?
+    };
+
+    match options.format {
+        ColumnarFormat::Parquet => {
+            let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+            let mut writer = ArrowWriter::try_new(Vec::new(), schema, Some(properties))?;
+            writer.write(&batch)?;
+            writer.into_inner()
+        }
+        ColumnarFormat::Arrow => {
+            let mut writer = FileWriter::try_new(Vec::new(), &schema)?;
+            writer.write(&batch)?;
+            writer.finish()?;
+            Ok(writer.into_inner()?)
+        }
+    }
+}
+
+fn string_array(cells: &[&str]) -> ArrayRef {
+    Arc::new(cells.iter().map(|text| Some(*text).filter(|text| !text.is_empty())).collect::<StringArray>())
+}
+
+// Values of a typed column, or None if a cell doesn't fit the type or the
+// type is stored as strings anyway
+fn typed_array(cells: &[&str], column_type: ColumnType) -> Option<ArrayRef> {
+    // Each non-empty cell parsed, with empty cells as None
+    fn parse<T>(cells: &[&str], parse: impl Fn(&str) -> Option<T>) -> Option<Vec<Option<T>>> {
+        cells
+            .iter()
+            .map(|text| match text.trim() {
+                "" => Some(None),
+                text => parse(text).map(Some),
+            })
+            .collect()
+    }
+    let whole = |values: &[Option<f64>]| values.iter().flatten().all(|value| value.fract() == 0.0 && value.abs() < 9e15);
+
+    let array: ArrayRef = match column_type {
+        ColumnType::Number | ColumnType::Currency => {
+            // Whole numbers are read from their digits, as an f64 only holds
+            // 53 bits of them. Zero-padded codes fit neither and stay strings.
+            if column_type == ColumnType::Number
+                && let Some(integers) = parse(cells, |text| column_type.integer(text))
+            {
+                Arc::new(integers.into_iter().collect::<Int64Array>())
+            } else {
+                let values = parse(cells, |text| column_type.exact_value(text))?;
+                Arc::new(values.into_iter().collect::<Float64Array>())
+            }
+        }
+        ColumnType::Date => {
+            let epoch = formula::date_serial(1970, 1, 1) as f64;
+            let values = parse(cells, |text| column_type.value(text))?;
+            if whole(&values) {
+                let days = values.iter().map(|value| value.map(|serial| (serial - epoch) as i32));
+                Arc::new(days.collect::<Date32Array>())
+            } else {
+                let millis = values.iter().map(|value| value.map(|serial| ((serial - epoch) * 86_400_000.0).round() as i64));
+                Arc::new(millis.collect::<TimestampMillisecondArray>())
+            }
+        }
+        ColumnType::Boolean => {
+            let values = parse(cells, |text| column_type.normalize(text).map(|b| b == "TRUE"))?;
+            Arc::new(values.into_iter().collect::<BooleanArray>())
+        }
+        ColumnType::Text | ColumnType::Email | ColumnType::Url => return None,
+    };
+    Some(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> (Sheet, HashMap<usize, ColumnType>) {
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["id", "price", "day", "seen", "ok", "note", "code"]);
+        sheet.push_row(["1", "$1,200.50", "2024-03-01", "2024-03-01 10:30", "yes", "first", "A1"]);
+        sheet.push_row(["2", "$3", "", "", "no", "", "7"]);
+        let types = HashMap::from([
+            (0, ColumnType::Number),
+            (1, ColumnType::Currency),
+            (2, ColumnType::Date),
+            (3, ColumnType::Date),
+            (4, ColumnType::Boolean),
+            // Doesn't fit, so it stays text
+            (6, ColumnType::Number),
+        ]);
+        (sheet, types)
+    }
+
+    #[test]
+    fn round_trips_typed_columns() {
+        let (sheet, types) = sample();
+        for format in [ColumnarFormat::Parquet, ColumnarFormat::Arrow] {
+            let options = ColumnarOptions { format, header: true };
+            let bytes = write_columnar(&sheet, &types, &options).unwrap();
+            let table = read_columnar(bytes, format).unwrap();
+            assert_eq!(
+                table.sheet.rows().collect::<Vec<_>>(),
+                [
+                    vec!["id", "price", "day", "seen", "ok", "note", "code"],
+                    vec!["1", "1200.5", "2024-03-01", "2024-03-01 10:30:00", "true", "first", "A1"],
+                    vec!["2", "3", "", "", "false", "", "7"],
+                ]
+            );
+            assert_eq!(
+                table.column_types,
+                HashMap::from([
+                    (0, ColumnType::Number),
+                    (1, ColumnType::Number),
+                    (2, ColumnType::Date),
+                    (3, ColumnType::Date),
+                    (4, ColumnType::Boolean),
+                ])
+            );
+        }
+    }
+
+    #[test]
+    fn reads_compressed_parquet() {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int64, true)]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef]).unwrap();
+        let mut codecs = vec![Compression::GZIP(Default::default()), Compression::LZ4_RAW, Compression::LZ4];
+        if cfg!(feature = "zstd") {
+            codecs.push(Compression::ZSTD(Default::default()));
+        }
+        for compression in codecs {
+            let properties = WriterProperties::builder().set_compression(compression).build();
+            let mut writer = ArrowWriter::try_new(Vec::new(), schema.clone(), Some(properties)).unwrap();
+            writer.write(&batch).unwrap();
+            let table = read_columnar(writer.into_inner().unwrap(), ColumnarFormat::Parquet).unwrap();
+            assert_eq!(table.sheet.rows().collect::<Vec<_>>(), [["n"], ["1"], ["2"], ["3"]], "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn names_fields_by_letter_without_a_header() {
+        let (sheet, types) = sample();
+        let options = ColumnarOptions { format: ColumnarFormat::Parquet, header: false };
+        let bytes = write_columnar(&sheet, &types, &options).unwrap();
+        let table = read_columnar(bytes, ColumnarFormat::Parquet).unwrap();
+        assert_eq!(table.sheet.rows().collect::<Vec<_>>()[0], ["A", "B", "C", "D", "E", "F", "G"]);
+        assert_eq!(table.sheet.num_rows(), 4);
+
+        assert!(read_columnar(b"not parquet".to_vec(), ColumnarFormat::Parquet).is_err());
+        assert!(read_columnar(b"not arrow".to_vec(), ColumnarFormat::Arrow).is_err());
+    }
+
+    #[test]
+    fn keeps_large_integers_and_zero_padded_codes_exact() {
+        let sheet = Sheet::from_rows([["id", "zip"], ["1234567890123456789", "02134"], ["-7", "90210"]]);
+        let types = HashMap::from([(0, ColumnType::Number), (1, ColumnType::Number)]);
+        for format in [ColumnarFormat::Parquet, ColumnarFormat::Arrow] {
+            let options = ColumnarOptions { format, header: true };
+            let table = read_columnar(write_columnar(&sheet, &types, &options).unwrap(), format).unwrap();
+            assert_eq!(table.sheet.rows().collect::<Vec<_>>(), sheet.rows().collect::<Vec<_>>());
+            assert_eq!(table.column_types, HashMap::from([(0, ColumnType::Number)]));
+        }
+    }
+}
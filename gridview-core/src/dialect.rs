@@ -0,0 +1,636 @@
+//! CSV dialect description and sniffing.
+//!
+//! A dialect captures how a delimited text file is laid out (separator, quoting,
+//! escaping, line endings and whether the first row is a header). `CsvDialect::sniff`
+//! guesses one from a sample of the file so the import dialog can start from a
+//! sensible default that the user is free to override. The same dialect is used
+//! when saving so an unchanged file is written back byte for byte.
+
+use std::collections::HashSet;
+
+use crate::encoding::TextEncoding;
+use crate::sheet::{ColumnFull, Sheet};
+
+/// Number of bytes read from the start of a file to guess its dialect
+pub const SNIFF_SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Delimiters offered in the import dialog, in the order they are tried when sniffing
+pub const DELIMITERS: &[(u8, &str)] = &[
+    (b',', "Comma"),
+    (b';', "Semicolon"),
+    (b'\t', "Tab"),
+    (b'|', "Pipe"),
+    (b':', "Colon"),
+    (b' ', "Space"),
+];
+
+/// Space is only offered manually; free text would otherwise look space-delimited
+const SNIFF_DELIMITERS: &[u8] = b",;\t|:";
+const SNIFF_ROWS: usize = 100;
+
+/// What ends each line of a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineTerminator {
+    /// A line feed, as on Unix and macOS
+    Lf,
+    /// A carriage return and a line feed, as on Windows
+    CrLf,
+    /// A carriage return alone, as on classic Mac OS
+    Cr,
+}
+
+impl LineTerminator {
+    /// Every line ending, in menu order
+    pub const ALL: [LineTerminator; 3] = [LineTerminator::Lf, LineTerminator::CrLf, LineTerminator::Cr];
+
+    /// Name shown in menus
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineTerminator::Lf => "LF (Unix, macOS)",
+            LineTerminator::CrLf => "CRLF (Windows)",
+            LineTerminator::Cr => "CR (Classic Mac)",
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            LineTerminator::Lf => "\n",
+            LineTerminator::CrLf => "\r\n",
+            LineTerminator::Cr => "\r",
+        }
+    }
+
+    fn to_csv(self) -> csv::Terminator {
+        match self {
+            LineTerminator::Lf => csv::Terminator::Any(b'\n'),
+            LineTerminator::CrLf => csv::Terminator::CRLF,
+            LineTerminator::Cr => csv::Terminator::Any(b'\r'),
+        }
+    }
+}
+
+/// Which fields are quoted when a file is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuoteStyle {
+    /// Quote only fields containing the delimiter, quotes or line breaks
+    Necessary,
+    /// Quote every field
+    Always,
+    /// Quote every field that isn't a number
+    NonNumeric,
+}
+
+impl QuoteStyle {
+    /// Every quoting style, in menu order
+    pub const ALL: [QuoteStyle; 3] = [QuoteStyle::Necessary, QuoteStyle::Always, QuoteStyle::NonNumeric];
+
+    /// Name shown in menus
+    pub fn label(&self) -> &'static str {
+        match self {
+            QuoteStyle::Necessary => "When necessary",
+            QuoteStyle::Always => "Always",
+            QuoteStyle::NonNumeric => "Non-numeric fields",
+        }
+    }
+}
+
+/// How a delimited text file is laid out: its separators, quoting and line
+/// endings
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvDialect {
+    /// Byte between fields, such as `,`, `;` or a tab
+    pub delimiter: u8,
+    /// `None` disables quoting entirely
+    pub quote: Option<u8>,
+    /// `None` means quotes inside quoted fields are escaped by doubling them
+    pub escape: Option<u8>,
+    /// Whether the first record names the columns
+    pub has_headers: bool,
+    /// Accept records whose field count differs from the first record
+    pub flexible: bool,
+    /// Line ending written after each record
+    pub terminator: LineTerminator,
+    /// Which fields are quoted when writing
+    pub quote_style: QuoteStyle,
+    /// Whether the last record is followed by a line terminator
+    pub trailing_newline: bool,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: Some(b'"'),
+            escape: None,
+            has_headers: true,
+            flexible: false,
+            terminator: LineTerminator::CrLf,
+            quote_style: QuoteStyle::Necessary,
+            trailing_newline: true,
+        }
+    }
+}
+
+impl CsvDialect {
+    /// Reader configured for this dialect. The header row is returned as an ordinary
+    /// record because the grid shows it as row 1.
+    pub fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .has_headers(false)
+            .flexible(self.flexible)
+            .delimiter(self.delimiter);
+        match self.quote {
+            Some(quote) => {
+                builder.quote(quote);
+            }
+            None => {
+                builder.quoting(false);
+            }
+        }
+        if let Some(escape) = self.escape {
+            builder.escape(Some(escape)).double_quote(false);
+        }
+        builder
+    }
+
+    /// Writer producing this dialect, except for the trailing newline which
+    /// `write_csv` takes care of
+    pub fn writer_builder(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .terminator(self.terminator.to_csv())
+            .quote_style(match (self.quote, self.quote_style) {
+                (None, _) => csv::QuoteStyle::Never,
+                (Some(_), QuoteStyle::Necessary) => csv::QuoteStyle::Necessary,
+                (Some(_), QuoteStyle::Always) => csv::QuoteStyle::Always,
+                (Some(_), QuoteStyle::NonNumeric) => csv::QuoteStyle::NonNumeric,
+            });
+        if let Some(quote) = self.quote {
+            builder.quote(quote);
+        }
+        if let Some(escape) = self.escape {
+            builder.escape(escape).double_quote(false);
+        }
+        builder
+    }
+
+    /// Guess the dialect of a file from its first few kilobytes
+    pub fn sniff(sample: &[u8]) -> Self {
+        let sample = trim_partial_line(sample);
+        let mut dialect = Self::default();
+
+        dialect.delimiter = sniff_delimiter(sample);
+        dialect.quote = sniff_quote(sample, dialect.delimiter);
+        if let Some(quote) = dialect.quote {
+            dialect.escape = sniff_escape(sample, quote);
+            if sniff_always_quoted(sample, dialect.delimiter, quote) {
+                dialect.quote_style = QuoteStyle::Always;
+            }
+        }
+        dialect.terminator = sniff_terminator(sample);
+
+        // A sample is far too small to fill a column
+        let parsed = read_csv(sample, &dialect, Some(SNIFF_ROWS)).unwrap_or_default();
+        dialect.has_headers = sniff_header(&parsed.rows);
+        dialect
+    }
+}
+
+/// Display name for a delimiter byte
+pub fn delimiter_label(delimiter: u8) -> String {
+    DELIMITERS
+        .iter()
+        .find(|(d, _)| *d == delimiter)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("Other ({})", delimiter as char))
+}
+
+/// A delimiter by its name in DELIMITERS ("tab"), as "\t", or as a single character
+pub fn parse_delimiter(text: &str) -> Option<u8> {
+    if let Some((value, _)) = DELIMITERS.iter().find(|(_, name)| name.eq_ignore_ascii_case(text)) {
+        return Some(*value);
+    }
+    match text.as_bytes() {
+        [b] if b.is_ascii() => Some(*b),
+        b"\\t" => Some(b'\t'),
+        _ => None,
+    }
+}
+
+/// Decode the start of a file and guess its dialect from it. Returns the
+/// decoded sample along with the dialect.
+pub fn sniff_sample(bytes: &[u8], encoding: &TextEncoding) -> (String, CsvDialect) {
+    let truncated = bytes.len() > SNIFF_SAMPLE_SIZE;
+    let mut sample = encoding.decode(&bytes[..bytes.len().min(SNIFF_SAMPLE_SIZE)]);
+    // Drop the partial record at the end of a cut-off sample
+    if truncated && let Some(end) = sample.rfind(['\n', '\r']) {
+        sample.truncate(end + 1);
+    }
+    let mut dialect = CsvDialect::sniff(sample.as_bytes());
+    // The sample may be cut off, so look at the end of the whole file
+    dialect.trailing_newline = encoding.ends_with_newline(bytes);
+    (sample, dialect)
+}
+
+/// A record the parser rejected. The row is still loaded as-is so nothing is lost,
+/// and the user decides in the import problems window whether to keep, fix or drop it.
+#[derive(Debug, Clone)]
+pub struct ImportProblem {
+    /// Index of the row in the grid
+    pub row: usize,
+    /// 1-based line in the source file where the record starts
+    pub line: u64,
+    /// What the parser found wrong
+    pub message: String,
+    /// Original text of the record, without its line terminator
+    pub raw: String,
+}
+
+/// Rows read from a delimited file, along with the records that didn't parse
+/// cleanly
+#[derive(Debug, Default)]
+pub struct ParsedCsv {
+    /// Every record, including those with problems
+    pub rows: Sheet,
+    /// Records that need a look, in file order
+    pub problems: Vec<ImportProblem>,
+}
+
+/// Parse delimited text into rows, recording every record the parser rejects.
+/// Stops after `limit` rows when one is given.
+pub fn read_csv(bytes: &[u8], dialect: &CsvDialect, limit: Option<usize>) -> Result<ParsedCsv, ColumnFull> {
+    let mut parsed = ParsedCsv::default();
+    CsvParser::new(bytes, dialect).read_rows(&mut parsed, limit.unwrap_or(usize::MAX))?;
+    Ok(parsed)
+}
+
+/// Incremental parser behind `read_csv`, so large files can be read a chunk of
+/// rows at a time with progress reported in between
+pub struct CsvParser<B: AsRef<[u8]>> {
+    reader: csv::Reader<std::io::Cursor<B>>,
+    record: csv::ByteRecord,
+    /// Lines are counted here because the reader's count lags behind on CRLF files
+    counted_to: usize,
+    line: u64,
+    finished: bool,
+}
+
+impl<B: AsRef<[u8]>> CsvParser<B> {
+    /// A parser over the whole file, starting at its first record
+    pub fn new(bytes: B, dialect: &CsvDialect) -> Self {
+        Self {
+            reader: dialect.reader_builder().from_reader(std::io::Cursor::new(bytes)),
+            record: csv::ByteRecord::new(),
+            counted_to: 0,
+            line: 1,
+            finished: false,
+        }
+    }
+
+    /// The text being parsed
+    pub fn bytes(&self) -> &[u8] {
+        self.reader.get_ref().get_ref().as_ref()
+    }
+
+    /// Byte offset the parser has reached
+    pub fn position(&self) -> usize {
+        self.reader.position().byte() as usize
+    }
+
+    /// Append up to `max_rows` more rows to `parsed`. Returns false once the
+    /// input is exhausted, and an error if a column grows past what a sheet
+    /// holds.
+    pub fn read_rows(&mut self, parsed: &mut ParsedCsv, max_rows: usize) -> Result<bool, ColumnFull> {
+        for _ in 0..max_rows {
+            let start = self.reader.position().clone();
+            let error = match self.reader.read_byte_record(&mut self.record) {
+                Ok(false) => {
+                    self.finished = true;
+                    break;
+                }
+                Ok(true) => match csv::StringRecord::from_byte_record(self.record.clone()) {
+                    Ok(record) => {
+                        parsed.rows.check_room(record.as_slice().len())?;
+                        parsed.rows.push_row(record.iter());
+                        None
+                    }
+                    Err(e) => Some(e.to_string()),
+                },
+                Err(e) => {
+                    // Anything other than a malformed record (e.g. an I/O error) ends the read
+                    if !matches!(e.kind(), csv::ErrorKind::UnequalLengths { .. } | csv::ErrorKind::Utf8 { .. }) {
+                        self.finished = true;
+                        break;
+                    }
+                    Some(e.to_string())
+                }
+            };
+
+            if let Some(message) = error {
+                let bytes = self.reader.get_ref().get_ref().as_ref();
+                let end = (self.reader.position().byte() as usize).min(bytes.len());
+                let mut start = (self.record.position().unwrap_or(&start).byte() as usize).min(end);
+                while start < end && matches!(bytes[start], b'\r' | b'\n') {
+                    start += 1;
+                }
+                self.line += bytes[self.counted_to..start].iter().filter(|&&b| b == b'\n').count() as u64;
+                self.counted_to = start;
+
+                // Invalid UTF-8 comes out as replacement characters, three
+                // bytes each
+                parsed.rows.check_room(self.record.as_slice().len() * 3)?;
+                parsed.problems.push(ImportProblem {
+                    row: parsed.rows.num_rows(),
+                    line: self.line,
+                    message,
+                    raw: String::from_utf8_lossy(&bytes[start..end]).trim_end_matches(['\r', '\n']).to_string(),
+                });
+                parsed.rows.push_row(self.record.iter().map(String::from_utf8_lossy));
+            }
+        }
+
+        Ok(!self.finished)
+    }
+}
+
+/// Write rows using the dialect, leaving off the final line terminator if the
+/// source file didn't have one
+pub fn write_csv(sheet: &Sheet, dialect: &CsvDialect) -> Result<String, Box<dyn std::error::Error>> {
+    let mut writer = dialect.writer_builder().from_writer(Vec::new());
+    for row in 0..sheet.num_rows() {
+        writer.write_record(sheet.row(row))?;
+    }
+    writer.flush()?;
+
+    let mut text = String::from_utf8(writer.into_inner()?)?;
+    if !dialect.trailing_newline && text.ends_with(dialect.terminator.as_str()) {
+        text.truncate(text.len() - dialect.terminator.as_str().len());
+    }
+    Ok(text)
+}
+
+/// Parse a single record typed in by the user when fixing an import problem
+pub fn parse_record(text: &str, dialect: &CsvDialect) -> Vec<String> {
+    let mut reader = dialect.reader_builder().flexible(true).from_reader(text.as_bytes());
+    reader
+        .records()
+        .next()
+        .and_then(|r| r.ok())
+        .map(|r| r.iter().map(|s| s.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Drop a trailing incomplete line so a cut-off record doesn't skew the guess
+fn trim_partial_line(sample: &[u8]) -> &[u8] {
+    match sample.iter().rposition(|&b| b == b'\n') {
+        Some(pos) if pos + 1 < sample.len() => &sample[..=pos],
+        _ => sample,
+    }
+}
+
+fn sniff_delimiter(sample: &[u8]) -> u8 {
+    let mut best: Option<(f64, usize, u8)> = None;
+
+    for &delimiter in SNIFF_DELIMITERS {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(delimiter)
+            .from_reader(sample);
+
+        let counts: Vec<usize> = reader
+            .records()
+            .take(SNIFF_ROWS)
+            .filter_map(|r| r.ok())
+            .map(|r| r.len())
+            .collect();
+        if counts.is_empty() {
+            continue;
+        }
+
+        // Most common field count and how many rows agree with it
+        let mut freq: Vec<(usize, usize)> = Vec::new();
+        for &count in &counts {
+            match freq.iter_mut().find(|(c, _)| *c == count) {
+                Some((_, n)) => *n += 1,
+                None => freq.push((count, 1)),
+            }
+        }
+        let (mode, agreeing) = freq
+            .into_iter()
+            .max_by_key(|&(count, n)| (n, count))
+            .unwrap_or((0, 0));
+        if mode < 2 {
+            continue;
+        }
+
+        let consistency = agreeing as f64 / counts.len() as f64;
+        let better = match best {
+            None => true,
+            Some((best_consistency, best_mode, _)) => {
+                consistency > best_consistency
+                    || (consistency == best_consistency && mode > best_mode)
+            }
+        };
+        if better {
+            best = Some((consistency, mode, delimiter));
+        }
+    }
+
+    best.map(|(_, _, d)| d).unwrap_or(b',')
+}
+
+/// A quote character opens a field right after a delimiter or line start
+fn sniff_quote(sample: &[u8], delimiter: u8) -> Option<u8> {
+    let count_opening = |quote: u8| {
+        sample
+            .iter()
+            .enumerate()
+            .filter(|&(i, &b)| {
+                b == quote && (i == 0 || matches!(sample[i - 1], b'\n' | b'\r') || sample[i - 1] == delimiter)
+            })
+            .count()
+    };
+
+    let double = count_opening(b'"');
+    let single = count_opening(b'\'');
+    if single > double { Some(b'\'') } else { Some(b'"') }
+}
+
+/// Count line breaks by kind; CRLF wins ties because it's the CSV default
+fn sniff_terminator(sample: &[u8]) -> LineTerminator {
+    let mut crlf = 0;
+    let mut lf = 0;
+    let mut cr = 0;
+    let mut i = 0;
+    while i < sample.len() {
+        match sample[i] {
+            b'\r' if sample.get(i + 1) == Some(&b'\n') => {
+                crlf += 1;
+                i += 1;
+            }
+            b'\r' => cr += 1,
+            b'\n' => lf += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if lf > crlf && lf >= cr {
+        LineTerminator::Lf
+    } else if cr > crlf && cr > lf {
+        LineTerminator::Cr
+    } else {
+        LineTerminator::CrLf
+    }
+}
+
+/// Every line starts and ends with a quote, and every delimiter sits between two
+/// quotes. Fields with embedded delimiters or line breaks make this answer no,
+/// which only means the file is saved with minimal quoting.
+fn sniff_always_quoted(sample: &[u8], delimiter: u8, quote: u8) -> bool {
+    let mut lines = sample
+        .split(|&b| b == b'\n' || b == b'\r')
+        .filter(|line| !line.is_empty())
+        .peekable();
+    if lines.peek().is_none() {
+        return false;
+    }
+
+    lines.all(|line| {
+        let delimiters = line.iter().filter(|&&b| b == delimiter).count();
+        let quoted = line.windows(3).filter(|w| w == &[quote, delimiter, quote]).count();
+        line.len() >= 2 && line[0] == quote && line[line.len() - 1] == quote && delimiters == quoted
+    })
+}
+
+/// Backslash escaping wins only when `\"` is more common than `""`
+fn sniff_escape(sample: &[u8], quote: u8) -> Option<u8> {
+    let backslashed = sample.windows(2).filter(|w| w[0] == b'\\' && w[1] == quote).count();
+    let doubled = sample.windows(2).filter(|w| w[0] == quote && w[1] == quote).count();
+    if backslashed > doubled { Some(b'\\') } else { None }
+}
+
+fn is_number(s: &str) -> bool {
+    s.parse::<f64>().is_ok()
+}
+
+/// Compare the first row against the rest, column by column. A header cell is
+/// usually text above numbers, or a different length than fixed-width values.
+pub fn sniff_header(rows: &Sheet) -> bool {
+    if rows.is_empty() {
+        return false;
+    }
+
+    let mut votes = 0i32;
+    for col_idx in 0..rows.num_cols() {
+        let label = rows.get(0, col_idx).trim();
+        let values: Vec<&str> = (1..rows.num_rows())
+            .map(|row| rows.get(row, col_idx).trim())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+
+        if values.iter().all(|v| is_number(v)) {
+            votes += if is_number(label) { -1 } else { 1 };
+            continue;
+        }
+
+        let len = values[0].chars().count();
+        if values.iter().all(|v| v.chars().count() == len) {
+            votes += if label.chars().count() == len { -1 } else { 1 };
+        }
+    }
+
+    if votes != 0 {
+        return votes > 0;
+    }
+
+    // No typed evidence either way: treat distinct, non-numeric labels as a header
+    let mut seen = HashSet::new();
+    rows.row(0).all(|label| {
+        let label = label.trim();
+        !label.is_empty() && !is_number(label) && seen.insert(label)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_the_delimiter_and_header() {
+        let dialect = CsvDialect::sniff(b"name;price\r\nwidget;12\r\ngadget;7\r\n");
+        assert_eq!(dialect.delimiter, b';');
+        assert_eq!(dialect.terminator, LineTerminator::CrLf);
+        assert!(dialect.has_headers);
+    }
+
+    #[test]
+    fn prefers_the_delimiter_giving_consistent_rows() {
+        let dialect = CsvDialect::sniff(b"id|note\n1|\"a, b, c\"\n2|\"d, e\"\n");
+        assert_eq!(dialect.delimiter, b'|');
+        assert_eq!(dialect.terminator, LineTerminator::Lf);
+
+        let dialect = CsvDialect::sniff(b"1\t2.5\t3\n4\t5\t6\n");
+        assert_eq!(dialect.delimiter, b'\t');
+        assert!(!dialect.has_headers);
+    }
+
+    #[test]
+    fn sniffs_quoting_and_escapes() {
+        let dialect = CsvDialect::sniff(b"\"a\",\"b\"\r\"say \\\"hi\\\"\",\"2\"\r");
+        assert_eq!(dialect.quote, Some(b'"'));
+        assert_eq!(dialect.escape, Some(b'\\'));
+        assert_eq!(dialect.quote_style, QuoteStyle::Always);
+        assert_eq!(dialect.terminator, LineTerminator::Cr);
+
+        let dialect = CsvDialect::sniff(b"name,quote\n'x','it''s'\n'y','no'\n");
+        assert_eq!(dialect.quote, Some(b'\''));
+        assert_eq!(dialect.escape, None);
+    }
+
+    #[test]
+    fn sniffs_a_cut_off_sample() {
+        let mut bytes = b"name,value\n".to_vec();
+        while bytes.len() <= SNIFF_SAMPLE_SIZE {
+            bytes.extend_from_slice(b"some name,12\n");
+        }
+        bytes.extend_from_slice(b"last,1");
+        let (sample, dialect) = sniff_sample(&bytes, &TextEncoding::default());
+        assert!(sample.ends_with('\n'));
+        assert!(sample.len() <= SNIFF_SAMPLE_SIZE);
+        assert_eq!(dialect.delimiter, b',');
+        assert!(dialect.has_headers);
+        assert!(!dialect.trailing_newline);
+    }
+
+    #[test]
+    fn keeps_rejected_records() {
+        let parsed = read_csv(b"a,b\n1,2\n3,4,5\n", &CsvDialect::default(), None).unwrap();
+        assert_eq!(parsed.rows.num_rows(), 3);
+        assert_eq!(parsed.problems.len(), 1);
+        assert_eq!((parsed.problems[0].row, parsed.problems[0].line), (2, 3));
+        assert_eq!(parsed.problems[0].raw, "3,4,5");
+    }
+
+    #[test]
+    fn writes_in_the_dialect() {
+        let dialect = CsvDialect { delimiter: b'\t', terminator: LineTerminator::Lf, ..Default::default() };
+        let parsed = read_csv(b"a\tb c\n\"x\ty\"\t2\n", &dialect, None).unwrap();
+        assert_eq!(parsed.rows.get(1, 0), "x\ty");
+        assert_eq!(write_csv(&parsed.rows, &dialect).unwrap(), "a\tb c\n\"x\ty\"\t2\n");
+    }
+
+    #[test]
+    fn parses_delimiter_names() {
+        assert_eq!(parse_delimiter("tab"), Some(b'\t'));
+        assert_eq!(parse_delimiter("Semicolon"), Some(b';'));
+        assert_eq!(parse_delimiter("|"), Some(b'|'));
+        assert_eq!(parse_delimiter("ab"), None);
+    }
+}
@@ -0,0 +1,434 @@
+//! Comparing two versions of a sheet and merging them.
+//!
+//! Rows are paired by position or by a key column, such as an id that both
+//! versions share. With a header row, columns are paired by their header text,
+//! so a column added or moved in one version lines up with the other;
+//! otherwise by position. Each pair of rows is the same, changed in some
+//! columns, added (only in the right-hand, newer version) or removed (only in
+//! the left-hand one).
+//!
+//! A [`Merge`] says which side each row, or each changed cell, is taken from,
+//! and [`Comparison::merge`] builds the resulting sheet.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::sheet::Sheet;
+
+/// How rows of the two sheets are paired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowAlignment {
+    /// The nth row with the nth row
+    Position,
+    /// Rows with the same text in the key columns, taken in order when a key
+    /// repeats. Rows with an empty key have no partner.
+    Key {
+        /// Key column of the left sheet
+        left: usize,
+        /// Key column of the right sheet
+        right: usize,
+    },
+}
+
+/// One of the two sheets being compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// The sheet on the left, usually the one being changed
+    Left,
+    /// The sheet on the right, usually the one compared against
+    Right,
+}
+
+/// How a row of the comparison differs between the sheets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowStatus {
+    /// In both sheets with the same text
+    Same,
+    /// In both sheets with some cells differing
+    Changed,
+    /// Only in the right sheet
+    Added,
+    /// Only in the left sheet
+    Removed,
+}
+
+/// A column of the comparison: its index in each sheet, if it is there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnPair {
+    /// Index in the left sheet
+    pub left: Option<usize>,
+    /// Index in the right sheet
+    pub right: Option<usize>,
+}
+
+/// A row of the comparison: its index in each sheet, if it is there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowPair {
+    /// Index in the left sheet
+    pub left: Option<usize>,
+    /// Index in the right sheet
+    pub right: Option<usize>,
+    /// How the row differs
+    pub status: RowStatus,
+    /// Columns of the comparison whose text differs, for changed rows
+    pub changed: Vec<usize>,
+}
+
+impl RowPair {
+    /// Index of the row in one of the sheets
+    pub fn row(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+}
+
+impl ColumnPair {
+    /// Index of the column in one of the sheets
+    pub fn col(&self, side: Side) -> Option<usize> {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+}
+
+/// Rows and columns of two sheets paired up, in the order they are shown
+#[derive(Debug, Clone, Default)]
+pub struct Comparison {
+    /// Columns, left sheet order first, then those only on the right
+    pub columns: Vec<ColumnPair>,
+    /// Rows in the order they are shown
+    pub rows: Vec<RowPair>,
+}
+
+/// Number of rows of each kind that differ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffCounts {
+    /// Rows only in the right sheet
+    pub added: usize,
+    /// Rows only in the left sheet
+    pub removed: usize,
+    /// Rows in both sheets whose text differs
+    pub changed: usize,
+}
+
+/// Compare two sheets. With `header`, the first rows are paired with each
+/// other and name the columns.
+pub fn compare(left: &Sheet, right: &Sheet, header: bool, alignment: RowAlignment) -> Comparison {
+    let header = header && !left.is_empty() && !right.is_empty();
+    let columns = if header {
+        pair_columns_by_name(left, right)
+    } else {
+        (0..left.num_cols().max(right.num_cols()))
+            .map(|col| ColumnPair {
+                left: (col < left.num_cols()).then_some(col),
+                right: (col < right.num_cols()).then_some(col),
+            })
+            .collect()
+    };
+
+    let first = if header { 1 } else { 0 };
+    let mut pairs = Vec::new();
+    if header {
+        pairs.push((Some(0), Some(0)));
+    }
+    match alignment {
+        RowAlignment::Position => {
+            for row in first..left.num_rows().max(right.num_rows()) {
+                pairs.push(((row < left.num_rows()).then_some(row), (row < right.num_rows()).then_some(row)));
+            }
+        }
+        RowAlignment::Key { left: left_key, right: right_key } => {
+            pair_rows_by_key(left, right, first, left_key, right_key, &mut pairs);
+        }
+    }
+
+    let rows = pairs
+        .into_iter()
+        .map(|(left_row, right_row)| {
+            let (status, changed) = match (left_row, right_row) {
+                (Some(l), Some(r)) => {
+                    let changed: Vec<usize> = columns
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, pair)| cell(left, l, pair.left) != cell(right, r, pair.right))
+                        .map(|(index, _)| index)
+                        .collect();
+                    let status = if changed.is_empty() { RowStatus::Same } else { RowStatus::Changed };
+                    (status, changed)
+                }
+                (None, _) => (RowStatus::Added, Vec::new()),
+                (_, None) => (RowStatus::Removed, Vec::new()),
+            };
+            RowPair { left: left_row, right: right_row, status, changed }
+        })
+        .collect();
+
+    Comparison { columns, rows }
+}
+
+fn cell(sheet: &Sheet, row: usize, col: Option<usize>) -> &str {
+    col.map_or("", |col| sheet.get(row, col))
+}
+
+// Columns with the same header text, in the left sheet's order, followed by
+// the columns only the right sheet has
+fn pair_columns_by_name(left: &Sheet, right: &Sheet) -> Vec<ColumnPair> {
+    let mut unused: Vec<bool> = vec![true; right.num_cols()];
+    let mut columns = Vec::new();
+    for col in 0..left.num_cols() {
+        let name = left.get(0, col);
+        let partner = (0..right.num_cols()).find(|&r| unused[r] && !name.is_empty() && right.get(0, r) == name);
+        if let Some(r) = partner {
+            unused[r] = false;
+        }
+        columns.push(ColumnPair { left: Some(col), right: partner });
+    }
+    for (col, _) in unused.iter().enumerate().filter(|(_, unused)| **unused) {
+        columns.push(ColumnPair { left: None, right: Some(col) });
+    }
+    columns
+}
+
+// Rows in the right sheet's order, with each left row that has no partner
+// placed before the next left row that does
+fn pair_rows_by_key(
+    left: &Sheet,
+    right: &Sheet,
+    first: usize,
+    left_key: usize,
+    right_key: usize,
+    pairs: &mut Vec<(Option<usize>, Option<usize>)>,
+) {
+    let mut by_key: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for row in first..left.num_rows() {
+        let key = left.get(row, left_key);
+        if !key.is_empty() {
+            by_key.entry(key).or_default().push_back(row);
+        }
+    }
+    let partners: Vec<Option<usize>> = (first..right.num_rows())
+        .map(|row| by_key.get_mut(right.get(row, right_key)).and_then(|rows| rows.pop_front()))
+        .collect();
+    let mut matched = vec![false; left.num_rows()];
+    for &row in partners.iter().flatten() {
+        matched[row] = true;
+    }
+
+    let mut next_left = first;
+    for (offset, partner) in partners.into_iter().enumerate() {
+        let right_row = first + offset;
+        if let Some(left_row) = partner {
+            while next_left < left_row {
+                if !matched[next_left] {
+                    pairs.push((Some(next_left), None));
+                }
+                next_left += 1;
+            }
+            next_left = next_left.max(left_row + 1);
+        }
+        pairs.push((partner, Some(right_row)));
+    }
+    for (row, _) in matched.iter().enumerate().skip(next_left).filter(|(_, matched)| !**matched) {
+        pairs.push((Some(row), None));
+    }
+}
+
+impl Comparison {
+    /// Number of rows added, removed and changed
+    pub fn counts(&self) -> DiffCounts {
+        let mut counts = DiffCounts::default();
+        for row in &self.rows {
+            match row.status {
+                RowStatus::Same => {}
+                RowStatus::Changed => counts.changed += 1,
+                RowStatus::Added => counts.added += 1,
+                RowStatus::Removed => counts.removed += 1,
+            }
+        }
+        counts
+    }
+
+    /// Text of a cell of the comparison on one side; empty where that side
+    /// doesn't have the row or column
+    pub fn text<'a>(&self, sheet: &'a Sheet, side: Side, row: usize, col: usize) -> &'a str {
+        match self.rows[row].row(side) {
+            Some(sheet_row) => cell(sheet, sheet_row, self.columns[col].col(side)),
+            None => "",
+        }
+    }
+
+    /// The sheet the merge choices produce. A row is kept if the side it is
+    /// taken from has it, and a column if some kept cell is taken from a side
+    /// that has the column. Rows keep their order in the merge's base side,
+    /// with rows that side doesn't have following the row they come after.
+    pub fn merge(&self, left: &Sheet, right: &Sheet, merge: &Merge) -> Sheet {
+        let sheets = |side| match side {
+            Side::Left => left,
+            Side::Right => right,
+        };
+        let mut position = (0, 0);
+        let mut order: Vec<((usize, usize), usize)> = Vec::with_capacity(self.rows.len());
+        for (index, row) in self.rows.iter().enumerate() {
+            position = match row.row(merge.base) {
+                Some(base_row) => (base_row, 0),
+                None => (position.0, position.1 + 1),
+            };
+            order.push((position, index));
+        }
+        order.sort();
+        let kept_rows: Vec<usize> = order
+            .into_iter()
+            .map(|(_, row)| row)
+            .filter(|&row| self.rows[row].row(merge.row_side(row)).is_some())
+            .collect();
+        let kept_cols: Vec<usize> = (0..self.columns.len())
+            .filter(|&col| {
+                kept_rows.iter().any(|&row| {
+                    let side = merge.cell_side(row, col);
+                    self.columns[col].col(side).is_some() && self.rows[row].row(side).is_some()
+                })
+            })
+            .collect();
+
+        let mut sheet = Sheet::new(0, kept_cols.len());
+        for &row in &kept_rows {
+            sheet.push_row(kept_cols.iter().map(|&col| {
+                let side = merge.cell_side(row, col);
+                self.text(sheets(side), side, row, col)
+            }));
+        }
+        sheet
+    }
+}
+
+/// Which side each row of a comparison, or each cell in it, is taken from
+#[derive(Debug, Clone)]
+pub struct Merge {
+    // Side whose row order the result keeps
+    base: Side,
+    rows: Vec<Side>,
+    cells: HashMap<(usize, usize), Side>,
+}
+
+impl Merge {
+    /// Take every row from one side
+    pub fn new(comparison: &Comparison, side: Side) -> Self {
+        Self { base: side, rows: vec![side; comparison.rows.len()], cells: HashMap::new() }
+    }
+
+    /// Side a row is taken from
+    pub fn row_side(&self, row: usize) -> Side {
+        self.rows.get(row).copied().unwrap_or(Side::Left)
+    }
+
+    /// Side a cell is taken from: its row's side unless chosen separately
+    pub fn cell_side(&self, row: usize, col: usize) -> Side {
+        self.cells.get(&(row, col)).copied().unwrap_or_else(|| self.row_side(row))
+    }
+
+    /// Take a whole row from one side, including cells chosen separately
+    pub fn set_row(&mut self, row: usize, side: Side) {
+        if let Some(row_side) = self.rows.get_mut(row) {
+            *row_side = side;
+            self.cells.retain(|&(r, _), _| r != row);
+        }
+    }
+
+    /// Take one cell from one side
+    pub fn set_cell(&mut self, row: usize, col: usize, side: Side) {
+        if side == self.row_side(row) {
+            self.cells.remove(&(row, col));
+        } else {
+            self.cells.insert((row, col), side);
+        }
+    }
+
+    /// Take everything from one side, keeping that side's row order
+    pub fn set_all(&mut self, side: Side) {
+        self.base = side;
+        self.rows.fill(side);
+        self.cells.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statuses(comparison: &Comparison) -> Vec<RowStatus> {
+        comparison.rows.iter().map(|row| row.status).collect()
+    }
+
+    #[test]
+    fn pairs_rows_by_position() {
+        let left = Sheet::from_rows([["a", "1"], ["b", "2"], ["c", "3"]]);
+        let right = Sheet::from_rows([["a", "1"], ["b", "5"]]);
+        let comparison = compare(&left, &right, false, RowAlignment::Position);
+        assert_eq!(statuses(&comparison), [RowStatus::Same, RowStatus::Changed, RowStatus::Removed]);
+        assert_eq!(comparison.rows[1].changed, [1]);
+        assert_eq!(comparison.counts(), DiffCounts { added: 0, removed: 1, changed: 1 });
+    }
+
+    #[test]
+    fn pairs_rows_by_key_and_columns_by_name() {
+        let left = Sheet::from_rows([["id", "name"], ["1", "ann"], ["2", "bob"], ["3", "cy"]]);
+        let right = Sheet::from_rows([["id", "email", "name"], ["3", "c@x", "cy"], ["1", "", "ann"], ["4", "d@x", "di"]]);
+        let comparison = compare(&left, &right, true, RowAlignment::Key { left: 0, right: 0 });
+        assert_eq!(
+            comparison.columns,
+            [
+                ColumnPair { left: Some(0), right: Some(0) },
+                ColumnPair { left: Some(1), right: Some(2) },
+                ColumnPair { left: None, right: Some(1) },
+            ]
+        );
+        let pairs: Vec<_> = comparison.rows.iter().map(|row| (row.left, row.right, row.status)).collect();
+        assert_eq!(
+            pairs,
+            [
+                // The new column's name is a change to the header
+                (Some(0), Some(0), RowStatus::Changed),
+                (Some(2), None, RowStatus::Removed),
+                (Some(3), Some(1), RowStatus::Changed),
+                (Some(1), Some(2), RowStatus::Same),
+                (None, Some(3), RowStatus::Added),
+            ]
+        );
+        assert_eq!(comparison.rows[2].changed, [2]);
+        assert_eq!(comparison.text(&right, Side::Right, 2, 2), "c@x");
+        assert_eq!(comparison.text(&left, Side::Left, 2, 2), "");
+    }
+
+    #[test]
+    fn merging_one_side_gives_that_side() {
+        let left = Sheet::from_rows([["id", "name"], ["1", "ann"], ["2", "bob"], ["3", "cy"]]);
+        let right = Sheet::from_rows([["id", "email", "name"], ["3", "c@x", "cy"], ["2", "b@x", "bobby"], ["4", "", "di"]]);
+        let comparison = compare(&left, &right, true, RowAlignment::Key { left: 0, right: 0 });
+
+        let mut merge = Merge::new(&comparison, Side::Left);
+        assert_eq!(comparison.merge(&left, &right, &merge).rows().collect::<Vec<_>>(), left.rows().collect::<Vec<_>>());
+        merge.set_all(Side::Right);
+        assert_eq!(
+            comparison.merge(&left, &right, &merge).rows().collect::<Vec<_>>(),
+            [["id", "name", "email"], ["3", "cy", "c@x"], ["2", "bobby", "b@x"], ["4", "di", ""]]
+        );
+    }
+
+    #[test]
+    fn merges_rows_and_cells_from_either_side() {
+        let left = Sheet::from_rows([["1", "a", "x"], ["2", "b", "y"]]);
+        let right = Sheet::from_rows([["1", "A", "X"], ["2", "b", "y"], ["3", "c", "z"]]);
+        let comparison = compare(&left, &right, false, RowAlignment::Position);
+        let mut merge = Merge::new(&comparison, Side::Left);
+        merge.set_cell(0, 2, Side::Right);
+        merge.set_row(2, Side::Right);
+        assert_eq!(
+            comparison.merge(&left, &right, &merge).rows().collect::<Vec<_>>(),
+            [["1", "a", "X"], ["2", "b", "y"], ["3", "c", "z"]]
+        );
+        merge.set_row(0, Side::Left);
+        assert_eq!(merge.cell_side(0, 2), Side::Left);
+    }
+}
@@ -0,0 +1,609 @@
+//! An open file: its cells and formulas, the format it was read in, the type
+//! of each column, and the undo history of the edits made to it.
+//!
+//! Every change goes through [`Edit`]s. Applying one keeps everything else
+//! that refers to rows and columns by index in step: formula references move
+//! with inserted and deleted rows and columns, formula results are
+//! recalculated, and import problems and column types follow their rows and
+//! columns.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::calc::Calculator;
+use crate::clipboard::{self, CopyOptions};
+use crate::columnar::{self, ColumnarFormat, ColumnarOptions};
+use crate::dialect::{self, CsvDialect, ImportProblem, ParsedCsv};
+use crate::encoding::TextEncoding;
+use crate::formula::{self, Shift};
+use crate::history::{CellChange, Edit, History, Operation};
+use crate::json::{self, JsonOptions};
+use crate::sheet::{ColumnFull, Sheet};
+use crate::sort::{self, SortKey};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::{self, Database, TableChanges, TableLink};
+use crate::types::{self, ColumnType};
+use crate::xlsx::{self, XlsxOptions};
+
+/// How [`Document::to_bytes`] writes the cells.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SaveOptions {
+    /// Write formula results instead of the formulas.
+    pub values: bool,
+    /// Write typed cells in plain form (`1234.5`, `2024-03-01`, `TRUE`).
+    pub plain: bool,
+    /// Whether the first row is a header, which `plain` leaves alone.
+    pub header: bool,
+}
+
+/// A sheet being edited, with what is needed to save it and undo changes.
+#[derive(Default)]
+pub struct Document {
+    /// The cells, as text.
+    pub sheet: Sheet,
+    /// Parsed formulas and their cached results.
+    pub calc: Calculator,
+    /// Layout of the file, reused when saving so an unchanged file is written
+    /// back exactly as it was read.
+    pub dialect: CsvDialect,
+    /// Character encoding of the file, reused when saving.
+    pub encoding: TextEncoding,
+    /// Records the parser rejected, loaded as-is.
+    pub problems: Vec<ImportProblem>,
+    /// Type of each column; columns not listed are text.
+    pub column_types: HashMap<usize, ColumnType>,
+    /// Operations that can be undone and redone.
+    pub history: History,
+}
+
+/// Guess the encoding and dialect of a file from its contents.
+pub fn detect_format(bytes: &[u8]) -> (CsvDialect, TextEncoding) {
+    let encoding = TextEncoding::detect(&bytes[..bytes.len().min(dialect::SNIFF_SAMPLE_SIZE)]);
+    let (_, dialect) = dialect::sniff_sample(bytes, &encoding);
+    (dialect, encoding)
+}
+
+impl Document {
+    /// A blank document of the given size.
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self {
+            sheet: Sheet::new(rows, cols),
+            ..Default::default()
+        }
+    }
+
+    /// Read a file, guessing its encoding and dialect.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?).map_err(std::io::Error::other)
+    }
+
+    /// Load the contents of a file, guessing its encoding and dialect. Fails
+    /// if a column holds more text than a sheet can.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ColumnFull> {
+        let (dialect, encoding) = detect_format(bytes);
+        Self::parse(bytes, dialect, encoding)
+    }
+
+    /// Load the contents of a file read with the given encoding and dialect.
+    pub fn parse(bytes: &[u8], dialect: CsvDialect, encoding: TextEncoding) -> Result<Self, ColumnFull> {
+        let text = encoding.decode(bytes);
+        let parsed = dialect::read_csv(text.as_bytes(), &dialect, None)?;
+        Ok(Self::from_parsed(parsed, dialect, encoding))
+    }
+
+    /// A document for rows parsed elsewhere, e.g. in the background. Formulas
+    /// are read and column types inferred, skipping the header row if the
+    /// dialect has one.
+    pub fn from_parsed(parsed: ParsedCsv, dialect: CsvDialect, encoding: TextEncoding) -> Self {
+        let mut document = Self {
+            sheet: parsed.rows,
+            problems: parsed.problems,
+            dialect,
+            encoding,
+            ..Default::default()
+        };
+        document.calc.rebuild(&document.sheet);
+        document.detect_column_types(document.dialect.has_headers);
+        document
+    }
+
+    /// A document for cells read from somewhere other than a delimited file,
+    /// such as a workbook. It is saved as CSV with the default dialect, and
+    /// whether the first row is a header is guessed from the cells.
+    pub fn from_sheet(sheet: Sheet) -> Self {
+        let dialect = CsvDialect { has_headers: dialect::sniff_header(&sheet), ..Default::default() };
+        let parsed = ParsedCsv { rows: sheet, problems: Vec::new() };
+        Self::from_parsed(parsed, dialect, TextEncoding::default())
+    }
+
+    /// A document for JSON records, with a header row of their keys when they
+    /// are objects.
+    pub fn from_json(bytes: &[u8]) -> Result<Self, json::ReadError> {
+        let table = json::read_json(bytes)?;
+        let dialect = CsvDialect { has_headers: table.header, ..Default::default() };
+        let parsed = ParsedCsv { rows: table.sheet, problems: Vec::new() };
+        Ok(Self::from_parsed(parsed, dialect, TextEncoding::default()))
+    }
+
+    /// A document for a Parquet or Arrow file, with a header row of field
+    /// names and column types taken from the schema.
+    pub fn from_columnar(bytes: Vec<u8>, format: ColumnarFormat) -> Result<Self, columnar::Error> {
+        let table = columnar::read_columnar(bytes, format)?;
+        let dialect = CsvDialect { has_headers: true, ..Default::default() };
+        let parsed = ParsedCsv { rows: table.sheet, problems: Vec::new() };
+        let mut document = Self::from_parsed(parsed, dialect, TextEncoding::default());
+        document.column_types = table.column_types;
+        Ok(document)
+    }
+
+    /// Infer the type of every column again.
+    pub fn detect_column_types(&mut self, header: bool) {
+        self.column_types = types::infer_column_types(&self.sheet, header);
+    }
+
+    /// Type of a column; text if none was set.
+    pub fn column_type(&self, col: usize) -> ColumnType {
+        self.column_types.get(&col).copied().unwrap_or(ColumnType::Text)
+    }
+
+    /// Column with the given header text, matched exactly and then ignoring
+    /// case, or with the given letters (`A`, `AB`).
+    pub fn find_column(&self, name: &str, header: bool) -> Option<usize> {
+        if header && !self.sheet.is_empty() {
+            let headers: Vec<&str> = self.sheet.row(0).collect();
+            let found = headers.iter().position(|text| *text == name).or_else(|| {
+                headers
+                    .iter()
+                    .position(|text| text.trim().eq_ignore_ascii_case(name.trim()))
+            });
+            if found.is_some() {
+                return found;
+            }
+        }
+        if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return None;
+        }
+        formula::column_index(name).filter(|&col| col < self.sheet.num_cols())
+    }
+
+    /// Text a cell shows: the result for formulas, the cell text otherwise.
+    pub fn text(&mut self, row: usize, col: usize) -> Cow<'_, str> {
+        self.calc.text(&self.sheet, row, col)
+    }
+
+    /// The file as it would be saved, in its dialect and encoding, along with
+    /// the number of characters the encoding couldn't represent (written as
+    /// `?`).
+    pub fn to_bytes(&mut self, options: SaveOptions) -> Result<(Vec<u8>, usize), Box<dyn std::error::Error>> {
+        let mut sheet = Cow::Borrowed(&self.sheet);
+        if options.values && self.calc.has_formulas() {
+            sheet = Cow::Owned(self.calc.computed_sheet(&self.sheet));
+        }
+        if options.plain {
+            sheet = Cow::Owned(types::normalized_sheet(&sheet, &self.column_types, options.header));
+        }
+        let text = dialect::write_csv(&sheet, &self.dialect)?;
+        Ok(self.encoding.encode(&text))
+    }
+
+    /// The cells as an Excel workbook with a single worksheet.
+    pub fn to_xlsx(&mut self, options: &XlsxOptions) -> Result<Vec<u8>, xlsx::WriteError> {
+        xlsx::write_sheet(&self.sheet, &mut self.calc, &self.column_types, options)
+    }
+
+    /// The cells as JSON records, with formulas replaced by their results.
+    pub fn to_json(&mut self, options: &JsonOptions) -> Vec<u8> {
+        let mut sheet = Cow::Borrowed(&self.sheet);
+        if self.calc.has_formulas() {
+            sheet = Cow::Owned(self.calc.computed_sheet(&self.sheet));
+        }
+        json::write_json(&sheet, &self.column_types, options)
+    }
+
+    /// The cells as a Parquet or Arrow file typed by the column types, with
+    /// formulas replaced by their results.
+    pub fn to_columnar(&mut self, options: &ColumnarOptions) -> Result<Vec<u8>, columnar::Error> {
+        let mut sheet = Cow::Borrowed(&self.sheet);
+        if self.calc.has_formulas() {
+            sheet = Cow::Owned(self.calc.computed_sheet(&self.sheet));
+        }
+        columnar::write_columnar(&sheet, &self.column_types, options)
+    }
+
+    /// Write the edits made since the rows were read from a database table
+    /// back to it, with formulas replaced by their results.
+    #[cfg(feature = "sqlite")]
+    pub fn save_to_table(&mut self, database: &mut Database, link: &mut TableLink) -> Result<TableChanges, sqlite::Error> {
+        let mut sheet = Cow::Borrowed(&self.sheet);
+        if self.calc.has_formulas() {
+            sheet = Cow::Owned(self.calc.computed_sheet(&self.sheet));
+        }
+        database.write_changes(link, &sheet)
+    }
+
+    /// The cells as a new table of a database, typed by the column types,
+    /// with formulas replaced by their results. Returns the number of rows
+    /// written.
+    #[cfg(feature = "sqlite")]
+    pub fn export_to_table(
+        &mut self,
+        database: &mut Database,
+        name: &str,
+        header: bool,
+        replace: bool,
+    ) -> Result<usize, sqlite::Error> {
+        let mut sheet = Cow::Borrowed(&self.sheet);
+        if self.calc.has_formulas() {
+            sheet = Cow::Owned(self.calc.computed_sheet(&self.sheet));
+        }
+        database.export_table(name, &sheet, &self.column_types, header, replace)
+    }
+
+    /// Rows and columns as a Markdown, HTML or LaTeX table, JSON or SQL, with
+    /// formulas replaced by their results.
+    pub fn copy_as(&mut self, rows: impl IntoIterator<Item = usize>, cols: Range<usize>, options: &CopyOptions) -> String {
+        let mut sheet = Cow::Borrowed(&self.sheet);
+        if self.calc.has_formulas() {
+            sheet = Cow::Owned(self.calc.computed_sheet(&self.sheet));
+        }
+        clipboard::format_cells(&sheet, &self.column_types, rows, cols, options)
+    }
+
+    /// Apply edits as one step in the undo history, under a label such as
+    /// "Paste". Formula references are moved to follow inserted and deleted
+    /// rows and columns.
+    pub fn apply_operation(&mut self, label: impl Into<String>, edits: Vec<Edit>) {
+        self.apply_operation_with(label, edits, |_, _| {});
+    }
+
+    /// `apply_operation`, calling `before_edit` with each edit and the sheet
+    /// it is about to change. Front-ends use this to keep their own state by
+    /// row or column index (a selection, column widths) in step.
+    pub fn apply_operation_with(
+        &mut self,
+        label: impl Into<String>,
+        mut edits: Vec<Edit>,
+        mut before_edit: impl FnMut(&Edit, &Sheet),
+    ) {
+        if edits.is_empty() {
+            return;
+        }
+        if let Some(update) = self.reference_updates(&edits) {
+            edits.push(update);
+        }
+        let undo_edits = self.apply_edits(edits, &mut before_edit);
+        self.history.push(Operation::new(label.into(), undo_edits));
+    }
+
+    /// Set cells as one undoable step, skipping cells that already hold the text.
+    pub fn set_cells(&mut self, label: impl Into<String>, changes: Vec<CellChange>) {
+        let changes: Vec<CellChange> = changes
+            .into_iter()
+            .filter(|change| self.sheet.get(change.row, change.col) != change.text)
+            .collect();
+        if !changes.is_empty() {
+            self.apply_operation(label, vec![Edit::SetCells(changes)]);
+        }
+    }
+
+    /// Sort the rows by the keys, in priority order, as one undoable step.
+    /// A header row stays first.
+    pub fn sort(&mut self, label: impl Into<String>, keys: &[SortKey], header: bool) {
+        if self.sheet.is_empty() || keys.is_empty() {
+            return;
+        }
+        let order = sort::sort_order(&self.sheet, &mut self.calc, keys, header);
+        self.apply_operation(label, vec![Edit::ReorderRows(order)]);
+    }
+
+    /// Undo the latest operation. Returns false if there was none.
+    pub fn undo(&mut self) -> bool {
+        self.undo_with(|_, _| {})
+    }
+
+    /// `undo`, calling `before_edit` as `apply_operation_with` does
+    pub fn undo_with(&mut self, mut before_edit: impl FnMut(&Edit, &Sheet)) -> bool {
+        let Some(op) = self.history.take_undo() else {
+            return false;
+        };
+        let redo_edits = self.apply_edits(op.edits, &mut before_edit);
+        self.history.push_undone(Operation::new(op.label, redo_edits));
+        true
+    }
+
+    /// Redo the latest undone operation. Returns false if there was none.
+    pub fn redo(&mut self) -> bool {
+        self.redo_with(|_, _| {})
+    }
+
+    /// `redo`, calling `before_edit` as `apply_operation_with` does
+    pub fn redo_with(&mut self, mut before_edit: impl FnMut(&Edit, &Sheet)) -> bool {
+        let Some(op) = self.history.take_redo() else {
+            return false;
+        };
+        let undo_edits = self.apply_edits(op.edits, &mut before_edit);
+        self.history.push_redone(Operation::new(op.label, undo_edits));
+        true
+    }
+
+    // Apply edits in order and return the edits that reverse them, in the
+    // order they have to be applied
+    fn apply_edits(&mut self, edits: Vec<Edit>, before_edit: &mut impl FnMut(&Edit, &Sheet)) -> Vec<Edit> {
+        let mut inverse: Vec<Edit> = edits
+            .into_iter()
+            .map(|edit| {
+                before_edit(&edit, &self.sheet);
+                // A deleted column's type goes with it, so undo puts it back
+                let removed_type = match edit {
+                    Edit::RemoveColumn(col_idx) => self.column_types.get(&col_idx).copied(),
+                    _ => None,
+                };
+                self.adjust_indices_for(&edit);
+                let mut inverse = edit.apply(&mut self.sheet);
+                if let Edit::InsertColumn(_, _, column_type) = &mut inverse {
+                    *column_type = removed_type;
+                }
+                self.recalculate_after(&inverse);
+                inverse
+            })
+            .collect();
+        inverse.reverse();
+        inverse
+    }
+
+    /// Formulas rewritten so their references follow the rows and columns
+    /// moved by `edits`, as an edit to apply after them.
+    pub fn reference_updates(&self, edits: &[Edit]) -> Option<Edit> {
+        let shifts: Vec<Shift> = edits
+            .iter()
+            .filter_map(|edit| match *edit {
+                Edit::InsertRow(at, _) => Some(Shift::InsertRow(at)),
+                Edit::RemoveRow(at) => Some(Shift::RemoveRow(at)),
+                Edit::InsertColumn(at, ..) => Some(Shift::InsertColumn(at)),
+                Edit::RemoveColumn(at) => Some(Shift::RemoveColumn(at)),
+                _ => None,
+            })
+            .collect();
+        if shifts.is_empty() || !self.calc.has_formulas() {
+            return None;
+        }
+
+        let changes: Vec<CellChange> = self
+            .calc
+            .formula_cells()
+            .filter_map(|cell| {
+                let original = self.sheet.get(cell.0, cell.1);
+                let mut text = original.to_string();
+                let mut position = cell;
+                for shift in &shifts {
+                    position = shift.move_cell(position)?;
+                    if let Some(adjusted) = formula::adjust_references(&text, *shift) {
+                        text = adjusted;
+                    }
+                }
+                (text != original).then_some(CellChange { row: position.0, col: position.1, text })
+            })
+            .collect();
+        (!changes.is_empty()).then_some(Edit::SetCells(changes))
+    }
+
+    // Bring formula results up to date after an edit, given its inverse
+    fn recalculate_after(&mut self, inverse: &Edit) {
+        match inverse {
+            Edit::SetCells(changes) => {
+                self.calc.cells_changed(&self.sheet, changes.iter().map(|change| (change.row, change.col)));
+            }
+            // Growing the sheet only adds empty cells, unless it puts back cells
+            // a shrink cut off
+            Edit::Resize(rows, cols, cut)
+                if cut.is_empty() && *rows <= self.sheet.num_rows() && *cols <= self.sheet.num_cols() => {}
+            _ => self.calc.rebuild(&self.sheet),
+        }
+    }
+
+    // Keep import problems and column types in step with the rows and columns
+    // they belong to
+    fn adjust_indices_for(&mut self, edit: &Edit) {
+        match *edit {
+            Edit::InsertRow(row_idx, _) => {
+                for problem in &mut self.problems {
+                    if problem.row >= row_idx {
+                        problem.row += 1;
+                    }
+                }
+            }
+            Edit::RemoveRow(row_idx) => {
+                // Forget problems reported for the deleted row and shift the rest
+                self.problems.retain(|p| p.row != row_idx);
+                for problem in &mut self.problems {
+                    if problem.row > row_idx {
+                        problem.row -= 1;
+                    }
+                }
+            }
+            Edit::InsertColumn(col_idx, _, inserted_type) => {
+                self.column_types = self
+                    .column_types
+                    .iter()
+                    .map(|(&idx, &column_type)| (if idx >= col_idx { idx + 1 } else { idx }, column_type))
+                    .collect();
+                if let Some(column_type) = inserted_type {
+                    self.column_types.insert(col_idx.min(self.sheet.num_cols()), column_type);
+                }
+            }
+            Edit::RemoveColumn(col_idx) => {
+                self.column_types.remove(&col_idx);
+                self.column_types = self
+                    .column_types
+                    .iter()
+                    .map(|(&idx, &column_type)| (if idx > col_idx { idx - 1 } else { idx }, column_type))
+                    .collect();
+            }
+            Edit::ReorderRows(_) => {
+                // Rows have moved, so problems can't be located anymore
+                self.problems.clear();
+            }
+            Edit::SetCells(_) | Edit::Resize(..) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{LineTerminator, QuoteStyle};
+    use crate::sort::SortType;
+
+    #[test]
+    fn round_trips_an_unchanged_file() {
+        let bytes = b"name;amount\r\n\"widget; large\";1,5\r\ngadget;2\r\n";
+        let mut document = Document::from_bytes(bytes).unwrap();
+        assert_eq!(document.dialect.delimiter, b';');
+        assert_eq!(document.sheet.num_rows(), 3);
+        let (saved, unmappable) = document.to_bytes(SaveOptions::default()).unwrap();
+        assert_eq!(saved, bytes);
+        assert_eq!(unmappable, 0);
+    }
+
+    #[test]
+    fn round_trips_quoting_and_line_endings() {
+        let files: [&[u8]; 4] = [
+            b"\"a\",\"b\"\n\"1\",\"x y\"\n",
+            b"a\tb\r1\t\"two\rlines\"\r",
+            b"a,b\n1,2",
+            b"a;b\r\n\"say \\\"hi\\\"\";2\r\n",
+        ];
+        for bytes in files {
+            let mut document = Document::from_bytes(bytes).unwrap();
+            let (saved, _) = document.to_bytes(SaveOptions::default()).unwrap();
+            assert_eq!(String::from_utf8_lossy(&saved), String::from_utf8_lossy(bytes));
+        }
+    }
+
+    #[test]
+    fn saves_in_a_chosen_dialect() {
+        let mut document = Document::from_bytes(b"name,n\nx,1\n").unwrap();
+        document.dialect.delimiter = b'|';
+        document.dialect.terminator = LineTerminator::CrLf;
+        document.dialect.quote_style = QuoteStyle::NonNumeric;
+        document.dialect.trailing_newline = false;
+        let (saved, _) = document.to_bytes(SaveOptions::default()).unwrap();
+        assert_eq!(saved, b"\"name\"|\"n\"\r\n\"x\"|1");
+    }
+
+    #[test]
+    fn saves_back_in_the_encoding_it_was_read_in() {
+        let latin = b"name,city\nRen\xE9e,Z\xFCrich\nFran\xE7ois,Orl\xE9ans\n";
+        let utf16: Vec<u8> = [0xFF, 0xFE].into_iter().chain("a,é\n1,2\n".encode_utf16().flat_map(u16::to_le_bytes)).collect();
+        for bytes in [&latin[..], &utf16] {
+            let mut document = Document::from_bytes(bytes).unwrap();
+            let (saved, unmappable) = document.to_bytes(SaveOptions::default()).unwrap();
+            assert_eq!(saved, bytes);
+            assert_eq!(unmappable, 0);
+        }
+        let document = Document::from_bytes(latin).unwrap();
+        assert_eq!(document.sheet.get(1, 1), "Zürich");
+    }
+
+    #[test]
+    fn infers_column_types_below_the_header() {
+        let document = Document::from_bytes(b"item,price,when\na,$1.50,2024-01-02\nb,$2,2024-02-03\n").unwrap();
+        assert_eq!(document.column_type(0), ColumnType::Text);
+        assert_eq!(document.column_type(1), ColumnType::Currency);
+        assert_eq!(document.column_type(2), ColumnType::Date);
+    }
+
+    #[test]
+    fn finds_columns_by_header_or_letter() {
+        let document = Document::from_bytes(b"Name,Price\nx,1\n").unwrap();
+        assert_eq!(document.find_column("Price", true), Some(1));
+        assert_eq!(document.find_column("price", true), Some(1));
+        assert_eq!(document.find_column("A", true), Some(0));
+        assert_eq!(document.find_column("C", true), None);
+        assert_eq!(document.find_column("Price", false), None);
+    }
+
+    #[test]
+    fn formulas_follow_deleted_rows_and_undo() {
+        let mut document = Document::from_bytes(b"1\n2\n3\n=SUM(A1:A3)\n").unwrap();
+        assert_eq!(document.text(3, 0), "6");
+        document.apply_operation("Delete row 1", vec![Edit::RemoveRow(0)]);
+        assert_eq!(document.sheet.get(2, 0), "=SUM(A1:A2)");
+        assert_eq!(document.text(2, 0), "5");
+        assert!(document.undo());
+        assert_eq!(document.sheet.get(3, 0), "=SUM(A1:A3)");
+        assert_eq!(document.text(3, 0), "6");
+        assert!(document.redo());
+        assert_eq!(document.sheet.num_rows(), 3);
+        assert!(!document.redo());
+    }
+
+    #[test]
+    fn formulas_follow_inserted_rows_and_columns() {
+        let mut document = Document::from_bytes(b"1,2\n=A1+B1,=SUM(A1:A2)\n").unwrap();
+        document.apply_operation("Insert row 1", vec![Edit::InsertRow(0, Vec::new())]);
+        document.apply_operation("Insert column A", vec![Edit::InsertColumn(0, None, None)]);
+        assert_eq!(document.sheet.get(2, 1), "=B2+C2");
+        assert_eq!(document.sheet.get(2, 2), "=SUM(B2:B3)");
+        assert_eq!(document.text(2, 1), "3");
+        document.apply_operation("Delete column C", vec![Edit::RemoveColumn(2)]);
+        assert_eq!(document.sheet.get(2, 1), "=B2+#REF!");
+        assert_eq!(document.text(2, 1), "#REF!");
+        assert!(document.undo());
+        assert_eq!(document.text(2, 1), "3");
+    }
+
+    #[test]
+    fn column_types_follow_inserted_columns() {
+        let mut document = Document::from_bytes(b"a,1\nb,2\n").unwrap();
+        assert_eq!(document.column_type(1), ColumnType::Number);
+        document.apply_operation("Insert column A", vec![Edit::InsertColumn(0, None, None)]);
+        assert_eq!(document.column_type(2), ColumnType::Number);
+        assert_eq!(document.column_type(1), ColumnType::Text);
+    }
+
+    #[test]
+    fn undoing_a_column_delete_restores_its_type() {
+        let mut document = Document::from_bytes(b"a,1,2024-01-02\nb,2,2024-02-03\n").unwrap();
+        document.apply_operation("Delete column B", vec![Edit::RemoveColumn(1)]);
+        assert_eq!(document.column_type(1), ColumnType::Date);
+        assert!(document.undo());
+        assert_eq!(document.column_type(1), ColumnType::Number);
+        assert_eq!(document.column_type(2), ColumnType::Date);
+        assert!(document.redo());
+        assert!(document.undo());
+        assert_eq!(document.column_type(1), ColumnType::Number);
+    }
+
+    #[test]
+    fn sorts_as_one_undoable_step() {
+        let mut document = Document::from_bytes(b"n\n10\n9\n100\n").unwrap();
+        let key = SortKey { col: 0, ascending: true, sort_type: SortType::Number, empties_last: true };
+        document.sort("Sort", &[key], true);
+        assert_eq!(document.sheet.column(0).collect::<Vec<_>>(), ["n", "9", "10", "100"]);
+        assert_eq!(document.history.done_labels().collect::<Vec<_>>(), ["Sort"]);
+        document.undo();
+        assert_eq!(document.sheet.column(0).collect::<Vec<_>>(), ["n", "10", "9", "100"]);
+    }
+
+    #[test]
+    fn guesses_the_header_of_a_sheet() {
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["name", "amount"]);
+        sheet.push_row(["a", "12"]);
+        let document = Document::from_sheet(sheet);
+        assert!(document.dialect.has_headers);
+        assert_eq!(document.column_type(1), ColumnType::Number);
+    }
+
+    #[test]
+    fn saves_values_and_plain_forms() {
+        let mut document = Document::from_bytes(b"a,b\n\"1,200\",=2*3\n").unwrap();
+        document.column_types.insert(0, ColumnType::Number);
+        let options = SaveOptions { values: true, plain: true, header: true };
+        let (saved, _) = document.to_bytes(options).unwrap();
+        assert_eq!(String::from_utf8(saved).unwrap(), "a,b\n1200,6\n");
+    }
+}
@@ -0,0 +1,183 @@
+//! Finding rows that repeat the values of some key columns.
+//!
+//! Key cells can be compared as they are, with surrounding spaces trimmed, or
+//! ignoring case. Rows whose key cells are all empty are never counted as
+//! duplicates, so blank emails in a contact list don't collapse into one row.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::history::{self, Edit};
+use crate::sheet::Sheet;
+
+/// Which columns make two rows the same, and how their cells are compared
+#[derive(Debug, Clone, Default)]
+pub struct DuplicateKey {
+    /// Columns compared, in any order
+    pub columns: Vec<usize>,
+    /// Ignore spaces around the text
+    pub trim: bool,
+    /// Ignore case when comparing text
+    pub ignore_case: bool,
+}
+
+/// Which row of a group of duplicates stays when the others are removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    /// The first row of the group
+    First,
+    /// The last row of the group
+    Last,
+    /// The row with the most non-empty cells, the first of them on a tie
+    MostComplete,
+}
+
+impl Keep {
+    /// Every choice, in the order offered
+    pub const ALL: [Keep; 3] = [Keep::First, Keep::Last, Keep::MostComplete];
+
+    /// Name shown in the window
+    pub fn label(self) -> &'static str {
+        match self {
+            Keep::First => "First row",
+            Keep::Last => "Last row",
+            Keep::MostComplete => "Most complete row",
+        }
+    }
+
+    /// The row kept out of a group of duplicates
+    pub fn kept(self, sheet: &Sheet, group: &[usize]) -> usize {
+        match self {
+            Keep::First => group[0],
+            Keep::Last => group[group.len() - 1],
+            Keep::MostComplete => {
+                let filled = |row: usize| sheet.row(row).filter(|text| !text.trim().is_empty()).count();
+                // max_by_key takes the last of equal rows, so look from the end
+                group.iter().rev().copied().max_by_key(|&row| filled(row)).unwrap_or(group[0])
+            }
+        }
+    }
+}
+
+impl DuplicateKey {
+    fn key<'a>(&self, sheet: &'a Sheet, row: usize) -> Vec<Cow<'a, str>> {
+        self.columns
+            .iter()
+            .map(|&col| {
+                let text = sheet.get(row, col);
+                let text = if self.trim { text.trim() } else { text };
+                if self.ignore_case { Cow::Owned(text.to_lowercase()) } else { Cow::Borrowed(text) }
+            })
+            .collect()
+    }
+
+    /// Groups of two or more rows with the same key, each listing its rows in
+    /// order and the groups ordered by their first row. A header row is left
+    /// out.
+    pub fn groups(&self, sheet: &Sheet, header: bool) -> Vec<Vec<usize>> {
+        if self.columns.is_empty() {
+            return Vec::new();
+        }
+        let first = usize::from(header && !sheet.is_empty());
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut seen: HashMap<Vec<Cow<str>>, usize> = HashMap::new();
+        for row in first..sheet.num_rows() {
+            let key = self.key(sheet, row);
+            if key.iter().all(|text| text.is_empty()) {
+                continue;
+            }
+            match seen.get(&key) {
+                Some(&group) => groups[group].push(row),
+                None => {
+                    seen.insert(key, groups.len());
+                    groups.push(vec![row]);
+                }
+            }
+        }
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+}
+
+/// Rows removed to leave one row of each group, in ascending order
+pub fn rows_to_remove(sheet: &Sheet, groups: &[Vec<usize>], keep: Keep) -> Vec<usize> {
+    let mut rows: Vec<usize> = groups
+        .iter()
+        .flat_map(|group| {
+            let kept = keep.kept(sheet, group);
+            group.iter().copied().filter(move |&row| row != kept)
+        })
+        .collect();
+    rows.sort_unstable();
+    rows
+}
+
+/// Edits that remove the duplicate rows, as one operation
+pub fn remove_duplicates(sheet: &Sheet, groups: &[Vec<usize>], keep: Keep) -> Vec<Edit> {
+    history::remove_rows(&rows_to_remove(sheet, groups, keep))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    fn contacts() -> Sheet {
+        let mut sheet = Sheet::new(0, 0);
+        for row in [
+            ["name", "email", "phone"],
+            ["Ann", "ann@x.com", ""],
+            ["Bob", "", ""],
+            ["ann", " ANN@x.com", "555"],
+            ["Cy", "", ""],
+            ["Ann", "ann@x.com", ""],
+        ] {
+            sheet.push_row(row);
+        }
+        sheet
+    }
+
+    #[test]
+    fn groups_follow_normalization() {
+        let sheet = contacts();
+        let exact = DuplicateKey { columns: vec![1], trim: false, ignore_case: false };
+        assert_eq!(exact.groups(&sheet, true), [[1, 5]]);
+
+        // Blank emails aren't duplicates of each other
+        let loose = DuplicateKey { columns: vec![1], trim: true, ignore_case: true };
+        assert_eq!(loose.groups(&sheet, true), [[1, 3, 5]]);
+
+        let two_columns = DuplicateKey { columns: vec![0, 1], trim: true, ignore_case: false };
+        assert_eq!(two_columns.groups(&sheet, true), [[1, 5]]);
+    }
+
+    #[test]
+    fn keep_chooses_the_remaining_row() {
+        let sheet = contacts();
+        let key = DuplicateKey { columns: vec![1], trim: true, ignore_case: true };
+        let groups = key.groups(&sheet, true);
+        assert_eq!(rows_to_remove(&sheet, &groups, Keep::First), [3, 5]);
+        assert_eq!(rows_to_remove(&sheet, &groups, Keep::Last), [1, 3]);
+        assert_eq!(rows_to_remove(&sheet, &groups, Keep::MostComplete), [1, 5]);
+
+        let mut edited = sheet.clone();
+        for edit in remove_duplicates(&sheet, &groups, Keep::MostComplete) {
+            edit.apply(&mut edited);
+        }
+        let names: Vec<&str> = edited.column(0).collect();
+        assert_eq!(names, ["name", "Bob", "ann", "Cy"]);
+    }
+
+    #[test]
+    fn formulas_below_removed_rows_follow_them() {
+        let mut document = Document::from_bytes(b"a,1\na,2\nb,3\nc,4\n=A4,=SUM(B1:B4)\n").unwrap();
+        let key = DuplicateKey { columns: vec![0], trim: false, ignore_case: false };
+        let groups = key.groups(&document.sheet, false);
+        let edits = remove_duplicates(&document.sheet, &groups, Keep::Last);
+        document.apply_operation("Remove duplicates", edits);
+        assert_eq!(document.sheet.get(3, 0), "=A3");
+        assert_eq!(document.sheet.get(3, 1), "=SUM(B1:B3)");
+        assert_eq!(document.text(3, 0), "c");
+        assert_eq!(document.text(3, 1), "9");
+    }
+}
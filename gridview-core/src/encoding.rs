@@ -0,0 +1,330 @@
+//! Character encoding detection and conversion.
+//!
+//! Files are decoded to UTF-8 before parsing and encoded back to their original
+//! encoding on save. Detection looks for a byte order mark first, then for the
+//! zero-byte pattern of BOM-less UTF-16, then checks whether the bytes are valid
+//! UTF-8, and finally falls back to a statistical guess for legacy encodings.
+
+use encoding_rs::Encoding;
+
+/// A character set files can be read and written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    /// UTF-8, with or without a byte order mark
+    Utf8,
+    /// UTF-16, little endian
+    Utf16Le,
+    /// UTF-16, big endian
+    Utf16Be,
+    /// True ISO-8859-1, where every byte maps to the code point of the same value.
+    /// encoding_rs treats the label as Windows-1252, which differs in 0x80-0x9F.
+    Latin1,
+    /// Any other encoding encoding_rs knows, such as Windows-1252
+    Legacy(&'static Encoding),
+}
+
+/// Encodings offered in the import dialog and the save encoding menu
+pub const CHARSETS: &[Charset] = &[
+    Charset::Utf8,
+    Charset::Utf16Le,
+    Charset::Utf16Be,
+    Charset::Legacy(encoding_rs::WINDOWS_1252),
+    Charset::Latin1,
+    Charset::Legacy(encoding_rs::ISO_8859_15),
+    Charset::Legacy(encoding_rs::WINDOWS_1250),
+    Charset::Legacy(encoding_rs::MACINTOSH),
+];
+
+impl Charset {
+    /// Name shown in menus
+    pub fn label(&self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Utf16Le => "UTF-16 LE",
+            Charset::Utf16Be => "UTF-16 BE",
+            Charset::Latin1 => "ISO-8859-1 (Latin-1)",
+            Charset::Legacy(encoding) if *encoding == encoding_rs::MACINTOSH => "Mac Roman",
+            Charset::Legacy(encoding) => encoding.name(),
+        }
+    }
+
+    /// Byte order mark of the charset; empty for those without one
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Charset::Utf8 => b"\xEF\xBB\xBF",
+            Charset::Utf16Le => b"\xFF\xFE",
+            Charset::Utf16Be => b"\xFE\xFF",
+            _ => b"",
+        }
+    }
+
+    /// Whether files in the charset can start with a byte order mark
+    pub fn supports_bom(&self) -> bool {
+        !self.bom().is_empty()
+    }
+}
+
+/// The encoding of a file: its charset and whether it has a byte order mark
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextEncoding {
+    /// Character set of the text
+    pub charset: Charset,
+    /// Whether the file starts with a byte order mark
+    pub bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self {
+            charset: Charset::Utf8,
+            bom: false,
+        }
+    }
+}
+
+impl TextEncoding {
+    /// Guess the encoding of a file from its first few kilobytes
+    pub fn detect(sample: &[u8]) -> Self {
+        for charset in [Charset::Utf8, Charset::Utf16Le, Charset::Utf16Be] {
+            if sample.starts_with(charset.bom()) {
+                return Self { charset, bom: true };
+            }
+        }
+
+        if let Some(charset) = detect_utf16_without_bom(sample) {
+            return Self { charset, bom: false };
+        }
+
+        // A sample cut off in the middle of a multi-byte character is still UTF-8
+        let is_utf8 = match std::str::from_utf8(sample) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        };
+        if is_utf8 {
+            return Self::default();
+        }
+
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(sample, false);
+        let guess = detector.guess(None, true);
+        let charset = if guess == encoding_rs::UTF_8 {
+            Charset::Utf8
+        } else {
+            Charset::Legacy(guess)
+        };
+        Self { charset, bom: false }
+    }
+
+    /// An encoding by its label in the app ("UTF-16 LE", "Latin-1") or any
+    /// name the WHATWG encoding standard knows ("utf-16le", "cp1252"). UTF-16 is
+    /// given a byte order mark, since it is hard to recognise without one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let key = |text: &str| -> String {
+            text.chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+                .to_ascii_lowercase()
+        };
+        let wanted = key(name);
+        let charset = CHARSETS
+            .iter()
+            .copied()
+            .find(|charset| charset.label().split(" (").any(|part| key(part) == wanted))
+            .or_else(|| {
+                let encoding = Encoding::for_label(name.trim().as_bytes())?;
+                Some(if encoding == encoding_rs::UTF_8 {
+                    Charset::Utf8
+                } else if encoding == encoding_rs::UTF_16LE {
+                    Charset::Utf16Le
+                } else if encoding == encoding_rs::UTF_16BE {
+                    Charset::Utf16Be
+                } else {
+                    Charset::Legacy(encoding)
+                })
+            })?;
+        let bom = matches!(charset, Charset::Utf16Le | Charset::Utf16Be);
+        Some(Self { charset, bom })
+    }
+
+    /// Decode to UTF-8, dropping the byte order mark. Malformed sequences become U+FFFD.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let bom = self.charset.bom();
+        let bytes = if !bom.is_empty() && bytes.starts_with(bom) {
+            &bytes[bom.len()..]
+        } else {
+            bytes
+        };
+
+        match self.charset {
+            Charset::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Charset::Utf16Le => encoding_rs::UTF_16LE.decode_without_bom_handling(bytes).0.into_owned(),
+            Charset::Utf16Be => encoding_rs::UTF_16BE.decode_without_bom_handling(bytes).0.into_owned(),
+            Charset::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+            Charset::Legacy(encoding) => encoding.decode_without_bom_handling(bytes).0.into_owned(),
+        }
+    }
+
+    /// Decode a whole file, taking its bytes. UTF-8 that is valid is reused
+    /// in place rather than copied, so a large file isn't held twice.
+    pub fn decode_owned(&self, mut bytes: Vec<u8>) -> String {
+        if self.charset != Charset::Utf8 {
+            return self.decode(&bytes);
+        }
+        if bytes.starts_with(Charset::Utf8.bom()) {
+            bytes.drain(..Charset::Utf8.bom().len());
+        }
+        String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+    }
+
+    /// Whether the raw file ends with a line break, without decoding all of it
+    pub fn ends_with_newline(&self, bytes: &[u8]) -> bool {
+        match self.charset {
+            Charset::Utf16Le => bytes.ends_with(b"\n\0") || bytes.ends_with(b"\r\0"),
+            Charset::Utf16Be => bytes.ends_with(b"\0\n") || bytes.ends_with(b"\0\r"),
+            // Every other supported encoding is ASCII-compatible
+            _ => bytes.ends_with(b"\n") || bytes.ends_with(b"\r"),
+        }
+    }
+
+    /// Encode UTF-8 text, writing a byte order mark if requested. Characters the
+    /// target encoding can't represent are replaced with '?'; their count is
+    /// returned alongside the bytes.
+    pub fn encode(&self, text: &str) -> (Vec<u8>, usize) {
+        let mut out = Vec::with_capacity(text.len() + 3);
+        if self.bom {
+            out.extend_from_slice(self.charset.bom());
+        }
+
+        let mut unmappable = 0;
+        match self.charset {
+            Charset::Utf8 => out.extend_from_slice(text.as_bytes()),
+            Charset::Utf16Le => {
+                for unit in text.encode_utf16() {
+                    out.extend_from_slice(&unit.to_le_bytes());
+                }
+            }
+            Charset::Utf16Be => {
+                for unit in text.encode_utf16() {
+                    out.extend_from_slice(&unit.to_be_bytes());
+                }
+            }
+            Charset::Latin1 => {
+                for c in text.chars() {
+                    if (c as u32) <= 0xFF {
+                        out.push(c as u32 as u8);
+                    } else {
+                        out.push(b'?');
+                        unmappable += 1;
+                    }
+                }
+            }
+            Charset::Legacy(encoding) => {
+                let mut encoder = encoding.new_encoder();
+                let mut buffer = vec![0u8; 8 * 1024];
+                let mut remaining = text;
+                loop {
+                    let (result, read, written) =
+                        encoder.encode_from_utf8_without_replacement(remaining, &mut buffer, true);
+                    out.extend_from_slice(&buffer[..written]);
+                    remaining = &remaining[read..];
+                    match result {
+                        encoding_rs::EncoderResult::InputEmpty => break,
+                        encoding_rs::EncoderResult::OutputFull => {}
+                        encoding_rs::EncoderResult::Unmappable(_) => {
+                            out.push(b'?');
+                            unmappable += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        (out, unmappable)
+    }
+}
+
+/// BOM-less UTF-16 text that is mostly ASCII has a zero in every other byte
+fn detect_utf16_without_bom(sample: &[u8]) -> Option<Charset> {
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+
+    let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+
+    if odd_zeros * 10 > pairs * 3 && even_zeros * 10 < pairs {
+        Some(Charset::Utf16Le)
+    } else if even_zeros * 10 > pairs * 3 && odd_zeros * 10 < pairs {
+        Some(Charset::Utf16Be)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_byte_order_marks_and_utf16() {
+        let detected = TextEncoding::detect(b"\xEF\xBB\xBFa,b");
+        assert_eq!((detected.charset, detected.bom), (Charset::Utf8, true));
+        let detected = TextEncoding::detect(b"a\0,\0b\0\n\0");
+        assert_eq!((detected.charset, detected.bom), (Charset::Utf16Le, false));
+        assert_eq!(TextEncoding::detect(b"plain").charset, Charset::Utf8);
+    }
+
+    #[test]
+    fn tells_utf8_from_legacy_encodings() {
+        // Cut off after the first byte of "é"
+        assert_eq!(TextEncoding::detect(b"name,caf\xC3\xA9\nx,\xC3").charset, Charset::Utf8);
+        let detected = TextEncoding::detect(b"name,city\nRen\xE9e,Z\xFCrich\nFran\xE7ois,Orl\xE9ans\n");
+        assert_eq!(detected.charset, Charset::Legacy(encoding_rs::WINDOWS_1252));
+        assert_eq!(TextEncoding { charset: detected.charset, bom: false }.decode(b"Z\xFCrich"), "Zürich");
+    }
+
+    #[test]
+    fn decodes_owned_bytes_like_borrowed_ones() {
+        let utf8 = TextEncoding::default();
+        let text = utf8.decode_owned(b"\xEF\xBB\xBFcaf\xC3\xA9".to_vec());
+        assert_eq!(text, "café");
+        assert_eq!(utf8.decode_owned(b"a\xFFb".to_vec()), utf8.decode(b"a\xFFb"));
+        let latin1 = TextEncoding { charset: Charset::Latin1, bom: false };
+        assert_eq!(latin1.decode_owned(b"\xE9".to_vec()), "é");
+    }
+
+    #[test]
+    fn latin1_maps_every_byte_to_itself() {
+        let encoding = TextEncoding { charset: Charset::Latin1, bom: false };
+        assert_eq!(encoding.decode(b"\x80\xE9"), "\u{80}é");
+        assert_eq!(encoding.encode("\u{80}é€"), (b"\x80\xE9?".to_vec(), 1));
+    }
+
+    #[test]
+    fn round_trips_utf16_with_bom() {
+        let encoding = TextEncoding { charset: Charset::Utf16Be, bom: true };
+        let (bytes, unmappable) = encoding.encode("é,1\n");
+        assert_eq!(unmappable, 0);
+        assert!(bytes.starts_with(&[0xFE, 0xFF]));
+        assert_eq!(encoding.decode(&bytes), "é,1\n");
+        assert!(encoding.ends_with_newline(&bytes));
+    }
+
+    #[test]
+    fn counts_unmappable_characters() {
+        let encoding = TextEncoding::from_name("windows-1252").unwrap();
+        let (bytes, unmappable) = encoding.encode("€ ✓");
+        assert_eq!(bytes, b"\x80 ?");
+        assert_eq!(unmappable, 1);
+        assert_eq!(encoding.decode(b"\x80"), "€");
+    }
+
+    #[test]
+    fn finds_encodings_by_name() {
+        assert_eq!(TextEncoding::from_name("UTF-8").unwrap().charset, Charset::Utf8);
+        let utf16 = TextEncoding::from_name("utf-16le").unwrap();
+        assert_eq!((utf16.charset, utf16.bom), (Charset::Utf16Le, true));
+        assert!(TextEncoding::from_name("no such encoding").is_none());
+    }
+}
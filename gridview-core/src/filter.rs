@@ -0,0 +1,114 @@
+//! Column filters.
+//!
+//! A filter decides from a cell's text whether its row is shown. Cells are
+//! matched on the text they show, so formulas are filtered by their results.
+
+use std::collections::HashSet;
+
+use crate::calc::Calculator;
+use crate::sheet::Sheet;
+use crate::types::ColumnType;
+
+/// A condition on the cells of a column; rows whose cell fails it are hidden
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnFilter {
+    /// Rows whose text is one of these are hidden. Storing what is unticked
+    /// rather than what is ticked keeps values that weren't listed visible.
+    Hidden(HashSet<String>),
+    /// Only rows whose text is one of these are shown
+    OneOf(HashSet<String>),
+    /// Case-insensitive; the needle is stored lowercased
+    Contains(String),
+    /// Case-insensitive; the prefix is stored lowercased
+    StartsWith(String),
+    /// Values within the bounds, inclusive, read as the column's type so
+    /// amounts and dates can be ranged; cells without a value never match
+    Between {
+        /// Smallest value shown
+        min: Option<f64>,
+        /// Largest value shown
+        max: Option<f64>,
+        /// Type the cells and bounds are read as
+        column_type: ColumnType,
+    },
+    /// Only empty cells, ignoring spaces
+    Empty,
+    /// Only cells with text other than spaces
+    NonEmpty,
+}
+
+impl ColumnFilter {
+    /// Rows whose text contains `needle`, ignoring case
+    pub fn contains(needle: &str) -> Self {
+        ColumnFilter::Contains(needle.to_lowercase())
+    }
+
+    /// Rows whose text starts with `prefix`, ignoring case
+    pub fn starts_with(prefix: &str) -> Self {
+        ColumnFilter::StartsWith(prefix.to_lowercase())
+    }
+
+    /// Whether a cell's text passes the filter, so its row stays visible
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            ColumnFilter::Hidden(values) => !values.contains(text),
+            ColumnFilter::OneOf(values) => values.contains(text),
+            ColumnFilter::Contains(needle) => text.to_lowercase().contains(needle.as_str()),
+            ColumnFilter::StartsWith(prefix) => text.to_lowercase().starts_with(prefix.as_str()),
+            ColumnFilter::Between { min, max, column_type } => match column_type.value(text) {
+                Some(n) => min.is_none_or(|min| n >= min) && max.is_none_or(|max| n <= max),
+                None => false,
+            },
+            ColumnFilter::Empty => text.trim().is_empty(),
+            ColumnFilter::NonEmpty => !text.trim().is_empty(),
+        }
+    }
+}
+
+/// Whether a row passes every (column, filter) pair, matching the text cells show
+pub fn row_matches<'a>(
+    filters: impl IntoIterator<Item = (usize, &'a ColumnFilter)>,
+    sheet: &Sheet,
+    calc: &mut Calculator,
+    row: usize,
+) -> bool {
+    filters
+        .into_iter()
+        .all(|(col, filter)| filter.matches(&calc.text(sheet, row, col)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_cell_text() {
+        assert!(ColumnFilter::contains("WID").matches("Blue widget"));
+        assert!(!ColumnFilter::starts_with("wid").matches("Blue widget"));
+        assert!(ColumnFilter::OneOf(HashSet::from(["a".to_string()])).matches("a"));
+        assert!(!ColumnFilter::Hidden(HashSet::from(["a".to_string()])).matches("a"));
+        assert!(ColumnFilter::Empty.matches("  "));
+        assert!(!ColumnFilter::NonEmpty.matches(""));
+    }
+
+    #[test]
+    fn ranges_read_the_column_type() {
+        let filter = ColumnFilter::Between { min: Some(10.0), max: None, column_type: ColumnType::Currency };
+        assert!(filter.matches("$1,200"));
+        assert!(!filter.matches("$5"));
+        assert!(!filter.matches("free"));
+    }
+
+    #[test]
+    fn rows_match_every_filter_on_shown_text() {
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["apple", "=2*3"]);
+        sheet.push_row(["apricot", "5"]);
+        let mut calc = Calculator::default();
+        calc.rebuild(&sheet);
+        let filters = [(0, ColumnFilter::starts_with("ap")), (1, ColumnFilter::OneOf(HashSet::from(["6".to_string()])))];
+        let pairs = || filters.iter().map(|(col, filter)| (*col, filter));
+        assert!(row_matches(pairs(), &sheet, &mut calc, 0));
+        assert!(!row_matches(pairs(), &sheet, &mut calc, 1));
+    }
+}
@@ -0,0 +1,1360 @@
+//! Spreadsheet formulas.
+//!
+//! A cell whose text starts with `=` holds a formula such as `=SUM(B2:B40)*2`.
+//! The text is parsed into an `Expr` tree once and evaluated against whatever
+//! implements `CellSource`; `calc` keeps the parsed formulas, tracks which cells
+//! they read and caches their results. Formulas are stored as plain text in the
+//! sheet, so the only place they are rewritten is `adjust_references`, which
+//! moves references when rows or columns are inserted or deleted.
+
+use std::fmt;
+
+use crate::types::plain_number;
+
+/// (row, column), both zero-based
+pub type CellRef = (usize, usize);
+
+/// Whether a cell's text is a formula: `=` followed by something
+pub fn is_formula(text: &str) -> bool {
+    text.len() > 1 && text.starts_with('=')
+}
+
+/// The errors a formula can evaluate to, shown as in Excel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormulaError {
+    /// Division by zero
+    Div0,
+    /// A reference to a cell that was deleted or is out of range
+    Ref,
+    /// An unknown function or name
+    Name,
+    /// An argument of the wrong type, such as text where a number is needed
+    Value,
+    /// A lookup that found nothing
+    NotAvailable,
+    /// A number out of range, such as a date past year 9999
+    Num,
+    /// A formula that refers back to itself
+    Cycle,
+    /// Text after `=` that doesn't parse as a formula
+    Syntax,
+}
+
+impl FormulaError {
+    const ALL: [FormulaError; 8] = [
+        FormulaError::Div0,
+        FormulaError::Ref,
+        FormulaError::Name,
+        FormulaError::Value,
+        FormulaError::NotAvailable,
+        FormulaError::Num,
+        FormulaError::Cycle,
+        FormulaError::Syntax,
+    ];
+
+    /// How the error is shown in a cell
+    pub fn label(&self) -> &'static str {
+        match self {
+            FormulaError::Div0 => "#DIV/0!",
+            FormulaError::Ref => "#REF!",
+            FormulaError::Name => "#NAME?",
+            FormulaError::Value => "#VALUE!",
+            FormulaError::NotAvailable => "#N/A",
+            FormulaError::Num => "#NUM!",
+            FormulaError::Cycle => "#CYCLE!",
+            FormulaError::Syntax => "#ERROR!",
+        }
+    }
+}
+
+/// What a formula evaluates to, or a cell holds
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// An empty cell
+    Empty,
+    /// A number; dates are day numbers
+    Number(f64),
+    /// Text that isn't a number
+    Text(String),
+    /// TRUE or FALSE
+    Bool(bool),
+    /// An error, which spreads to formulas using the value
+    Error(FormulaError),
+}
+
+impl Value {
+    /// How a plain (non-formula) cell is seen by formulas: numbers and
+    /// TRUE/FALSE are typed, anything else is text
+    pub fn from_cell(text: &str) -> Value {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            Value::Empty
+        } else if let Some(n) = plain_number(trimmed) {
+            Value::Number(n)
+        } else if trimmed.eq_ignore_ascii_case("TRUE") {
+            Value::Bool(true)
+        } else if trimmed.eq_ignore_ascii_case("FALSE") {
+            Value::Bool(false)
+        } else {
+            Value::Text(text.to_string())
+        }
+    }
+
+    fn as_number(&self) -> Result<f64, FormulaError> {
+        match self {
+            Value::Empty => Ok(0.0),
+            Value::Number(n) => Ok(*n),
+            Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+            Value::Text(s) => plain_number(s.trim()).ok_or(FormulaError::Value),
+            Value::Error(e) => Err(*e),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, FormulaError> {
+        match self {
+            Value::Empty => Ok(false),
+            Value::Number(n) => Ok(*n != 0.0),
+            Value::Bool(b) => Ok(*b),
+            Value::Text(s) if s.eq_ignore_ascii_case("TRUE") => Ok(true),
+            Value::Text(s) if s.eq_ignore_ascii_case("FALSE") => Ok(false),
+            Value::Text(_) => Err(FormulaError::Value),
+            Value::Error(e) => Err(*e),
+        }
+    }
+
+    fn as_text(&self) -> Result<String, FormulaError> {
+        match self {
+            Value::Error(e) => Err(*e),
+            other => Ok(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Empty => Ok(()),
+            Value::Number(n) => f.write_str(&format_number(*n)),
+            Value::Text(s) => f.write_str(s),
+            Value::Bool(b) => f.write_str(if *b { "TRUE" } else { "FALSE" }),
+            Value::Error(e) => f.write_str(e.label()),
+        }
+    }
+}
+
+/// Shortest readable form: integers without a decimal point, fractions rounded to
+/// 15 significant digits so 0.1 + 0.2 shows as 0.3
+pub fn format_number(n: f64) -> String {
+    if n == 0.0 {
+        return "0".to_string();
+    }
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        return format!("{}", n as i64);
+    }
+    let magnitude = n.abs().log10().floor() as i32;
+    if !(-9..15).contains(&magnitude) {
+        return format!("{:e}", n);
+    }
+    let decimals = (14 - magnitude).clamp(0, 20) as usize;
+    let text = format!("{:.*}", decimals, n);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    text.to_string()
+}
+
+/// Rectangular block of cells, inclusive at both ends. Whole-column ranges such
+/// as `A:B` end at row usize::MAX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    /// Top-left cell
+    pub start: CellRef,
+    /// Bottom-right cell
+    pub end: CellRef,
+}
+
+impl Range {
+    fn new(a: CellRef, b: CellRef) -> Self {
+        Self {
+            start: (a.0.min(b.0), a.1.min(b.1)),
+            end: (a.0.max(b.0), a.1.max(b.1)),
+        }
+    }
+
+    /// The range of a single cell
+    pub fn cell(cell: CellRef) -> Self {
+        Self { start: cell, end: cell }
+    }
+
+    /// Whether a cell is inside the range
+    pub fn contains(&self, (row, col): CellRef) -> bool {
+        (self.start.0..=self.end.0).contains(&row) && (self.start.1..=self.end.1).contains(&col)
+    }
+
+    /// Whether the range is a single cell
+    pub fn is_cell(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Number of rows once clipped to a sheet of `rows` rows
+    fn height(&self, rows: usize) -> usize {
+        (self.end.0.min(rows.saturating_sub(1)) + 1).saturating_sub(self.start.0)
+    }
+
+    fn width(&self) -> usize {
+        self.end.1 - self.start.1 + 1
+    }
+
+    /// Number of cells once clipped to a sheet of `rows` rows
+    pub fn cell_count(&self, rows: usize) -> usize {
+        self.height(rows) * self.width()
+    }
+
+    /// Cells in row-major order, clipped to a sheet of `rows` rows
+    pub fn cells(&self, rows: usize) -> impl Iterator<Item = CellRef> + use<> {
+        let (start, width) = (self.start, self.width());
+        let height = self.height(rows);
+        (0..height).flat_map(move |r| (0..width).map(move |c| (start.0 + r, start.1 + c)))
+    }
+}
+
+/// Where formulas get the values of the cells they refer to
+pub trait CellSource {
+    /// Value of a cell, as a formula sees it
+    fn value(&self, cell: CellRef) -> Value;
+    /// Number of rows, to clip whole-column ranges to
+    fn num_rows(&self) -> usize;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(Value),
+    Cell(CellRef),
+    Range(Range),
+    Negate(Box<Expr>),
+    Percent(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+/// A parsed formula. Text that doesn't parse still makes a formula, one that
+/// evaluates to #ERROR! (or #NAME? for unknown functions).
+#[derive(Debug, Clone)]
+pub struct Formula {
+    expr: Result<Expr, FormulaError>,
+}
+
+impl Formula {
+    /// Parse the text of a formula cell, with or without the leading `=`
+    pub fn parse(text: &str) -> Self {
+        let source = text.strip_prefix('=').unwrap_or(text);
+        let expr = tokenize(source).and_then(|tokens| Parser { tokens, pos: 0 }.parse());
+        Self { expr }
+    }
+
+    /// Every cell or range the formula reads
+    pub fn references(&self) -> Vec<Range> {
+        fn collect(expr: &Expr, out: &mut Vec<Range>) {
+            match expr {
+                Expr::Literal(_) => {}
+                Expr::Cell(cell) => out.push(Range::cell(*cell)),
+                Expr::Range(range) => out.push(*range),
+                Expr::Negate(inner) | Expr::Percent(inner) => collect(inner, out),
+                Expr::Binary(_, a, b) => {
+                    collect(a, out);
+                    collect(b, out);
+                }
+                Expr::Call(_, args) => args.iter().for_each(|arg| collect(arg, out)),
+            }
+        }
+        let mut out = Vec::new();
+        if let Ok(expr) = &self.expr {
+            collect(expr, &mut out);
+        }
+        out
+    }
+
+    /// Value of the formula, reading cells from `source`
+    pub fn evaluate(&self, source: &dyn CellSource) -> Value {
+        match &self.expr {
+            Ok(expr) => Evaluator { source }.scalar(expr),
+            Err(e) => Value::Error(*e),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tokenizer
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    /// Function names, cell references, TRUE/FALSE and bare column letters
+    Name(String),
+    Error(FormulaError),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    Colon,
+}
+
+/// Tokens with their byte span in the source, which `adjust_references` needs
+fn tokenize_spans(source: &str) -> Result<Vec<(Token, std::ops::Range<usize>)>, FormulaError> {
+    const OPERATORS: &[&str] = &["<>", "<=", ">=", "+", "-", "*", "/", "^", "&", "=", "<", ">", "%"];
+
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let token = if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        } else if c.is_ascii_digit() || (c == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)) {
+            while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                i += 1;
+            }
+            // Exponent, only if digits follow
+            if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+                let mut j = i + 1;
+                if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-') {
+                    j += 1;
+                }
+                if j < bytes.len() && bytes[j].is_ascii_digit() {
+                    i = j;
+                    while i < bytes.len() && bytes[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            Token::Number(source[start..i].parse().map_err(|_| FormulaError::Syntax)?)
+        } else if c == b'"' {
+            // Strings use "" for a literal quote
+            let mut text = String::new();
+            i += 1;
+            loop {
+                let end = source[i..].find('"').ok_or(FormulaError::Syntax)? + i;
+                text.push_str(&source[i..end]);
+                i = end + 1;
+                if bytes.get(i) == Some(&b'"') {
+                    text.push('"');
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            Token::Text(text)
+        } else if c == b'#' {
+            let error = FormulaError::ALL
+                .into_iter()
+                .find(|e| source[i..].to_ascii_uppercase().starts_with(e.label()))
+                .ok_or(FormulaError::Syntax)?;
+            i += error.label().len();
+            Token::Error(error)
+        } else if c.is_ascii_alphabetic() || c == b'$' || c == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'$' | b'_' | b'.')) {
+                i += 1;
+            }
+            Token::Name(source[start..i].to_string())
+        } else if c == b'(' {
+            i += 1;
+            Token::LParen
+        } else if c == b')' {
+            i += 1;
+            Token::RParen
+        } else if c == b',' || c == b';' {
+            i += 1;
+            Token::Comma
+        } else if c == b':' {
+            i += 1;
+            Token::Colon
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| source[i..].starts_with(*op))
+                .ok_or(FormulaError::Syntax)?;
+            i += op.len();
+            Token::Op(op)
+        };
+        tokens.push((token, start..i));
+    }
+    Ok(tokens)
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, FormulaError> {
+    Ok(tokenize_spans(source)?.into_iter().map(|(token, _)| token).collect())
+}
+
+/// A cell reference as written, remembering which parts are absolute ($A$1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RefText {
+    row: usize,
+    col: usize,
+    abs_row: bool,
+    abs_col: bool,
+}
+
+impl RefText {
+    fn parse(name: &str) -> Option<Self> {
+        let (abs_col, rest) = strip_dollar(name);
+        let letters = rest.bytes().take_while(u8::is_ascii_alphabetic).count();
+        let (col, rest) = rest.split_at(letters);
+        let (abs_row, digits) = strip_dollar(rest);
+        if col.is_empty() || col.len() > 3 || digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let row: usize = digits.parse().ok()?;
+        Some(Self {
+            row: row.checked_sub(1)?,
+            col: column_index(col)?,
+            abs_row,
+            abs_col,
+        })
+    }
+
+    fn cell(&self) -> CellRef {
+        (self.row, self.col)
+    }
+}
+
+impl fmt::Display for RefText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dollar = |abs: bool| if abs { "$" } else { "" };
+        write!(
+            f,
+            "{}{}{}{}",
+            dollar(self.abs_col),
+            column_letters(self.col),
+            dollar(self.abs_row),
+            self.row + 1
+        )
+    }
+}
+
+fn strip_dollar(text: &str) -> (bool, &str) {
+    match text.strip_prefix('$') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    }
+}
+
+/// Column letters of a bare column reference such as the `B` in `B:B`
+fn column_only(name: &str) -> Option<(usize, bool)> {
+    let (abs, letters) = strip_dollar(name);
+    if letters.is_empty() || letters.len() > 3 || !letters.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some((column_index(letters)?, abs))
+}
+
+/// "A" -> 0, "Z" -> 25, "AA" -> 26
+pub fn column_index(letters: &str) -> Option<usize> {
+    letters.bytes().try_fold(0usize, |acc, b| {
+        let digit = (b.to_ascii_uppercase() as usize).checked_sub(b'A' as usize)? + 1;
+        Some(acc * 26 + digit)
+    })?
+    .checked_sub(1)
+}
+
+/// 0 -> "A", 25 -> "Z", 26 -> "AA"
+pub fn column_letters(col: usize) -> String {
+    let mut result = String::new();
+    let mut num = col + 1;
+    while num > 0 {
+        num -= 1;
+        result.insert(0, (b'A' + (num % 26) as u8) as char);
+        num /= 26;
+    }
+    result
+}
+
+// ---------------------------------------------------------------------------
+// Parser, with Excel's precedence from loosest to tightest: comparison, `&`,
+// `+ -`, `* /`, `^`, unary minus, `%`
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn parse(mut self) -> Result<Expr, FormulaError> {
+        let expr = self.comparison()?;
+        if self.pos < self.tokens.len() {
+            return Err(FormulaError::Syntax);
+        }
+        Ok(expr)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), FormulaError> {
+        if self.next() == Some(token) { Ok(()) } else { Err(FormulaError::Syntax) }
+    }
+
+    /// Consume one of the given operators if it is next
+    fn operator(&mut self, ops: &[(&str, BinaryOp)]) -> Option<BinaryOp> {
+        let Some(Token::Op(op)) = self.peek() else {
+            return None;
+        };
+        let found = ops.iter().find(|(text, _)| text == op).map(|(_, op)| *op);
+        if found.is_some() {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn binary_level(
+        &mut self,
+        ops: &[(&str, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr, FormulaError>,
+    ) -> Result<Expr, FormulaError> {
+        let mut left = operand(self)?;
+        while let Some(op) = self.operator(ops) {
+            let right = operand(self)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, FormulaError> {
+        use BinaryOp::*;
+        self.binary_level(
+            &[("=", Eq), ("<>", Ne), ("<", Lt), (">", Gt), ("<=", Le), (">=", Ge)],
+            Self::concat,
+        )
+    }
+
+    fn concat(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_level(&[("&", BinaryOp::Concat)], Self::additive)
+    }
+
+    fn additive(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_level(&[("+", BinaryOp::Add), ("-", BinaryOp::Sub)], Self::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_level(&[("*", BinaryOp::Mul), ("/", BinaryOp::Div)], Self::power)
+    }
+
+    fn power(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_level(&[("^", BinaryOp::Pow)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        match self.peek() {
+            Some(Token::Op("-")) => {
+                self.pos += 1;
+                Ok(Expr::Negate(Box::new(self.unary()?)))
+            }
+            Some(Token::Op("+")) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => {
+                let mut expr = self.primary()?;
+                while self.peek() == Some(&Token::Op("%")) {
+                    self.pos += 1;
+                    expr = Expr::Percent(Box::new(expr));
+                }
+                Ok(expr)
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, FormulaError> {
+        match self.next().ok_or(FormulaError::Syntax)? {
+            Token::Number(n) => Ok(Expr::Literal(Value::Number(n))),
+            Token::Text(s) => Ok(Expr::Literal(Value::Text(s))),
+            Token::Error(e) => Ok(Expr::Literal(Value::Error(e))),
+            Token::LParen => {
+                let expr = self.comparison()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Token::Name(name) => self.name(name),
+            _ => Err(FormulaError::Syntax),
+        }
+    }
+
+    fn name(&mut self, name: String) -> Result<Expr, FormulaError> {
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let mut args = Vec::new();
+            if self.peek() != Some(&Token::RParen) {
+                loop {
+                    // An argument left out, as in IF(A1,,1), counts as empty
+                    if matches!(self.peek(), Some(Token::Comma | Token::RParen)) {
+                        args.push(Expr::Literal(Value::Empty));
+                    } else {
+                        args.push(self.comparison()?);
+                    }
+                    if self.peek() == Some(&Token::Comma) {
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            self.expect(Token::RParen)?;
+            return Ok(Expr::Call(name.to_ascii_uppercase(), args));
+        }
+
+        let range_end = |parser: &mut Self| -> Option<String> {
+            if parser.peek() != Some(&Token::Colon) {
+                return None;
+            }
+            match parser.tokens.get(parser.pos + 1) {
+                Some(Token::Name(end)) => {
+                    let end = end.clone();
+                    parser.pos += 2;
+                    Some(end)
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(start) = RefText::parse(&name) {
+            return match range_end(self) {
+                Some(end) => {
+                    let end = RefText::parse(&end).ok_or(FormulaError::Syntax)?;
+                    Ok(Expr::Range(Range::new(start.cell(), end.cell())))
+                }
+                None => Ok(Expr::Cell(start.cell())),
+            };
+        }
+        if let Some((start_col, _)) = column_only(&name)
+            && let Some(end) = range_end(self)
+        {
+            let (end_col, _) = column_only(&end).ok_or(FormulaError::Syntax)?;
+            return Ok(Expr::Range(Range::new((0, start_col), (usize::MAX, end_col))));
+        }
+        if name.eq_ignore_ascii_case("TRUE") {
+            return Ok(Expr::Literal(Value::Bool(true)));
+        }
+        if name.eq_ignore_ascii_case("FALSE") {
+            return Ok(Expr::Literal(Value::Bool(false)));
+        }
+        Ok(Expr::Literal(Value::Error(FormulaError::Name)))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Evaluation
+
+/// A function argument: ranges are passed through so functions like SUM can
+/// walk them, everything else is already a single value
+enum Arg {
+    Value(Value),
+    Range(Range),
+}
+
+struct Evaluator<'a> {
+    source: &'a dyn CellSource,
+}
+
+impl Evaluator<'_> {
+    fn scalar(&self, expr: &Expr) -> Value {
+        match expr {
+            Expr::Literal(value) => value.clone(),
+            Expr::Cell(cell) => self.source.value(*cell),
+            // A range where one value is expected only works if it is a single cell
+            Expr::Range(range) if range.is_cell() => self.source.value(range.start),
+            Expr::Range(_) => Value::Error(FormulaError::Value),
+            Expr::Negate(inner) => match self.number(inner) {
+                Ok(n) => Value::Number(-n),
+                Err(e) => Value::Error(e),
+            },
+            Expr::Percent(inner) => match self.number(inner) {
+                Ok(n) => Value::Number(n / 100.0),
+                Err(e) => Value::Error(e),
+            },
+            Expr::Binary(op, left, right) => self.binary(*op, left, right),
+            Expr::Call(name, args) => self.call(name, args),
+        }
+    }
+
+    fn number(&self, expr: &Expr) -> Result<f64, FormulaError> {
+        self.scalar(expr).as_number()
+    }
+
+    fn binary(&self, op: BinaryOp, left: &Expr, right: &Expr) -> Value {
+        let left = self.scalar(left);
+        let right = self.scalar(right);
+        if let Value::Error(e) = left {
+            return Value::Error(e);
+        }
+        if let Value::Error(e) = right {
+            return Value::Error(e);
+        }
+
+        let arithmetic = |f: fn(f64, f64) -> f64| match (left.as_number(), right.as_number()) {
+            (Ok(a), Ok(b)) => number_result(f(a, b)),
+            (Err(e), _) | (_, Err(e)) => Value::Error(e),
+        };
+        let compare = |test: fn(std::cmp::Ordering) -> bool| Value::Bool(test(compare_values(&left, &right)));
+
+        match op {
+            BinaryOp::Add => arithmetic(|a, b| a + b),
+            BinaryOp::Sub => arithmetic(|a, b| a - b),
+            BinaryOp::Mul => arithmetic(|a, b| a * b),
+            BinaryOp::Div => match (left.as_number(), right.as_number()) {
+                (Ok(_), Ok(0.0)) => Value::Error(FormulaError::Div0),
+                (Ok(a), Ok(b)) => number_result(a / b),
+                (Err(e), _) | (_, Err(e)) => Value::Error(e),
+            },
+            BinaryOp::Pow => arithmetic(f64::powf),
+            BinaryOp::Concat => Value::Text(format!("{}{}", left, right)),
+            BinaryOp::Eq => compare(|o| o.is_eq()),
+            BinaryOp::Ne => compare(|o| o.is_ne()),
+            BinaryOp::Lt => compare(|o| o.is_lt()),
+            BinaryOp::Gt => compare(|o| o.is_gt()),
+            BinaryOp::Le => compare(|o| o.is_le()),
+            BinaryOp::Ge => compare(|o| o.is_ge()),
+        }
+    }
+
+    fn arg(&self, expr: &Expr) -> Arg {
+        match expr {
+            Expr::Range(range) => Arg::Range(*range),
+            other => Arg::Value(self.scalar(other)),
+        }
+    }
+
+    /// Values of all arguments with ranges flattened. Empty cells in ranges are
+    /// skipped; `typed_only` also skips text and booleans found in ranges, the
+    /// way SUM and AVERAGE ignore them.
+    fn values(&self, args: &[Expr], typed_only: bool) -> Vec<Value> {
+        let mut out = Vec::new();
+        for arg in args {
+            match self.arg(arg) {
+                Arg::Value(value) => out.push(value),
+                Arg::Range(range) => {
+                    for cell in range.cells(self.source.num_rows()) {
+                        match self.source.value(cell) {
+                            Value::Empty => {}
+                            Value::Text(_) | Value::Bool(_) if typed_only => {}
+                            value => out.push(value),
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn numbers(&self, args: &[Expr]) -> Result<Vec<f64>, FormulaError> {
+        self.values(args, true).iter().map(Value::as_number).collect()
+    }
+
+    fn call(&self, name: &str, args: &[Expr]) -> Value {
+        let arity = |min: usize, max: usize| (min..=max).contains(&args.len());
+        let result = match name {
+            "SUM" => self.numbers(args).map(|n| Value::Number(n.iter().sum())),
+            "AVERAGE" => self.numbers(args).and_then(|n| {
+                if n.is_empty() {
+                    Err(FormulaError::Div0)
+                } else {
+                    Ok(Value::Number(n.iter().sum::<f64>() / n.len() as f64))
+                }
+            }),
+            "MIN" | "MAX" => self.numbers(args).map(|n| {
+                let pick = if name == "MIN" { f64::min } else { f64::max };
+                Value::Number(n.into_iter().reduce(pick).unwrap_or(0.0))
+            }),
+            "COUNT" => Ok(Value::Number(
+                self.values(args, false)
+                    .iter()
+                    .filter(|v| matches!(v, Value::Number(_)))
+                    .count() as f64,
+            )),
+            "COUNTA" => Ok(Value::Number(
+                self.values(args, false)
+                    .iter()
+                    .filter(|v| !matches!(v, Value::Empty))
+                    .count() as f64,
+            )),
+            "IF" if arity(2, 3) => self.scalar(&args[0]).as_bool().map(|condition| {
+                match (condition, args.get(2)) {
+                    (true, _) => self.scalar(&args[1]),
+                    (false, Some(otherwise)) => self.scalar(otherwise),
+                    (false, None) => Value::Bool(false),
+                }
+            }),
+            "IFERROR" if arity(2, 2) => Ok(match self.scalar(&args[0]) {
+                Value::Error(_) => self.scalar(&args[1]),
+                value => value,
+            }),
+            "AND" | "OR" => self
+                .values(args, false)
+                .iter()
+                .filter(|v| !matches!(v, Value::Text(_)))
+                .map(Value::as_bool)
+                .collect::<Result<Vec<_>, _>>()
+                .map(|b| {
+                    Value::Bool(if name == "AND" { b.iter().all(|&b| b) } else { b.iter().any(|&b| b) })
+                }),
+            "NOT" if arity(1, 1) => self.scalar(&args[0]).as_bool().map(|b| Value::Bool(!b)),
+            "CONCAT" | "CONCATENATE" => self
+                .values(args, false)
+                .iter()
+                .map(Value::as_text)
+                .collect::<Result<String, _>>()
+                .map(Value::Text),
+            "LEN" if arity(1, 1) => self.scalar(&args[0]).as_text().map(|s| Value::Number(s.chars().count() as f64)),
+            "UPPER" if arity(1, 1) => self.scalar(&args[0]).as_text().map(|s| Value::Text(s.to_uppercase())),
+            "LOWER" if arity(1, 1) => self.scalar(&args[0]).as_text().map(|s| Value::Text(s.to_lowercase())),
+            "TRIM" if arity(1, 1) => self
+                .scalar(&args[0])
+                .as_text()
+                .map(|s| Value::Text(s.split_whitespace().collect::<Vec<_>>().join(" "))),
+            "ABS" if arity(1, 1) => self.number(&args[0]).map(|n| Value::Number(n.abs())),
+            "ROUND" if arity(1, 2) => self.round(args),
+            "VLOOKUP" if arity(3, 4) => self.vlookup(args),
+            "XLOOKUP" if arity(3, 4) => self.xlookup(args),
+            "TEXT" if arity(2, 2) => self.text(args),
+            "DATE" if arity(3, 3) => self.date(args),
+            // Known function with the wrong number of arguments
+            "IF" | "IFERROR" | "NOT" | "LEN" | "UPPER" | "LOWER" | "TRIM" | "ABS" | "ROUND" | "VLOOKUP" | "XLOOKUP"
+            | "TEXT" | "DATE" => Err(FormulaError::Value),
+            _ => Err(FormulaError::Name),
+        };
+        result.unwrap_or_else(Value::Error)
+    }
+
+    fn round(&self, args: &[Expr]) -> Result<Value, FormulaError> {
+        let n = self.number(&args[0])?;
+        let digits = match args.get(1) {
+            Some(arg) => self.number(arg)?.trunc() as i32,
+            None => 0,
+        };
+        let scale = 10f64.powi(digits);
+        Ok(number_result((n * scale).round() / scale))
+    }
+
+    /// VLOOKUP(value, table, column, [approximate = TRUE]). An approximate
+    /// lookup expects the first column sorted ascending and takes the last row
+    /// not greater than the value.
+    fn vlookup(&self, args: &[Expr]) -> Result<Value, FormulaError> {
+        let needle = self.scalar(&args[0]);
+        if let Value::Error(e) = needle {
+            return Err(e);
+        }
+        let Arg::Range(table) = self.arg(&args[1]) else {
+            return Err(FormulaError::Value);
+        };
+        let column = self.number(&args[2])?.trunc();
+        if column < 1.0 {
+            return Err(FormulaError::Value);
+        }
+        let column = column as usize - 1;
+        if column >= table.width() {
+            return Err(FormulaError::Ref);
+        }
+        let approximate = match args.get(3) {
+            Some(arg) => self.scalar(arg).as_bool()?,
+            None => true,
+        };
+
+        let mut found = None;
+        for row in table.start.0..table.start.0 + table.height(self.source.num_rows()) {
+            let key = self.source.value((row, table.start.1));
+            let order = compare_values(&key, &needle);
+            if order.is_eq() {
+                found = Some(row);
+                break;
+            }
+            if approximate {
+                if order.is_gt() {
+                    break;
+                }
+                found = Some(row);
+            }
+        }
+        let row = found.ok_or(FormulaError::NotAvailable)?;
+        Ok(self.source.value((row, table.start.1 + column)))
+    }
+
+    /// XLOOKUP(value, lookup range, return range, [if not found]). Exact match
+    /// only; the ranges are a single column or row of the same length.
+    fn xlookup(&self, args: &[Expr]) -> Result<Value, FormulaError> {
+        let needle = self.scalar(&args[0]);
+        if let Value::Error(e) = needle {
+            return Err(e);
+        }
+        let (Arg::Range(lookup), Arg::Range(results)) = (self.arg(&args[1]), self.arg(&args[2])) else {
+            return Err(FormulaError::Value);
+        };
+        let rows = self.source.num_rows();
+        let vertical = lookup.width() == 1;
+        if !vertical && lookup.height(rows) != 1 {
+            return Err(FormulaError::Value);
+        }
+
+        let position = lookup
+            .cells(rows)
+            .position(|cell| compare_values(&self.source.value(cell), &needle).is_eq());
+        match position {
+            Some(offset) => {
+                let cell = if vertical {
+                    (results.start.0 + offset, results.start.1)
+                } else {
+                    (results.start.0, results.start.1 + offset)
+                };
+                if results.contains(cell) {
+                    Ok(self.source.value(cell))
+                } else {
+                    Err(FormulaError::Value)
+                }
+            }
+            None => match args.get(3) {
+                Some(fallback) => Ok(self.scalar(fallback)),
+                None => Err(FormulaError::NotAvailable),
+            },
+        }
+    }
+
+    fn text(&self, args: &[Expr]) -> Result<Value, FormulaError> {
+        let value = self.scalar(&args[0]);
+        let format = self.scalar(&args[1]).as_text()?;
+        match value.as_number() {
+            Ok(n) => format_with_pattern(n, &format).map(Value::Text),
+            // TEXT of non-numeric text returns it unchanged
+            Err(FormulaError::Value) => value.as_text().map(Value::Text),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn date(&self, args: &[Expr]) -> Result<Value, FormulaError> {
+        let year = self.number(&args[0])?.trunc();
+        let month = self.number(&args[1])?.trunc();
+        let day = self.number(&args[2])?.trunc();
+        // No supported date lies outside these, and the day count can't overflow inside them
+        if !(0.0..=9999.0).contains(&year) || month.abs() > 1e6 || day.abs() > 1e8 {
+            return Err(FormulaError::Num);
+        }
+        let (year, month, day) = (year as i64, month as i64, day as i64);
+        // Months outside 1-12 roll over into neighbouring years, days into neighbouring months
+        let year = year + (month - 1).div_euclid(12);
+        let month = (month - 1).rem_euclid(12) + 1;
+        let serial = days_from_civil(year, month, 1) + day - 1 - EPOCH_DAYS;
+        if !(0..=MAX_SERIAL).contains(&serial) {
+            return Err(FormulaError::Num);
+        }
+        Ok(Value::Number(serial as f64))
+    }
+}
+
+fn number_result(n: f64) -> Value {
+    if n.is_finite() { Value::Number(n) } else { Value::Error(FormulaError::Num) }
+}
+
+/// Ordering used by comparisons and lookups: numbers before text before
+/// booleans, text compared without regard to case, empty cells as 0 or ""
+pub fn compare_values(a: &Value, b: &Value) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Number(_) | Value::Empty => 0,
+            Value::Text(_) => 1,
+            Value::Bool(_) => 2,
+            Value::Error(_) => 3,
+        }
+    }
+    match (a, b) {
+        (Value::Empty, Value::Text(s)) => "".cmp(s.to_lowercase().as_str()),
+        (Value::Text(s), Value::Empty) => s.to_lowercase().as_str().cmp(""),
+        (Value::Text(x), Value::Text(y)) => x.to_lowercase().cmp(&y.to_lowercase()),
+        (Value::Bool(x), Value::Bool(y)) => x.cmp(y),
+        (Value::Number(_) | Value::Empty, Value::Number(_) | Value::Empty) => {
+            let x = a.as_number().unwrap_or(0.0);
+            let y = b.as_number().unwrap_or(0.0);
+            x.partial_cmp(&y).unwrap_or(Ordering::Equal)
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Dates are day numbers counted the way spreadsheets do, with 1 = 1900-01-01
+// (valid from March 1900 on, since Excel treats 1900 as a leap year)
+
+const EPOCH_DAYS: i64 = -25569; // 1899-12-30 relative to 1970-01-01
+const MAX_SERIAL: i64 = 2958465; // 9999-12-31, the last date spreadsheets support
+
+/// Days since 1970-01-01 (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Day number of a date, as DATE() returns it
+pub fn date_serial(year: i64, month: i64, day: i64) -> i64 {
+    days_from_civil(year, month, day) - EPOCH_DAYS
+}
+
+/// Year, month and day of a day number
+pub fn serial_date(serial: i64) -> (i64, i64, i64) {
+    civil_from_days(serial + EPOCH_DAYS)
+}
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November",
+    "December",
+];
+const WEEKDAYS: [&str; 7] = ["Thursday", "Friday", "Saturday", "Sunday", "Monday", "Tuesday", "Wednesday"];
+
+/// Format a number with a TEXT() pattern. Supports date patterns built from
+/// y, m and d (e.g. "yyyy-mm-dd", "d mmm yyyy") and number patterns with 0/#
+/// digits, a decimal point, a thousands separator and %, e.g. "#,##0.00".
+/// Numbers that aren't a supported date fail a date pattern with #NUM!.
+fn format_with_pattern(n: f64, pattern: &str) -> Result<String, FormulaError> {
+    let lower = pattern.to_ascii_lowercase();
+    if lower.is_empty() || lower == "general" || lower == "@" {
+        return Ok(format_number(n));
+    }
+    if !lower.contains(['0', '#']) && lower.contains(['y', 'm', 'd']) {
+        return format_date(n, &lower);
+    }
+
+    let Some(first) = pattern.find(['0', '#', '.']) else {
+        return Ok(pattern.to_string());
+    };
+    let last = pattern.rfind(['0', '#']).unwrap_or(first).max(first);
+    let (prefix, number, suffix) = (&pattern[..first], &pattern[first..=last], &pattern[last + 1..]);
+
+    let value = if pattern.contains('%') { n * 100.0 } else { n };
+    let decimals = number.split_once('.').map_or(0, |(_, fraction)| {
+        fraction.chars().filter(|c| matches!(c, '0' | '#')).count()
+    });
+    let mut digits = format!("{:.*}", decimals, value.abs());
+    if number.contains(',') {
+        let (int_part, fraction) = digits.split_at(digits.find('.').unwrap_or(digits.len()));
+        let mut grouped = String::new();
+        for (i, c) in int_part.chars().enumerate() {
+            if i > 0 && (int_part.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(c);
+        }
+        digits = grouped + fraction;
+    }
+    let sign = if value < 0.0 && digits.bytes().any(|b| matches!(b, b'1'..=b'9')) { "-" } else { "" };
+    Ok(format!("{}{}{}{}", sign, prefix, digits, suffix))
+}
+
+fn format_date(serial: f64, pattern: &str) -> Result<String, FormulaError> {
+    let serial = serial.floor();
+    if !(0.0..=MAX_SERIAL as f64).contains(&serial) {
+        return Err(FormulaError::Num);
+    }
+    let days = serial as i64 + EPOCH_DAYS;
+    let (year, month, day) = civil_from_days(days);
+    let weekday = WEEKDAYS[days.rem_euclid(7) as usize];
+    let month_name = MONTHS[(month - 1) as usize];
+
+    let mut out = String::new();
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        let run = rest.len() - rest.trim_start_matches(c).len();
+        let text = match (c, run) {
+            ('y', 1..=2) => format!("{:02}", year % 100),
+            ('y', _) => format!("{:04}", year),
+            ('m', 1) => month.to_string(),
+            ('m', 2) => format!("{:02}", month),
+            ('m', 3) => month_name[..3].to_string(),
+            ('m', _) => month_name.to_string(),
+            ('d', 1) => day.to_string(),
+            ('d', 2) => format!("{:02}", day),
+            ('d', 3) => weekday[..3].to_string(),
+            ('d', _) => weekday.to_string(),
+            _ => rest[..run].to_string(),
+        };
+        out.push_str(&text);
+        rest = &rest[run..];
+    }
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// Reference adjustment
+
+/// A structural change that moves cells, as seen by the formulas referring to them
+#[derive(Debug, Clone, Copy)]
+pub enum Shift {
+    /// A row inserted at the index
+    InsertRow(usize),
+    /// The row at the index removed
+    RemoveRow(usize),
+    /// A column inserted at the index
+    InsertColumn(usize),
+    /// The column at the index removed
+    RemoveColumn(usize),
+}
+
+impl Shift {
+    /// Where a cell ends up, or None if it was deleted
+    pub fn move_cell(&self, (row, col): CellRef) -> Option<CellRef> {
+        match *self {
+            Shift::InsertRow(at) => Some((if row >= at { row + 1 } else { row }, col)),
+            Shift::InsertColumn(at) => Some((row, if col >= at { col + 1 } else { col })),
+            Shift::RemoveRow(at) if row == at => None,
+            Shift::RemoveRow(at) => Some((if row > at { row - 1 } else { row }, col)),
+            Shift::RemoveColumn(at) if col == at => None,
+            Shift::RemoveColumn(at) => Some((row, if col > at { col - 1 } else { col })),
+        }
+    }
+
+    /// Move one axis of a range. Inserting inside a range widens it, deleting
+    /// inside shrinks it; None if every row or column of it was deleted.
+    fn move_span(&self, start: usize, end: usize, rows: bool) -> Option<(usize, usize)> {
+        let (insert, at) = match (*self, rows) {
+            (Shift::InsertRow(at), true) | (Shift::InsertColumn(at), false) => (true, at),
+            (Shift::RemoveRow(at), true) | (Shift::RemoveColumn(at), false) => (false, at),
+            _ => return Some((start, end)),
+        };
+        let bump = |i: usize| if i >= at && i != usize::MAX { i + 1 } else { i };
+        if insert {
+            Some((bump(start), bump(end)))
+        } else if start == at && end == at {
+            None
+        } else {
+            let start = if start > at { start - 1 } else { start };
+            let end = if end >= at && end != usize::MAX { end - 1 } else { end };
+            Some((start, end))
+        }
+    }
+}
+
+/// Rewrite the references in a formula after rows or columns moved, replacing
+/// references to deleted cells with #REF!. Returns None if nothing changed.
+pub fn adjust_references(text: &str, shift: Shift) -> Option<String> {
+    let source = text.strip_prefix('=')?;
+    let tokens = tokenize_spans(source).ok()?;
+
+    // (byte span, replacement) for each reference that moved
+    let mut replacements: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let (Token::Name(name), span) = &tokens[i] else {
+            i += 1;
+            continue;
+        };
+        if tokens.get(i + 1).is_some_and(|(t, _)| *t == Token::LParen) {
+            i += 1;
+            continue;
+        }
+        let range_end = match (tokens.get(i + 1), tokens.get(i + 2)) {
+            (Some((Token::Colon, _)), Some((Token::Name(end), end_span))) => Some((end, end_span.end)),
+            _ => None,
+        };
+
+        let replacement = match (RefText::parse(name), range_end) {
+            (Some(start), Some((end, span_end))) => RefText::parse(end).map(|end| {
+                let rows = shift.move_span(start.row.min(end.row), start.row.max(end.row), true);
+                let cols = shift.move_span(start.col.min(end.col), start.col.max(end.col), false);
+                let text = match (rows, cols) {
+                    (Some(rows), Some(cols)) => {
+                        let start = RefText { row: rows.0, col: cols.0, ..start };
+                        let end = RefText { row: rows.1, col: cols.1, ..end };
+                        format!("{}:{}", start, end)
+                    }
+                    _ => FormulaError::Ref.label().to_string(),
+                };
+                (span.start..span_end, text)
+            }),
+            (Some(cell), None) => {
+                let text = match shift.move_cell(cell.cell()) {
+                    Some((row, col)) => RefText { row, col, ..cell }.to_string(),
+                    None => FormulaError::Ref.label().to_string(),
+                };
+                Some((span.clone(), text))
+            }
+            (None, Some((end, span_end))) => match (column_only(name), column_only(end)) {
+                (Some((start, start_abs)), Some((end, end_abs))) => {
+                    let text = match shift.move_span(start.min(end), start.max(end), false) {
+                        Some((start, end)) => {
+                            let dollar = |abs: bool| if abs { "$" } else { "" };
+                            format!("{}{}:{}{}", dollar(start_abs), column_letters(start), dollar(end_abs), column_letters(end))
+                        }
+                        None => FormulaError::Ref.label().to_string(),
+                    };
+                    Some((span.start..span_end, text))
+                }
+                _ => None,
+            },
+            (None, None) => None,
+        };
+
+        i += if range_end.is_some() { 3 } else { 1 };
+        if let Some((span, text)) = replacement
+            && source[span.clone()] != text
+        {
+            replacements.push((span, text));
+        }
+    }
+
+    if replacements.is_empty() {
+        return None;
+    }
+    let mut out = String::from("=");
+    let mut copied = 0;
+    for (span, text) in replacements {
+        out.push_str(&source[copied..span.start]);
+        out.push_str(&text);
+        copied = span.end;
+    }
+    out.push_str(&source[copied..]);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rows of cells
+    struct Cells<'a>(&'a [&'a [&'a str]]);
+
+    impl CellSource for Cells<'_> {
+        fn value(&self, (row, col): CellRef) -> Value {
+            match self.0.get(row).and_then(|cells| cells.get(col)) {
+                Some(text) => Value::from_cell(text),
+                None => Value::Empty,
+            }
+        }
+
+        fn num_rows(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    fn eval(text: &str) -> String {
+        eval_in(text, &[&["1"], &["2"], &["x"], &["4"]])
+    }
+
+    fn eval_in(text: &str, rows: &[&[&str]]) -> String {
+        Formula::parse(text).evaluate(&Cells(rows)).to_string()
+    }
+
+    #[test]
+    fn evaluates_arithmetic_and_functions() {
+        assert_eq!(eval("=1+2*3"), "7");
+        assert_eq!(eval("=(1+2)*3"), "9");
+        assert_eq!(eval("=SUM(A1:A4)"), "7");
+        assert_eq!(eval("=AVERAGE(A1,A2)"), "1.5");
+        assert_eq!(eval("=IF(A2>A1,\"up\",\"down\")"), "up");
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(eval("=1/0"), "#DIV/0!");
+        assert_eq!(eval("=NOSUCH(1)"), "#NAME?");
+        assert_eq!(eval("=1+"), "#ERROR!");
+        assert_eq!(eval("=A3+1"), "#VALUE!");
+    }
+
+    #[test]
+    fn reads_column_letters() {
+        assert_eq!(column_index("A"), Some(0));
+        assert_eq!(column_index("z"), Some(25));
+        assert_eq!(column_index("AA"), Some(26));
+        assert_eq!(column_index(""), None);
+    }
+
+    #[test]
+    fn references_follow_moved_rows_and_columns() {
+        assert_eq!(adjust_references("=A1+B3", Shift::InsertRow(1)).as_deref(), Some("=A1+B4"));
+        assert_eq!(adjust_references("=SUM(A1:A5)", Shift::RemoveRow(2)).as_deref(), Some("=SUM(A1:A4)"));
+        assert_eq!(adjust_references("=B2*2", Shift::RemoveColumn(1)).as_deref(), Some("=#REF!*2"));
+        assert_eq!(adjust_references("=A1", Shift::InsertColumn(3)), None);
+        assert_eq!(Shift::RemoveRow(0).move_cell((0, 0)), None);
+    }
+
+    #[test]
+    fn ranges_and_fixed_references_follow_moves() {
+        assert_eq!(adjust_references("=$B$2+C1", Shift::InsertColumn(0)).as_deref(), Some("=$C$2+D1"));
+        assert_eq!(adjust_references("=SUM(A2:B3)", Shift::InsertRow(2)).as_deref(), Some("=SUM(A2:B4)"));
+        assert_eq!(adjust_references("=SUM(A1:C1)", Shift::RemoveColumn(0)).as_deref(), Some("=SUM(A1:B1)"));
+        assert_eq!(adjust_references("=SUM(B:D)", Shift::InsertColumn(1)).as_deref(), Some("=SUM(C:E)"));
+        assert_eq!(adjust_references("=SUM(A2:B2)", Shift::RemoveRow(1)).as_deref(), Some("=SUM(#REF!)"));
+    }
+
+    #[test]
+    fn builds_and_formats_dates() {
+        assert_eq!(eval("=DATE(2024,3,1)"), "45352");
+        assert_eq!(eval("=TEXT(DATE(2024,3,1),\"yyyy-mm-dd\")"), "2024-03-01");
+        assert_eq!(eval("=TEXT(DATE(2023,14,0),\"dddd d mmmm yy\")"), "Wednesday 31 January 24");
+        assert_eq!(eval("=TEXT(1234.5,\"#,##0.00\")"), "1,234.50");
+        assert_eq!(eval("=TEXT(0.25,\"0%\")"), "25%");
+        assert_eq!(eval("=TEXT(\"abc\",\"yyyy\")"), "abc");
+    }
+
+    #[test]
+    fn dates_out_of_range_are_errors() {
+        assert_eq!(eval("=DATE(1899,1,1)"), "#NUM!");
+        assert_eq!(eval("=DATE(10000,1,1)"), "#NUM!");
+        assert_eq!(eval("=DATE(9999,12,32)"), "#NUM!");
+        assert_eq!(eval("=DATE(1E300,1,1)"), "#NUM!");
+        assert_eq!(eval("=DATE(2024,-1E300,1)"), "#NUM!");
+        assert_eq!(eval("=DATE(2024,1,1E18)"), "#NUM!");
+        assert_eq!(eval("=TEXT(1E300,\"yyyy\")"), "#NUM!");
+        assert_eq!(eval("=TEXT(-1,\"d mmm\")"), "#NUM!");
+    }
+
+    #[test]
+    fn looks_up_values() {
+        let rows: &[&[&str]] = &[&["apple", "1"], &["banana", "2"], &["cherry", "3"]];
+        assert_eq!(eval_in("=VLOOKUP(\"Banana\",A1:B3,2,FALSE)", rows), "2");
+        assert_eq!(eval_in("=VLOOKUP(\"blueberry\",A1:B3,2)", rows), "2");
+        assert_eq!(eval_in("=VLOOKUP(\"aardvark\",A1:B3,2)", rows), "#N/A");
+        assert_eq!(eval_in("=VLOOKUP(\"blueberry\",A1:B3,2,FALSE)", rows), "#N/A");
+        assert_eq!(eval_in("=VLOOKUP(\"apple\",A1:B3,3,FALSE)", rows), "#REF!");
+        assert_eq!(eval_in("=XLOOKUP(\"cherry\",A1:A3,B1:B3)", rows), "3");
+        assert_eq!(eval_in("=XLOOKUP(1,B1:B3,A1:A3)", rows), "apple");
+        assert_eq!(eval_in("=XLOOKUP(\"kiwi\",A1:A3,B1:B3,\"none\")", rows), "none");
+        assert_eq!(eval_in("=XLOOKUP(\"kiwi\",A1:A3,B1:B3)", rows), "#N/A");
+    }
+}
@@ -0,0 +1,362 @@
+//! Undo history.
+//!
+//! Every change to the sheet is an `Edit`. Applying an edit returns its inverse,
+//! so the log only keeps what is needed to reverse each change (the old text of
+//! the cells it touched, the contents of a removed row or column, the row order
+//! before a sort) rather than copies of the whole sheet. The edits making up one
+//! user action are grouped into an `Operation`, which is what the History panel
+//! lists and what undo and redo step over. The log has no length limit, but the
+//! oldest operations are forgotten once the text held by the operations to undo
+//! and to redo passes MAX_HISTORY_BYTES.
+
+use std::collections::VecDeque;
+
+use crate::sheet::Sheet;
+use crate::types::ColumnType;
+
+const MAX_HISTORY_BYTES: usize = 256 * 1024 * 1024;
+
+/// New text for a cell, or, once applied, the text it replaced
+#[derive(Debug, Clone)]
+pub struct CellChange {
+    /// Row of the cell
+    pub row: usize,
+    /// Column of the cell
+    pub col: usize,
+    /// Text of the cell
+    pub text: String,
+}
+
+/// A change to a sheet. Applying one returns the edit that reverses it.
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// Swap the text of each cell with `text`
+    SetCells(Vec<CellChange>),
+    /// Insert a row at the index, filled with the given cells (blank if empty)
+    InsertRow(usize, Vec<String>),
+    /// Remove the row at the index
+    RemoveRow(usize),
+    /// Insert a column at the index, filled from a one-column sheet (blank if
+    /// None), of the given type (text if None)
+    InsertColumn(usize, Option<Sheet>, Option<ColumnType>),
+    /// Remove the column at the index
+    RemoveColumn(usize),
+    /// Rearrange rows so that row `i` becomes the old row `order[i]`
+    ReorderRows(Vec<usize>),
+    /// Grow or shrink the sheet to (rows, cols), then fill in the given cells
+    /// the growing added. The inverse of a shrink holds the cells it cut off.
+    Resize(usize, usize, Vec<CellChange>),
+}
+
+impl Edit {
+    /// Apply the edit to the sheet and return the edit that reverses it
+    pub fn apply(self, sheet: &mut Sheet) -> Edit {
+        match self {
+            Edit::SetCells(mut changes) => {
+                for change in &mut changes {
+                    let old = sheet.get(change.row, change.col).to_string();
+                    sheet.set(change.row, change.col, &change.text);
+                    change.text = old;
+                }
+                // Undo in reverse order so repeated cells end up with their first value
+                changes.reverse();
+                Edit::SetCells(changes)
+            }
+            Edit::InsertRow(at, cells) => {
+                sheet.insert_row(at);
+                sheet.set_row(at, &cells);
+                Edit::RemoveRow(at)
+            }
+            Edit::RemoveRow(at) => {
+                let cells = sheet.row(at).map(str::to_string).collect();
+                sheet.remove_row(at);
+                Edit::InsertRow(at, cells)
+            }
+            Edit::InsertColumn(at, cells, _) => {
+                match cells {
+                    Some(column) => sheet.insert_column_from(at, column),
+                    None => sheet.insert_column(at),
+                }
+                Edit::RemoveColumn(at)
+            }
+            // The sheet doesn't know the column's type; a document fills it in
+            Edit::RemoveColumn(at) => Edit::InsertColumn(at, sheet.take_column(at), None),
+            Edit::ReorderRows(order) => {
+                sheet.reorder_rows(&order);
+                let mut inverse = vec![0; order.len()];
+                for (new_row, &old_row) in order.iter().enumerate() {
+                    inverse[old_row] = new_row;
+                }
+                Edit::ReorderRows(inverse)
+            }
+            Edit::Resize(rows, cols, cells) => {
+                // Cells outside the new size, only keeping the ones with text
+                let previous = (sheet.num_rows(), sheet.num_cols());
+                let cut = (0..previous.0)
+                    .flat_map(|row| {
+                        let first = if row < rows { cols.min(previous.1) } else { 0 };
+                        (first..previous.1).map(move |col| (row, col))
+                    })
+                    .filter(|&(row, col)| !sheet.get(row, col).is_empty())
+                    .map(|(row, col)| CellChange { row, col, text: sheet.get(row, col).to_string() })
+                    .collect();
+                sheet.resize(rows, cols);
+                for change in &cells {
+                    sheet.set(change.row, change.col, &change.text);
+                }
+                Edit::Resize(previous.0, previous.1, cut)
+            }
+        }
+    }
+
+    /// Rough number of bytes held, for capping the history
+    fn size(&self) -> usize {
+        match self {
+            Edit::SetCells(changes) | Edit::Resize(_, _, changes) => changes
+                .iter()
+                .map(|c| std::mem::size_of::<CellChange>() + c.text.len())
+                .sum(),
+            Edit::InsertRow(_, cells) => cells.iter().map(|c| std::mem::size_of::<String>() + c.len()).sum(),
+            Edit::InsertColumn(_, Some(column), _) => column.memory_size(),
+            Edit::ReorderRows(order) => order.len() * std::mem::size_of::<usize>(),
+            _ => 0,
+        }
+    }
+}
+
+/// Edits that remove rows, given in ascending order. Each row is removed on
+/// its own, from the bottom up, so formula references and anything else kept
+/// by row index follow the rows that stay.
+pub fn remove_rows(rows: &[usize]) -> Vec<Edit> {
+    rows.iter().rev().map(|&row| Edit::RemoveRow(row)).collect()
+}
+
+/// A step of the history: one user action, made of edits
+pub struct Operation {
+    /// Name shown in the History panel and the Edit menu
+    pub label: String,
+    /// Edits that take the sheet to the other side of this operation, in the
+    /// order they must be applied
+    pub edits: Vec<Edit>,
+    size: usize,
+}
+
+impl Operation {
+    /// An operation of the given edits, sized for the history limit
+    pub fn new(label: String, edits: Vec<Edit>) -> Self {
+        let size = label.len() + edits.iter().map(Edit::size).sum::<usize>();
+        Self { label, edits, size }
+    }
+}
+
+/// Undo and redo stacks, trimmed from the oldest end to stay under a
+/// memory limit
+pub struct History {
+    /// Applied operations, oldest first, each holding the edits that undo it
+    done: VecDeque<Operation>,
+    /// Undone operations, most recently undone last, each holding the edits that redo it
+    undone: Vec<Operation>,
+    /// Size of the operations on both stacks
+    size: usize,
+    max_size: usize,
+    /// Whether old operations have been dropped to stay under the size limit
+    trimmed: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::with_max_size(MAX_HISTORY_BYTES)
+    }
+}
+
+impl History {
+    fn with_max_size(max_size: usize) -> Self {
+        Self { done: VecDeque::new(), undone: Vec::new(), size: 0, max_size, trimmed: false }
+    }
+
+    /// Record a new operation, given the edits that undo it. Anything that was
+    /// undone can no longer be redone.
+    pub fn push(&mut self, op: Operation) {
+        self.size -= self.undone.drain(..).map(|op| op.size).sum::<usize>();
+        self.push_done(op);
+    }
+
+    fn push_done(&mut self, op: Operation) {
+        self.size += op.size;
+        self.done.push_back(op);
+        self.trim();
+    }
+
+    /// Forget the oldest operations to undo, then the last ones to redo, until
+    /// the history fits its limit, always keeping the latest of each
+    fn trim(&mut self) {
+        while self.size > self.max_size && self.done.len() > 1 {
+            if let Some(oldest) = self.done.pop_front() {
+                self.size -= oldest.size;
+                self.trimmed = true;
+            }
+        }
+        while self.size > self.max_size && self.undone.len() > 1 {
+            let farthest = self.undone.remove(0);
+            self.size -= farthest.size;
+        }
+    }
+
+    /// Take the latest operation to undo it. Hand it back with `push_undone`,
+    /// holding the edits that redo it.
+    pub fn take_undo(&mut self) -> Option<Operation> {
+        let op = self.done.pop_back()?;
+        self.size -= op.size;
+        Some(op)
+    }
+
+    /// Put back an operation taken by `take_undo`, holding the edits that
+    /// redo it
+    pub fn push_undone(&mut self, op: Operation) {
+        self.size += op.size;
+        self.undone.push(op);
+        self.trim();
+    }
+
+    /// Take the latest undone operation to redo it. Hand it back with
+    /// `push_redone`, holding the edits that undo it again.
+    pub fn take_redo(&mut self) -> Option<Operation> {
+        let op = self.undone.pop()?;
+        self.size -= op.size;
+        Some(op)
+    }
+
+    /// Put back an operation taken by `take_redo`, holding the edits that
+    /// undo it again
+    pub fn push_redone(&mut self, op: Operation) {
+        self.push_done(op);
+    }
+
+    /// Labels of applied operations, oldest first
+    pub fn done_labels(&self) -> impl Iterator<Item = &str> {
+        self.done.iter().map(|op| op.label.as_str())
+    }
+
+    /// Labels of undone operations in the order they would be redone
+    pub fn undone_labels(&self) -> impl Iterator<Item = &str> {
+        self.undone.iter().rev().map(|op| op.label.as_str())
+    }
+
+    /// Number of operations that can be undone
+    pub fn undo_count(&self) -> usize {
+        self.done.len()
+    }
+
+    /// Number of operations that can be redone
+    pub fn redo_count(&self) -> usize {
+        self.undone.len()
+    }
+
+    /// Whether old operations have been dropped to stay under the size limit
+    pub fn is_trimmed(&self) -> bool {
+        self.trimmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet() -> Sheet {
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["a", "b"]);
+        sheet.push_row(["c", "d"]);
+        sheet
+    }
+
+    #[test]
+    fn every_edit_returns_its_inverse() {
+        let edits = [
+            Edit::SetCells(vec![CellChange { row: 1, col: 0, text: "x".into() }]),
+            Edit::InsertRow(1, vec!["p".into(), "q".into()]),
+            Edit::RemoveRow(0),
+            Edit::InsertColumn(1, None, None),
+            Edit::RemoveColumn(0),
+            Edit::ReorderRows(vec![1, 0]),
+            Edit::Resize(3, 3, Vec::new()),
+        ];
+        for edit in edits {
+            let mut edited = sheet();
+            let inverse = edit.clone().apply(&mut edited);
+            assert_ne!(edited.rows().collect::<Vec<_>>(), sheet().rows().collect::<Vec<_>>(), "{:?} changed nothing", edit);
+            inverse.apply(&mut edited);
+            assert_eq!(edited.rows().collect::<Vec<_>>(), sheet().rows().collect::<Vec<_>>(), "{:?} wasn't undone", edit);
+        }
+    }
+
+    #[test]
+    fn remove_rows_keeps_order() {
+        let mut edited = sheet();
+        edited.push_row(["e", "f"]);
+        let original = edited.rows().map(|row| row.join(",")).collect::<Vec<_>>();
+        let mut undo = Vec::new();
+        for edit in remove_rows(&[0, 2]) {
+            undo.push(edit.apply(&mut edited));
+        }
+        assert_eq!(edited.rows().collect::<Vec<_>>(), [["c", "d"]]);
+        for edit in undo.into_iter().rev() {
+            edit.apply(&mut edited);
+        }
+        assert_eq!(edited.rows().map(|row| row.join(",")).collect::<Vec<_>>(), original);
+    }
+
+    #[test]
+    fn new_operations_clear_redo() {
+        let mut history = History::default();
+        history.push(Operation::new("One".into(), Vec::new()));
+        history.push(Operation::new("Two".into(), Vec::new()));
+        let op = history.take_undo().unwrap();
+        history.push_undone(op);
+        assert_eq!(history.done_labels().collect::<Vec<_>>(), ["One"]);
+        assert_eq!(history.undone_labels().collect::<Vec<_>>(), ["Two"]);
+
+        history.push(Operation::new("Three".into(), Vec::new()));
+        assert_eq!(history.redo_count(), 0);
+        assert_eq!(history.done_labels().collect::<Vec<_>>(), ["One", "Three"]);
+        assert!(!history.is_trimmed());
+    }
+
+    #[test]
+    fn undoing_a_shrink_puts_back_the_cut_cells() {
+        let mut edited = sheet();
+        edited.resize(3, 3);
+        edited.set(2, 2, "e");
+        let inverse = Edit::Resize(1, 1, Vec::new()).apply(&mut edited);
+        assert_eq!(edited.rows().collect::<Vec<_>>(), [["a"]]);
+        let redo = inverse.apply(&mut edited);
+        assert_eq!(edited.rows().collect::<Vec<_>>(), [["a", "b", ""], ["c", "d", ""], ["", "", "e"]]);
+        redo.apply(&mut edited);
+        assert_eq!(edited.rows().collect::<Vec<_>>(), [["a"]]);
+    }
+
+    #[test]
+    fn redo_operations_count_toward_the_limit() {
+        let mut history = History::with_max_size(10);
+        for label in ["aaaa", "bbbb", "cccc"] {
+            history.push(Operation::new(label.into(), Vec::new()));
+            let op = history.take_undo().unwrap();
+            history.push_undone(op);
+            let op = history.take_redo().unwrap();
+            history.push_redone(op);
+        }
+        assert_eq!(history.done_labels().collect::<Vec<_>>(), ["bbbb", "cccc"]);
+        assert!(history.is_trimmed());
+
+        for _ in 0..2 {
+            let op = history.take_undo().unwrap();
+            history.push_undone(op);
+        }
+        history.push_undone(Operation::new("dddd".into(), Vec::new()));
+        assert_eq!(history.undone_labels().collect::<Vec<_>>(), ["dddd", "bbbb"]);
+        assert_eq!(history.size, 8);
+
+        history.push(Operation::new("eeee".into(), Vec::new()));
+        assert_eq!(history.redo_count(), 0);
+        assert_eq!(history.size, 4);
+    }
+}
@@ -0,0 +1,268 @@
+//! Adding columns from another sheet by matching key columns, the way a
+//! database join or a spreadsheet lookup does.
+//!
+//! Keys are compared after trimming surrounding spaces. When a key repeats in
+//! the other sheet the first of its rows is used, and the repeats are
+//! reported. Rows of the other sheet with an empty key never match. What
+//! happens to rows without a partner depends on the [`JoinKind`].
+
+use std::collections::HashMap;
+
+use crate::history::{self, Edit};
+use crate::sheet::Sheet;
+
+// Repeated keys listed in a report before the rest are only counted
+const MAX_REPORTED_DUPLICATES: usize = 20;
+
+/// What happens to rows without a partner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Keep every row; rows without a match get empty cells
+    Left,
+    /// Keep only rows with a match
+    Inner,
+    /// Keep every row and add the other sheet's unmatched rows at the end
+    FullOuter,
+}
+
+impl JoinKind {
+    /// Every kind, in the order offered
+    pub const ALL: [JoinKind; 3] = [JoinKind::Left, JoinKind::Inner, JoinKind::FullOuter];
+
+    /// Name shown in the window
+    pub fn label(self) -> &'static str {
+        match self {
+            JoinKind::Left => "Left: keep all rows",
+            JoinKind::Inner => "Inner: keep matched rows only",
+            JoinKind::FullOuter => "Full outer: also add unmatched rows from the other file",
+        }
+    }
+}
+
+/// Which columns to add and how rows are matched
+#[derive(Debug, Clone)]
+pub struct Join {
+    /// Key column of the sheet being added to
+    pub key: usize,
+    /// Key column of the other sheet
+    pub other_key: usize,
+    /// Columns of the other sheet to add, in order
+    pub columns: Vec<usize>,
+    /// What happens to rows without a partner
+    pub kind: JoinKind,
+    /// Whether the first row of each sheet is a header
+    pub header: bool,
+    /// Whether the first row of the other sheet is a header
+    pub other_header: bool,
+}
+
+/// What a join matched
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JoinReport {
+    /// Rows that found a partner
+    pub matched: usize,
+    /// Rows that didn't, which an inner join removes
+    pub unmatched: usize,
+    /// Rows of the other sheet no row matched, including those with an empty
+    /// key, which a full outer join adds
+    pub unused: usize,
+    /// Keys found on more than one row of the other sheet, up to a limit
+    pub duplicate_keys: Vec<String>,
+    /// Number of such keys, including those not listed
+    pub duplicate_count: usize,
+}
+
+impl Join {
+    /// Edits that perform the join on `sheet`, as one operation: the new
+    /// columns are added after the last one, then unmatched rows are added or
+    /// removed.
+    pub fn edits(&self, sheet: &Sheet, other: &Sheet) -> (Vec<Edit>, JoinReport) {
+        let first = usize::from(self.header && !sheet.is_empty());
+        let other_first = usize::from(self.other_header && !other.is_empty());
+        let mut report = JoinReport::default();
+
+        // First row of the other sheet for each key
+        let mut lookup: HashMap<&str, usize> = HashMap::new();
+        let mut repeats: HashMap<&str, usize> = HashMap::new();
+        // Rows without a key, left out of the lookup; wholly empty rows
+        // aren't counted as rows at all
+        let mut keyless = Vec::new();
+        for row in other_first..other.num_rows() {
+            let key = other.get(row, self.other_key).trim();
+            if key.is_empty() {
+                if other.row(row).any(|text| !text.trim().is_empty()) {
+                    keyless.push(row);
+                }
+                continue;
+            }
+            if lookup.contains_key(key) {
+                *repeats.entry(key).or_default() += 1;
+            } else {
+                lookup.insert(key, row);
+            }
+        }
+        let mut duplicates: Vec<&str> = repeats.into_keys().collect();
+        duplicates.sort_unstable();
+        report.duplicate_count = duplicates.len();
+        report.duplicate_keys = duplicates.iter().take(MAX_REPORTED_DUPLICATES).map(|key| key.to_string()).collect();
+
+        let partners: Vec<Option<usize>> = (0..sheet.num_rows())
+            .map(|row| match row < first {
+                true => None,
+                false => lookup.get(sheet.get(row, self.key).trim()).copied(),
+            })
+            .collect();
+        let mut used = vec![false; other.num_rows()];
+        for &row in partners.iter().flatten() {
+            used[row] = true;
+        }
+        report.matched = partners.iter().flatten().count();
+        report.unmatched = sheet.num_rows() - first - report.matched;
+        report.unused = lookup.values().filter(|&&row| !used[row]).count() + keyless.len();
+
+        let mut edits = Vec::new();
+        let width = sheet.num_cols();
+        for (offset, &other_col) in self.columns.iter().enumerate() {
+            let mut column = Sheet::new(0, 1);
+            for (row, partner) in partners.iter().enumerate() {
+                let text = match partner {
+                    Some(other_row) => other.get(*other_row, other_col),
+                    None if row < first && other_first > 0 => other.get(0, other_col),
+                    None => "",
+                };
+                column.push_row([text]);
+            }
+            edits.push(Edit::InsertColumn(width + offset, Some(column), None));
+        }
+
+        match self.kind {
+            JoinKind::Left => {}
+            JoinKind::Inner => {
+                let unmatched: Vec<usize> = (first..sheet.num_rows()).filter(|&row| partners[row].is_none()).collect();
+                edits.extend(history::remove_rows(&unmatched));
+            }
+            JoinKind::FullOuter => {
+                let mut at = sheet.num_rows();
+                for (row, &used) in used.iter().enumerate().skip(other_first) {
+                    // Later rows with a repeated key are left out as for matches
+                    let key = other.get(row, self.other_key).trim();
+                    let unmatched = match key {
+                        "" => keyless.binary_search(&row).is_ok(),
+                        key => !used && lookup.get(key) == Some(&row),
+                    };
+                    if !unmatched {
+                        continue;
+                    }
+                    let mut cells = vec![String::new(); width + self.columns.len()];
+                    if self.key < width {
+                        cells[self.key] = other.get(row, self.other_key).to_string();
+                    }
+                    for (offset, &other_col) in self.columns.iter().enumerate() {
+                        cells[width + offset] = other.get(row, other_col).to_string();
+                    }
+                    edits.push(Edit::InsertRow(at, cells));
+                    at += 1;
+                }
+            }
+        }
+        (edits, report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    fn joined(sheet: &Sheet, other: &Sheet, join: &Join) -> (Vec<Vec<String>>, JoinReport) {
+        let mut result = sheet.clone();
+        let (edits, report) = join.edits(sheet, other);
+        let mut undo = Vec::new();
+        for edit in edits {
+            undo.push(edit.apply(&mut result));
+        }
+        let rows = result.rows().map(|row| row.into_iter().map(str::to_string).collect()).collect();
+
+        // Undoing gives back the original
+        for edit in undo.into_iter().rev() {
+            edit.apply(&mut result);
+        }
+        assert_eq!(result.rows().collect::<Vec<_>>(), sheet.rows().collect::<Vec<_>>());
+        (rows, report)
+    }
+
+    fn contacts() -> (Sheet, Sheet) {
+        let contacts = Sheet::from_rows([["id", "name"], ["1", "ann"], ["2", "bob"], ["3", "cy"]]);
+        let details = Sheet::from_rows([
+            ["email", "id", "city"],
+            ["c@x", " 3 ", "Oslo"],
+            ["a@x", "1", "Rome"],
+            ["a2@x", "1", "Nice"],
+            ["d@x", "4", "Lima"],
+        ]);
+        (contacts, details)
+    }
+
+    fn join(kind: JoinKind) -> Join {
+        Join { key: 0, other_key: 1, columns: vec![2, 0], kind, header: true, other_header: true }
+    }
+
+    #[test]
+    fn left_join_adds_columns_to_every_row() {
+        let (contacts, details) = contacts();
+        let (rows, report) = joined(&contacts, &details, &join(JoinKind::Left));
+        assert_eq!(
+            rows,
+            [
+                ["id", "name", "city", "email"],
+                ["1", "ann", "Rome", "a@x"],
+                ["2", "bob", "", ""],
+                ["3", "cy", "Oslo", "c@x"],
+            ]
+        );
+        assert_eq!(
+            report,
+            JoinReport { matched: 2, unmatched: 1, unused: 1, duplicate_keys: vec!["1".to_string()], duplicate_count: 1 }
+        );
+    }
+
+    #[test]
+    fn inner_join_removes_unmatched_rows() {
+        let (contacts, details) = contacts();
+        let (rows, _) = joined(&contacts, &details, &join(JoinKind::Inner));
+        assert_eq!(rows, [["id", "name", "city", "email"], ["1", "ann", "Rome", "a@x"], ["3", "cy", "Oslo", "c@x"]]);
+    }
+
+    #[test]
+    fn inner_join_moves_formula_references_with_the_kept_rows() {
+        let (mut contacts, details) = contacts();
+        contacts.push_row(["3", "=B4"]);
+        let mut document = Document::from_sheet(contacts);
+        let (edits, _) = join(JoinKind::Inner).edits(&document.sheet, &details);
+        document.apply_operation("Merge columns", edits);
+        assert_eq!(document.sheet.get(3, 1), "=B3");
+        assert_eq!(document.text(3, 1), "cy");
+    }
+
+    #[test]
+    fn full_outer_join_adds_unmatched_rows() {
+        let (contacts, details) = contacts();
+        let (rows, _) = joined(&contacts, &details, &join(JoinKind::FullOuter));
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[4], ["4", "", "Lima", "d@x"]);
+    }
+
+    #[test]
+    fn full_outer_join_keeps_rows_without_a_key() {
+        let (contacts, mut details) = contacts();
+        details.push_row(["e@x", " ", "Kyiv"]);
+        details.push_row(["", "", ""]);
+        let (rows, report) = joined(&contacts, &details, &join(JoinKind::FullOuter));
+        assert_eq!(report.unused, 2);
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows[5], [" ", "", "Kyiv", "e@x"]);
+
+        let (_, report) = joined(&contacts, &details, &join(JoinKind::Left));
+        assert_eq!(report.unused, 2);
+    }
+}
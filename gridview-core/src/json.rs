@@ -0,0 +1,442 @@
+//! JSON arrays of records and newline-delimited JSON (NDJSON).
+//!
+//! Reading accepts a single array of records or records one after another,
+//! which is what NDJSON is. Records that are objects become rows under a
+//! header of their keys, in the order the keys first appear. Nested objects
+//! are flattened into dotted names (`address.city`), numbered where a record
+//! also has a key with the dot in it, and arrays are kept as JSON text in one
+//! cell. Numbers come in as plain decimals, `true` and
+//! `false` as text and `null` as an empty cell. Records that are arrays become
+//! rows without a header.
+//!
+//! Writing produces objects keyed by the header row, or arrays when there is
+//! no header. Numbers, amounts and booleans in typed columns are written as
+//! JSON values, cells holding JSON arrays or objects as the JSON they hold,
+//! empty cells as `null` and everything else as strings. Whole numbers are
+//! written digit for digit; zero-padded codes and numbers too large to write
+//! exactly stay strings. Dotted names can be nested back into objects.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use serde_json::{Map, Number, Value};
+
+use crate::formula::column_letters;
+use crate::sheet::Sheet;
+use crate::types::ColumnType;
+
+/// Why a JSON file couldn't be read
+pub type ReadError = serde_json::Error;
+
+// Column for records that aren't objects among ones that are
+const VALUE_KEY: &str = "value";
+
+/// One array of records, or one record per line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonLayout {
+    /// A single JSON array holding every record
+    Array,
+    /// One record per line: NDJSON
+    Lines,
+}
+
+impl JsonLayout {
+    /// Layout going by a file's extension: `.json`, or `.ndjson` and `.jsonl`
+    pub fn for_path(path: impl AsRef<Path>) -> Option<JsonLayout> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => Some(JsonLayout::Array),
+            "ndjson" | "jsonl" => Some(JsonLayout::Lines),
+            _ => None,
+        }
+    }
+
+    /// Extension a written file gets
+    pub fn extension(self) -> &'static str {
+        match self {
+            JsonLayout::Array => "json",
+            JsonLayout::Lines => "ndjson",
+        }
+    }
+}
+
+/// File extensions read as JSON
+pub const EXTENSIONS: &[&str] = &["json", "ndjson", "jsonl"];
+
+/// Whether a file name has a JSON extension, ignoring case
+pub fn is_json_path(path: impl AsRef<Path>) -> bool {
+    JsonLayout::for_path(path).is_some()
+}
+
+/// Cells read from a JSON file
+pub struct JsonTable {
+    /// The records as rows, under a header of their keys when they are objects
+    pub sheet: Sheet,
+    /// Whether the first row holds the keys of object records
+    pub header: bool,
+}
+
+/// Read JSON records into rows
+pub fn read_json(bytes: &[u8]) -> Result<JsonTable, ReadError> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let mut values = serde_json::Deserializer::from_slice(bytes)
+        .into_iter::<Value>()
+        .collect::<Result<Vec<Value>, _>>()?;
+    // A lone array holds the records; otherwise each value is one
+    let records = match values.as_mut_slice() {
+        [Value::Array(records)] => std::mem::take(records),
+        _ => values,
+    };
+
+    let header = records.iter().any(Value::is_object);
+    let mut sheet = Sheet::new(0, 0);
+    if !header {
+        for record in &records {
+            match record {
+                Value::Array(items) => sheet.push_row(items.iter().map(cell_text)),
+                other => sheet.push_row([cell_text(other)]),
+            }
+        }
+        return Ok(JsonTable { sheet, header });
+    }
+
+    let mut keys: Vec<String> = Vec::new();
+    let mut columns: HashMap<String, usize> = HashMap::new();
+    // Whether every value a column got was null
+    let mut null_only: Vec<bool> = Vec::new();
+    let mut rows: Vec<Vec<(usize, String)>> = Vec::with_capacity(records.len());
+    for record in &records {
+        let mut fields = Vec::new();
+        match record {
+            Value::Object(object) => flatten("", object, &mut fields),
+            other => fields.push((VALUE_KEY.to_string(), other)),
+        }
+        // A flattened name can clash with a literal dotted key of the same
+        // record (`{"a": {"b": 1}, "a.b": 2}`), which then gets a number
+        let mut seen = HashSet::new();
+        let row = fields
+            .into_iter()
+            .map(|(base, value)| {
+                let mut key = base.clone();
+                let mut n = 2;
+                while !seen.insert(key.clone()) {
+                    key = format!("{}_{}", base, n);
+                    n += 1;
+                }
+                let col = *columns.entry(key.clone()).or_insert_with(|| {
+                    keys.push(key);
+                    null_only.push(true);
+                    keys.len() - 1
+                });
+                null_only[col] &= value.is_null();
+                (col, cell_text(value))
+            })
+            .collect();
+        rows.push(row);
+    }
+
+    // A key that is only ever null where other records hold an object
+    // (`"a": null` beside `"a": {"b": 1}`) needs no column of its own
+    let parents: HashSet<&str> = keys
+        .iter()
+        .flat_map(|key| key.match_indices('.').map(|(at, _)| &key[..at]))
+        .collect();
+    let mut kept = Vec::with_capacity(keys.len());
+    let mut header_row = Vec::with_capacity(keys.len());
+    for (key, &null_only) in keys.iter().zip(&null_only) {
+        let keep = !(null_only && parents.contains(key.as_str()));
+        kept.push(keep.then_some(header_row.len()));
+        if keep {
+            header_row.push(key.as_str());
+        }
+    }
+
+    sheet = Sheet::new(0, header_row.len());
+    sheet.push_row(&header_row);
+    let mut cells = vec![String::new(); header_row.len()];
+    for row in rows {
+        cells.iter_mut().for_each(String::clear);
+        for (col, text) in row {
+            if let Some(col) = kept[col] {
+                cells[col] = text;
+            }
+        }
+        sheet.push_row(&cells);
+    }
+    Ok(JsonTable { sheet, header })
+}
+
+// Fields of an object with nested objects spread out under dotted names
+fn flatten<'a>(prefix: &str, object: &'a Map<String, Value>, fields: &mut Vec<(String, &'a Value)>) {
+    for (key, value) in object {
+        let name = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            Value::Object(inner) if !inner.is_empty() => flatten(&name, inner, fields),
+            other => fields.push((name, other)),
+        }
+    }
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Array(_) | Value::Object(_) => value.to_string(),
+    }
+}
+
+/// How [`write_json`] lays out the records
+#[derive(Debug, Clone)]
+pub struct JsonOptions {
+    /// Whether records go in one array or one per line
+    pub layout: JsonLayout,
+    /// The first row is a header naming the keys; without one each row is
+    /// written as an array
+    pub header: bool,
+    /// Turn dotted names into nested objects
+    pub nest: bool,
+}
+
+/// Rows as JSON records. Formulas should already have been replaced by their
+/// results, since JSON has no way to keep them.
+pub fn write_json(sheet: &Sheet, column_types: &HashMap<usize, ColumnType>, options: &JsonOptions) -> Vec<u8> {
+    let types: Vec<ColumnType> = (0..sheet.num_cols())
+        .map(|col| column_types.get(&col).copied().unwrap_or(ColumnType::Text))
+        .collect();
+    let header = options.header && !sheet.is_empty();
+    let keys = if header { object_keys(sheet) } else { Vec::new() };
+
+    let records = (usize::from(header)..sheet.num_rows()).map(|row| {
+        let values = sheet.row(row).zip(&types).map(|(text, &column_type)| json_value(text, column_type));
+        if !header {
+            return Value::Array(values.collect());
+        }
+        let mut object = Map::new();
+        for (key, value) in keys.iter().zip(values) {
+            if options.nest {
+                insert_nested(&mut object, key, value);
+            } else {
+                object.insert(key.clone(), value);
+            }
+        }
+        Value::Object(object)
+    });
+
+    let mut out = String::new();
+    match options.layout {
+        JsonLayout::Array => {
+            // One record per line keeps large files readable and diffable
+            out.push('[');
+            for (i, record) in records.enumerate() {
+                out.push_str(if i == 0 { "\n  " } else { ",\n  " });
+                out.push_str(&record.to_string());
+            }
+            out.push_str("\n]\n");
+        }
+        JsonLayout::Lines => {
+            for record in records {
+                out.push_str(&record.to_string());
+                out.push('\n');
+            }
+        }
+    }
+    out.into_bytes()
+}
+
+// Keys from the header row: empty names become the column letter and
+// repeated ones get a number, so no column is lost
+pub(crate) fn object_keys(sheet: &Sheet) -> Vec<String> {
+    let mut seen = HashSet::new();
+    sheet
+        .row(0)
+        .enumerate()
+        .map(|(col, name)| {
+            let base = if name.is_empty() { column_letters(col) } else { name.to_string() };
+            let mut key = base.clone();
+            let mut n = 2;
+            while !seen.insert(key.clone()) {
+                key = format!("{}_{}", base, n);
+                n += 1;
+            }
+            key
+        })
+        .collect()
+}
+
+fn json_value(text: &str, column_type: ColumnType) -> Value {
+    if text.is_empty() {
+        return Value::Null;
+    }
+    match column_type {
+        // Zero-padded codes and whole numbers past 2^53 have no exact float
+        // form, so unless they are plain integers they stay strings
+        ColumnType::Number | ColumnType::Currency => {
+            if let Some(integer) = column_type.integer(text) {
+                return Value::Number(integer.into());
+            }
+            if let Some(value) = column_type.exact_value(text)
+                && let Some(number) = json_number(value)
+            {
+                return Value::Number(number);
+            }
+        }
+        ColumnType::Boolean => {
+            if let Some(b) = column_type.normalize(text) {
+                return Value::Bool(b == "TRUE");
+            }
+        }
+        _ => {}
+    }
+    // Arrays and objects that were read from JSON go back as they were
+    let trimmed = text.trim_start();
+    if (trimmed.starts_with('[') || trimmed.starts_with('{'))
+        && let Ok(value @ (Value::Array(_) | Value::Object(_))) = serde_json::from_str(text)
+    {
+        return value;
+    }
+    Value::String(text.to_string())
+}
+
+// Whole numbers are written without a fraction
+fn json_number(value: f64) -> Option<Number> {
+    if value.fract() != 0.0 {
+        Number::from_f64(value)
+    } else {
+        Some(Number::from(value as i64))
+    }
+}
+
+// Put a value under a dotted name as nested objects. A name that clashes with
+// another (`a` holding a value and `a.b`) is kept flat instead, and a plain
+// name that dotted names already nest under goes beside them with a number,
+// unless it is null and has nothing to add.
+fn insert_nested(object: &mut Map<String, Value>, key: &str, value: Value) {
+    let parts: Vec<&str> = key.split('.').collect();
+    if parts.len() < 2 || parts.iter().any(|part| part.is_empty()) {
+        if !object.contains_key(key) {
+            object.insert(key.to_string(), value);
+        } else if !value.is_null() {
+            let mut n = 2;
+            while object.contains_key(&format!("{}_{}", key, n)) {
+                n += 1;
+            }
+            object.insert(format!("{}_{}", key, n), value);
+        }
+        return;
+    }
+    let mut target = &mut *object;
+    for part in &parts[..parts.len() - 1] {
+        let slot = target.entry(part.to_string()).or_insert_with(|| Value::Object(Map::new()));
+        match slot {
+            Value::Object(inner) => target = inner,
+            _ => {
+                object.insert(key.to_string(), value);
+                return;
+            }
+        }
+    }
+    let last = parts[parts.len() - 1];
+    if target.contains_key(last) {
+        object.insert(key.to_string(), value);
+    } else {
+        target.insert(last.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::infer_column_types;
+
+    #[test]
+    fn reads_arrays_and_lines_alike() {
+        let array = br#"[{"id": 1, "name": "Ann", "address": {"city": "Oslo"}},
+                         {"id": 2.50, "tags": ["a", "b"], "active": true, "name": null}]"#;
+        let lines = b"{\"id\": 1, \"name\": \"Ann\", \"address\": {\"city\": \"Oslo\"}}\n\
+                      {\"id\": 2.50, \"tags\": [\"a\", \"b\"], \"active\": true, \"name\": null}\n";
+        for bytes in [&array[..], &lines[..]] {
+            let table = read_json(bytes).unwrap();
+            assert!(table.header);
+            assert_eq!(
+                table.sheet.rows().collect::<Vec<_>>(),
+                [
+                    vec!["id", "name", "address.city", "tags", "active"],
+                    vec!["1", "Ann", "Oslo", "", ""],
+                    vec!["2.5", "", "", r#"["a","b"]"#, "true"],
+                ]
+            );
+        }
+
+        let table = read_json(b"[[1, \"a\"], [2]]").unwrap();
+        assert!(!table.header);
+        assert_eq!(table.sheet.rows().collect::<Vec<_>>(), [vec!["1", "a"], vec!["2", ""]]);
+
+        assert!(read_json(b"[{\"id\": 1},").is_err());
+    }
+
+    #[test]
+    fn writes_typed_values_and_nests_names() {
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["id", "price", "address.city", "address.zip", "tags", "ok", ""]);
+        sheet.push_row(["1", "$1,200.50", "Oslo", "0150", r#"["a"]"#, "yes", "x"]);
+        sheet.push_row(["2", "", "", "", "[draft]", "no", ""]);
+        let types = HashMap::from([(0, ColumnType::Number), (1, ColumnType::Currency), (5, ColumnType::Boolean)]);
+
+        let options = JsonOptions { layout: JsonLayout::Lines, header: true, nest: true };
+        let text = String::from_utf8(write_json(&sheet, &types, &options)).unwrap();
+        assert_eq!(
+            text,
+            "{\"id\":1,\"price\":1200.5,\"address\":{\"city\":\"Oslo\",\"zip\":\"0150\"},\"tags\":[\"a\"],\"ok\":true,\"G\":\"x\"}\n\
+             {\"id\":2,\"price\":null,\"address\":{\"city\":null,\"zip\":null},\"tags\":\"[draft]\",\"ok\":false,\"G\":null}\n"
+        );
+
+        // Written and read back, the cells are the same apart from the
+        // formatting of typed values
+        let options = JsonOptions { layout: JsonLayout::Array, header: true, nest: false };
+        let table = read_json(&write_json(&sheet, &types, &options)).unwrap();
+        assert_eq!(table.sheet.rows().collect::<Vec<_>>()[1], ["1", "1200.5", "Oslo", "0150", r#"["a"]"#, "true", "x"]);
+    }
+
+    #[test]
+    fn keeps_large_integers_and_zero_padded_codes_exact() {
+        let json = br#"{"id":1234567890123456789,"zip":"02134"}"#;
+        let table = read_json(json).unwrap();
+        let options = JsonOptions { layout: JsonLayout::Lines, header: true, nest: false };
+        let inferred = infer_column_types(&table.sheet, true);
+        let numbers = HashMap::from([(0, ColumnType::Number), (1, ColumnType::Number)]);
+        for types in [inferred, numbers] {
+            let text = String::from_utf8(write_json(&table.sheet, &types, &options)).unwrap();
+            assert_eq!(text.trim_end().as_bytes(), json);
+        }
+    }
+
+    #[test]
+    fn null_objects_round_trip_with_their_nested_keys() {
+        let json = br#"[{"id":1,"a":{"b":2,"c":{"d":"x"}}},{"id":2,"a":null}]"#;
+        let table = read_json(json).unwrap();
+        assert_eq!(
+            table.sheet.rows().collect::<Vec<_>>(),
+            [vec!["id", "a.b", "a.c.d"], vec!["1", "2", "x"], vec!["2", "", ""]]
+        );
+        let options = JsonOptions { layout: JsonLayout::Lines, header: true, nest: true };
+        let types = infer_column_types(&table.sheet, true);
+        let text = String::from_utf8(write_json(&table.sheet, &types, &options)).unwrap();
+        assert_eq!(
+            text,
+            "{\"id\":1,\"a\":{\"b\":2,\"c\":{\"d\":\"x\"}}}\n{\"id\":2,\"a\":{\"b\":null,\"c\":{\"d\":null}}}\n"
+        );
+
+        // A plain name after the dotted ones doesn't replace their object
+        let sheet = Sheet::from_rows([["a.b", "a"], ["1", "x"], ["2", ""]]);
+        let text = String::from_utf8(write_json(&sheet, &HashMap::new(), &options)).unwrap();
+        assert_eq!(text, "{\"a\":{\"b\":\"1\"},\"a_2\":\"x\"}\n{\"a\":{\"b\":\"2\"}}\n");
+    }
+
+    #[test]
+    fn dotted_keys_clashing_with_nested_ones_get_a_number() {
+        let table = read_json(br#"{"a":{"b":2},"a.b":3}"#).unwrap();
+        assert_eq!(table.sheet.rows().collect::<Vec<_>>(), [vec!["a.b", "a.b_2"], vec!["2", "3"]]);
+    }
+}
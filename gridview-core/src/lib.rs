@@ -0,0 +1,48 @@
+//! The spreadsheet engine behind GridView, without any user interface.
+//!
+//! A [`Document`] holds a [`Sheet`] of text cells together with everything
+//! needed to edit and save it: the [`CsvDialect`] and [`TextEncoding`] it was
+//! read in, parsed formulas and their results, the inferred [`ColumnType`] of
+//! each column and an undo [`History`]. Changes are made by applying
+//! [`Edit`]s, each of which returns the edit that reverses it.
+//!
+//! ```
+//! use gridview_core::{Document, SaveOptions};
+//! use gridview_core::sort::{SortKey, SortType};
+//!
+//! let mut doc = Document::from_bytes(b"item,price\na,$12\nb,$3\n").unwrap();
+//! let key = SortKey { col: 1, ascending: true, sort_type: SortType::Number, empties_last: true };
+//! doc.sort("Sort by price", &[key], true);
+//! let (bytes, _) = doc.to_bytes(SaveOptions::default()).unwrap();
+//! assert_eq!(bytes, b"item,price\nb,$3\na,$12\n");
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod calc;
+pub mod clipboard;
+pub mod columnar;
+pub mod dialect;
+pub mod diff;
+pub mod document;
+pub mod duplicates;
+pub mod encoding;
+pub mod filter;
+pub mod formula;
+pub mod history;
+pub mod join;
+pub mod json;
+pub mod search;
+pub mod sheet;
+pub mod sort;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod types;
+pub mod xlsx;
+
+pub use dialect::CsvDialect;
+pub use document::{Document, SaveOptions};
+pub use encoding::TextEncoding;
+pub use history::{CellChange, Edit, History};
+pub use sheet::Sheet;
+pub use types::ColumnType;
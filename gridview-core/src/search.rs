@@ -0,0 +1,159 @@
+//! Find & Replace.
+//!
+//! Every search, plain text or not, is compiled to a regex: plain queries are
+//! escaped, "whole cell" anchors the pattern and case-insensitivity becomes the
+//! (?i) flag, so matching and replacing share one code path. Replacements in
+//! regex mode can refer to capture groups as $1 or ${name}; in plain mode the
+//! replacement is inserted literally.
+
+use regex::{NoExpand, Regex};
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::formula::CellRef;
+use crate::history::CellChange;
+use crate::sheet::Sheet;
+
+/// Why a search pattern couldn't be compiled
+pub type PatternError = regex::Error;
+
+/// A compiled search pattern, matched against the text of cells
+pub struct Matcher {
+    regex: Regex,
+    expand: bool,
+}
+
+impl Matcher {
+    /// A matcher for `query`, as a regular expression or as plain text, and
+    /// optionally only for cells whose whole text matches
+    pub fn new(query: &str, case_sensitive: bool, use_regex: bool, whole_cell: bool) -> Result<Self, PatternError> {
+        let mut pattern = if use_regex { query.to_string() } else { regex::escape(query) };
+        if whole_cell {
+            pattern = format!("^(?:{})$", pattern);
+        }
+        if !case_sensitive {
+            pattern = format!("(?i){}", pattern);
+        }
+        Ok(Self {
+            regex: Regex::new(&pattern)?,
+            expand: use_regex,
+        })
+    }
+
+    /// Whether the pattern is found anywhere in `text`, or matches all of it
+    /// for whole-cell searches
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// The text with every match replaced, or None if it would not change
+    pub fn replace(&self, text: &str, replacement: &str) -> Option<String> {
+        let replaced = if self.expand {
+            self.regex.replace_all(text, replacement)
+        } else {
+            self.regex.replace_all(text, NoExpand(replacement))
+        };
+        match replaced {
+            Cow::Owned(new) if new != text => Some(new),
+            _ => None,
+        }
+    }
+}
+
+/// Cells in the given rows and columns whose text matches, in row-major order
+pub fn find(sheet: &Sheet, rows: impl IntoIterator<Item = usize>, cols: Range<usize>, matcher: &Matcher) -> Vec<CellRef> {
+    let mut results = Vec::new();
+    for row in rows {
+        for col in cols.clone() {
+            if matcher.is_match(sheet.get(row, col)) {
+                results.push((row, col));
+            }
+        }
+    }
+    results
+}
+
+/// Changes replacing every match in the given cells, skipping cells the
+/// replacement would leave as they are
+pub fn replacements(
+    sheet: &Sheet,
+    cells: impl IntoIterator<Item = CellRef>,
+    matcher: &Matcher,
+    replacement: &str,
+) -> Vec<CellChange> {
+    cells
+        .into_iter()
+        .filter_map(|(row, col)| {
+            let text = matcher.replace(sheet.get(row, col), replacement)?;
+            Some(CellChange { row, col, text })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_plain_text_literally() {
+        let matcher = Matcher::new("a.b", false, false, false).unwrap();
+        assert!(matcher.is_match("XA.BY"));
+        assert!(!matcher.is_match("axb"));
+        assert_eq!(matcher.replace("a.b a.b", "$1").as_deref(), Some("$1 $1"));
+    }
+
+    #[test]
+    fn matches_whole_cells_and_case() {
+        let matcher = Matcher::new("yes", true, false, true).unwrap();
+        assert!(matcher.is_match("yes"));
+        assert!(!matcher.is_match("Yes"));
+        assert!(!matcher.is_match("yes!"));
+    }
+
+    #[test]
+    fn replaces_with_capture_groups() {
+        let matcher = Matcher::new(r"(\d+)-(\d+)", false, true, false).unwrap();
+        assert_eq!(matcher.replace("10-20", "$2-$1").as_deref(), Some("20-10"));
+        assert_eq!(matcher.replace("none", "x"), None);
+        assert!(Matcher::new("(", false, true, false).is_err());
+    }
+
+    #[test]
+    fn whole_cell_covers_every_alternative() {
+        let matcher = Matcher::new("yes|no", false, true, true).unwrap();
+        assert!(matcher.is_match("No"));
+        assert!(!matcher.is_match("yes please"));
+        assert!(!matcher.is_match("not now"));
+        assert_eq!(matcher.replace("YES", "true").as_deref(), Some("true"));
+        assert_eq!(matcher.replace("yes, no", "true"), None);
+    }
+
+    #[test]
+    fn ignores_case_beyond_ascii() {
+        let matcher = Matcher::new("ÉTÉ", false, false, false).unwrap();
+        assert!(matcher.is_match("un été chaud"));
+        assert!(!Matcher::new("ÉTÉ", true, false, false).unwrap().is_match("été"));
+    }
+
+    #[test]
+    fn replaces_with_named_groups() {
+        let matcher = Matcher::new(r"(?P<last>\w+), (?P<first>\w+)", true, true, false).unwrap();
+        assert_eq!(matcher.replace("Lovelace, Ada", "${first} ${last}").as_deref(), Some("Ada Lovelace"));
+        let plain = Matcher::new("$", true, false, false).unwrap();
+        assert_eq!(plain.replace("$5", "USD ").as_deref(), Some("USD 5"));
+    }
+
+    #[test]
+    fn finds_and_replaces_in_a_block() {
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["cat", "dog"]);
+        sheet.push_row(["catalog", "cat"]);
+        let matcher = Matcher::new("cat", false, false, false).unwrap();
+        assert_eq!(find(&sheet, 0..2, 0..2, &matcher), [(0, 0), (1, 0), (1, 1)]);
+        assert_eq!(find(&sheet, [1], 1..2, &matcher), [(1, 1)]);
+
+        let changes = replacements(&sheet, [(0, 0), (0, 1), (1, 0)], &matcher, "cow");
+        let changed: Vec<_> = changes.iter().map(|c| (c.row, c.col, c.text.as_str())).collect();
+        assert_eq!(changed, [(0, 0, "cow"), (1, 0, "cowalog")]);
+    }
+}
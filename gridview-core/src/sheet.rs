@@ -0,0 +1,459 @@
+//! Cell storage for a spreadsheet.
+//!
+//! Cells are stored column by column. Each column keeps its text in a single
+//! arena string and refers to it with a (start, len) span per cell, so a cell
+//! costs 8 bytes plus its text rather than a separately allocated String. While
+//! a column has few distinct values (categories, flags, repeated dates) they are
+//! interned, so repeats share one copy of the text. Edits append to the arena;
+//! the space they leave behind is reclaimed once it outweighs the live text.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Columns stop interning once they have this many distinct values
+const MAX_INTERNED: usize = 1024;
+/// Longer values are rarely repeated, so they aren't looked up
+const MAX_INTERNED_LEN: usize = 64;
+/// Unreferenced arena bytes tolerated before a column is compacted
+const MIN_GARBAGE: usize = 1024 * 1024;
+
+/// Text a column can hold, as cells find theirs by 32-bit offsets
+const MAX_COLUMN_TEXT: usize = u32::MAX as usize;
+
+/// A column would hold more than the 4 GiB of text a sheet allows per column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnFull;
+
+impl fmt::Display for ColumnFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a column holds more than 4 GiB of text")
+    }
+}
+
+impl std::error::Error for ColumnFull {}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Span {
+    start: u32,
+    len: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Column {
+    arena: String,
+    spans: Vec<Span>,
+    /// Distinct values seen so far, while there are few enough to be worth sharing
+    interned: Option<HashMap<Box<str>, Span>>,
+    /// Arena bytes no longer referenced by any cell (an estimate, see `release`)
+    garbage: usize,
+}
+
+impl Column {
+    fn new(rows: usize) -> Self {
+        Self {
+            arena: String::new(),
+            spans: vec![Span::default(); rows],
+            interned: Some(HashMap::new()),
+            garbage: 0,
+        }
+    }
+
+    fn get(&self, row: usize) -> &str {
+        let span = self.spans[row];
+        &self.arena[span.start as usize..(span.start + span.len) as usize]
+    }
+
+    /// Copy a value into the arena, or find the interned copy
+    fn store(&mut self, value: &str) -> Span {
+        if value.is_empty() {
+            return Span::default();
+        }
+        let internable = value.len() <= MAX_INTERNED_LEN;
+        if internable
+            && let Some(span) = self.interned.as_ref().and_then(|interned| interned.get(value))
+        {
+            return *span;
+        }
+
+        let start = u32::try_from(self.arena.len()).expect("column text exceeds 4 GiB");
+        let span = Span { start, len: value.len() as u32 };
+        self.arena.push_str(value);
+
+        if internable && let Some(interned) = &mut self.interned {
+            if interned.len() < MAX_INTERNED {
+                interned.insert(value.into(), span);
+            } else {
+                self.interned = None;
+            }
+        }
+        span
+    }
+
+    /// Account for a span that is no longer used by a cell. Interned values are
+    /// shared, so only text that can't have been interned counts as garbage.
+    fn release(&mut self, span: Span) {
+        if self.interned.is_none() || span.len as usize > MAX_INTERNED_LEN {
+            self.garbage += span.len as usize;
+        }
+    }
+
+    fn set(&mut self, row: usize, value: &str) {
+        if self.get(row) == value {
+            return;
+        }
+        self.release(self.spans[row]);
+        self.spans[row] = self.store(value);
+        self.compact_if_needed();
+    }
+
+    fn compact_if_needed(&mut self) {
+        if self.garbage > MIN_GARBAGE && self.garbage > self.arena.len() / 2 {
+            let mut column = Column::new(0);
+            column.arena.reserve(self.arena.len().saturating_sub(self.garbage));
+            column.spans.reserve_exact(self.spans.len());
+            for row in 0..self.spans.len() {
+                let span = column.store(self.get(row));
+                column.spans.push(span);
+            }
+            *self = column;
+        }
+    }
+}
+
+/// A grid of text cells, stored column by column
+#[derive(Debug, Clone, Default)]
+pub struct Sheet {
+    columns: Vec<Column>,
+    rows: usize,
+}
+
+impl Sheet {
+    /// A blank sheet of the given size
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self {
+            columns: (0..cols).map(|_| Column::new(rows)).collect(),
+            rows,
+        }
+    }
+
+    /// A sheet holding the given rows, as wide as the widest of them
+    #[cfg(test)]
+    pub(crate) fn from_rows<R, S>(rows: impl IntoIterator<Item = R>) -> Self
+    where
+        R: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut sheet = Sheet::default();
+        for row in rows {
+            sheet.push_row(row);
+        }
+        sheet
+    }
+
+    /// Number of rows
+    pub fn num_rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of columns
+    pub fn num_cols(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Whether the sheet has no rows
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Whether a cell is inside the sheet
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        row < self.rows && col < self.columns.len()
+    }
+
+    /// Text of a cell; empty for cells outside the sheet
+    pub fn get(&self, row: usize, col: usize) -> &str {
+        if self.contains(row, col) {
+            self.columns[col].get(row)
+        } else {
+            ""
+        }
+    }
+
+    /// Set a cell inside the sheet. Cells outside it are ignored; use
+    /// `ensure_size` first to grow the sheet.
+    pub fn set(&mut self, row: usize, col: usize, value: &str) {
+        if self.contains(row, col) {
+            self.columns[col].set(row, value);
+        }
+    }
+
+    /// Text of the cells of a row, left to right
+    pub fn row(&self, row: usize) -> impl Iterator<Item = &str> + '_ {
+        self.columns.iter().map(move |column| column.get(row))
+    }
+
+    /// Every row, top to bottom
+    #[cfg(test)]
+    pub(crate) fn rows(&self) -> impl Iterator<Item = Vec<&str>> + '_ {
+        (0..self.rows).map(move |row| self.row(row).collect())
+    }
+
+    /// Text of the cells of a column, top to bottom; empty for a column past
+    /// the last
+    pub fn column(&self, col: usize) -> impl Iterator<Item = &str> + '_ {
+        let column = self.columns.get(col);
+        (0..self.rows).map(move |row| column.map_or("", |column| column.get(row)))
+    }
+
+    /// Check that `len` more bytes of text would fit in any one column.
+    /// Readers of large files check each row before pushing it, as a sheet
+    /// can't store a column past 4 GiB.
+    pub fn check_room(&self, len: usize) -> Result<(), ColumnFull> {
+        if len <= MAX_COLUMN_TEXT && self.columns.iter().all(|column| column.arena.len() + len <= MAX_COLUMN_TEXT) {
+            Ok(())
+        } else {
+            Err(ColumnFull)
+        }
+    }
+
+    /// Append a row, adding columns if it is wider than the sheet. Missing
+    /// trailing fields are left empty.
+    pub fn push_row<S: AsRef<str>>(&mut self, fields: impl IntoIterator<Item = S>) {
+        let row = self.rows;
+        self.rows += 1;
+        for column in &mut self.columns {
+            column.spans.push(Span::default());
+        }
+        for (col, field) in fields.into_iter().enumerate() {
+            if col >= self.columns.len() {
+                self.columns.push(Column::new(self.rows));
+            }
+            let column = &mut self.columns[col];
+            column.spans[row] = column.store(field.as_ref());
+        }
+    }
+
+    /// Replace the contents of a row, adding columns if needed
+    pub fn set_row<S: AsRef<str>>(&mut self, row: usize, fields: impl IntoIterator<Item = S>) {
+        if row >= self.rows {
+            return;
+        }
+        let mut width = 0;
+        for (col, field) in fields.into_iter().enumerate() {
+            self.ensure_size(self.rows, col + 1);
+            self.columns[col].set(row, field.as_ref());
+            width = col + 1;
+        }
+        for column in &mut self.columns[width..] {
+            column.set(row, "");
+        }
+    }
+
+    /// Grow the sheet to at least the given size
+    pub fn ensure_size(&mut self, rows: usize, cols: usize) {
+        if rows > self.rows {
+            for column in &mut self.columns {
+                column.spans.resize(rows, Span::default());
+            }
+            self.rows = rows;
+        }
+        while self.columns.len() < cols {
+            self.columns.push(Column::new(self.rows));
+        }
+    }
+
+    /// Insert a blank row, at the end if `at` is past it
+    pub fn insert_row(&mut self, at: usize) {
+        let at = at.min(self.rows);
+        for column in &mut self.columns {
+            column.spans.insert(at, Span::default());
+        }
+        self.rows += 1;
+    }
+
+    /// Remove a row; rows past the end are ignored
+    pub fn remove_row(&mut self, at: usize) {
+        if at >= self.rows {
+            return;
+        }
+        for column in &mut self.columns {
+            let span = column.spans.remove(at);
+            column.release(span);
+            column.compact_if_needed();
+        }
+        self.rows -= 1;
+    }
+
+    /// Insert a blank column, at the end if `at` is past it
+    pub fn insert_column(&mut self, at: usize) {
+        let at = at.min(self.columns.len());
+        self.columns.insert(at, Column::new(self.rows));
+    }
+
+    /// Insert a column taken from a one-column sheet, e.g. one returned by
+    /// `take_column`
+    pub fn insert_column_from(&mut self, at: usize, mut column: Sheet) {
+        let at = at.min(self.columns.len());
+        let mut column = column.columns.pop().unwrap_or_else(|| Column::new(0));
+        column.spans.resize(self.rows, Span::default());
+        self.columns.insert(at, column);
+    }
+
+    /// Remove a column, returning it as a one-column sheet
+    pub fn take_column(&mut self, at: usize) -> Option<Sheet> {
+        if at >= self.columns.len() {
+            return None;
+        }
+        let column = self.columns.remove(at);
+        Some(Sheet { columns: vec![column], rows: self.rows })
+    }
+
+    /// Grow or shrink the sheet to exactly the given size
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        self.columns.truncate(cols);
+        for column in &mut self.columns {
+            if rows < self.rows {
+                for row in rows..self.rows {
+                    let span = column.spans[row];
+                    column.release(span);
+                }
+                column.spans.truncate(rows);
+                column.compact_if_needed();
+            } else {
+                column.spans.resize(rows, Span::default());
+            }
+        }
+        self.rows = rows;
+        while self.columns.len() < cols {
+            self.columns.push(Column::new(rows));
+        }
+    }
+
+    /// Rearrange rows so that row `i` becomes the old row `order[i]`. `order`
+    /// must be a permutation of the row indices.
+    pub fn reorder_rows(&mut self, order: &[usize]) {
+        debug_assert_eq!(order.len(), self.rows);
+        for column in &mut self.columns {
+            column.spans = order.iter().map(|&row| column.spans[row]).collect();
+        }
+    }
+
+    /// Approximate heap memory used by the cells
+    pub fn memory_size(&self) -> usize {
+        self.columns
+            .iter()
+            .map(|column| column.arena.capacity() + column.spans.capacity() * std::mem::size_of::<Span>())
+            .sum()
+    }
+
+    /// Release spare capacity, e.g. once a file has finished loading
+    pub fn shrink_to_fit(&mut self) {
+        for column in &mut self.columns {
+            column.arena.shrink_to_fit();
+            column.spans.shrink_to_fit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_from_rows_and_reads_them_back() {
+        let sheet = Sheet::from_rows([vec!["a", "b"], vec!["c"]]);
+        assert_eq!((sheet.num_rows(), sheet.num_cols()), (2, 2));
+        assert_eq!(sheet.rows().collect::<Vec<_>>(), [["a", "b"], ["c", ""]]);
+        assert!(Sheet::from_rows(Vec::<Vec<&str>>::new()).rows().next().is_none());
+    }
+
+    #[test]
+    fn pushes_rows_and_reads_cells() {
+        let mut sheet = Sheet::new(0, 2);
+        sheet.push_row(["a", "b"]);
+        sheet.push_row(["c", "d", "e"]);
+        sheet.push_row(["f"]);
+        assert_eq!((sheet.num_rows(), sheet.num_cols()), (3, 3));
+        assert_eq!(sheet.row(0).collect::<Vec<_>>(), ["a", "b", ""]);
+        assert_eq!(sheet.column(2).collect::<Vec<_>>(), ["", "e", ""]);
+        assert_eq!(sheet.get(5, 5), "");
+    }
+
+    #[test]
+    fn overwrites_repeated_values() {
+        let mut sheet = Sheet::new(3, 1);
+        for row in 0..3 {
+            sheet.set(row, 0, "same");
+        }
+        sheet.set(1, 0, "other");
+        sheet.set(9, 0, "outside");
+        assert_eq!(sheet.column(0).collect::<Vec<_>>(), ["same", "other", "same"]);
+        assert_eq!(sheet.num_rows(), 3);
+    }
+
+    #[test]
+    fn repeated_values_share_their_text() {
+        let mut sheet = Sheet::new(0, 2);
+        for row in 0..10_000 {
+            sheet.push_row([if row % 2 == 0 { "yes" } else { "no" }, &row.to_string()]);
+        }
+        assert_eq!(sheet.columns[0].arena, "yesno");
+        assert_eq!(sheet.get(9_999, 0), "no");
+        // Too many distinct values to intern, but every cell still reads back
+        assert!(sheet.columns[1].interned.is_none());
+        assert_eq!(sheet.get(4_321, 1), "4321");
+        sheet.shrink_to_fit();
+        assert!(sheet.memory_size() < 10_000 * 8 * 2 + 50_000);
+    }
+
+    #[test]
+    fn reclaims_space_left_by_edits() {
+        let mut sheet = Sheet::new(2, 1);
+        sheet.set(1, 0, "kept");
+        for i in 0..50_000 {
+            sheet.set(0, 0, &format!("{:0>100}", i));
+        }
+        assert_eq!(sheet.get(0, 0), format!("{:0>100}", 49_999));
+        assert_eq!(sheet.get(1, 0), "kept");
+        assert!(sheet.columns[0].arena.len() < 3 * MIN_GARBAGE);
+    }
+
+    #[test]
+    fn refuses_text_past_a_columns_limit() {
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["abc", ""]);
+        assert_eq!(sheet.check_room(MAX_COLUMN_TEXT - 3), Ok(()));
+        assert_eq!(sheet.check_room(MAX_COLUMN_TEXT - 2), Err(ColumnFull));
+        assert_eq!(Sheet::new(0, 0).check_room(MAX_COLUMN_TEXT + 1), Err(ColumnFull));
+    }
+
+    #[test]
+    fn inserts_and_removes_rows_and_columns() {
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["a", "b"]);
+        sheet.push_row(["c", "d"]);
+        sheet.insert_row(1);
+        sheet.insert_column(0);
+        assert_eq!(sheet.row(0).collect::<Vec<_>>(), ["", "a", "b"]);
+        assert_eq!(sheet.row(1).collect::<Vec<_>>(), ["", "", ""]);
+        sheet.remove_row(1);
+        let column = sheet.take_column(2).unwrap();
+        assert_eq!(column.column(0).collect::<Vec<_>>(), ["b", "d"]);
+        sheet.insert_column_from(0, column);
+        assert_eq!(sheet.row(1).collect::<Vec<_>>(), ["d", "", "c"]);
+    }
+
+    #[test]
+    fn reorders_and_resizes() {
+        let mut sheet = Sheet::new(0, 0);
+        for text in ["x", "y", "z"] {
+            sheet.push_row([text]);
+        }
+        sheet.reorder_rows(&[2, 0, 1]);
+        assert_eq!(sheet.column(0).collect::<Vec<_>>(), ["z", "x", "y"]);
+        sheet.resize(2, 2);
+        assert_eq!(sheet.row(1).collect::<Vec<_>>(), ["x", ""]);
+        sheet.ensure_size(1, 1);
+        assert_eq!((sheet.num_rows(), sheet.num_cols()), (2, 2));
+    }
+}
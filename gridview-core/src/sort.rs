@@ -0,0 +1,268 @@
+//! Sorting rows by one or more columns.
+//!
+//! Each key says how to compare its column: natural text order (so "item 9"
+//! comes before "item 10"), numbers, dates, case-insensitive text, or
+//! locale-style text where accents and case only break ties. Empty cells go
+//! first or last whatever the direction. The sort is stable, so rows that
+//! compare equal on every key keep their order, and a frozen header row stays
+//! on top.
+
+use std::cmp::Ordering;
+
+use crate::calc::Calculator;
+use crate::sheet::Sheet;
+use crate::types::{self, ColumnType};
+
+/// How the cells of a sort key column are compared
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortType {
+    /// Text, with runs of digits compared by value ("item2" before "item10")
+    Natural,
+    /// Numbers and amounts by value
+    Number,
+    /// Dates and times in order
+    Date,
+    /// Text, ignoring case
+    CaseInsensitive,
+    /// Text, ignoring case and accents
+    Locale,
+}
+
+impl SortType {
+    /// Every sort type, in menu order
+    pub const ALL: [SortType; 5] = [
+        SortType::Natural,
+        SortType::Number,
+        SortType::Date,
+        SortType::CaseInsensitive,
+        SortType::Locale,
+    ];
+
+    /// Name shown in the Sort dialog
+    pub fn label(self) -> &'static str {
+        match self {
+            SortType::Natural => "Text (natural)",
+            SortType::Number => "Number",
+            SortType::Date => "Date",
+            SortType::CaseInsensitive => "Text (ignore case)",
+            SortType::Locale => "Text (ignore accents)",
+        }
+    }
+
+    /// How a column of the given type sorts by default
+    pub fn for_column(column_type: ColumnType) -> SortType {
+        match column_type {
+            ColumnType::Number | ColumnType::Currency => SortType::Number,
+            ColumnType::Date => SortType::Date,
+            _ => SortType::Natural,
+        }
+    }
+
+    /// Numeric sort value for cells of this type, None for text types
+    fn parse(self, text: &str) -> Option<f64> {
+        match self {
+            // Plain numbers as well as amounts like "$1,200" or "(3.00)"
+            SortType::Number => types::parse_currency(text),
+            SortType::Date => types::parse_date(text),
+            _ => None,
+        }
+    }
+}
+
+/// One column to sort by
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    /// Column compared
+    pub col: usize,
+    /// Whether smaller values come first
+    pub ascending: bool,
+    /// How the cells are compared
+    pub sort_type: SortType,
+    /// Whether empty cells go last whichever the direction
+    pub empties_last: bool,
+}
+
+/// Text with runs of digits compared by their value, ignoring case
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let digits = |text: &str| text.bytes().take_while(u8::is_ascii_digit).count();
+    let (mut i, mut j) = (0, 0);
+    while let (Some(x), Some(y)) = (a[i..].chars().next(), b[j..].chars().next()) {
+        let order = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let (x_end, y_end) = (i + digits(&a[i..]), j + digits(&b[j..]));
+            let (x_value, y_value) = (a[i..x_end].trim_start_matches('0'), b[j..y_end].trim_start_matches('0'));
+            (i, j) = (x_end, y_end);
+            x_value.len().cmp(&y_value.len()).then_with(|| x_value.cmp(y_value))
+        } else {
+            (i, j) = (i + x.len_utf8(), j + y.len_utf8());
+            x.to_lowercase().cmp(y.to_lowercase())
+        };
+        if order.is_ne() {
+            return order;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j)).then_with(|| case_insensitive_cmp(a, b))
+}
+
+fn case_insensitive_cmp(a: &str, b: &str) -> Ordering {
+    let lower = |text| str::chars(text).flat_map(char::to_lowercase);
+    lower(a).cmp(lower(b)).then_with(|| a.cmp(b))
+}
+
+/// Base letter of common accented Latin letters
+fn strip_accent(c: char) -> char {
+    const ACCENTED: &str = "àáâãäåāăąçćčďèéêëēĕėęěìíîïīĭįłñńňòóôõöøōŏőŕřśšşťùúûüūŭůűųýÿžźż";
+    const BASE: &str = "aaaaaaaaacccdeeeeeeeeeiiiiiiilnnnoooooooooorrssstuuuuuuuuuyyzzz";
+    match ACCENTED.chars().position(|a| a == c) {
+        Some(i) => BASE.chars().nth(i).unwrap_or(c),
+        None => c,
+    }
+}
+
+/// Dictionary-style order: letters first, then accents, then case
+fn locale_cmp(a: &str, b: &str) -> Ordering {
+    let base = |text| str::chars(text).flat_map(char::to_lowercase).map(strip_accent);
+    base(a).cmp(base(b)).then_with(|| case_insensitive_cmp(a, b))
+}
+
+fn compare_text(sort_type: SortType, a: &str, b: &str) -> Ordering {
+    match sort_type {
+        SortType::CaseInsensitive => case_insensitive_cmp(a, b),
+        SortType::Locale => locale_cmp(a, b),
+        _ => natural_cmp(a, b),
+    }
+}
+
+/// A column's cells as the sort sees them
+struct KeyColumn<'a> {
+    key: &'a SortKey,
+    texts: Vec<std::borrow::Cow<'a, str>>,
+    /// Parsed values for number and date keys
+    values: Vec<Option<f64>>,
+}
+
+impl KeyColumn<'_> {
+    fn compare(&self, a: usize, b: usize) -> Ordering {
+        let (a_text, b_text) = (self.texts[a].trim(), self.texts[b].trim());
+        match (a_text.is_empty(), b_text.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return if self.key.empties_last { Ordering::Greater } else { Ordering::Less },
+            (false, true) => return if self.key.empties_last { Ordering::Less } else { Ordering::Greater },
+            (false, false) => {}
+        }
+
+        // Cells that don't parse as the key's type go after the ones that do
+        let order = match (self.values.get(a).copied().flatten(), self.values.get(b).copied().flatten()) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => compare_text(self.key.sort_type, a_text, b_text),
+        };
+        if self.key.ascending { order } else { order.reverse() }
+    }
+}
+
+/// Order of the rows sorted by the keys, in priority order: row `i` of the
+/// result is row `order[i]` of the sheet. Formulas are sorted by their results,
+/// and a header row stays first.
+pub fn sort_order(sheet: &Sheet, calc: &mut Calculator, keys: &[SortKey], header: bool) -> Vec<usize> {
+    let num_rows = sheet.num_rows();
+    for key in keys {
+        calc.prepare(sheet, 0..num_rows, key.col..key.col + 1);
+    }
+    let columns: Vec<KeyColumn> = keys
+        .iter()
+        .map(|key| {
+            let texts: Vec<_> = (0..num_rows).map(|row| calc.display(sheet, row, key.col)).collect();
+            let values = match key.sort_type {
+                SortType::Number | SortType::Date => texts.iter().map(|text| key.sort_type.parse(text)).collect(),
+                _ => Vec::new(),
+            };
+            KeyColumn { key, texts, values }
+        })
+        .collect();
+
+    let first = if header && num_rows > 1 { 1 } else { 0 };
+    let mut order: Vec<usize> = (0..num_rows).collect();
+    order[first..].sort_by(|&a, &b| {
+        columns
+            .iter()
+            .map(|column| column.compare(a, b))
+            .find(|order| order.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(cells: &[&str], keys: &[SortKey], header: bool) -> Vec<String> {
+        let mut sheet = Sheet::new(0, 0);
+        for text in cells {
+            sheet.push_row(text.split('|'));
+        }
+        let mut calc = Calculator::default();
+        calc.rebuild(&sheet);
+        let order = sort_order(&sheet, &mut calc, keys, header);
+        order.iter().map(|&row| sheet.row(row).collect::<Vec<_>>().join("|")).collect()
+    }
+
+    fn key(col: usize, ascending: bool, sort_type: SortType) -> SortKey {
+        SortKey { col, ascending, sort_type, empties_last: true }
+    }
+
+    #[test]
+    fn sorts_text_naturally() {
+        let rows = sorted(&["item 10", "Item 9", "item 1"], &[key(0, true, SortType::Natural)], false);
+        assert_eq!(rows, ["item 1", "Item 9", "item 10"]);
+    }
+
+    #[test]
+    fn sorts_numbers_and_dates_by_value() {
+        let rows = sorted(&["$1,200", "(3)", "25"], &[key(0, true, SortType::Number)], false);
+        assert_eq!(rows, ["(3)", "25", "$1,200"]);
+        let rows = sorted(&["03/01/2024", "2023-12-31", "2024-02-01"], &[key(0, false, SortType::Date)], false);
+        assert_eq!(rows, ["03/01/2024", "2024-02-01", "2023-12-31"]);
+    }
+
+    #[test]
+    fn ignores_case_or_accents_on_request() {
+        let cells = ["éclair", "ecrou", "Eclair", "apple"];
+        let rows = sorted(&cells, &[key(0, true, SortType::Locale)], false);
+        assert_eq!(rows, ["apple", "Eclair", "éclair", "ecrou"]);
+        let rows = sorted(&cells, &[key(0, true, SortType::CaseInsensitive)], false);
+        assert_eq!(rows, ["apple", "Eclair", "ecrou", "éclair"]);
+    }
+
+    #[test]
+    fn puts_cells_of_another_type_after_values() {
+        let rows = sorted(&["n/a", "5", "1"], &[key(0, true, SortType::Number)], false);
+        assert_eq!(rows, ["1", "5", "n/a"]);
+        assert_eq!(SortType::for_column(ColumnType::Currency), SortType::Number);
+        assert_eq!(SortType::for_column(ColumnType::Date), SortType::Date);
+        assert_eq!(SortType::for_column(ColumnType::Text), SortType::Natural);
+    }
+
+    #[test]
+    fn keeps_empties_last_and_header_first() {
+        let rows = sorted(&["n", "2", "", "10"], &[key(0, false, SortType::Number)], true);
+        assert_eq!(rows, ["n", "10", "2", ""]);
+        let empties_first = SortKey { empties_last: false, ..key(0, true, SortType::Number) };
+        let rows = sorted(&["n", "2", "", "10"], &[empties_first], true);
+        assert_eq!(rows, ["n", "", "2", "10"]);
+    }
+
+    #[test]
+    fn breaks_ties_with_later_keys_and_is_stable() {
+        let cells = ["b|2|x", "a|2|y", "b|1|z", "a|2|w"];
+        let keys = [key(1, true, SortType::Number), key(0, true, SortType::Natural)];
+        assert_eq!(sorted(&cells, &keys, false), ["b|1|z", "a|2|y", "a|2|w", "b|2|x"]);
+    }
+
+    #[test]
+    fn sorts_formulas_by_their_results() {
+        let rows = sorted(&["5", "=1+1", "3"], &[key(0, true, SortType::Number)], false);
+        assert_eq!(rows, ["=1+1", "3", "5"]);
+    }
+}
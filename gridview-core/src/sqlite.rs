@@ -0,0 +1,764 @@
+//! SQLite databases, with the `sqlite` feature.
+//!
+//! A table, a view or the result of a `SELECT` is read into a [`Sheet`] of
+//! text under a header row of its column names. Column types follow the
+//! declared types where they say something (`INTEGER`, `REAL`, `DATE`, ...)
+//! and are inferred from the cells otherwise. NULL becomes an empty cell, and
+//! blobs are shown by their size since they have no text form; columns
+//! holding blobs are never written back.
+//!
+//! Rows read from a table keep their rowid in a [`TableLink`], which follows
+//! the rows as they are inserted, removed and sorted, and gives a removed row
+//! its rowid back when the removal is undone. Saving compares the
+//! sheet with what was read and writes only the difference: an `UPDATE` of
+//! the changed cells of each row, a `DELETE` for each row that is gone and an
+//! `INSERT` for each new row, all in one transaction. Cell text is bound as
+//! text, so SQLite's column affinity turns "42" back into a number, and empty
+//! cells are NULL.
+//!
+//! [`Database::export_table`] goes the other way and creates a table from a
+//! sheet, with a column type for each typed column.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::limits::Limit;
+use rusqlite::{Connection, OpenFlags, params_from_iter};
+
+use crate::dialect::{CsvDialect, ParsedCsv};
+use crate::document::Document;
+use crate::encoding::TextEncoding;
+use crate::formula::column_letters;
+use crate::history::Edit;
+use crate::sheet::Sheet;
+use crate::types::ColumnType;
+
+/// Why a database couldn't be read or written
+pub type Error = rusqlite::Error;
+
+/// File extensions of SQLite databases
+pub const EXTENSIONS: &[&str] = &["sqlite", "sqlite3", "db"];
+
+/// Whether a file name has a SQLite extension, ignoring case
+pub fn is_sqlite_path(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known)))
+}
+
+/// A table or view listed in a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableInfo {
+    /// Name of the table or view
+    pub name: String,
+    /// Whether it is a view, which can't be written back to
+    pub view: bool,
+}
+
+/// What to read from a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Every row of a table or view
+    Table(String),
+    /// The rows of a read-only statement, usually a `SELECT`
+    Query(String),
+}
+
+/// Rows read from a database
+pub struct SqlTable {
+    /// A header row of column names, then the rows
+    pub sheet: Sheet,
+    /// Types of the columns whose declared type names one
+    pub column_types: HashMap<usize, ColumnType>,
+    /// Where the rows came from, for tables that have rowids
+    pub link: Option<TableLink>,
+}
+
+impl SqlTable {
+    /// A document for the rows, with the header row and the declared column
+    /// types; other columns get inferred types
+    pub fn into_document(self) -> (Document, Option<TableLink>) {
+        let dialect = CsvDialect { has_headers: true, ..Default::default() };
+        let parsed = ParsedCsv { rows: self.sheet, problems: Vec::new() };
+        let mut document = Document::from_parsed(parsed, dialect, TextEncoding::default());
+        document.column_types.extend(self.column_types);
+        (document, self.link)
+    }
+}
+
+/// The table a sheet was read from, and which row of it each sheet row is
+#[derive(Debug, Clone)]
+pub struct TableLink {
+    /// Name of the table
+    pub table: String,
+    /// Column names of the table as read
+    names: Vec<String>,
+    /// Whether each table column is declared BLOB or held a blob. Its cells
+    /// only show the size, so they are never written.
+    blobs: Vec<bool>,
+    /// Table column of each sheet column; None for columns added since
+    columns: Vec<Option<usize>>,
+    /// Rowid of each sheet row; None for the header and rows added since
+    rowids: Vec<Option<i64>>,
+    /// Text of each row as read or last saved, by table column
+    saved: HashMap<i64, Vec<String>>,
+    /// Rowids of removed rows, most recent last, with a hash of their cells
+    /// to know the row again if the removal is undone
+    removed: Vec<(i64, u64)>,
+}
+
+fn row_hash<'a>(cells: impl Iterator<Item = &'a str>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for cell in cells {
+        cell.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Rows that differ between a sheet and the table it was read from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableChanges {
+    /// Rowids of rows no longer in the sheet
+    pub deleted: Vec<i64>,
+    /// Rowid and the changed cells of each edited row, by table column
+    pub updated: Vec<(i64, Vec<(usize, String)>)>,
+    /// Sheet row and the non-empty cells of each new row, by table column
+    pub inserted: Vec<(usize, Vec<(usize, String)>)>,
+}
+
+impl TableChanges {
+    /// Whether there is nothing to write
+    pub fn is_empty(&self) -> bool {
+        self.deleted.is_empty() && self.updated.is_empty() && self.inserted.is_empty()
+    }
+}
+
+impl TableLink {
+    /// Follow an edit about to be applied to the sheet
+    pub fn adjust_for(&mut self, edit: &Edit, sheet: &Sheet) {
+        match edit {
+            Edit::SetCells(_) => {}
+            Edit::InsertRow(at, cells) => {
+                // Undoing a removal inserts the row with the cells it had, so
+                // it is the same table row again
+                let hash = row_hash(cells.iter().map(String::as_str));
+                let rowid = self
+                    .removed
+                    .iter()
+                    .rposition(|&(rowid, removed)| removed == hash && self.saved.contains_key(&rowid))
+                    .map(|index| self.removed.remove(index).0);
+                self.rowids.insert((*at).min(self.rowids.len()), rowid);
+            }
+            Edit::RemoveRow(at) => {
+                if *at < self.rowids.len()
+                    && let Some(rowid) = self.rowids.remove(*at)
+                {
+                    self.removed.push((rowid, row_hash(sheet.row(*at))));
+                }
+            }
+            Edit::InsertColumn(at, ..) => self.columns.insert((*at).min(self.columns.len()), None),
+            Edit::RemoveColumn(at) => {
+                if *at < self.columns.len() {
+                    self.columns.remove(*at);
+                }
+            }
+            Edit::ReorderRows(order) => {
+                self.rowids = order.iter().map(|&row| self.rowids.get(row).copied().flatten()).collect();
+            }
+            Edit::Resize(rows, cols, _) => {
+                self.rowids.resize(*rows, None);
+                self.columns.resize(*cols, None);
+            }
+        }
+    }
+
+    /// What saving `sheet` to the table would change. The first row is the
+    /// header. Cells of blob columns are left out.
+    pub fn changes(&self, sheet: &Sheet) -> TableChanges {
+        let mut changes = TableChanges::default();
+        let mut present = HashSet::new();
+        for row in 1..sheet.num_rows() {
+            let cells = self
+                .columns
+                .iter()
+                .enumerate()
+                .filter_map(|(col, column)| column.map(|column| (column, sheet.get(row, col))))
+                .filter(|&(column, _)| !self.blobs[column]);
+            match self.rowids.get(row).copied().flatten() {
+                Some(rowid) => {
+                    present.insert(rowid);
+                    let saved = &self.saved[&rowid];
+                    let changed: Vec<(usize, String)> = cells
+                        .filter(|&(column, text)| saved[column] != text)
+                        .map(|(column, text)| (column, text.to_string()))
+                        .collect();
+                    if !changed.is_empty() {
+                        changes.updated.push((rowid, changed));
+                    }
+                }
+                None => {
+                    let filled: Vec<(usize, String)> = cells
+                        .filter(|(_, text)| !text.is_empty())
+                        .map(|(column, text)| (column, text.to_string()))
+                        .collect();
+                    // Blank rows, like one just added at the bottom, aren't written
+                    if !filled.is_empty() {
+                        changes.inserted.push((row, filled));
+                    }
+                }
+            }
+        }
+        changes.deleted = self.saved.keys().copied().filter(|rowid| !present.contains(rowid)).collect();
+        changes.deleted.sort_unstable();
+        changes
+    }
+
+    // Record a successful save
+    fn saved(&mut self, changes: &TableChanges, inserted: &[i64]) {
+        for rowid in &changes.deleted {
+            self.saved.remove(rowid);
+        }
+        // Deleted rows can't come back as they were
+        self.removed.retain(|(rowid, _)| self.saved.contains_key(rowid));
+        for (rowid, cells) in &changes.updated {
+            if let Some(saved) = self.saved.get_mut(rowid) {
+                for (column, text) in cells {
+                    saved[*column] = text.clone();
+                }
+            }
+        }
+        for ((row, cells), &rowid) in changes.inserted.iter().zip(inserted) {
+            let mut saved = vec![String::new(); self.names.len()];
+            for (column, text) in cells {
+                saved[*column] = text.clone();
+            }
+            self.saved.insert(rowid, saved);
+            self.rowids[*row] = Some(rowid);
+        }
+    }
+}
+
+/// An open database file
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Open an existing database
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let connection = Connection::open_with_flags(path, flags)?;
+        // SQLite only looks at the file once it has to, so find out now
+        // whether it is a database at all
+        connection.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))?;
+        Self::new(connection)
+    }
+
+    /// Open a database, creating the file if there isn't one
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// An empty database in memory, for running queries over sheets
+    /// exported to it
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    // SQLite counts ATTACH as read-only, so queries could otherwise open and
+    // create other files; no databases can be attached at all
+    fn new(connection: Connection) -> Result<Self, Error> {
+        connection.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0)?;
+        Ok(Self { connection })
+    }
+
+    /// Tables and views by name, leaving out SQLite's own
+    pub fn tables(&self) -> Result<Vec<TableInfo>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT name, type FROM sqlite_master WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok(TableInfo { name: row.get(0)?, view: row.get::<_, String>(1)? == "view" })
+        })?;
+        rows.collect()
+    }
+
+    /// Read a table, a view or the rows of a query. Only tables with rowids
+    /// come with a link to write edits back by, and queries that would change
+    /// the database are refused.
+    pub fn read(&self, source: &Source) -> Result<SqlTable, Error> {
+        match source {
+            Source::Table(name) => {
+                // Views and tables created WITHOUT ROWID have no rowid to
+                // write changes back by
+                let with_rowid = format!("SELECT _rowid_, * FROM {}", quote(name));
+                match self.connection.prepare(&with_rowid) {
+                    Ok(mut statement) => read_rows(&mut statement, Some(name)),
+                    Err(_) => read_rows(&mut self.connection.prepare(&format!("SELECT * FROM {}", quote(name)))?, None),
+                }
+            }
+            Source::Query(sql) => read_rows(&mut self.prepare_query(sql)?, None),
+        }
+    }
+
+    /// Check that a query is valid and wouldn't change the database, without
+    /// running it
+    pub fn check_query(&self, sql: &str) -> Result<(), Error> {
+        self.prepare_query(sql).map(|_| ())
+    }
+
+    fn prepare_query(&self, sql: &str) -> Result<rusqlite::Statement<'_>, Error> {
+        let statement = self.connection.prepare(sql)?;
+        if !statement.readonly() {
+            return Err(Error::InvalidQuery);
+        }
+        Ok(statement)
+    }
+
+    /// Write the changes to a linked sheet back to its table, in one
+    /// transaction. The link then refers to the saved rows, including the
+    /// rowids of new ones.
+    pub fn write_changes(&mut self, link: &mut TableLink, sheet: &Sheet) -> Result<TableChanges, Error> {
+        let changes = link.changes(sheet);
+        let table = quote(&link.table);
+        let mut inserted = Vec::with_capacity(changes.inserted.len());
+
+        let transaction = self.connection.transaction()?;
+        for rowid in &changes.deleted {
+            transaction.execute(&format!("DELETE FROM {} WHERE _rowid_ = ?1", table), [rowid])?;
+        }
+        for (rowid, cells) in &changes.updated {
+            let assignments: Vec<String> = cells
+                .iter()
+                .enumerate()
+                .map(|(i, (column, _))| format!("{} = ?{}", quote(&link.names[*column]), i + 1))
+                .collect();
+            let sql = format!("UPDATE {} SET {} WHERE _rowid_ = ?{}", table, assignments.join(", "), cells.len() + 1);
+            let values = cells.iter().map(|(_, text)| text_value(text)).chain([Value::Integer(*rowid)]);
+            transaction.execute(&sql, params_from_iter(values))?;
+        }
+        for (_, cells) in &changes.inserted {
+            let names: Vec<String> = cells.iter().map(|(column, _)| quote(&link.names[*column])).collect();
+            let placeholders: Vec<String> = (1..=cells.len()).map(|i| format!("?{}", i)).collect();
+            let sql = format!("INSERT INTO {} ({}) VALUES ({})", table, names.join(", "), placeholders.join(", "));
+            transaction.execute(&sql, params_from_iter(cells.iter().map(|(_, text)| text_value(text))))?;
+            inserted.push(transaction.last_insert_rowid());
+        }
+        transaction.commit()?;
+
+        link.saved(&changes, &inserted);
+        Ok(changes)
+    }
+
+    /// Create a table holding the cells, with the first row as column names
+    /// when `header` is set. Numbers become INTEGER columns when every value is
+    /// whole and REAL otherwise, amounts REAL, dates DATE columns of ISO text
+    /// and booleans BOOLEAN columns of 1 and 0. A column with cells that don't
+    /// fit its type, zero-padded codes or whole numbers too large for a REAL,
+    /// and every untyped column, is TEXT. An existing table of
+    /// the same name is an error unless `replace` is set. Returns the number
+    /// of rows written.
+    pub fn export_table(
+        &mut self,
+        name: &str,
+        sheet: &Sheet,
+        column_types: &HashMap<usize, ColumnType>,
+        header: bool,
+        replace: bool,
+    ) -> Result<usize, Error> {
+        let header = header && !sheet.is_empty();
+        let first = usize::from(header);
+        let names = if header {
+            crate::json::object_keys(sheet)
+        } else {
+            (0..sheet.num_cols()).map(column_letters).collect()
+        };
+        let storage: Vec<Storage> = (0..sheet.num_cols())
+            .map(|col| {
+                let column_type = column_types.get(&col).copied().unwrap_or(ColumnType::Text);
+                Storage::for_column((first..sheet.num_rows()).map(|row| sheet.get(row, col)), column_type)
+            })
+            .collect();
+
+        let transaction = self.connection.transaction()?;
+        if replace {
+            transaction.execute(&format!("DROP TABLE IF EXISTS {}", quote(name)), [])?;
+        }
+        let definitions: Vec<String> = names
+            .iter()
+            .zip(&storage)
+            .map(|(name, storage)| format!("{} {}", quote(name), storage.declared_type()))
+            .collect();
+        transaction.execute(&format!("CREATE TABLE {} ({})", quote(name), definitions.join(", ")), [])?;
+        if !names.is_empty() {
+            let placeholders: Vec<String> = (1..=names.len()).map(|i| format!("?{}", i)).collect();
+            let mut insert = transaction.prepare(&format!("INSERT INTO {} VALUES ({})", quote(name), placeholders.join(", ")))?;
+            for row in first..sheet.num_rows() {
+                insert.execute(params_from_iter(sheet.row(row).zip(&storage).map(|(text, storage)| storage.value(text))))?;
+            }
+        }
+        transaction.commit()?;
+        Ok(sheet.num_rows() - first)
+    }
+}
+
+// Rows of a statement, whose first column is the rowid when reading a
+// table to link to
+fn read_rows(statement: &mut rusqlite::Statement, table: Option<&str>) -> Result<SqlTable, Error> {
+    let skip = usize::from(table.is_some());
+    let columns = statement.columns();
+    let names: Vec<String> = columns.iter().skip(skip).map(|column| column.name().to_string()).collect();
+    let column_types = columns
+        .iter()
+        .skip(skip)
+        .enumerate()
+        .filter_map(|(col, column)| declared_type(column.decl_type()?).map(|column_type| (col, column_type)))
+        .collect();
+
+    let mut blobs: Vec<bool> = columns
+        .iter()
+        .skip(skip)
+        .map(|column| column.decl_type().is_some_and(|declared| declared.to_ascii_uppercase().contains("BLOB")))
+        .collect();
+
+    let mut sheet = Sheet::new(0, names.len());
+    sheet.push_row(&names);
+    let mut rowids = vec![None];
+    let mut saved = HashMap::new();
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let cells = (skip..skip + names.len())
+            .map(|index| {
+                let value = row.get_ref(index)?;
+                blobs[index - skip] |= matches!(value, ValueRef::Blob(_));
+                Ok(cell_text(value))
+            })
+            .collect::<Result<Vec<String>, Error>>()?;
+        sheet.push_row(&cells);
+        if table.is_some() {
+            let rowid: i64 = row.get(0)?;
+            rowids.push(Some(rowid));
+            saved.insert(rowid, cells);
+        }
+    }
+
+    let link = table.map(|table| TableLink {
+        table: table.to_string(),
+        columns: (0..names.len()).map(Some).collect(),
+        names,
+        blobs,
+        rowids,
+        saved,
+        removed: Vec::new(),
+    });
+    Ok(SqlTable { sheet, column_types, link })
+}
+
+// How a column is stored when exporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Storage {
+    Integer,
+    Real(ColumnType),
+    Date,
+    Boolean,
+    Text,
+}
+
+impl Storage {
+    fn for_column<'a>(mut cells: impl Iterator<Item = &'a str>, column_type: ColumnType) -> Storage {
+        let storage = match column_type {
+            ColumnType::Number => Storage::Integer,
+            ColumnType::Currency => Storage::Real(column_type),
+            ColumnType::Date => Storage::Date,
+            ColumnType::Boolean => Storage::Boolean,
+            ColumnType::Text | ColumnType::Email | ColumnType::Url => return Storage::Text,
+        };
+        // Whole numbers are stored from their digits, as an f64 only holds 53
+        // bits of them. Zero-padded codes have no plain form, so they keep
+        // their column as text.
+        let (mut integers, mut exact) = (true, true);
+        let fits = cells.all(|text| {
+            let text = text.trim();
+            if text.is_empty() {
+                return true;
+            }
+            integers &= column_type.integer(text).is_some();
+            exact &= !column_type.is_numeric() || column_type.exact_value(text).is_some();
+            column_type.normalize(text).is_some()
+        });
+        match storage {
+            _ if !fits => Storage::Text,
+            Storage::Integer if integers => Storage::Integer,
+            _ if !exact => Storage::Text,
+            Storage::Integer => Storage::Real(column_type),
+            storage => storage,
+        }
+    }
+
+    fn declared_type(self) -> &'static str {
+        match self {
+            Storage::Integer => "INTEGER",
+            Storage::Real(_) => "REAL",
+            Storage::Date => "DATE",
+            Storage::Boolean => "BOOLEAN",
+            Storage::Text => "TEXT",
+        }
+    }
+
+    fn value(self, text: &str) -> Value {
+        if text.trim().is_empty() {
+            return Value::Null;
+        }
+        let value = match self {
+            Storage::Integer => ColumnType::Number.integer(text).map(Value::Integer),
+            Storage::Real(column_type) => column_type.value(text).map(Value::Real),
+            Storage::Date => ColumnType::Date.normalize(text).map(Value::Text),
+            Storage::Boolean => ColumnType::Boolean.normalize(text).map(|b| Value::Integer(i64::from(b == "TRUE"))),
+            Storage::Text => None,
+        };
+        value.unwrap_or_else(|| Value::Text(text.to_string()))
+    }
+}
+
+// The column type a declared type stands for, following SQLite's affinity
+// rules for the numeric ones
+fn declared_type(declared: &str) -> Option<ColumnType> {
+    let declared = declared.to_ascii_uppercase();
+    if declared.contains("INT") {
+        Some(ColumnType::Number)
+    } else if ["CHAR", "CLOB", "TEXT"].iter().any(|text| declared.contains(text)) {
+        Some(ColumnType::Text)
+    } else if declared.contains("DATE") || declared.contains("TIME") {
+        Some(ColumnType::Date)
+    } else if ["REAL", "FLOA", "DOUB", "NUMERIC", "DECIMAL"].iter().any(|number| declared.contains(number)) {
+        Some(ColumnType::Number)
+    } else {
+        None
+    }
+}
+
+fn cell_text(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+        ValueRef::Blob(blob) => format!("[{} bytes]", blob.len()),
+    }
+}
+
+fn text_value(text: &str) -> Value {
+    if text.is_empty() { Value::Null } else { Value::Text(text.to_string()) }
+}
+
+// A table or column name as an SQL identifier
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Database {
+        let database = Database::in_memory().unwrap();
+        database
+            .connection
+            .execute_batch(
+                "CREATE TABLE people (id INTEGER PRIMARY KEY, name TEXT NOT NULL, born DATE, score REAL);
+                 INSERT INTO people VALUES (1, 'Ann', '1990-05-01', 3.5), (2, 'Bob', NULL, 2), (3, 'Cy', NULL, NULL);
+                 CREATE VIEW names AS SELECT name FROM people;",
+            )
+            .unwrap();
+        database
+    }
+
+    #[test]
+    fn reads_tables_views_and_queries() {
+        let database = database();
+        assert_eq!(
+            database.tables().unwrap(),
+            [TableInfo { name: "names".into(), view: true }, TableInfo { name: "people".into(), view: false }]
+        );
+
+        let table = database.read(&Source::Table("people".into())).unwrap();
+        assert_eq!(
+            table.sheet.rows().collect::<Vec<_>>(),
+            [
+                vec!["id", "name", "born", "score"],
+                vec!["1", "Ann", "1990-05-01", "3.5"],
+                vec!["2", "Bob", "", "2"],
+                vec!["3", "Cy", "", ""],
+            ]
+        );
+        assert_eq!(
+            table.column_types,
+            HashMap::from([
+                (0, ColumnType::Number),
+                (1, ColumnType::Text),
+                (2, ColumnType::Date),
+                (3, ColumnType::Number)
+            ])
+        );
+        assert!(table.link.is_some());
+
+        let view = database.read(&Source::Table("names".into())).unwrap();
+        assert_eq!(view.sheet.num_rows(), 4);
+        assert!(view.link.is_none());
+
+        let query = database.read(&Source::Query("SELECT name, score * 2 AS double FROM people WHERE score > 2".into()));
+        assert_eq!((query.unwrap().sheet).rows().collect::<Vec<_>>(), [vec!["name", "double"], vec!["Ann", "7"]]);
+        assert!(matches!(database.read(&Source::Query("DELETE FROM people".into())), Err(Error::InvalidQuery)));
+    }
+
+    #[test]
+    fn writes_back_edits() {
+        let mut database = database();
+        let table = database.read(&Source::Table("people".into())).unwrap();
+        let (mut sheet, mut link) = (table.sheet, table.link.unwrap());
+        let edits = [
+            Edit::SetCells(vec![crate::CellChange { row: 1, col: 1, text: "Anne".into() }]),
+            // Sort by name descending, then drop Bob and add Dee
+            Edit::ReorderRows(vec![0, 3, 2, 1]),
+            Edit::RemoveRow(2),
+            Edit::InsertRow(3, vec!["".into(), "Dee".into(), "2001-01-01".into(), "".into()]),
+            Edit::InsertRow(4, Vec::new()),
+            Edit::InsertColumn(4, None, None),
+        ];
+        for edit in edits {
+            link.adjust_for(&edit, &sheet);
+            edit.apply(&mut sheet);
+        }
+        sheet.set(1, 4, "not saved");
+
+        let changes = database.write_changes(&mut link, &sheet).unwrap();
+        assert_eq!(changes.deleted, [2]);
+        assert_eq!(changes.updated, [(1, vec![(1, "Anne".to_string())])]);
+        assert_eq!(changes.inserted.len(), 1);
+        assert!(link.changes(&sheet).is_empty());
+
+        let saved = database.read(&Source::Query("SELECT * FROM people ORDER BY id".into())).unwrap();
+        assert_eq!(
+            saved.sheet.rows().collect::<Vec<_>>()[1..],
+            [vec!["1", "Anne", "1990-05-01", "3.5"], vec!["3", "Cy", "", ""], vec!["4", "Dee", "2001-01-01", ""]]
+        );
+    }
+
+    #[test]
+    fn undone_removals_keep_their_rows_and_blobs_are_kept() {
+        let mut database = database();
+        database
+            .connection
+            .execute_batch("ALTER TABLE people ADD COLUMN photo BLOB; UPDATE people SET photo = x'00ff10' WHERE id = 2;")
+            .unwrap();
+        let table = database.read(&Source::Table("people".into())).unwrap();
+        let (mut sheet, mut link) = (table.sheet, table.link.unwrap());
+        assert_eq!(sheet.get(2, 4), "[3 bytes]");
+
+        // Remove Bob, undo that, then add a copy of him and retype the blob
+        let mut apply = |edit: Edit, sheet: &mut Sheet| {
+            link.adjust_for(&edit, sheet);
+            edit.apply(sheet)
+        };
+        let undo = apply(Edit::RemoveRow(2), &mut sheet);
+        apply(undo, &mut sheet);
+        let mut bob: Vec<String> = sheet.row(2).map(str::to_string).collect();
+        bob[0].clear();
+        apply(Edit::InsertRow(4, bob), &mut sheet);
+        apply(Edit::SetCells(vec![crate::CellChange { row: 2, col: 4, text: "[4 bytes]".into() }]), &mut sheet);
+
+        let changes = database.write_changes(&mut link, &sheet).unwrap();
+        assert!(changes.deleted.is_empty());
+        assert!(changes.updated.is_empty());
+        assert_eq!(changes.inserted.len(), 1);
+        let saved = database.read(&Source::Query("SELECT id, name, hex(photo) FROM people ORDER BY id".into())).unwrap();
+        assert_eq!(
+            saved.sheet.rows().collect::<Vec<_>>()[1..],
+            [vec!["1", "Ann", ""], vec!["2", "Bob", "00FF10"], vec!["3", "Cy", ""], vec!["4", "Bob", ""]]
+        );
+    }
+
+    #[test]
+    fn exports_typed_columns() {
+        let mut database = Database::in_memory().unwrap();
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["id", "price", "day", "ok", "code", "id"]);
+        sheet.push_row(["1", "$1,200.50", "03/01/2024", "yes", "A1", "x"]);
+        sheet.push_row(["2", "", "", "no", "7", "y"]);
+        let types = HashMap::from([
+            (0, ColumnType::Number),
+            (1, ColumnType::Currency),
+            (2, ColumnType::Date),
+            (3, ColumnType::Boolean),
+            (4, ColumnType::Number),
+        ]);
+        assert_eq!(database.export_table("items", &sheet, &types, true, false).unwrap(), 2);
+        assert!(database.export_table("items", &sheet, &types, true, false).is_err());
+        database.export_table("items", &sheet, &types, true, true).unwrap();
+
+        let declared: Vec<(String, String)> = database
+            .connection
+            .prepare("SELECT name, type FROM pragma_table_info('items')")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let declared: Vec<(&str, &str)> = declared.iter().map(|(name, ty)| (name.as_str(), ty.as_str())).collect();
+        assert_eq!(
+            declared,
+            [("id", "INTEGER"), ("price", "REAL"), ("day", "DATE"), ("ok", "BOOLEAN"), ("code", "TEXT"), ("id_2", "TEXT")]
+        );
+        let table = database.read(&Source::Query("SELECT typeof(id), price, day, ok FROM items".into())).unwrap();
+        assert_eq!(table.sheet.rows().collect::<Vec<_>>()[1..], [vec!["integer", "1200.5", "2024-03-01", "1"], vec!["integer", "", "", "0"]]);
+    }
+
+    #[test]
+    fn exports_large_integers_and_zero_padded_codes_exactly() {
+        let mut database = Database::in_memory().unwrap();
+        let sheet = Sheet::from_rows([["id", "zip"], ["1234567890123456789", "02134"], ["-7", "90210"]]);
+        let types = HashMap::from([(0, ColumnType::Number), (1, ColumnType::Number)]);
+        database.export_table("places", &sheet, &types, true, false).unwrap();
+        let sql = "SELECT typeof(id), id, typeof(zip), zip FROM places";
+        let table = database.read(&Source::Query(sql.into())).unwrap();
+        assert_eq!(
+            table.sheet.rows().collect::<Vec<_>>()[1..],
+            [
+                vec!["integer", "1234567890123456789", "text", "02134"],
+                vec!["integer", "-7", "text", "90210"]
+            ]
+        );
+    }
+
+    #[test]
+    fn queries_across_exported_sheets() {
+        let mut database = Database::in_memory().unwrap();
+        let mut sales = Sheet::new(0, 0);
+        sales.push_row(["region", "amount"]);
+        sales.push_row(["north", "$10.50"]);
+        sales.push_row(["south", "$4"]);
+        sales.push_row(["north", "$2"]);
+        let mut regions = Sheet::new(0, 0);
+        regions.push_row(["A", "B"]);
+        regions.push_row(["north", "Anna"]);
+        regions.push_row(["south", "Bo"]);
+        database.export_table("t", &sales, &HashMap::from([(1, ColumnType::Currency)]), true, false).unwrap();
+        database.export_table("regions", &regions, &HashMap::new(), false, false).unwrap();
+
+        let sql = "SELECT B AS manager, sum(amount) AS total FROM t JOIN regions ON region = A \
+                   WHERE amount > 3 GROUP BY B ORDER BY total DESC";
+        let table = database.read(&Source::Query(sql.into())).unwrap();
+        assert_eq!(table.sheet.rows().collect::<Vec<_>>(), [vec!["manager", "total"], vec!["Anna", "10.5"], vec!["Bo", "4"]]);
+    }
+
+    #[test]
+    fn queries_cannot_attach_other_databases() {
+        let database = Database::in_memory().unwrap();
+        assert!(database.read(&Source::Query("ATTACH DATABASE ':memory:' AS other".into())).is_err());
+        assert!(database.read(&Source::Query("SELECT * FROM other.sqlite_master".into())).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+//! Column types.
+//!
+//! Cells are stored as text, but each column has a type that says how to read
+//! it: as a number ("1,234.50"), a currency amount ("$12", "(3.00)"), a date
+//! ("2024-03-01"), a boolean, an email address or a URL. Types are inferred from
+//! a sample of each column when a file is opened and can be changed from the
+//! column header. The type decides how cells are aligned, sorted and filtered,
+//! marks the cells that don't fit it, and lets Save As write values in a plain
+//! form.
+
+use std::collections::HashMap;
+
+use crate::formula::{self, format_number};
+use crate::sheet::Sheet;
+
+/// Non-empty cells looked at when inferring a column's type
+const INFER_SAMPLE: usize = 1000;
+
+const CURRENCY_SYMBOLS: [char; 6] = ['$', '€', '£', '¥', '₹', '₩'];
+
+/// 2^53: past it, an f64 no longer holds every whole number
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// What the cells of a column hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnType {
+    /// Any text
+    Text,
+    /// Plain decimal numbers, optionally with thousands separators
+    Number,
+    /// Amounts with an optional currency symbol, negatives possibly in
+    /// parentheses
+    Currency,
+    /// Dates, optionally with a time
+    Date,
+    /// Yes/no values: true, false, yes or no
+    Boolean,
+    /// Email addresses
+    Email,
+    /// Web addresses
+    Url,
+}
+
+impl ColumnType {
+    /// Every type, in menu order
+    pub const ALL: [ColumnType; 7] = [
+        ColumnType::Text,
+        ColumnType::Number,
+        ColumnType::Currency,
+        ColumnType::Date,
+        ColumnType::Boolean,
+        ColumnType::Email,
+        ColumnType::Url,
+    ];
+
+    /// Name shown in menus and accepted by `from_name`
+    pub fn label(self) -> &'static str {
+        match self {
+            ColumnType::Text => "Text",
+            ColumnType::Number => "Number",
+            ColumnType::Currency => "Currency",
+            ColumnType::Date => "Date",
+            ColumnType::Boolean => "Boolean",
+            ColumnType::Email => "Email",
+            ColumnType::Url => "URL",
+        }
+    }
+
+    /// The type with this label, ignoring case
+    pub fn from_name(name: &str) -> Option<ColumnType> {
+        ColumnType::ALL.into_iter().find(|ty| ty.label().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Short marker drawn in the column header
+    pub fn tag(self) -> &'static str {
+        match self {
+            ColumnType::Text => "",
+            ColumnType::Number => "123",
+            ColumnType::Currency => "$",
+            ColumnType::Date => "date",
+            ColumnType::Boolean => "bool",
+            ColumnType::Email => "@",
+            ColumnType::Url => "url",
+        }
+    }
+
+    /// Whether the type has a numeric value that sorts and aligns like a number
+    pub fn is_numeric(self) -> bool {
+        matches!(self, ColumnType::Number | ColumnType::Currency)
+    }
+
+    /// The most specific type nearly all sampled cells fit. A few stray
+    /// values don't stop a column being typed; they are shown as invalid.
+    pub fn infer<'a>(cells: impl Iterator<Item = &'a str>) -> ColumnType {
+        let sample: Vec<&str> = cells.map(str::trim).filter(|text| !text.is_empty()).take(INFER_SAMPLE).collect();
+        if sample.is_empty() {
+            return ColumnType::Text;
+        }
+        let fits = |ty: ColumnType| sample.iter().filter(|text| ty.is_valid(text)).count() * 100 >= sample.len() * 95;
+        // Zip codes, phone numbers and IDs keep their leading zeros as text
+        let zero_padded = sample.iter().any(|text| is_zero_padded(text));
+        [
+            ColumnType::Boolean,
+            ColumnType::Number,
+            ColumnType::Currency,
+            ColumnType::Date,
+            ColumnType::Email,
+            ColumnType::Url,
+        ]
+        .into_iter()
+        .find(|&ty| !(zero_padded && ty.is_numeric()) && fits(ty))
+        .unwrap_or(ColumnType::Text)
+    }
+
+    /// Whether a cell fits the type; empty cells always do
+    pub fn is_valid(self, text: &str) -> bool {
+        let text = text.trim();
+        text.is_empty()
+            || match self {
+                ColumnType::Text => true,
+                ColumnType::Number | ColumnType::Currency | ColumnType::Date => self.value(text).is_some(),
+                ColumnType::Boolean => parse_bool(text).is_some(),
+                ColumnType::Email => is_email(text),
+                ColumnType::Url => is_url(text),
+            }
+    }
+
+    /// Numeric value of a cell, for the types that have one: dates are day
+    /// numbers as DATE() returns them. Text columns accept plain numbers.
+    pub fn value(self, text: &str) -> Option<f64> {
+        match self {
+            ColumnType::Number | ColumnType::Text => parse_number(text),
+            ColumnType::Currency => parse_currency(text),
+            ColumnType::Date => parse_date(text),
+            _ => None,
+        }
+    }
+
+    /// Whole-number value of a Number or Currency cell, read from its digits
+    /// rather than through `value` so that integers beyond an f64's 53 bits of
+    /// precision keep every digit. None for fractions, exponents, zero-padded
+    /// codes and integers outside the i64 range.
+    pub fn integer(self, text: &str) -> Option<i64> {
+        if !self.is_numeric() || is_zero_padded(text) || text.contains(['.', 'e', 'E']) {
+            return None;
+        }
+        let negative = self.value(text)?.is_sign_negative();
+        let digits: String = text.chars().filter(char::is_ascii_digit).collect();
+        if negative {
+            format!("-{}", digits).parse().ok()
+        } else {
+            digits.parse().ok()
+        }
+    }
+
+    /// `value`, if writing it as a float number keeps the cell as it reads:
+    /// None for zero-padded codes, which would lose their zeros, and whole
+    /// numbers past 2^53, which would lose their low digits
+    pub fn exact_value(self, text: &str) -> Option<f64> {
+        if is_zero_padded(text) {
+            return None;
+        }
+        self.value(text).filter(|value| value.fract() != 0.0 || value.abs() < MAX_EXACT_INTEGER)
+    }
+
+    /// Text for a value returned by `value`
+    pub fn format_value(self, value: f64) -> String {
+        match self {
+            ColumnType::Date => format_date(value),
+            _ => format_number(value),
+        }
+    }
+
+    /// A cell written in plain form: numbers without separators or currency
+    /// symbols, ISO dates, TRUE/FALSE. None if the cell doesn't fit the type
+    /// or has no other form; zero-padded numbers have none, as it would drop
+    /// their zeros.
+    pub fn normalize(self, text: &str) -> Option<String> {
+        match self {
+            ColumnType::Number | ColumnType::Currency if is_zero_padded(text) => None,
+            ColumnType::Number | ColumnType::Currency | ColumnType::Date => {
+                self.value(text).map(|value| self.format_value(value))
+            }
+            ColumnType::Boolean => parse_bool(text).map(|b| if b { "TRUE" } else { "FALSE" }.to_string()),
+            ColumnType::Text | ColumnType::Email | ColumnType::Url => None,
+        }
+    }
+}
+
+/// Decimal numbers, optionally with thousands separators ("1,234.50")
+pub fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let unsigned = text.strip_prefix(['-', '+']).unwrap_or(text);
+    let int_part = &unsigned[..unsigned.find(['.', 'e', 'E']).unwrap_or(unsigned.len())];
+    if !int_part.contains(',') {
+        return plain_number(text);
+    }
+    let mut groups = int_part.split(',');
+    let first = groups.next()?;
+    if first.is_empty() || first.len() > 3 || !groups.all(|group| group.len() == 3) {
+        return None;
+    }
+    plain_number(&text.replace(',', ""))
+}
+
+/// Numbers written with leading zeros ("02134", "007"), which are codes
+/// rather than amounts: reading them as numbers would lose the zeros
+pub fn is_zero_padded(text: &str) -> bool {
+    let text = text.trim();
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit() && parse_number(text).is_some()
+}
+
+/// Only plain decimal notation counts as a number; Rust would also accept "inf" and "NaN"
+pub(crate) fn plain_number(text: &str) -> Option<f64> {
+    let plain = text
+        .bytes()
+        .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'-' | b'+' | b'e' | b'E'));
+    if plain && text.bytes().any(|b| b.is_ascii_digit()) {
+        text.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Amounts with an optional currency symbol before or after the number, and
+/// negatives written with a minus sign or in parentheses ("(3.00)")
+pub fn parse_currency(text: &str) -> Option<f64> {
+    let mut text = text.trim();
+    let mut negative = false;
+    if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        negative = true;
+        text = inner.trim();
+    }
+    if let Some(rest) = text.strip_prefix('-') {
+        negative = !negative;
+        text = rest.trim_start();
+    }
+    let text = text
+        .strip_prefix(CURRENCY_SYMBOLS)
+        .or_else(|| text.strip_suffix(CURRENCY_SYMBOLS))
+        .unwrap_or(text)
+        .trim();
+    let value = parse_number(text)?;
+    Some(if negative { -value } else { value })
+}
+
+/// A date, optionally with a time, as a day number with the time as a
+/// fraction. Year-first dates use -, / or . ("2024-03-01"); with the year last,
+/// slashes are read month first ("03/01/2024") and dots or dashes day first
+/// ("01.03.2024"). A time may follow after a space or "T".
+pub fn parse_date(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (date, time) = match text.find([' ', 'T']) {
+        Some(at) => (&text[..at], Some(text[at + 1..].trim())),
+        None => (text, None),
+    };
+
+    let separator = date.chars().find(|c| matches!(c, '-' | '/' | '.'))?;
+    let parts: Vec<&str> = date.split(separator).collect();
+    if parts.len() != 3 || parts.iter().any(|p| p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    let numbers: Vec<i64> = parts.iter().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let (year, month, day) = if parts[0].len() == 4 {
+        (numbers[0], numbers[1], numbers[2])
+    } else if parts[2].len() == 4 && separator == '/' {
+        (numbers[2], numbers[0], numbers[1])
+    } else if parts[2].len() == 4 {
+        (numbers[2], numbers[1], numbers[0])
+    } else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let serial = formula::date_serial(year, month, day);
+    // Rejects days past the end of the month, like 2023-02-29
+    if formula::serial_date(serial) != (year, month, day) {
+        return None;
+    }
+
+    let seconds = match time {
+        Some(time) => {
+            let parts: Vec<&str> = time.split(':').collect();
+            if !(2..=3).contains(&parts.len()) {
+                return None;
+            }
+            let hours: u32 = parts[0].parse().ok()?;
+            let minutes: u32 = parts[1].parse().ok()?;
+            let seconds: f64 = parts.get(2).map_or(Some(0.0), |s| s.parse().ok())?;
+            if hours > 23 || minutes > 59 || !(0.0..60.0).contains(&seconds) {
+                return None;
+            }
+            (hours * 3600 + minutes * 60) as f64 + seconds
+        }
+        None => 0.0,
+    };
+    Some(serial as f64 + seconds / 86400.0)
+}
+
+/// ISO form of a day number, with the time if there is one
+pub fn format_date(serial: f64) -> String {
+    let days = serial.floor();
+    let (year, month, day) = formula::serial_date(days as i64);
+    let seconds = ((serial - days) * 86400.0).round() as i64;
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+    match seconds {
+        0 => date,
+        s if s % 60 == 0 => format!("{} {:02}:{:02}", date, s / 3600, s / 60 % 60),
+        s => format!("{} {:02}:{:02}:{:02}", date, s / 3600, s / 60 % 60, s % 60),
+    }
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" => Some(true),
+        "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn is_email(text: &str) -> bool {
+    let Some((local, domain)) = text.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && !text.contains(char::is_whitespace)
+        && domain.contains('.')
+        && domain.split('.').all(|part| !part.is_empty())
+}
+
+fn is_url(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    let rest = ["https://", "http://", "ftp://", "www."]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix));
+    rest.is_some_and(|rest| !rest.is_empty()) && !text.contains(char::is_whitespace)
+}
+
+/// Type of every column, skipping a header row
+pub fn infer_column_types(sheet: &Sheet, header: bool) -> HashMap<usize, ColumnType> {
+    let first = if header { 1 } else { 0 };
+    (0..sheet.num_cols())
+        .map(|col| (col, ColumnType::infer(sheet.column(col).skip(first))))
+        .collect()
+}
+
+/// Copy of a sheet with typed cells written in plain form. A header row and
+/// cells that don't fit their column's type are left as they are.
+pub fn normalized_sheet(sheet: &Sheet, column_types: &HashMap<usize, ColumnType>, header: bool) -> Sheet {
+    let mut normalized = sheet.clone();
+    let first = if header { 1 } else { 0 };
+    for (&col, &column_type) in column_types {
+        for row in first..sheet.num_rows() {
+            let text = sheet.get(row, col);
+            if let Some(plain) = column_type.normalize(text)
+                && plain != text
+            {
+                normalized.set(row, col, &plain);
+            }
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_the_most_specific_type() {
+        assert_eq!(ColumnType::infer(["1", "2.5", "1,000"].into_iter()), ColumnType::Number);
+        assert_eq!(ColumnType::infer(["$1", "(3.00)", "4 €"].into_iter()), ColumnType::Currency);
+        assert_eq!(ColumnType::infer(["2024-03-01", "03/02/2024"].into_iter()), ColumnType::Date);
+        assert_eq!(ColumnType::infer(["yes", "no", "TRUE"].into_iter()), ColumnType::Boolean);
+        assert_eq!(ColumnType::infer(["a@b.com", "c@d.org"].into_iter()), ColumnType::Email);
+        assert_eq!(ColumnType::infer(["1", "two"].into_iter()), ColumnType::Text);
+        assert_eq!(ColumnType::infer(["", " "].into_iter()), ColumnType::Text);
+    }
+
+    #[test]
+    fn a_few_stray_values_keep_the_type() {
+        let mut cells: Vec<String> = (0..40).map(|n| n.to_string()).collect();
+        cells.push("n/a".into());
+        assert_eq!(ColumnType::infer(cells.iter().map(String::as_str)), ColumnType::Number);
+        assert!(!ColumnType::Number.is_valid("n/a"));
+        assert!(ColumnType::Number.is_valid(" "));
+        cells.extend(["?", "-"].map(String::from));
+        assert_eq!(ColumnType::infer(cells.iter().map(String::as_str)), ColumnType::Text);
+
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["site", "ok"]);
+        sheet.push_row(["https://example.com/a", "yes"]);
+        let types = infer_column_types(&sheet, true);
+        assert_eq!((types[&0], types[&1]), (ColumnType::Url, ColumnType::Boolean));
+        assert_eq!(ColumnType::Boolean.normalize("Yes").as_deref(), Some("TRUE"));
+        assert!(!ColumnType::Url.is_valid("example com"));
+    }
+
+    #[test]
+    fn zero_padded_codes_stay_text() {
+        let zips = ["02134", "90210", "10001", "60614"];
+        assert_eq!(ColumnType::infer(zips.into_iter()), ColumnType::Text);
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["zip", "qty"]);
+        sheet.push_row(["02134", "0"]);
+        sheet.push_row(["90210", "0.5"]);
+        let types = infer_column_types(&sheet, true);
+        assert_eq!((types[&0], types[&1]), (ColumnType::Text, ColumnType::Number));
+
+        assert!(is_zero_padded(" 007") && is_zero_padded("-0123"));
+        assert!(!is_zero_padded("0") && !is_zero_padded("0.25") && !is_zero_padded("01.03.2024"));
+        // Typed as numbers by hand, they still aren't rewritten
+        assert_eq!(ColumnType::Number.normalize("02134"), None);
+        assert_eq!(ColumnType::Number.value("02134"), Some(2134.0));
+    }
+
+    #[test]
+    fn parses_numbers_amounts_and_dates() {
+        assert_eq!(parse_number("1,234.50"), Some(1234.5));
+        assert_eq!(parse_number("inf"), None);
+        assert_eq!(parse_currency("(3.00)"), Some(-3.0));
+        assert_eq!(parse_currency("-$1,200"), Some(-1200.0));
+        assert_eq!(ColumnType::Number.integer("1234567890123456789"), Some(1234567890123456789));
+        assert_eq!(ColumnType::Currency.integer("(-$1,200)"), Some(1200));
+        assert_eq!(ColumnType::Currency.integer("($1,200)"), Some(-1200));
+        assert_eq!(ColumnType::Number.integer("2.0"), None);
+        assert_eq!(ColumnType::Currency.exact_value("$9,007,199,254,740,991"), Some(9_007_199_254_740_991.0));
+        assert_eq!(ColumnType::Number.exact_value("9007199254740993"), None);
+        assert_eq!(ColumnType::Number.exact_value("1e300"), None);
+        assert_eq!(ColumnType::Number.exact_value("0.5e-3"), Some(0.0005));
+        assert_eq!(ColumnType::Number.exact_value("007"), None);
+        assert_eq!(parse_date("03/01/2024"), parse_date("2024-03-01"));
+        assert_eq!(parse_date("01.03.2024"), parse_date("2024-03-01"));
+        assert_eq!(parse_date("2024-02-30"), None);
+        assert_eq!(format_date(parse_date("2024-03-01 12:00").unwrap()), "2024-03-01 12:00");
+    }
+
+    #[test]
+    fn normalizes_cells_that_fit() {
+        assert_eq!(ColumnType::Currency.normalize("$1,200.50").as_deref(), Some("1200.5"));
+        assert_eq!(ColumnType::Date.normalize("03/01/2024").as_deref(), Some("2024-03-01"));
+        assert_eq!(ColumnType::Number.normalize("n/a"), None);
+
+        let mut sheet = Sheet::new(0, 0);
+        sheet.push_row(["when", "cost"]);
+        sheet.push_row(["03/01/2024", "$5"]);
+        let types = infer_column_types(&sheet, true);
+        let plain = normalized_sheet(&sheet, &types, true);
+        assert_eq!(plain.row(0).collect::<Vec<_>>(), ["when", "cost"]);
+        assert_eq!(plain.row(1).collect::<Vec<_>>(), ["2024-03-01", "5"]);
+    }
+
+    #[test]
+    fn finds_types_by_name() {
+        assert_eq!(ColumnType::from_name("url"), Some(ColumnType::Url));
+        assert_eq!(ColumnType::from_name(" Number "), Some(ColumnType::Number));
+        assert_eq!(ColumnType::from_name("integer"), None);
+    }
+}
@@ -0,0 +1,317 @@
+//! Excel workbooks (.xlsx).
+//!
+//! Reading takes one worksheet into a [`Sheet`] of text, the way the cells
+//! would look in a CSV export: formulas are read as the results Excel cached
+//! when the file was last saved, numbers as plain decimals and dates in ISO
+//! form, so column type inference treats them like any other file. Cells keep
+//! their position, so a sheet whose data starts at C3 still starts at C3.
+//!
+//! Writing does the reverse for the columns that have a type: numbers, amounts
+//! and dates are written as Excel values with a number format, booleans as
+//! booleans, and formulas as formulas along with their current result.
+//! Everything else, including cells that don't fit their column's type,
+//! zero-padded codes and whole numbers too large for Excel to hold exactly,
+//! is written as text.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use calamine::{Data, Reader, SheetType, Xlsx};
+use rust_xlsxwriter::{Format, Formula, Workbook as WorkbookWriter, Worksheet};
+
+use crate::calc::Calculator;
+use crate::formula::{self, FormulaError, Value};
+use crate::sheet::Sheet;
+use crate::types::ColumnType;
+
+/// Why a workbook couldn't be read
+pub type ReadError = calamine::XlsxError;
+
+/// Why a workbook couldn't be written
+pub type WriteError = rust_xlsxwriter::XlsxError;
+
+/// File extension of the workbooks this module reads and writes
+pub const EXTENSION: &str = "xlsx";
+
+// Largest sheet Excel can hold
+const MAX_ROWS: usize = 1_048_576;
+const MAX_COLS: usize = 16_384;
+
+/// Whether a file name has a workbook extension, ignoring case
+pub fn is_workbook_path(path: impl AsRef<std::path::Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(EXTENSION))
+}
+
+/// A workbook opened for reading
+pub struct Workbook {
+    reader: Xlsx<Cursor<Vec<u8>>>,
+}
+
+impl Workbook {
+    /// Open a workbook from the bytes of an .xlsx file
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, ReadError> {
+        Ok(Self { reader: Xlsx::new(Cursor::new(bytes))? })
+    }
+
+    /// Names of the worksheets in workbook order. Chart sheets and macro
+    /// sheets are left out since they have no cells to show.
+    pub fn sheet_names(&self) -> Vec<String> {
+        self.reader
+            .sheets_metadata()
+            .iter()
+            .filter(|sheet| sheet.typ == SheetType::WorkSheet)
+            .map(|sheet| sheet.name.clone())
+            .collect()
+    }
+
+    /// The cells of a worksheet as text
+    pub fn read_sheet(&mut self, name: &str) -> Result<Sheet, ReadError> {
+        let range = self.reader.worksheet_range(name)?;
+        let Some((first_row, first_col)) = range.start() else {
+            return Ok(Sheet::new(0, 0));
+        };
+        let (first_row, first_col) = (first_row as usize, first_col as usize);
+
+        let mut sheet = Sheet::new(0, first_col + range.width());
+        for _ in 0..first_row {
+            sheet.push_row(std::iter::empty::<&str>());
+        }
+        let leading = vec![String::new(); first_col];
+        for row in range.rows() {
+            sheet.push_row(leading.iter().cloned().chain(row.iter().map(cell_text)));
+        }
+        sheet.shrink_to_fit();
+        Ok(sheet)
+    }
+}
+
+// Text of a cell as read from the file
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(text) | Data::DateTimeIso(text) | Data::DurationIso(text) => text.clone(),
+        Data::Int(n) => n.to_string(),
+        Data::Float(n) => formula::format_number(*n),
+        Data::Bool(true) => "TRUE".to_string(),
+        Data::Bool(false) => "FALSE".to_string(),
+        Data::Error(error) => error.to_string(),
+        Data::DateTime(datetime) if datetime.is_duration() => {
+            let seconds = (datetime.as_f64() * 86400.0).round() as i64;
+            format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+        }
+        Data::DateTime(datetime) => {
+            let (year, month, day, hour, minute, second, _) = datetime.to_ymd_hms_milli();
+            let time = match (hour, minute, second) {
+                (0, 0, 0) => String::new(),
+                (h, m, 0) => format!("{:02}:{:02}", h, m),
+                (h, m, s) => format!("{:02}:{:02}:{:02}", h, m, s),
+            };
+            // Times without a date are stored on day 0
+            if datetime.as_f64() < 1.0 && !time.is_empty() {
+                time
+            } else if time.is_empty() {
+                format!("{:04}-{:02}-{:02}", year, month, day)
+            } else {
+                format!("{:04}-{:02}-{:02} {}", year, month, day, time)
+            }
+        }
+    }
+}
+
+/// How [`write_sheet`] lays out the worksheet
+#[derive(Debug, Clone, Default)]
+pub struct XlsxOptions {
+    /// Name of the worksheet; "Sheet1" if empty
+    pub sheet_name: String,
+    /// Write formula results instead of the formulas
+    pub values: bool,
+    /// The first row is a header: it is written as bold text and frozen so
+    /// it stays in view while scrolling
+    pub header: bool,
+    /// Width of each column in pixels. Columns past the end keep Excel's
+    /// default width.
+    pub column_widths: Vec<f32>,
+}
+
+/// A workbook holding the sheet as its only worksheet
+pub fn write_sheet(
+    sheet: &Sheet,
+    calc: &mut Calculator,
+    column_types: &HashMap<usize, ColumnType>,
+    options: &XlsxOptions,
+) -> Result<Vec<u8>, WriteError> {
+    if sheet.num_rows() > MAX_ROWS || sheet.num_cols() > MAX_COLS {
+        return Err(WriteError::RowColumnLimitError);
+    }
+
+    let mut workbook = WorkbookWriter::new();
+    let worksheet = workbook.add_worksheet();
+    if !options.sheet_name.is_empty() {
+        worksheet.set_name(&options.sheet_name)?;
+    }
+    for (col, &width) in options.column_widths.iter().enumerate().take(sheet.num_cols()) {
+        worksheet.set_column_width_pixels(col as u16, width.round() as u32)?;
+    }
+
+    let formats = Formats {
+        header: Format::new().set_bold(),
+        currency: Format::new().set_num_format("#,##0.00"),
+        date: Format::new().set_num_format("yyyy-mm-dd"),
+        datetime: Format::new().set_num_format("yyyy-mm-dd hh:mm"),
+    };
+    let header = options.header && !sheet.is_empty();
+    if header {
+        worksheet.set_freeze_panes(1, 0)?;
+    }
+
+    for col in 0..sheet.num_cols() {
+        let column_type = column_types.get(&col).copied().unwrap_or(ColumnType::Text);
+        for row in 0..sheet.num_rows() {
+            let text = sheet.get(row, col);
+            if text.is_empty() {
+                continue;
+            }
+            let (xl_row, xl_col) = (row as u32, col as u16);
+            if header && row == 0 {
+                worksheet.write_string_with_format(xl_row, xl_col, text, &formats.header)?;
+            } else if formula::is_formula(text) {
+                let value = calc.value(sheet, (row, col));
+                match value {
+                    // Text Excel couldn't read as a formula either
+                    Value::Error(FormulaError::Syntax) => {
+                        worksheet.write_string(xl_row, xl_col, text)?;
+                    }
+                    _ if options.values => {
+                        write_typed(worksheet, xl_row, xl_col, &value.to_string(), column_type, &formats)?;
+                    }
+                    _ => {
+                        worksheet.write_formula(xl_row, xl_col, Formula::new(text).set_result(value.to_string()))?;
+                    }
+                }
+            } else {
+                write_typed(worksheet, xl_row, xl_col, text, column_type, &formats)?;
+            }
+        }
+    }
+
+    workbook.save_to_buffer()
+}
+
+struct Formats {
+    header: Format,
+    currency: Format,
+    date: Format,
+    datetime: Format,
+}
+
+// Write a cell as the value its column's type reads from it, or as text
+fn write_typed(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    text: &str,
+    column_type: ColumnType,
+    formats: &Formats,
+) -> Result<(), WriteError> {
+    let format = match column_type {
+        ColumnType::Number => None,
+        ColumnType::Currency => Some(&formats.currency),
+        ColumnType::Date => match column_type.value(text) {
+            Some(n) if n.fract() != 0.0 => Some(&formats.datetime),
+            _ => Some(&formats.date),
+        },
+        ColumnType::Boolean => {
+            match column_type.normalize(text).as_deref() {
+                Some("TRUE") => worksheet.write_boolean(row, col, true)?,
+                Some("FALSE") => worksheet.write_boolean(row, col, false)?,
+                _ => worksheet.write_string(row, col, text)?,
+            };
+            return Ok(());
+        }
+        _ => {
+            worksheet.write_string(row, col, text)?;
+            return Ok(());
+        }
+    };
+    // Excel numbers are f64s, which would drop the zeros of zero-padded
+    // codes and the low digits of whole numbers past 2^53
+    let value = column_type.exact_value(text);
+    match (value, format) {
+        (Some(n), Some(format)) => worksheet.write_number_with_format(row, col, n, format)?,
+        (Some(n), None) => worksheet.write_number(row, col, n)?,
+        (None, _) => worksheet.write_string(row, col, text)?,
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(sheet: &Sheet, column_types: &HashMap<usize, ColumnType>, options: &XlsxOptions) -> Sheet {
+        let mut calc = Calculator::default();
+        calc.rebuild(sheet);
+        let bytes = write_sheet(sheet, &mut calc, column_types, options).unwrap();
+        let mut workbook = Workbook::from_bytes(bytes).unwrap();
+        let name = workbook.sheet_names().remove(0);
+        workbook.read_sheet(&name).unwrap()
+    }
+
+    #[test]
+    fn round_trips_typed_values() {
+        let original = Sheet::from_rows([
+            ["item", "price", "when", "ok", "code"],
+            ["a", "$1,200.50", "03/01/2024", "yes", "007"],
+            ["b", "(3)", "2024-03-02 12:30", "no", "010"],
+        ]);
+        let types = HashMap::from([
+            (1, ColumnType::Currency),
+            (2, ColumnType::Date),
+            (3, ColumnType::Boolean),
+        ]);
+        let read = round_trip(&original, &types, &XlsxOptions { header: true, ..Default::default() });
+        assert_eq!(read.row(0).collect::<Vec<_>>(), ["item", "price", "when", "ok", "code"]);
+        assert_eq!(read.row(1).collect::<Vec<_>>(), ["a", "1200.5", "2024-03-01", "TRUE", "007"]);
+        assert_eq!(read.row(2).collect::<Vec<_>>(), ["b", "-3", "2024-03-02 12:30", "FALSE", "010"]);
+    }
+
+    #[test]
+    fn keeps_large_integers_and_zero_padded_codes_exact() {
+        let original = Sheet::from_rows([["1234567890123456789", "02134"], ["42", "90210"]]);
+        let types = HashMap::from([(0, ColumnType::Number), (1, ColumnType::Number)]);
+        let read = round_trip(&original, &types, &XlsxOptions::default());
+        assert_eq!(read.rows().collect::<Vec<_>>(), original.rows().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reads_formulas_as_their_results() {
+        let original = Sheet::from_rows([["2", "3", "=A1*B1"], ["=1+", "", "=SUM(A1:C1)"]]);
+        let read = round_trip(&original, &HashMap::new(), &XlsxOptions::default());
+        assert_eq!(read.row(0).collect::<Vec<_>>(), ["2", "3", "6"]);
+        assert_eq!(read.row(1).collect::<Vec<_>>(), ["=1+", "", "11"]);
+    }
+
+    #[test]
+    fn keeps_cells_in_place_and_names_the_sheet() {
+        let original = Sheet::from_rows([["", "", ""], ["", "", "x"]]);
+        let mut calc = Calculator::default();
+        let options = XlsxOptions { sheet_name: "Data".to_string(), column_widths: vec![40.0, 120.0], ..Default::default() };
+        let bytes = write_sheet(&original, &mut calc, &HashMap::new(), &options).unwrap();
+        let mut workbook = Workbook::from_bytes(bytes).unwrap();
+        assert_eq!(workbook.sheet_names(), ["Data"]);
+        let read = workbook.read_sheet("Data").unwrap();
+        assert_eq!((read.num_rows(), read.num_cols()), (2, 3));
+        assert_eq!(read.get(1, 2), "x");
+    }
+
+    #[test]
+    fn recognises_workbook_paths() {
+        assert!(is_workbook_path("Report.XLSX"));
+        assert!(is_workbook_path("dir/book.xlsx"));
+        assert!(!is_workbook_path("data.csv"));
+        assert!(!is_workbook_path("xlsx"));
+    }
+}
@@ -0,0 +1,877 @@
+// Command-line interface.
+//
+// `csv-app <command> FILE ...` works on a file without opening a window, for
+// scripts and CI. Files are read the way the Open dialog would read them
+// (detected encoding and dialect, import problems recorded rather than fatal)
+// and written the way Save does, and sorting, filtering and type detection are
+// the same code the app runs. Anything that isn't a command is taken as a file
+// to open in the window.
+//
+// Exit codes: 0 on success, 1 when `validate` finds problems, 2 for bad
+// arguments or files that can't be read or written.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+
+use gridview_core::columnar::{ColumnarFormat, ColumnarOptions};
+use gridview_core::dialect::{self, CsvDialect, LineTerminator, QuoteStyle};
+use gridview_core::filter::{self, ColumnFilter};
+use gridview_core::formula;
+use gridview_core::search::Matcher;
+use gridview_core::sort::{self, SortKey, SortType};
+use gridview_core::json::{self, JsonLayout, JsonOptions};
+use gridview_core::xlsx::{self, Workbook, XlsxOptions};
+use gridview_core::{ColumnType, Document, SaveOptions, Sheet, TextEncoding};
+
+// Invalid cells listed per column by `validate` before the rest are counted
+const MAX_REPORTED_PER_COLUMN: usize = 100;
+
+const USAGE: &str = "\
+Usage:
+  csv-app [FILE]                       Open the window, optionally with FILE
+  csv-app convert INPUT OUTPUT [options]
+  csv-app sort INPUT --by COLUMN[:SPEC] ... [options]
+  csv-app filter INPUT [filters] [options]
+  csv-app stats INPUT [--csv]
+  csv-app validate INPUT [--type COLUMN=TYPE] [--required COLUMN]
+
+Columns are given by header name or by letter (A, B, ..., AA).
+
+Reading:
+  --delimiter D        Input delimiter: comma, semicolon, tab, pipe, colon, space or one character
+  --encoding NAME      Input encoding, e.g. utf-8, utf-16le, windows-1252, latin1
+  --header             Treat the first row as a header
+  --no-header          Treat the first row as data
+  --sheet NAME         Worksheet to read from an .xlsx workbook (default: the first)
+
+.json, .ndjson, .parquet and .arrow files are read as records, with a header
+row of their keys or field names.
+
+Writing (convert, sort, filter):
+  -o, --output FILE    Write to FILE instead of standard output; an .xlsx
+                       FILE is written as an Excel workbook, a .json FILE as
+                       an array of records, an .ndjson or .jsonl FILE as
+                       one record per line and a .parquet or .arrow FILE as
+                       columns typed by the column types
+  --out-delimiter D    Delimiter to write (default: the input's, or tab for .tsv)
+  --out-encoding NAME  Encoding to write (default: the input's)
+  --line-ending E      lf, crlf or cr (default: the input's)
+  --quote STYLE        necessary, always or non-numeric
+  --values             Write formula results instead of formulas
+  --plain              Write typed values in plain form (1234.5, 2024-03-01, TRUE)
+  --nest               Write dotted column names (address.city) as nested JSON objects
+  --type COLUMN=TYPE   Write COLUMN as TYPE instead of its detected type
+
+Sorting:
+  --by COLUMN[:asc|desc][:natural|number|date|nocase|locale][:empties-first|empties-last]
+                       Sort key; repeat for tie-breakers. The type defaults to the column's.
+
+Filtering (every filter must match):
+  --equals COLUMN=VALUE       Value is one of; repeat for more values
+  --contains COLUMN=TEXT      Text contains, ignoring case
+  --starts-with COLUMN=TEXT   Text starts with, ignoring case
+  --min COLUMN=VALUE          Value at least, read as the column's type
+  --max COLUMN=VALUE          Value at most
+  --empty COLUMN              Cell is empty
+  --non-empty COLUMN          Cell is not empty
+  --search PATTERN            Some cell in the row matches
+  --regex, --case-sensitive, --whole-cell
+                              How --search matches
+
+Validation:
+  --type COLUMN=TYPE   Check COLUMN as TYPE instead of its detected type
+                       (text, number, currency, date, boolean, email, url)
+  --required COLUMN    Report empty cells in COLUMN
+
+Exit status is 0 on success, 1 if validate finds problems and 2 on errors.";
+
+const COMMANDS: &[&str] = &["convert", "sort", "filter", "stats", "validate", "help", "-h", "--help"];
+
+// Flags that are followed by a value
+const VALUE_FLAGS: &[&str] = &[
+    "-o",
+    "--output",
+    "--delimiter",
+    "--encoding",
+    "--sheet",
+    "--out-delimiter",
+    "--out-encoding",
+    "--line-ending",
+    "--quote",
+    "--by",
+    "--equals",
+    "--contains",
+    "--starts-with",
+    "--min",
+    "--max",
+    "--empty",
+    "--non-empty",
+    "--search",
+    "--type",
+    "--required",
+];
+
+const READ_FLAGS: &[&str] = &["--delimiter", "--encoding", "--header", "--no-header", "--sheet"];
+const WRITE_FLAGS: &[&str] = &[
+    "-o",
+    "--output",
+    "--out-delimiter",
+    "--out-encoding",
+    "--line-ending",
+    "--quote",
+    "--values",
+    "--plain",
+    "--nest",
+    "--type",
+];
+const FILTER_FLAGS: &[&str] = &[
+    "--equals",
+    "--contains",
+    "--starts-with",
+    "--min",
+    "--max",
+    "--empty",
+    "--non-empty",
+    "--search",
+    "--regex",
+    "--case-sensitive",
+    "--whole-cell",
+];
+
+// Whether a first argument names a command rather than a file to open
+pub fn is_command(arg: &str) -> bool {
+    COMMANDS.contains(&arg)
+}
+
+// Run a command and return the process exit code
+pub fn run(args: &[String]) -> i32 {
+    let result = match args.split_first() {
+        Some((command, rest)) => match command.as_str() {
+            "convert" => convert(rest),
+            "sort" => sort(rest),
+            "filter" => filter(rest),
+            "stats" => stats(rest),
+            "validate" => validate(rest),
+            _ => print_out(&format!("{}\n", USAGE)).map(|_| 0),
+        },
+        None => Err("no command given".to_string()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("csv-app: {}", e);
+            eprintln!("Run 'csv-app --help' for usage.");
+            2
+        }
+    }
+}
+
+// Arguments split into positionals and flags, in the order given
+struct Args {
+    positional: Vec<String>,
+    flags: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    // Split the arguments, rejecting flags the command doesn't take.
+    // Values can follow their flag as the next argument or after '='.
+    fn parse(args: &[String], allowed: &[&[&str]]) -> Result<Self, String> {
+        let mut parsed = Args { positional: Vec::new(), flags: Vec::new() };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                parsed.positional.push(arg.clone());
+                continue;
+            }
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            if !allowed.iter().any(|flags| flags.contains(&flag)) {
+                return Err(format!("unknown option '{}'", flag));
+            }
+            let value = if VALUE_FLAGS.contains(&flag) {
+                match inline.or_else(|| args.next().cloned()) {
+                    Some(value) => Some(value),
+                    None => return Err(format!("'{}' needs a value", flag)),
+                }
+            } else if inline.is_some() {
+                return Err(format!("'{}' doesn't take a value", flag));
+            } else {
+                None
+            };
+            parsed.flags.push((flag.to_string(), value));
+        }
+        Ok(parsed)
+    }
+
+    fn has(&self, flag: &str) -> bool {
+        self.flags.iter().any(|(name, _)| name == flag)
+    }
+
+    // Last value given for any of the names
+    fn value(&self, names: &[&str]) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    // Which of several opposing switches was given last
+    fn last_switch(&self, names: &[&str]) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .map(|(name, _)| name.as_str())
+    }
+
+    // Every value given for a flag, in order
+    fn values<'a>(&'a self, flag: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.flags
+            .iter()
+            .filter(move |(name, _)| name == flag)
+            .filter_map(|(_, value)| value.as_deref())
+    }
+
+    // The input file, with exactly `count` positionals expected
+    fn positionals(&self, count: usize, names: &str) -> Result<&[String], String> {
+        if self.positional.len() != count {
+            return Err(format!("expected {}", names));
+        }
+        Ok(&self.positional)
+    }
+}
+
+// Read a file the way the Open dialog would, with the reading options
+// overriding what was detected
+fn open(path: &str, args: &Args) -> Result<Document, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+    if xlsx::is_workbook_path(path) {
+        return open_workbook(path, bytes, args);
+    }
+    if json::is_json_path(path) {
+        return open_json(path, &bytes, args);
+    }
+    if let Some(format) = ColumnarFormat::for_path(path) {
+        let mut doc = Document::from_columnar(bytes, format).map_err(|e| format!("can't read {}: {}", path, e))?;
+        override_header(&mut doc, args);
+        return Ok(doc);
+    }
+    let (mut dialect, mut encoding) = gridview_core::document::detect_format(&bytes);
+    if let Some(name) = args.value(&["--encoding"]) {
+        encoding = parse_encoding(name)?;
+        (_, dialect) = dialect::sniff_sample(&bytes, &encoding);
+    }
+    if let Some(delimiter) = args.value(&["--delimiter"]) {
+        dialect.delimiter = parse_delimiter(delimiter)?;
+    }
+    if let Some(switch) = args.last_switch(&["--header", "--no-header"]) {
+        dialect.has_headers = switch == "--header";
+    }
+
+    let mut doc = Document::parse(&bytes, dialect, encoding).map_err(|e| format!("can't read {}: {}", path, e))?;
+    // Results are worked out before rows move, while references still point
+    // where they should
+    if args.has("--values") && doc.calc.has_formulas() {
+        doc.sheet = doc.calc.computed_sheet(&doc.sheet);
+        doc.calc = Default::default();
+    }
+    Ok(doc)
+}
+
+// Read a worksheet of a workbook. Formulas come in as the results Excel saved.
+fn open_workbook(path: &str, bytes: Vec<u8>, args: &Args) -> Result<Document, String> {
+    let mut workbook = Workbook::from_bytes(bytes).map_err(|e| format!("can't read {}: {}", path, e))?;
+    let sheets = workbook.sheet_names();
+    let name = match args.value(&["--sheet"]) {
+        Some(name) if sheets.iter().any(|sheet| sheet == name) => name.to_string(),
+        Some(name) => return Err(format!("{} has no worksheet named '{}'", path, name)),
+        None => sheets.into_iter().next().ok_or_else(|| format!("{} has no worksheets", path))?,
+    };
+    let sheet = workbook.read_sheet(&name).map_err(|e| format!("can't read {}: {}", path, e))?;
+
+    let mut doc = Document::from_sheet(sheet);
+    override_header(&mut doc, args);
+    Ok(doc)
+}
+
+// Read JSON records, with a header row of their keys when they are objects
+fn open_json(path: &str, bytes: &[u8], args: &Args) -> Result<Document, String> {
+    let mut doc = Document::from_json(bytes).map_err(|e| format!("can't read {}: {}", path, e))?;
+    override_header(&mut doc, args);
+    Ok(doc)
+}
+
+// --header and --no-header replace the guess made when reading
+fn override_header(doc: &mut Document, args: &Args) {
+    if let Some(switch) = args.last_switch(&["--header", "--no-header"]) {
+        doc.dialect.has_headers = switch == "--header";
+        doc.detect_column_types(doc.dialect.has_headers);
+    }
+}
+
+fn has_header(doc: &Document) -> bool {
+    doc.dialect.has_headers && !doc.sheet.is_empty()
+}
+
+// First data row
+fn first_row(doc: &Document) -> usize {
+    if has_header(doc) { 1 } else { 0 }
+}
+
+fn column(doc: &Document, name: &str) -> Result<usize, String> {
+    doc.find_column(name, has_header(doc))
+        .ok_or_else(|| format!("no column named '{}'", name))
+}
+
+// Types given with --type COLUMN=TYPE
+fn column_types(doc: &Document, args: &Args) -> Result<Vec<(usize, ColumnType)>, String> {
+    args.values("--type")
+        .map(|assignment| {
+            let (name, type_name) = split_assignment("--type", assignment)?;
+            let column_type = ColumnType::from_name(type_name).ok_or_else(|| format!("unknown type '{}'", type_name))?;
+            Ok((column(doc, name)?, column_type))
+        })
+        .collect()
+}
+
+// "B3" for messages
+fn cell_name(row: usize, col: usize) -> String {
+    format!("{}{}", formula::column_letters(col), row + 1)
+}
+
+// "B (Price)" for messages
+fn column_name(doc: &Document, col: usize) -> String {
+    let letter = formula::column_letters(col);
+    match has_header(doc) {
+        true if !doc.sheet.get(0, col).is_empty() => format!("{} ({})", letter, doc.sheet.get(0, col)),
+        _ => letter,
+    }
+}
+
+// Write rows of a document, as Save does
+fn write(doc: &Document, rows: impl IntoIterator<Item = usize>, args: &Args) -> Result<(), String> {
+    let output = args.value(&["-o", "--output"]);
+    let mut dialect = doc.dialect.clone();
+    match args.value(&["--out-delimiter"]) {
+        Some(delimiter) => dialect.delimiter = parse_delimiter(delimiter)?,
+        None if output.is_some_and(|path| has_extension(path, "tsv")) => dialect.delimiter = b'\t',
+        None => {}
+    }
+    if let Some(ending) = args.value(&["--line-ending"]) {
+        dialect.terminator = match ending.to_ascii_lowercase().as_str() {
+            "lf" => LineTerminator::Lf,
+            "crlf" => LineTerminator::CrLf,
+            "cr" => LineTerminator::Cr,
+            _ => return Err(format!("unknown line ending '{}'", ending)),
+        };
+    }
+    if let Some(style) = args.value(&["--quote"]) {
+        dialect.quote_style = match style.to_ascii_lowercase().as_str() {
+            "necessary" => QuoteStyle::Necessary,
+            "always" => QuoteStyle::Always,
+            "non-numeric" => QuoteStyle::NonNumeric,
+            _ => return Err(format!("unknown quote style '{}'", style)),
+        };
+    }
+    let encoding = match args.value(&["--out-encoding"]) {
+        Some(name) => parse_encoding(name)?,
+        None => doc.encoding,
+    };
+
+    // Formula results were already computed at open for --values
+    let mut sheet = Sheet::new(0, doc.sheet.num_cols());
+    for row in rows {
+        sheet.push_row(doc.sheet.row(row));
+    }
+    let mut out = Document {
+        sheet,
+        dialect,
+        encoding,
+        column_types: doc.column_types.clone(),
+        ..Default::default()
+    };
+    out.column_types.extend(column_types(doc, args)?);
+    let json_layout = output.and_then(JsonLayout::for_path);
+    let columnar_format = output.and_then(ColumnarFormat::for_path);
+    let bytes = if output.is_some_and(xlsx::is_workbook_path) {
+        let options = XlsxOptions { header: has_header(doc), ..Default::default() };
+        out.to_xlsx(&options).map_err(|e| e.to_string())?
+    } else if let Some(layout) = json_layout {
+        out.to_json(&JsonOptions { layout, header: has_header(doc), nest: args.has("--nest") })
+    } else if let Some(format) = columnar_format {
+        out.to_columnar(&ColumnarOptions { format, header: has_header(doc) }).map_err(|e| e.to_string())?
+    } else {
+        let options = SaveOptions { values: false, plain: args.has("--plain"), header: has_header(doc) };
+        let (bytes, unmappable) = out.to_bytes(options).map_err(|e| e.to_string())?;
+        if unmappable > 0 {
+            eprintln!(
+                "Warning: {} character(s) can't be represented in {} and were written as '?'",
+                unmappable,
+                encoding.charset.label()
+            );
+        }
+        bytes
+    };
+    match output {
+        Some(path) if path != "-" => std::fs::write(path, bytes).map_err(|e| format!("can't write {}: {}", path, e)),
+        _ => print_bytes(&bytes),
+    }
+}
+
+fn has_extension(path: &str, extension: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+fn parse_delimiter(text: &str) -> Result<u8, String> {
+    dialect::parse_delimiter(text).ok_or_else(|| format!("unknown delimiter '{}'", text))
+}
+
+fn parse_encoding(name: &str) -> Result<TextEncoding, String> {
+    TextEncoding::from_name(name).ok_or_else(|| format!("unknown encoding '{}'", name))
+}
+
+// Write to standard output. A reader that stops early, like `head`, is not
+// an error.
+fn print_bytes(bytes: &[u8]) -> Result<(), String> {
+    match std::io::stdout().lock().write_all(bytes) {
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn print_out(text: &str) -> Result<(), String> {
+    print_bytes(text.as_bytes())
+}
+
+// "COLUMN=VALUE"
+fn split_assignment<'a>(flag: &str, text: &'a str) -> Result<(&'a str, &'a str), String> {
+    text.split_once('=')
+        .ok_or_else(|| format!("'{}' expects COLUMN=VALUE, got '{}'", flag, text))
+}
+
+fn convert(args: &[String]) -> Result<i32, String> {
+    let mut args = Args::parse(args, &[READ_FLAGS, WRITE_FLAGS])?;
+    let paths = args.positionals(2, "INPUT and OUTPUT files")?.to_vec();
+    // The output can also be given positionally
+    args.flags.push(("--output".to_string(), Some(paths[1].clone())));
+    let doc = open(&paths[0], &args)?;
+    write(&doc, 0..doc.sheet.num_rows(), &args)?;
+    Ok(0)
+}
+
+// Sort key from "COLUMN[:asc|desc][:TYPE][:empties-first|empties-last]".
+// Options are read off the end so column names may contain ':'.
+fn parse_sort_key(doc: &Document, spec: &str) -> Result<SortKey, String> {
+    let mut parts: Vec<&str> = spec.split(':').collect();
+    let mut ascending = true;
+    let mut sort_type = None;
+    let mut empties_last = None;
+    while parts.len() > 1 {
+        let option = parts[parts.len() - 1].to_ascii_lowercase();
+        match option.as_str() {
+            "asc" => ascending = true,
+            "desc" => ascending = false,
+            "natural" | "text" => sort_type = Some(SortType::Natural),
+            "number" => sort_type = Some(SortType::Number),
+            "date" => sort_type = Some(SortType::Date),
+            "nocase" => sort_type = Some(SortType::CaseInsensitive),
+            "locale" => sort_type = Some(SortType::Locale),
+            "empties-first" => empties_last = Some(false),
+            "empties-last" => empties_last = Some(true),
+            _ => break,
+        }
+        parts.pop();
+    }
+    let col = column(doc, &parts.join(":"))?;
+    Ok(SortKey {
+        col,
+        ascending,
+        sort_type: sort_type.unwrap_or_else(|| SortType::for_column(doc.column_type(col))),
+        empties_last: empties_last.unwrap_or(true),
+    })
+}
+
+fn sort(args: &[String]) -> Result<i32, String> {
+    let args = Args::parse(args, &[READ_FLAGS, WRITE_FLAGS, &["--by"]])?;
+    let path = args.positionals(1, "one INPUT file")?[0].clone();
+    let mut doc = open(&path, &args)?;
+    let keys = args
+        .values("--by")
+        .map(|spec| parse_sort_key(&doc, spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err("sort needs at least one --by COLUMN".to_string());
+    }
+
+    let header = has_header(&doc);
+    let order = sort::sort_order(&doc.sheet, &mut doc.calc, &keys, header);
+    write(&doc, order, &args)?;
+    Ok(0)
+}
+
+// The column filters given on the command line. Repeated --equals for a
+// column add values, and --min and --max for a column make one range.
+fn parse_filters(doc: &Document, args: &Args) -> Result<Vec<(usize, ColumnFilter)>, String> {
+    let mut filters: Vec<(usize, ColumnFilter)> = Vec::new();
+    for (flag, value) in &args.flags {
+        let Some(value) = value else {
+            continue;
+        };
+        let filter = match flag.as_str() {
+            "--empty" => (column(doc, value)?, ColumnFilter::Empty),
+            "--non-empty" => (column(doc, value)?, ColumnFilter::NonEmpty),
+            "--equals" | "--contains" | "--starts-with" | "--min" | "--max" => {
+                let (name, text) = split_assignment(flag, value)?;
+                let col = column(doc, name)?;
+                match flag.as_str() {
+                    "--equals" => {
+                        if let Some((_, ColumnFilter::OneOf(values))) = filters
+                            .iter_mut()
+                            .find(|(c, filter)| *c == col && matches!(filter, ColumnFilter::OneOf(_)))
+                        {
+                            values.insert(text.to_string());
+                            continue;
+                        }
+                        (col, ColumnFilter::OneOf(HashSet::from([text.to_string()])))
+                    }
+                    "--contains" => (col, ColumnFilter::contains(text)),
+                    "--starts-with" => (col, ColumnFilter::starts_with(text)),
+                    _ => {
+                        let column_type = doc.column_type(col);
+                        let bound = column_type.value(text).ok_or_else(|| {
+                            format!("'{}' is not a valid {} for {}", text, column_type.label(), flag)
+                        })?;
+                        let existing = filters
+                            .iter_mut()
+                            .find(|(c, filter)| *c == col && matches!(filter, ColumnFilter::Between { .. }));
+                        if let Some((_, ColumnFilter::Between { min, max, .. })) = existing {
+                            if flag == "--min" { *min = Some(bound) } else { *max = Some(bound) }
+                            continue;
+                        }
+                        let (min, max) = if flag == "--min" { (Some(bound), None) } else { (None, Some(bound)) };
+                        (col, ColumnFilter::Between { min, max, column_type })
+                    }
+                }
+            }
+            _ => continue,
+        };
+        filters.push(filter);
+    }
+    Ok(filters)
+}
+
+fn filter(args: &[String]) -> Result<i32, String> {
+    let args = Args::parse(args, &[READ_FLAGS, WRITE_FLAGS, FILTER_FLAGS])?;
+    let path = args.positionals(1, "one INPUT file")?[0].clone();
+    let mut doc = open(&path, &args)?;
+    let filters = parse_filters(&doc, &args)?;
+    let matcher = match args.value(&["--search"]) {
+        Some(pattern) => Some(
+            Matcher::new(pattern, args.has("--case-sensitive"), args.has("--regex"), args.has("--whole-cell"))
+                .map_err(|e| format!("invalid pattern: {}", e))?,
+        ),
+        None => None,
+    };
+
+    let first = first_row(&doc);
+    let mut rows: Vec<usize> = (0..first).collect();
+    for row in first..doc.sheet.num_rows() {
+        if !filter::row_matches(filters.iter().map(|(col, filter)| (*col, filter)), &doc.sheet, &mut doc.calc, row) {
+            continue;
+        }
+        if let Some(matcher) = &matcher
+            && !(0..doc.sheet.num_cols()).any(|col| matcher.is_match(&doc.calc.text(&doc.sheet, row, col)))
+        {
+            continue;
+        }
+        rows.push(row);
+    }
+    write(&doc, rows, &args)?;
+    Ok(0)
+}
+
+// Summary of one column for `stats`
+struct ColumnStats {
+    name: String,
+    column_type: ColumnType,
+    filled: usize,
+    empty: usize,
+    distinct: usize,
+    invalid: usize,
+    min: Option<f64>,
+    max: Option<f64>,
+    mean: Option<f64>,
+}
+
+impl ColumnStats {
+    fn new(doc: &mut Document, col: usize) -> Self {
+        let column_type = doc.column_type(col);
+        let mut stats = ColumnStats {
+            name: column_name(doc, col),
+            column_type,
+            filled: 0,
+            empty: 0,
+            distinct: 0,
+            invalid: 0,
+            min: None,
+            max: None,
+            mean: None,
+        };
+        let mut distinct = HashSet::new();
+        let mut sum = 0.0;
+        let mut count = 0;
+        for row in first_row(doc)..doc.sheet.num_rows() {
+            let text = doc.calc.text(&doc.sheet, row, col);
+            if text.trim().is_empty() {
+                stats.empty += 1;
+                continue;
+            }
+            stats.filled += 1;
+            if !column_type.is_valid(&text) {
+                stats.invalid += 1;
+            }
+            let value = match column_type {
+                ColumnType::Text | ColumnType::Boolean | ColumnType::Email | ColumnType::Url => None,
+                _ => column_type.value(&text),
+            };
+            if let Some(value) = value {
+                stats.min = Some(stats.min.map_or(value, |min| min.min(value)));
+                stats.max = Some(stats.max.map_or(value, |max| max.max(value)));
+                sum += value;
+                count += 1;
+            }
+            distinct.insert(text.into_owned());
+        }
+        stats.distinct = distinct.len();
+        if column_type.is_numeric() && count > 0 {
+            stats.mean = Some(sum / count as f64);
+        }
+        stats
+    }
+
+    fn cells(&self) -> Vec<String> {
+        let format = |value: Option<f64>| value.map(|v| self.column_type.format_value(v)).unwrap_or_default();
+        vec![
+            self.name.clone(),
+            self.column_type.label().to_string(),
+            self.filled.to_string(),
+            self.empty.to_string(),
+            self.distinct.to_string(),
+            self.invalid.to_string(),
+            format(self.min),
+            format(self.max),
+            self.mean.map(formula::format_number).unwrap_or_default(),
+        ]
+    }
+}
+
+fn stats(args: &[String]) -> Result<i32, String> {
+    let args = Args::parse(args, &[READ_FLAGS, &["--csv"]])?;
+    let path = args.positionals(1, "one INPUT file")?[0].clone();
+    let mut doc = open(&path, &args)?;
+
+    let mut table = vec![
+        ["Column", "Type", "Filled", "Empty", "Distinct", "Invalid", "Min", "Max", "Mean"]
+            .map(String::from)
+            .to_vec(),
+    ];
+    for col in 0..doc.sheet.num_cols() {
+        table.push(ColumnStats::new(&mut doc, col).cells());
+    }
+
+    if args.has("--csv") {
+        let mut sheet = Sheet::new(0, table[0].len());
+        for row in &table {
+            sheet.push_row(row);
+        }
+        let text = dialect::write_csv(&sheet, &CsvDialect::default()).map_err(|e| e.to_string())?;
+        print_out(&text)?;
+        return Ok(0);
+    }
+
+    let data_rows = doc.sheet.num_rows() - first_row(&doc);
+    let mut out = String::new();
+    writeln!(out, "File:       {}", path).ok();
+    writeln!(
+        out,
+        "Rows:       {}{}",
+        data_rows,
+        if has_header(&doc) { " (plus a header row)" } else { "" }
+    ).ok();
+    writeln!(out, "Columns:    {}", doc.sheet.num_cols()).ok();
+    if xlsx::is_workbook_path(&path) {
+        writeln!(out, "Format:     Excel workbook").ok();
+    } else if let Some(layout) = JsonLayout::for_path(&path) {
+        let format = match layout {
+            JsonLayout::Array => "JSON array",
+            JsonLayout::Lines => "newline-delimited JSON",
+        };
+        writeln!(out, "Format:     {}", format).ok();
+    } else if let Some(format) = ColumnarFormat::for_path(&path) {
+        let format = match format {
+            ColumnarFormat::Parquet => "Parquet",
+            ColumnarFormat::Arrow => "Arrow IPC",
+        };
+        writeln!(out, "Format:     {}", format).ok();
+    } else {
+        writeln!(out, "Delimiter:  {}", dialect::delimiter_label(doc.dialect.delimiter)).ok();
+        writeln!(
+            out,
+            "Encoding:   {}{}",
+            doc.encoding.charset.label(),
+            if doc.encoding.bom { " with BOM" } else { "" }
+        ).ok();
+    }
+    writeln!(out, "Problems:   {}", doc.problems.len()).ok();
+    writeln!(out).ok();
+    let widths: Vec<usize> = (0..table[0].len())
+        .map(|i| table.iter().map(|row| row[i].chars().count()).max().unwrap_or(0))
+        .collect();
+    for row in &table {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| match i {
+                // Counts and values line up on the right
+                0 | 1 => format!("{:<width$}", cell),
+                _ => format!("{:>width$}", cell),
+            })
+            .collect();
+        writeln!(out, "{}", line.join("  ").trim_end()).ok();
+    }
+    print_out(&out)?;
+    Ok(0)
+}
+
+fn validate(args: &[String]) -> Result<i32, String> {
+    let args = Args::parse(args, &[READ_FLAGS, &["--type", "--required"]])?;
+    let path = args.positionals(1, "one INPUT file")?[0].clone();
+    let mut doc = open(&path, &args)?;
+    let types = column_types(&doc, &args)?;
+    doc.column_types.extend(types);
+    let required = args
+        .values("--required")
+        .map(|name| column(&doc, name))
+        .collect::<Result<HashSet<_>, _>>()?;
+
+    let mut out = String::new();
+    let mut problems = 0;
+    for problem in &doc.problems {
+        writeln!(out, "line {}: {}", problem.line, problem.message).ok();
+        problems += 1;
+    }
+    for col in 0..doc.sheet.num_cols() {
+        let column_type = doc.column_type(col);
+        let is_required = required.contains(&col);
+        if column_type == ColumnType::Text && !is_required {
+            continue;
+        }
+        let name = column_name(&doc, col);
+        let mut found = 0;
+        for row in first_row(&doc)..doc.sheet.num_rows() {
+            let text = doc.calc.text(&doc.sheet, row, col);
+            let message = if is_required && text.trim().is_empty() {
+                format!("{}: {} is empty", cell_name(row, col), name)
+            } else if !column_type.is_valid(&text) {
+                format!(
+                    "{}: '{}' is not a valid {} for {}",
+                    cell_name(row, col),
+                    text,
+                    column_type.label().to_lowercase(),
+                    name
+                )
+            } else {
+                continue;
+            };
+            found += 1;
+            if found <= MAX_REPORTED_PER_COLUMN {
+                writeln!(out, "{}", message).ok();
+            }
+        }
+        if found > MAX_REPORTED_PER_COLUMN {
+            writeln!(out, "... and {} more in {}", found - MAX_REPORTED_PER_COLUMN, name).ok();
+        }
+        problems += found;
+    }
+
+    if problems == 0 {
+        writeln!(out, "{}: OK", path).ok();
+    } else {
+        writeln!(out, "{}: {} problem(s)", path, problems).ok();
+    }
+    print_out(&out)?;
+    Ok(if problems == 0 { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    // A file in the temp directory, kept apart from other test runs
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("csv-app-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn splits_flags_and_values() {
+        let args = strings(&["in.csv", "--by", "A", "--by=B:desc", "--no-header", "--header"]);
+        let args = Args::parse(&args, &[READ_FLAGS, &["--by"]]).unwrap();
+        assert_eq!(args.positional, ["in.csv"]);
+        assert_eq!(args.values("--by").collect::<Vec<_>>(), ["A", "B:desc"]);
+        assert_eq!(args.last_switch(&["--header", "--no-header"]), Some("--header"));
+
+        let error = |args: &[&str]| Args::parse(&strings(args), &[READ_FLAGS]).err().unwrap();
+        assert_eq!(error(&["--bogus"]), "unknown option '--bogus'");
+        assert_eq!(error(&["--delimiter"]), "'--delimiter' needs a value");
+        assert_eq!(error(&["--header=yes"]), "'--header' doesn't take a value");
+    }
+
+    #[test]
+    fn reads_sort_keys_and_filters() {
+        let doc = Document::from_bytes(b"name,price\nx,$1\ny,$3\n").unwrap();
+        let key = parse_sort_key(&doc, "price:desc").unwrap();
+        assert_eq!((key.col, key.ascending, key.sort_type, key.empties_last), (1, false, SortType::Number, true));
+        let key = parse_sort_key(&doc, "A:nocase:empties-first").unwrap();
+        assert_eq!((key.col, key.sort_type, key.empties_last), (0, SortType::CaseInsensitive, false));
+        assert_eq!(parse_sort_key(&doc, "cost").unwrap_err(), "no column named 'cost'");
+
+        let args = strings(&["--equals", "name=x", "--equals", "name=y", "--min", "price=$2", "--max", "B=5"]);
+        let filters = parse_filters(&doc, &Args::parse(&args, &[FILTER_FLAGS]).unwrap()).unwrap();
+        assert_eq!(filters.len(), 2);
+        assert!(matches!(&filters[0], (0, ColumnFilter::OneOf(values)) if values.len() == 2));
+        assert!(matches!(filters[1], (1, ColumnFilter::Between { min: Some(2.0), max: Some(5.0), .. })));
+    }
+
+    #[test]
+    fn sorts_filters_and_converts_files() {
+        let input = temp_file("people.csv", "name;age\r\nbob;31\r\nann;4\r\ncid;17\r\n");
+        let output = temp_file("out.csv", "");
+        let read = || std::fs::read_to_string(&output).unwrap();
+
+        assert_eq!(run(&strings(&["sort", &input, "--by", "age", "-o", &output])), 0);
+        assert_eq!(read(), "name;age\r\nann;4\r\ncid;17\r\nbob;31\r\n");
+        assert_eq!(run(&strings(&["filter", &input, "--min", "age=10", "--line-ending", "lf", "-o", &output])), 0);
+        assert_eq!(read(), "name;age\nbob;31\ncid;17\n");
+        assert_eq!(run(&strings(&["convert", &input, &output, "--out-delimiter", "tab", "--no-header"])), 0);
+        assert_eq!(read(), "name\tage\r\nbob\t31\r\nann\t4\r\ncid\t17\r\n");
+
+        assert_eq!(run(&strings(&["sort", &input])), 2);
+        for path in [input, output] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
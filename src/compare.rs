@@ -0,0 +1,456 @@
+// Comparing two open documents side by side and merging them.
+//
+// The Compare window picks a Before and an After document from the open tabs
+// and how to pair their rows. The comparison opens in a tab of its own, with
+// copies of both sheets: the two versions are drawn next to each other, one
+// line per pair of rows, with added rows in green, removed rows in red and
+// changed cells in amber. Clicking a cell takes that cell from its side, and
+// the arrows between the halves take the whole row. The merged result opens as
+// a new document, ready to be saved like any other.
+
+use eframe::egui;
+
+use gridview_core::diff::{self, Comparison, Merge, RowAlignment, RowStatus, Side};
+use gridview_core::dialect::ParsedCsv;
+use gridview_core::encoding::TextEncoding;
+use gridview_core::{CsvDialect, Document, Sheet};
+
+use crate::grid::ROW_HEIGHT;
+use crate::SpreadsheetApp;
+
+// Width of the status column and of the gutter between the two halves
+const STATUS_WIDTH: f32 = 24.0;
+const GUTTER_WIDTH: f32 = 56.0;
+
+// State of the Compare window
+pub struct CompareDialog {
+    before: usize,
+    after: usize,
+    // Column of the Before document whose values pair the rows, None to pair
+    // them by position
+    key: Option<usize>,
+}
+
+impl CompareDialog {
+    // Keep pointing at the same documents while tabs are opened and closed
+    pub(crate) fn tab_inserted(&mut self, index: usize) {
+        for tab in [&mut self.before, &mut self.after] {
+            if *tab >= index {
+                *tab += 1;
+            }
+        }
+    }
+
+    // Returns false if one of the documents was the tab closed
+    pub(crate) fn tab_removed(&mut self, index: usize) -> bool {
+        if self.before == index || self.after == index {
+            return false;
+        }
+        for tab in [&mut self.before, &mut self.after] {
+            if *tab > index {
+                *tab -= 1;
+            }
+        }
+        true
+    }
+}
+
+pub struct CompareView {
+    before_name: String,
+    after_name: String,
+    left: Sheet,
+    right: Sheet,
+    // Format of the Before document, which the merged result is saved in
+    dialect: CsvDialect,
+    encoding: TextEncoding,
+    header: bool,
+    comparison: Comparison,
+    merge: Merge,
+    only_differences: bool,
+    // Rows of the comparison on screen
+    shown: Vec<usize>,
+}
+
+impl CompareView {
+    pub fn title(&self) -> String {
+        format!("{} ↔ {}", self.before_name, self.after_name)
+    }
+
+    fn update_shown(&mut self) {
+        let header = self.header;
+        self.shown = (0..self.comparison.rows.len())
+            .filter(|&row| {
+                !self.only_differences || (header && row == 0) || self.comparison.rows[row].status != RowStatus::Same
+            })
+            .collect();
+    }
+}
+
+#[derive(Clone, Copy)]
+enum CellKind {
+    Plain,
+    Added,
+    Removed,
+    Changed,
+    // The side doesn't have this row or column
+    Missing,
+}
+
+fn cell_color(kind: CellKind, dark_mode: bool) -> egui::Color32 {
+    match (kind, dark_mode) {
+        (CellKind::Plain, _) => egui::Color32::TRANSPARENT,
+        (CellKind::Added, true) => egui::Color32::from_rgb(30, 80, 40),
+        (CellKind::Added, false) => egui::Color32::from_rgb(200, 240, 200),
+        (CellKind::Removed, true) => egui::Color32::from_rgb(100, 35, 35),
+        (CellKind::Removed, false) => egui::Color32::from_rgb(250, 205, 205),
+        (CellKind::Changed, true) => egui::Color32::from_rgb(110, 85, 20),
+        (CellKind::Changed, false) => egui::Color32::from_rgb(255, 230, 160),
+        (CellKind::Missing, true) => egui::Color32::from_rgb(40, 40, 40),
+        (CellKind::Missing, false) => egui::Color32::from_rgb(235, 235, 235),
+    }
+}
+
+impl SpreadsheetApp {
+    // Documents that can be compared: open tabs that aren't comparisons
+    fn comparable_tabs(&self) -> Vec<usize> {
+        (0..self.tabs.len()).filter(|&index| !self.tab_is_comparison(index)).collect()
+    }
+
+    pub(crate) fn can_compare(&self) -> bool {
+        self.comparable_tabs().len() >= 2
+    }
+
+    pub(crate) fn open_compare_dialog(&mut self) {
+        let tabs = self.comparable_tabs();
+        if tabs.len() < 2 {
+            return;
+        }
+        // The active document is the newer version, compared against the tab
+        // before it
+        let after = if tabs.contains(&self.active_tab) { self.active_tab } else { tabs[tabs.len() - 1] };
+        let before = tabs.iter().rev().copied().find(|&index| index < after).unwrap_or(tabs[1]);
+        self.compare_dialog = Some(CompareDialog { before, after, key: None });
+    }
+
+    // Column of the After document paired with a key column of the Before
+    // one: the column with the same header, or at the same position
+    fn after_key_column(&self, dialog: &CompareDialog, key: usize) -> Option<usize> {
+        let before = self.tab_document(dialog.before);
+        let after = self.tab_document(dialog.after);
+        if self.tab_has_header(dialog.before) {
+            let name = before.sheet.get(0, key);
+            after.find_column(name, true).filter(|&col| after.sheet.get(0, col) == name)
+        } else {
+            (key < after.sheet.num_cols()).then_some(key)
+        }
+    }
+
+    fn tab_column_label(&self, tab: usize, col: usize) -> String {
+        Self::column_label(self.tab_document(tab), self.tab_has_header(tab), col)
+    }
+
+    pub(crate) fn show_compare_dialog(&mut self, ctx: &egui::Context) {
+        let Some(mut dialog) = self.compare_dialog.take() else {
+            return;
+        };
+
+        let tabs = self.comparable_tabs();
+        let names: Vec<(usize, String)> = tabs.iter().map(|&index| (index, self.tab_name(index))).collect();
+        let name_of = |index: usize| names.iter().find(|(i, _)| *i == index).map(|(_, name)| name.clone()).unwrap_or_default();
+        let mut confirmed = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+
+        egui::Window::new("Compare Documents")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("compare_options")
+                    .num_columns(2)
+                    .spacing([10.0, 6.0])
+                    .show(ui, |ui| {
+                        ui.label("Before:");
+                        let before = dialog.before;
+                        egui::ComboBox::from_id_salt("compare_before")
+                            .selected_text(name_of(dialog.before))
+                            .show_ui(ui, |ui| {
+                                for (index, name) in &names {
+                                    ui.selectable_value(&mut dialog.before, *index, name);
+                                }
+                            });
+                        // Key columns are columns of the Before document
+                        if dialog.before != before {
+                            dialog.key = None;
+                        }
+                        ui.end_row();
+
+                        ui.label("After:");
+                        egui::ComboBox::from_id_salt("compare_after")
+                            .selected_text(name_of(dialog.after))
+                            .show_ui(ui, |ui| {
+                                for (index, name) in &names {
+                                    ui.selectable_value(&mut dialog.after, *index, name);
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Match rows by:");
+                        let selected = match dialog.key {
+                            Some(col) => self.tab_column_label(dialog.before, col),
+                            None => "Position".to_string(),
+                        };
+                        egui::ComboBox::from_id_salt("compare_key")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut dialog.key, None, "Position");
+                                for col in 0..self.tab_document(dialog.before).sheet.num_cols() {
+                                    ui.selectable_value(&mut dialog.key, Some(col), self.tab_column_label(dialog.before, col));
+                                }
+                            });
+                        ui.end_row();
+                    });
+
+                let same = dialog.before == dialog.after;
+                let missing_key = dialog.key.is_some_and(|key| self.after_key_column(&dialog, key).is_none());
+                if same {
+                    ui.colored_label(ui.visuals().error_fg_color, "Choose two different documents.");
+                } else if missing_key {
+                    ui.colored_label(ui.visuals().error_fg_color, "The After document has no matching key column.");
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!same && !missing_key, egui::Button::new("Compare")).clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            self.start_comparison(&dialog);
+        } else if !cancelled {
+            self.compare_dialog = Some(dialog);
+        }
+    }
+
+    // Compare the chosen documents in a new tab
+    fn start_comparison(&mut self, dialog: &CompareDialog) {
+        let alignment = match dialog.key {
+            Some(key) => match self.after_key_column(dialog, key) {
+                Some(after_key) => RowAlignment::Key { left: key, right: after_key },
+                None => return,
+            },
+            None => RowAlignment::Position,
+        };
+        let header = self.tab_has_header(dialog.before);
+        let before = self.tab_document(dialog.before);
+        let after = self.tab_document(dialog.after);
+        let comparison = diff::compare(&before.sheet, &after.sheet, header, alignment);
+        let mut view = CompareView {
+            before_name: self.tab_name(dialog.before),
+            after_name: self.tab_name(dialog.after),
+            left: before.sheet.clone(),
+            right: after.sheet.clone(),
+            dialect: before.dialect.clone(),
+            encoding: before.encoding,
+            header,
+            merge: Merge::new(&comparison, Side::Left),
+            comparison,
+            only_differences: false,
+            shown: Vec::new(),
+        };
+        view.update_shown();
+
+        self.new_tab();
+        self.doc = Document::new(0, 0);
+        self.compare = Some(view);
+    }
+
+    // Open the merged result of the active comparison as a new document
+    pub(crate) fn open_merged_result(&mut self) {
+        let Some(view) = &self.compare else {
+            return;
+        };
+        let sheet = view.comparison.merge(&view.left, &view.right, &view.merge);
+        let dialect = CsvDialect { has_headers: view.header, ..view.dialect.clone() };
+        let doc = Document::from_parsed(ParsedCsv { rows: sheet, problems: Vec::new() }, dialect, view.encoding);
+
+        self.new_tab();
+        self.doc = doc;
+        self.freeze_top_row = self.doc.dialect.has_headers;
+        // Nothing has been saved yet
+        self.has_unsaved_changes = true;
+    }
+
+    pub(crate) fn show_compare_view(&mut self, ui: &mut egui::Ui) {
+        let dark_mode = self.dark_mode;
+        let column_width = self.default_column_width;
+        let mut open_merged = false;
+        let mut merge_changed = false;
+        let Some(view) = self.compare.as_mut() else {
+            return;
+        };
+
+        let counts = view.comparison.counts();
+        ui.horizontal(|ui| {
+            ui.label(format!("Before: {}", view.before_name));
+            ui.label("→");
+            ui.label(format!("After: {}", view.after_name));
+            ui.separator();
+            ui.label(format!("{} added, {} removed, {} changed", counts.added, counts.removed, counts.changed));
+            ui.separator();
+            if ui.checkbox(&mut view.only_differences, "Only differences").changed() {
+                view.update_shown();
+            }
+            ui.separator();
+            if ui.button("Take All Before").clicked() {
+                view.merge.set_all(Side::Left);
+                merge_changed = true;
+            }
+            if ui.button("Take All After").clicked() {
+                view.merge.set_all(Side::Right);
+                merge_changed = true;
+            }
+            if ui.button("Open Merged Result").clicked() {
+                open_merged = true;
+            }
+        });
+        ui.label("Click a cell to take it from that side, or an arrow to take the whole row.");
+        ui.separator();
+
+        let columns = view.comparison.columns.clone();
+        let half_width = columns.len() as f32 * column_width;
+        let text_color = ui.visuals().text_color();
+        let weak_color = ui.visuals().weak_text_color();
+        let grid_color = ui.visuals().widgets.noninteractive.bg_stroke.color;
+        let chosen_stroke = egui::Stroke::new(1.5, egui::Color32::from_rgb(66, 133, 244));
+
+        egui::ScrollArea::horizontal().auto_shrink(false).show(ui, |ui| {
+            ui.spacing_mut().item_spacing = egui::vec2(0.0, 0.0);
+
+            // Column letters of each side
+            ui.horizontal(|ui| {
+                ui.add_space(STATUS_WIDTH);
+                for side in [Side::Left, Side::Right] {
+                    for pair in &columns {
+                        let label = pair.col(side).map(Self::col_index_to_letter).unwrap_or_default();
+                        ui.add_sized([column_width, ROW_HEIGHT], egui::Label::new(egui::RichText::new(label).strong()));
+                    }
+                    if side == Side::Left {
+                        ui.add_space(GUTTER_WIDTH);
+                    }
+                }
+            });
+
+            egui::ScrollArea::vertical().auto_shrink(false).show_rows(ui, ROW_HEIGHT, view.shown.len(), |ui, range| {
+                for &row in &view.shown[range] {
+                    let pair = &view.comparison.rows[row];
+                    let is_header = view.header && row == 0;
+                    let row_side = view.merge.row_side(row);
+                    ui.horizontal(|ui| {
+                        let marker = match pair.status {
+                            RowStatus::Same => "",
+                            RowStatus::Changed => "~",
+                            RowStatus::Added => "+",
+                            RowStatus::Removed => "−",
+                        };
+                        ui.add_sized([STATUS_WIDTH, ROW_HEIGHT], egui::Label::new(marker));
+
+                        for side in [Side::Left, Side::Right] {
+                            let sheet = if side == Side::Left { &view.left } else { &view.right };
+                            let (rect, _) = ui.allocate_exact_size(egui::vec2(half_width, ROW_HEIGHT), egui::Sense::hover());
+                            for (col, column) in columns.iter().enumerate() {
+                                let x = rect.left() + col as f32 * column_width;
+                                let cell_rect = egui::Rect::from_x_y_ranges(x..=x + column_width, rect.y_range());
+                                let kind = match pair.status {
+                                    _ if pair.row(side).is_none() || column.col(side).is_none() => CellKind::Missing,
+                                    RowStatus::Added => CellKind::Added,
+                                    RowStatus::Removed => CellKind::Removed,
+                                    RowStatus::Changed if pair.changed.contains(&col) => CellKind::Changed,
+                                    _ => CellKind::Plain,
+                                };
+                                let painter = ui.painter_at(cell_rect);
+                                painter.rect_filled(cell_rect, 0.0, cell_color(kind, dark_mode));
+                                painter.rect_stroke(cell_rect, 0.0, egui::Stroke::new(0.5, grid_color), egui::epaint::StrokeKind::Inside);
+
+                                // Cells that differ are outlined on the side the merge takes
+                                let chosen = view.merge.cell_side(row, col) == side;
+                                if !matches!(kind, CellKind::Plain | CellKind::Missing) && chosen {
+                                    painter.rect_stroke(cell_rect, 0.0, chosen_stroke, egui::epaint::StrokeKind::Inside);
+                                }
+
+                                let text = view.comparison.text(sheet, side, row, col);
+                                if !text.is_empty() {
+                                    let color = if matches!(kind, CellKind::Plain) || chosen { text_color } else { weak_color };
+                                    let font_id = if is_header { egui::FontId::proportional(14.0) } else { egui::FontId::default() };
+                                    painter.with_clip_rect(cell_rect.shrink2(egui::vec2(4.0, 0.0))).text(
+                                        cell_rect.left_center() + egui::vec2(4.0, 0.0),
+                                        egui::Align2::LEFT_CENTER,
+                                        text,
+                                        font_id,
+                                        color,
+                                    );
+                                }
+
+                                let response = ui.interact(cell_rect, ui.id().with((row, side == Side::Left, col)), egui::Sense::click());
+                                if response.clicked() && !matches!(kind, CellKind::Missing | CellKind::Plain) {
+                                    match pair.status {
+                                        RowStatus::Changed => view.merge.set_cell(row, col, side),
+                                        _ => view.merge.set_row(row, side),
+                                    }
+                                    merge_changed = true;
+                                }
+                            }
+
+                            if side == Side::Left {
+                                // Take the whole row from either side
+                                let layout = egui::Layout::left_to_right(egui::Align::Center);
+                                ui.allocate_ui_with_layout(egui::vec2(GUTTER_WIDTH, ROW_HEIGHT), layout, |ui| {
+                                    ui.set_min_size(egui::vec2(GUTTER_WIDTH, ROW_HEIGHT));
+                                    ui.spacing_mut().item_spacing.x = 4.0;
+                                    ui.add_space(8.0);
+                                    if pair.status != RowStatus::Same {
+                                        if ui.selectable_label(row_side == Side::Left, "◀").on_hover_text("Take this row from Before").clicked() {
+                                            view.merge.set_row(row, Side::Left);
+                                            merge_changed = true;
+                                        }
+                                        if ui.selectable_label(row_side == Side::Right, "▶").on_hover_text("Take this row from After").clicked() {
+                                            view.merge.set_row(row, Side::Right);
+                                            merge_changed = true;
+                                        }
+                                    }
+                                });
+                            }
+                        }
+                    });
+                }
+            });
+        });
+
+        // Merge choices are work that closing the tab would lose
+        if merge_changed {
+            self.has_unsaved_changes = true;
+        }
+        if open_merged {
+            self.open_merged_result();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialog_follows_its_tabs() {
+        let mut dialog = CompareDialog { before: 1, after: 3, key: None };
+        dialog.tab_inserted(2);
+        assert_eq!((dialog.before, dialog.after), (1, 4));
+        assert!(dialog.tab_removed(0));
+        assert_eq!((dialog.before, dialog.after), (0, 3));
+        assert!(!dialog.tab_removed(3));
+    }
+}
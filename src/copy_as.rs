@@ -0,0 +1,197 @@
+// Copying cells as a Markdown, HTML or LaTeX table, JSON or SQL INSERT
+// statements, for pasting into documents and programs rather than another
+// spreadsheet.
+//
+// The Copy as submenus of the context menus copy straight away, naming the
+// columns from the header row when the top row is frozen. The Copy As window
+// previews the text, lets the first row and the SQL table name be changed, and
+// can save the text to a file instead.
+
+use eframe::egui;
+
+use gridview_core::clipboard::{CopyFormat, CopyOptions};
+
+use crate::{Selection, SpreadsheetApp};
+
+// Rows shown in the window's preview
+const PREVIEW_ROWS: usize = 50;
+
+// State of the Copy As window
+pub struct CopyAsDialog {
+    selection: Selection,
+    format: CopyFormat,
+    header: bool,
+    table: String,
+    // Text for the first rows, made again when an option changes
+    preview: String,
+}
+
+impl CopyAsDialog {
+    fn options(&self) -> CopyOptions {
+        CopyOptions { format: self.format, header: self.header, table: self.table.trim().to_string() }
+    }
+}
+
+// What was picked from a Copy as submenu
+pub enum CopyAs {
+    Format(CopyFormat),
+    // The Copy As window, to change the options first
+    Window,
+}
+
+// Items of a Copy as submenu: one per format, then the window
+pub(crate) fn copy_as_menu(ui: &mut egui::Ui) -> Option<CopyAs> {
+    let mut picked = None;
+    ui.menu_button("Copy as…", |ui| {
+        for format in CopyFormat::ALL {
+            if ui.button(format.label()).clicked() {
+                picked = Some(CopyAs::Format(format));
+                ui.close();
+            }
+        }
+        ui.separator();
+        if ui.button("Options...").clicked() {
+            picked = Some(CopyAs::Window);
+            ui.close();
+        }
+    });
+    picked
+}
+
+impl SpreadsheetApp {
+    // Text of the selected cells in a format. Rows hidden by filters are left
+    // out, as when copying.
+    fn selection_as(&mut self, selection: &Selection, options: &CopyOptions, max_rows: usize) -> String {
+        let Some((rows, cols)) = self.selection_ranges(selection) else {
+            return String::new();
+        };
+        let rows: Vec<usize> = self.visible_rows_in(rows).take(max_rows).collect();
+        self.doc.copy_as(rows, cols, options)
+    }
+
+    pub(crate) fn copy_selection_as(&mut self, selection: &Selection, format: CopyFormat) {
+        let options = CopyOptions { format, header: self.freeze_top_row, table: self.document_name() };
+        let text = self.selection_as(selection, &options, usize::MAX);
+        if !text.is_empty() {
+            let _ = self.clipboard.set_text(text);
+        }
+    }
+
+    pub(crate) fn open_copy_as_dialog(&mut self, selection: Selection) {
+        if let Some((row, col)) = self.editing_cell.take() {
+            self.commit_cell_edit(row, col);
+        }
+        let mut dialog = CopyAsDialog {
+            selection,
+            format: CopyFormat::Markdown,
+            header: self.freeze_top_row,
+            table: self.document_name(),
+            preview: String::new(),
+        };
+        self.update_copy_as_preview(&mut dialog);
+        self.copy_as_dialog = Some(dialog);
+    }
+
+    fn update_copy_as_preview(&mut self, dialog: &mut CopyAsDialog) {
+        dialog.preview = self.selection_as(&dialog.selection, &dialog.options(), PREVIEW_ROWS);
+    }
+
+    pub(crate) fn show_copy_as_dialog(&mut self, ctx: &egui::Context) {
+        let Some(mut dialog) = self.copy_as_dialog.take() else {
+            return;
+        };
+
+        let mut copy = false;
+        let mut save = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        let mut changed = false;
+        let rows = self.selection_ranges(&dialog.selection).map_or(0, |(rows, _)| self.visible_rows_in(rows).count());
+
+        egui::Window::new("Copy As")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(560.0)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for format in CopyFormat::ALL {
+                        changed |= ui.radio_value(&mut dialog.format, format, format.label()).changed();
+                    }
+                });
+                changed |= ui.checkbox(&mut dialog.header, "First row names the columns").changed();
+                if dialog.format == CopyFormat::Sql {
+                    ui.horizontal(|ui| {
+                        ui.label("Table name:");
+                        changed |= ui.text_edit_singleline(&mut dialog.table).changed();
+                    });
+                }
+                ui.separator();
+
+                egui::ScrollArea::both().id_salt("copy_as_preview").max_height(300.0).show(ui, |ui| {
+                    ui.add(egui::TextEdit::multiline(&mut dialog.preview.as_str()).code_editor().desired_width(f32::INFINITY));
+                });
+                if rows > PREVIEW_ROWS {
+                    ui.label(format!("Showing the first {} of {} rows", PREVIEW_ROWS, rows));
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let ready = rows > 0 && !(dialog.format == CopyFormat::Sql && dialog.table.trim().is_empty());
+                    if ui.add_enabled(ready, egui::Button::new("Copy")).clicked() {
+                        copy = true;
+                    }
+                    if ui.add_enabled(ready, egui::Button::new("Save...")).clicked() {
+                        save = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if changed {
+            self.update_copy_as_preview(&mut dialog);
+        }
+        if copy {
+            let text = self.selection_as(&dialog.selection, &dialog.options(), usize::MAX);
+            let _ = self.clipboard.set_text(text);
+        }
+        let saved = save && self.save_copy_as(&dialog);
+        if !(copy || saved || cancelled) {
+            self.copy_as_dialog = Some(dialog);
+        }
+    }
+
+    // Write the text to a file, named after the table. Returns whether it was
+    // written.
+    fn save_copy_as(&mut self, dialog: &CopyAsDialog) -> bool {
+        let options = dialog.options();
+        let text = self.selection_as(&dialog.selection, &options, usize::MAX);
+        let extension = options.format.extension();
+        let name = if options.table.is_empty() { "table" } else { &options.table };
+        let filename = format!("{}.{}", name, extension);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let Some(path) = rfd::FileDialog::new()
+                .add_filter(options.format.label(), &[extension])
+                .set_file_name(&filename)
+                .save_file()
+            else {
+                return false;
+            };
+            match std::fs::write(&path, text) {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Error saving file: {}", e);
+                    false
+                }
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.download_file(text.as_bytes(), &filename);
+            true
+        }
+    }
+}
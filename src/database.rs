@@ -0,0 +1,246 @@
+// SQLite databases, in native builds only.
+//
+// Open SQLite lists the tables and views of a database file and reads the one
+// picked, or the rows of a SELECT, into a tab of its own. A table keeps a link
+// to its rows, so Save writes the edits back with UPDATE, INSERT and DELETE
+// instead of overwriting the file. Views and queries have nothing to write
+// back to and are saved elsewhere with Save As. Export to SQLite goes the
+// other way and writes the document to a new table, typed by its column types.
+
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+
+use gridview_core::sqlite::{self, Database, Source, TableInfo};
+
+use crate::loader::BackgroundLoad;
+use crate::SpreadsheetApp;
+
+// State of the Open SQLite window
+pub struct DatabaseDialog {
+    path: PathBuf,
+    tables: Vec<TableInfo>,
+    // Table to read, or None for the query
+    selected: Option<usize>,
+    query: String,
+    error: Option<String>,
+}
+
+// State of the Export to SQLite window
+pub struct ExportTableDialog {
+    table: String,
+    replace: bool,
+    error: Option<String>,
+    // Set once the table is written, to say where
+    done: Option<String>,
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+impl SpreadsheetApp {
+    pub(crate) fn pick_database(&mut self) {
+        if let Some(path) = rfd::FileDialog::new().add_filter("SQLite database", sqlite::EXTENSIONS).pick_file() {
+            self.open_database(path);
+        }
+    }
+
+    // List the tables of a database to pick one from
+    pub(crate) fn open_database(&mut self, path: PathBuf) {
+        match Database::open(&path).and_then(|database| database.tables()) {
+            Ok(tables) => {
+                let selected = if tables.is_empty() { None } else { Some(0) };
+                self.database_dialog = Some(DatabaseDialog { path, tables, selected, query: String::new(), error: None });
+            }
+            Err(e) => eprintln!("Error opening database {}: {}", path.display(), e),
+        }
+    }
+
+    pub(crate) fn show_database_dialog(&mut self, ctx: &egui::Context) {
+        let Some(mut dialog) = self.database_dialog.take() else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+
+        egui::Window::new("Open SQLite Database")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(420.0)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("Tables in {}:", file_name(&dialog.path)));
+                egui::ScrollArea::vertical().id_salt("database_tables").max_height(240.0).show(ui, |ui| {
+                    for (index, table) in dialog.tables.iter().enumerate() {
+                        let label = if table.view { format!("{} (view)", table.name) } else { table.name.clone() };
+                        if ui.selectable_value(&mut dialog.selected, Some(index), label).double_clicked() {
+                            confirmed = true;
+                        }
+                    }
+                    if dialog.tables.is_empty() {
+                        ui.label("The database has no tables.");
+                    }
+                });
+                ui.separator();
+
+                ui.radio_value(&mut dialog.selected, None, "Query:");
+                let query = egui::TextEdit::multiline(&mut dialog.query)
+                    .code_editor()
+                    .desired_rows(3)
+                    .desired_width(f32::INFINITY)
+                    .hint_text("SELECT * FROM ...");
+                if ui.add(query).changed() {
+                    dialog.selected = None;
+                    dialog.error = None;
+                }
+                let editable = dialog.selected.is_some_and(|index| !dialog.tables[index].view);
+                if !editable {
+                    ui.weak("Edits to views and queries can only be saved to another file.");
+                }
+                if let Some(error) = &dialog.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let ready = dialog.selected.is_some() || !dialog.query.trim().is_empty();
+                    if ui.add_enabled(ready, egui::Button::new("Open")).clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            let source = match dialog.selected {
+                Some(index) => Source::Table(dialog.tables[index].name.clone()),
+                None => Source::Query(dialog.query.trim().to_string()),
+            };
+            // Mistakes in a query are shown here rather than failing the load
+            if let Source::Query(sql) = &source
+                && let Err(e) = Database::open(&dialog.path).and_then(|database| database.check_query(sql))
+            {
+                dialog.error = Some(e.to_string());
+                self.database_dialog = Some(dialog);
+                return;
+            }
+            // A table that is already open is shown rather than opened twice
+            if let Source::Table(name) = &source
+                && let Some(index) = self.tab_with_table(&dialog.path, name)
+            {
+                self.switch_tab(index);
+                return;
+            }
+            self.start_loading(BackgroundLoad::read_table(dialog.path, source));
+        } else if !cancelled {
+            self.database_dialog = Some(dialog);
+        }
+    }
+
+    // Write the edits back to the table the document was read from
+    pub(crate) fn save_to_table(&mut self, path: &Path) {
+        let Some(mut link) = self.table_link.take() else {
+            return;
+        };
+        match Database::open(path).and_then(|mut database| self.doc.save_to_table(&mut database, &mut link)) {
+            Ok(_) => self.has_unsaved_changes = false,
+            Err(e) => eprintln!("Error saving table {}: {}", link.table, e),
+        }
+        self.table_link = Some(link);
+    }
+
+    pub(crate) fn open_export_table_dialog(&mut self) {
+        if let Some((row, col)) = self.editing_cell.take() {
+            self.commit_cell_edit(row, col);
+        }
+        let table = self.document_name();
+        self.export_table_dialog = Some(ExportTableDialog { table, replace: false, error: None, done: None });
+    }
+
+    pub(crate) fn show_export_table_dialog(&mut self, ctx: &egui::Context) {
+        let Some(mut dialog) = self.export_table_dialog.take() else {
+            return;
+        };
+
+        let mut choose = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+
+        egui::Window::new("Export to SQLite")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                if let Some(done) = &dialog.done {
+                    ui.label(done);
+                    ui.add_space(10.0);
+                    if ui.button("Close").clicked() {
+                        cancelled = true;
+                    }
+                    return;
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Table name:");
+                    ui.text_edit_singleline(&mut dialog.table);
+                });
+                ui.checkbox(&mut dialog.replace, "Replace a table of the same name");
+                ui.weak("Columns are typed by their column types.");
+                if !self.freeze_top_row {
+                    ui.weak("The first row isn't a header, so the columns are named A, B, C, ...");
+                }
+                if let Some(error) = &dialog.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let ready = !dialog.table.trim().is_empty() && self.doc.sheet.num_cols() > 0;
+                    if ui.add_enabled(ready, egui::Button::new("Choose Database...")).clicked() {
+                        choose = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if choose
+            && let Some(mut path) = rfd::FileDialog::new().add_filter("SQLite database", sqlite::EXTENSIONS).save_file()
+        {
+            if !sqlite::is_sqlite_path(&path) {
+                path.set_extension("db");
+            }
+            self.export_table(&mut dialog, &path);
+        }
+        if !cancelled {
+            self.export_table_dialog = Some(dialog);
+        }
+    }
+
+    // Write the document to a new table, creating the database if needed
+    fn export_table(&mut self, dialog: &mut ExportTableDialog, path: &Path) {
+        let name = dialog.table.trim();
+        let header = self.freeze_top_row;
+        let exported = Database::create(path)
+            .and_then(|mut database| self.doc.export_to_table(&mut database, name, header, dialog.replace));
+        match exported {
+            Ok(rows) => {
+                dialog.done = Some(format!(
+                    "Wrote {} row{} to table \"{}\" in {}.",
+                    rows,
+                    if rows == 1 { "" } else { "s" },
+                    name,
+                    file_name(path)
+                ));
+            }
+            Err(e) => {
+                eprintln!("Error exporting to {}: {}", path.display(), e);
+                dialog.error = Some(format!("Could not export: {}", e));
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+// Finding rows that repeat the values of some key columns, like the same
+// person listed twice in a contact list.
+//
+// The Remove Duplicates window picks the key columns, how their cells are
+// compared and which row of each group stays, and previews the groups before
+// anything changes. Removing is one undoable step. Highlighting instead leaves
+// the rows alone and colors their key cells in the grid, kept up to date as
+// the sheet is edited until it is cleared.
+
+use eframe::egui;
+
+use gridview_core::duplicates::{self, DuplicateKey, Keep};
+use gridview_core::Edit;
+
+use crate::{Selection, SpreadsheetApp};
+
+// Rows of the groups listed in the preview
+const PREVIEW_ROWS: usize = 100;
+
+// State of the Remove Duplicates window
+pub struct DuplicateDialog {
+    // Which columns are part of the key
+    columns: Vec<bool>,
+    trim: bool,
+    ignore_case: bool,
+    keep: Keep,
+    // Groups for the current options, worked out again when they change
+    groups: Vec<Vec<usize>>,
+    removed: Vec<usize>,
+}
+
+impl DuplicateDialog {
+    fn key(&self) -> DuplicateKey {
+        DuplicateKey {
+            columns: (0..self.columns.len()).filter(|&col| self.columns[col]).collect(),
+            trim: self.trim,
+            ignore_case: self.ignore_case,
+        }
+    }
+
+    // The window isn't modal, so its ticks follow their columns as columns
+    // are inserted and deleted
+    fn adjust_for(&mut self, edit: &Edit) {
+        match *edit {
+            Edit::InsertColumn(at, ..) => self.columns.insert(at.min(self.columns.len()), false),
+            Edit::RemoveColumn(at) if at < self.columns.len() => {
+                self.columns.remove(at);
+            }
+            Edit::Resize(_, cols, _) => self.columns.truncate(cols),
+            _ => {}
+        }
+    }
+}
+
+// Duplicate cells drawn in the grid
+pub struct DuplicateHighlight {
+    key: DuplicateKey,
+    // Whether each row shares its key with another row
+    rows: Vec<bool>,
+    groups: usize,
+    count: usize,
+}
+
+impl SpreadsheetApp {
+    pub(crate) fn open_duplicate_dialog(&mut self) {
+        if let Some((row, col)) = self.editing_cell.take() {
+            self.commit_cell_edit(row, col);
+        }
+        let num_cols = self.doc.sheet.num_cols();
+        // Selected columns make the key, or all of them for rows repeated whole.
+        // An open highlight brings its own options.
+        let (columns, trim, ignore_case) = match &self.duplicate_highlight {
+            Some(highlight) => (
+                (0..num_cols).map(|col| highlight.key.columns.contains(&col)).collect(),
+                highlight.key.trim,
+                highlight.key.ignore_case,
+            ),
+            None => {
+                let selected = match self.selection {
+                    Selection::Column(col) => Some((col, col)),
+                    Selection::CellRange { start, end } => Some((start.1.min(end.1), start.1.max(end.1))),
+                    _ => None,
+                };
+                let columns = (0..num_cols)
+                    .map(|col| selected.is_none_or(|(first, last)| (first..=last).contains(&col)))
+                    .collect();
+                (columns, true, true)
+            }
+        };
+        let mut dialog = DuplicateDialog { columns, trim, ignore_case, keep: Keep::First, groups: Vec::new(), removed: Vec::new() };
+        self.update_duplicate_preview(&mut dialog);
+        self.duplicate_dialog = Some(dialog);
+    }
+
+    fn update_duplicate_preview(&self, dialog: &mut DuplicateDialog) {
+        dialog.columns.resize(self.doc.sheet.num_cols(), false);
+        dialog.groups = dialog.key().groups(&self.doc.sheet, self.freeze_top_row);
+        dialog.removed = duplicates::rows_to_remove(&self.doc.sheet, &dialog.groups, dialog.keep);
+    }
+
+    pub(crate) fn show_duplicate_dialog(&mut self, ctx: &egui::Context) {
+        let Some(mut dialog) = self.duplicate_dialog.take() else {
+            return;
+        };
+
+        let mut remove = false;
+        let mut highlight = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        let mut changed = false;
+
+        egui::Window::new("Remove Duplicates")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(560.0)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Key columns:");
+                        egui::ScrollArea::vertical().id_salt("duplicate_columns").max_height(160.0).show(ui, |ui| {
+                            for (col, key) in dialog.columns.iter_mut().enumerate() {
+                                changed |= ui.checkbox(key, Self::column_label(&self.doc, self.freeze_top_row, col)).changed();
+                            }
+                        });
+                    });
+                    ui.separator();
+                    ui.vertical(|ui| {
+                        changed |= ui.checkbox(&mut dialog.trim, "Ignore surrounding spaces").changed();
+                        changed |= ui.checkbox(&mut dialog.ignore_case, "Ignore case").changed();
+                        ui.add_space(6.0);
+                        ui.label("Keep:");
+                        for keep in Keep::ALL {
+                            changed |= ui.radio_value(&mut dialog.keep, keep, keep.label()).changed();
+                        }
+                    });
+                });
+                ui.separator();
+
+                let rows: usize = dialog.groups.iter().map(Vec::len).sum();
+                if dialog.groups.is_empty() {
+                    ui.label("No duplicate rows.");
+                } else {
+                    ui.label(format!(
+                        "{} rows share their key with another row, in {} group{}. Removing keeps one row of each group and removes {}.",
+                        rows,
+                        dialog.groups.len(),
+                        if dialog.groups.len() == 1 { "" } else { "s" },
+                        dialog.removed.len()
+                    ));
+                }
+
+                let key_columns = dialog.key().columns;
+                egui::ScrollArea::both().id_salt("duplicate_preview").max_height(240.0).show(ui, |ui| {
+                    egui::Grid::new("duplicate_preview_grid").striped(true).show(ui, |ui| {
+                        ui.strong("Row");
+                        ui.strong("");
+                        for &col in &key_columns {
+                            ui.strong(Self::column_label(&self.doc, self.freeze_top_row, col));
+                        }
+                        ui.end_row();
+
+                        for row in dialog.groups.iter().flatten().take(PREVIEW_ROWS) {
+                            ui.label((row + 1).to_string());
+                            if dialog.removed.binary_search(row).is_ok() {
+                                ui.colored_label(ui.visuals().error_fg_color, "Remove");
+                            } else {
+                                ui.label("Keep");
+                            }
+                            for &col in &key_columns {
+                                ui.label(self.doc.sheet.get(*row, col));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+                if rows > PREVIEW_ROWS {
+                    ui.label(format!("… and {} more rows", rows - PREVIEW_ROWS));
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!dialog.removed.is_empty(), egui::Button::new("Remove Duplicates")).clicked() {
+                        remove = true;
+                    }
+                    if ui.add_enabled(!key_columns.is_empty(), egui::Button::new("Highlight Only")).clicked() {
+                        highlight = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if changed {
+            self.update_duplicate_preview(&mut dialog);
+        }
+        if remove {
+            // Rows are found again in case the sheet changed since the preview
+            self.update_duplicate_preview(&mut dialog);
+            let edits = duplicates::remove_duplicates(&self.doc.sheet, &dialog.groups, dialog.keep);
+            let label = format!("Remove {} duplicate rows", dialog.removed.len());
+            self.apply_operation(label, edits);
+        } else if highlight {
+            self.duplicate_highlight = Some(DuplicateHighlight { key: dialog.key(), rows: Vec::new(), groups: 0, count: 0 });
+            self.refresh_duplicate_highlight();
+        } else if !cancelled {
+            self.duplicate_dialog = Some(dialog);
+        }
+    }
+
+    // Find the highlighted duplicates again after the sheet changed
+    pub(crate) fn refresh_duplicate_highlight(&mut self) {
+        let Some(highlight) = &mut self.duplicate_highlight else {
+            return;
+        };
+        let groups = highlight.key.groups(&self.doc.sheet, self.freeze_top_row);
+        highlight.rows = vec![false; self.doc.sheet.num_rows()];
+        for &row in groups.iter().flatten() {
+            highlight.rows[row] = true;
+        }
+        highlight.groups = groups.len();
+        highlight.count = groups.iter().map(Vec::len).sum();
+    }
+
+    // Keep the highlighted key columns, and those ticked in the window, in
+    // step with inserted and deleted columns
+    pub(crate) fn adjust_duplicate_highlight_for(&mut self, edit: &Edit) {
+        if let Some(dialog) = &mut self.duplicate_dialog {
+            dialog.adjust_for(edit);
+        }
+        let Some(highlight) = &mut self.duplicate_highlight else {
+            return;
+        };
+        let columns = &mut highlight.key.columns;
+        match *edit {
+            Edit::InsertColumn(at, ..) => {
+                for col in columns.iter_mut().filter(|col| **col >= at) {
+                    *col += 1;
+                }
+            }
+            Edit::RemoveColumn(at) => {
+                columns.retain(|&col| col != at);
+                for col in columns.iter_mut().filter(|col| **col > at) {
+                    *col -= 1;
+                }
+            }
+            Edit::Resize(_, cols, _) => columns.retain(|&col| col < cols),
+            _ => {}
+        }
+        if columns.is_empty() {
+            self.duplicate_highlight = None;
+        }
+    }
+
+    pub(crate) fn is_duplicate_cell(&self, row: usize, col: usize) -> bool {
+        self.duplicate_highlight
+            .as_ref()
+            .is_some_and(|highlight| highlight.rows.get(row) == Some(&true) && highlight.key.columns.contains(&col))
+    }
+
+    // Summary shown in the menu bar while duplicates are highlighted
+    pub(crate) fn duplicate_highlight_summary(&self) -> Option<String> {
+        let highlight = self.duplicate_highlight.as_ref()?;
+        Some(format!(
+            "Duplicates: {} rows in {} group{}",
+            highlight.count,
+            highlight.groups,
+            if highlight.groups == 1 { "" } else { "s" }
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticked_columns_follow_inserts_and_deletes() {
+        let mut dialog = DuplicateDialog {
+            columns: vec![false, true, false, true],
+            trim: true,
+            ignore_case: true,
+            keep: Keep::First,
+            groups: Vec::new(),
+            removed: Vec::new(),
+        };
+        dialog.adjust_for(&Edit::InsertColumn(1, None, None));
+        assert_eq!(dialog.key().columns, [2, 4]);
+        dialog.adjust_for(&Edit::RemoveColumn(2));
+        assert_eq!(dialog.key().columns, [3]);
+        dialog.adjust_for(&Edit::RemoveColumn(9));
+        dialog.adjust_for(&Edit::Resize(0, 3, Vec::new()));
+        assert!(dialog.key().columns.is_empty());
+        assert_eq!(dialog.columns.len(), 3);
+    }
+}
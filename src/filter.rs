@@ -0,0 +1,521 @@
+// Column filters (AutoFilter).
+//
+// Filters hide rows from the view without touching the sheet. The rows that
+// pass every filter are kept as an ascending list of data row indices, which
+// the grid uses to map what is on screen to the sheet. Cells are matched on
+// the text they show, so formulas are filtered by their results.
+//
+// The list is worked out when a filter is applied. Edits don't re-run it: a
+// row whose cell is changed so it no longer matches stays in view until the
+// filters are applied again, the way spreadsheet AutoFilters behave. Inserted
+// rows are always shown.
+
+use eframe::egui;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::rc::Rc;
+
+use gridview_core::filter::{ColumnFilter, row_matches};
+use gridview_core::history::Edit;
+use gridview_core::Sheet;
+use gridview_core::types::ColumnType;
+
+use crate::SpreadsheetApp;
+
+// Distinct values listed in the filter window
+const MAX_LISTED_VALUES: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FilterKind {
+    Values,
+    Contains,
+    StartsWith,
+    Between,
+    Empty,
+    NonEmpty,
+}
+
+impl FilterKind {
+    const ALL: [FilterKind; 6] = [
+        FilterKind::Values,
+        FilterKind::Contains,
+        FilterKind::StartsWith,
+        FilterKind::Between,
+        FilterKind::Empty,
+        FilterKind::NonEmpty,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            FilterKind::Values => "Value is one of",
+            FilterKind::Contains => "Text contains",
+            FilterKind::StartsWith => "Text starts with",
+            FilterKind::Between => "Value between",
+            FilterKind::Empty => "Is empty",
+            FilterKind::NonEmpty => "Is not empty",
+        }
+    }
+}
+
+// State of the filter window for one column
+pub struct FilterEditor {
+    col: usize,
+    kind: FilterKind,
+    text: String,
+    min: String,
+    max: String,
+    // Type the range bounds are read as
+    column_type: ColumnType,
+    // Distinct values in rows passing the other filters: (text, rows, ticked)
+    values: Vec<(String, usize, bool)>,
+    // More distinct values exist than are listed
+    truncated: bool,
+    value_search: String,
+    error: Option<String>,
+}
+
+impl FilterEditor {
+    // The filter described by the window, None if it would show everything
+    fn build(&self) -> Result<Option<ColumnFilter>, String> {
+        let filter = match self.kind {
+            FilterKind::Values => {
+                let hidden: HashSet<String> =
+                    self.values.iter().filter(|(_, _, ticked)| !ticked).map(|(value, _, _)| value.clone()).collect();
+                (!hidden.is_empty()).then_some(ColumnFilter::Hidden(hidden))
+            }
+            FilterKind::Contains => (!self.text.is_empty()).then(|| ColumnFilter::contains(&self.text)),
+            FilterKind::StartsWith => (!self.text.is_empty()).then(|| ColumnFilter::starts_with(&self.text)),
+            FilterKind::Between => {
+                let min = parse_bound(self.column_type, &self.min, "Minimum")?;
+                let max = parse_bound(self.column_type, &self.max, "Maximum")?;
+                let column_type = self.column_type;
+                (min.is_some() || max.is_some()).then_some(ColumnFilter::Between { min, max, column_type })
+            }
+            FilterKind::Empty => Some(ColumnFilter::Empty),
+            FilterKind::NonEmpty => Some(ColumnFilter::NonEmpty),
+        };
+        Ok(filter)
+    }
+}
+
+fn parse_bound(column_type: ColumnType, text: &str, name: &str) -> Result<Option<f64>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let expected = if column_type == ColumnType::Date { "a date" } else { "a number" };
+    match column_type.value(text) {
+        Some(value) => Ok(Some(value)),
+        None => Err(format!("{} is not {}: {}", name, expected, text)),
+    }
+}
+
+// Listed values in order: numbers by value, then text ignoring case
+fn compare_listed(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.total_cmp(&y),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Err(_)) => a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b)),
+    }
+}
+
+impl SpreadsheetApp {
+    // Whether a row passes every filter, optionally ignoring one column's
+    fn passes_filters(&mut self, row: usize, except: Option<usize>) -> bool {
+        // A frozen header row is never filtered out
+        if self.freeze_top_row && row == 0 {
+            return true;
+        }
+        let Self { filters, doc, .. } = self;
+        let filters = filters.iter().filter(|(col, _)| Some(**col) != except).map(|(&col, filter)| (col, filter));
+        row_matches(filters, &doc.sheet, &mut doc.calc, row)
+    }
+
+    // Work out which rows are shown
+    pub(crate) fn apply_filters(&mut self) {
+        if self.filters.is_empty() {
+            self.visible_rows = None;
+            return;
+        }
+        let rows: Vec<usize> = (0..self.doc.sheet.num_rows()).filter(|&row| self.passes_filters(row, None)).collect();
+        self.visible_rows = Some(Rc::new(rows));
+    }
+
+    pub(crate) fn visible_row_count(&self) -> usize {
+        self.visible_rows.as_ref().map_or(self.doc.sheet.num_rows(), |rows| rows.len())
+    }
+
+    // Shown rows within a range of data rows, in order
+    pub(crate) fn visible_rows_in(&self, rows: Range<usize>) -> impl Iterator<Item = usize> + '_ {
+        let (all, shown): (Range<usize>, &[usize]) = match &self.visible_rows {
+            None => (rows, &[]),
+            Some(visible) => {
+                let start = visible.partition_point(|&row| row < rows.start);
+                let end = visible.partition_point(|&row| row < rows.end);
+                (0..0, &visible[start..end])
+            }
+        };
+        all.chain(shown.iter().copied())
+    }
+
+    // The shown row `delta` rows away from `row`, clamped to the first/last one
+    pub(crate) fn step_visible_row(&self, row: usize, delta: isize) -> usize {
+        let Some(visible) = &self.visible_rows else {
+            let last = self.doc.sheet.num_rows().saturating_sub(1);
+            return row.saturating_add_signed(delta).min(last);
+        };
+        if visible.is_empty() {
+            return row;
+        }
+        let position = match visible.binary_search(&row) {
+            Ok(i) => i as isize + delta,
+            // From a hidden row, one step down lands on the next shown row
+            Err(i) if delta > 0 => i as isize + delta - 1,
+            Err(i) => i as isize + delta,
+        };
+        visible[position.clamp(0, visible.len() as isize - 1) as usize]
+    }
+
+    // Keep the filters and shown rows in step with an edit about to be applied
+    pub(crate) fn adjust_filters_for(&mut self, edit: &Edit, sheet: &Sheet) {
+        match *edit {
+            Edit::InsertColumn(col_idx, ..) => {
+                self.filters = std::mem::take(&mut self.filters)
+                    .into_iter()
+                    .map(|(idx, filter)| (if idx >= col_idx { idx + 1 } else { idx }, filter))
+                    .collect();
+                // The filter window stays with its column
+                if let Some(editor) = &mut self.filter_editor
+                    && editor.col >= col_idx
+                {
+                    editor.col += 1;
+                }
+            }
+            Edit::RemoveColumn(col_idx) => {
+                self.filters.remove(&col_idx);
+                self.filters = std::mem::take(&mut self.filters)
+                    .into_iter()
+                    .map(|(idx, filter)| (if idx > col_idx { idx - 1 } else { idx }, filter))
+                    .collect();
+                match &mut self.filter_editor {
+                    Some(editor) if editor.col == col_idx => self.filter_editor = None,
+                    Some(editor) if editor.col > col_idx => editor.col -= 1,
+                    _ => {}
+                }
+            }
+            Edit::Resize(_, cols, _) => {
+                self.filters.retain(|&idx, _| idx < cols);
+                if self.filter_editor.as_ref().is_some_and(|editor| editor.col >= cols) {
+                    self.filter_editor = None;
+                }
+            }
+            _ => {}
+        }
+
+        let num_rows = sheet.num_rows();
+        let Some(visible) = &mut self.visible_rows else {
+            return;
+        };
+        let visible = Rc::make_mut(visible);
+        match *edit {
+            // New rows are shown
+            Edit::InsertRow(row_idx, _) => {
+                let at = visible.partition_point(|&row| row < row_idx);
+                for row in &mut visible[at..] {
+                    *row += 1;
+                }
+                visible.insert(at, row_idx);
+            }
+            Edit::RemoveRow(row_idx) => {
+                let at = visible.partition_point(|&row| row < row_idx);
+                if visible.get(at) == Some(&row_idx) {
+                    visible.remove(at);
+                }
+                for row in &mut visible[at..] {
+                    *row -= 1;
+                }
+            }
+            Edit::Resize(rows, _, _) if rows > num_rows => visible.extend(num_rows..rows),
+            Edit::Resize(rows, _, _) => visible.retain(|&row| row < rows),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn open_filter_editor(&mut self, col: usize) {
+        // List the values of the rows the other filters let through
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut truncated = false;
+        for row in 0..self.doc.sheet.num_rows() {
+            if (self.freeze_top_row && row == 0) || !self.passes_filters(row, Some(col)) {
+                continue;
+            }
+            let text = self.doc.calc.text(&self.doc.sheet, row, col);
+            if let Some(count) = counts.get_mut(text.as_ref()) {
+                *count += 1;
+            } else if counts.len() < MAX_LISTED_VALUES {
+                counts.insert(text.into_owned(), 1);
+            } else {
+                truncated = true;
+            }
+        }
+
+        let mut editor = FilterEditor {
+            col,
+            kind: FilterKind::Values,
+            text: String::new(),
+            min: String::new(),
+            max: String::new(),
+            column_type: self.column_type(col),
+            values: Vec::new(),
+            truncated,
+            value_search: String::new(),
+            error: None,
+        };
+
+        // Start from the column's current filter
+        let mut shown = None;
+        let hidden = match self.filters.get(&col) {
+            Some(ColumnFilter::Hidden(hidden)) => Some(hidden),
+            Some(ColumnFilter::OneOf(values)) => {
+                shown = Some(values);
+                None
+            }
+            Some(ColumnFilter::Contains(text)) => {
+                editor.kind = FilterKind::Contains;
+                editor.text = text.clone();
+                None
+            }
+            Some(ColumnFilter::StartsWith(text)) => {
+                editor.kind = FilterKind::StartsWith;
+                editor.text = text.clone();
+                None
+            }
+            Some(ColumnFilter::Between { min, max, column_type }) => {
+                editor.kind = FilterKind::Between;
+                editor.column_type = *column_type;
+                editor.min = min.map(|n| column_type.format_value(n)).unwrap_or_default();
+                editor.max = max.map(|n| column_type.format_value(n)).unwrap_or_default();
+                None
+            }
+            Some(ColumnFilter::Empty) => {
+                editor.kind = FilterKind::Empty;
+                None
+            }
+            Some(ColumnFilter::NonEmpty) => {
+                editor.kind = FilterKind::NonEmpty;
+                None
+            }
+            None => None,
+        };
+        // Hidden values are filtered out of the rows counted above, so add them back
+        if let Some(hidden) = hidden {
+            for value in hidden {
+                counts.entry(value.clone()).or_insert(0);
+            }
+        }
+        editor.values = counts
+            .into_iter()
+            .map(|(value, count)| {
+                let ticked = hidden.is_none_or(|hidden| !hidden.contains(&value))
+                    && shown.is_none_or(|shown| shown.contains(&value));
+                (value, count, ticked)
+            })
+            .collect();
+        editor.values.sort_by(|a, b| compare_listed(&a.0, &b.0));
+
+        self.filter_editor = Some(editor);
+    }
+
+    pub(crate) fn clear_filter(&mut self, col: usize) {
+        if self.filters.remove(&col).is_some() {
+            self.apply_filters();
+        }
+    }
+
+    pub(crate) fn clear_all_filters(&mut self) {
+        self.filters.clear();
+        self.visible_rows = None;
+    }
+
+    pub(crate) fn show_filter_editor(&mut self, ctx: &egui::Context) {
+        let Some(editor) = &mut self.filter_editor else {
+            return;
+        };
+
+        let mut window_open = true;
+        let mut apply = false;
+        let mut clear = false;
+        let mut cancel = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        egui::Window::new(format!("Filter Column {}", Self::col_index_to_letter(editor.col)))
+            .id(egui::Id::new("filter_editor"))
+            .open(&mut window_open)
+            .collapsible(false)
+            .resizable(false)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                egui::ComboBox::from_label("Show rows where")
+                    .selected_text(editor.kind.label())
+                    .show_ui(ui, |ui| {
+                        for kind in FilterKind::ALL {
+                            ui.selectable_value(&mut editor.kind, kind, kind.label());
+                        }
+                    });
+                ui.add_space(4.0);
+
+                match editor.kind {
+                    FilterKind::Values => {
+                        ui.horizontal(|ui| {
+                            ui.label("Search:");
+                            ui.text_edit_singleline(&mut editor.value_search);
+                        });
+                        let search = editor.value_search.to_lowercase();
+                        let listed = |value: &str| search.is_empty() || value.to_lowercase().contains(&search);
+
+                        // Select All/None act on the values the search leaves listed
+                        ui.horizontal(|ui| {
+                            if ui.button("Select All").clicked() {
+                                for (value, _, ticked) in &mut editor.values {
+                                    *ticked |= listed(value);
+                                }
+                            }
+                            if ui.button("Select None").clicked() {
+                                for (value, _, ticked) in &mut editor.values {
+                                    *ticked &= !listed(value);
+                                }
+                            }
+                        });
+
+                        egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                            for (value, count, ticked) in &mut editor.values {
+                                if !listed(value) {
+                                    continue;
+                                }
+                                let label = if value.is_empty() { "(Empty)" } else { value.as_str() };
+                                ui.checkbox(ticked, format!("{} ({})", label, count));
+                            }
+                        });
+                        if editor.truncated {
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "Only the first {} distinct values are listed; the rest stay shown",
+                                    MAX_LISTED_VALUES
+                                ))
+                                .weak(),
+                            );
+                        }
+                    }
+                    FilterKind::Contains | FilterKind::StartsWith => {
+                        ui.horizontal(|ui| {
+                            ui.label("Text:");
+                            ui.text_edit_singleline(&mut editor.text);
+                        });
+                        ui.label(egui::RichText::new("Case is ignored").weak());
+                    }
+                    FilterKind::Between => {
+                        egui::Grid::new("filter_range").num_columns(2).show(ui, |ui| {
+                            ui.label("Minimum:");
+                            ui.text_edit_singleline(&mut editor.min);
+                            ui.end_row();
+                            ui.label("Maximum:");
+                            ui.text_edit_singleline(&mut editor.max);
+                            ui.end_row();
+                        });
+                        let hint = if editor.column_type == ColumnType::Date {
+                            "Dates such as 2024-03-01; leave a bound empty for no limit"
+                        } else {
+                            "Leave a bound empty for no limit"
+                        };
+                        ui.label(egui::RichText::new(hint).weak());
+                    }
+                    FilterKind::Empty | FilterKind::NonEmpty => {}
+                }
+
+                if let Some(error) = &editor.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    apply = ui.button("Apply").clicked();
+                    clear = ui.button("Clear").clicked();
+                    cancel |= ui.button("Cancel").clicked();
+                });
+            });
+
+        if apply {
+            match editor.build() {
+                Ok(filter) => {
+                    let col = editor.col;
+                    match filter {
+                        Some(filter) => self.filters.insert(col, filter),
+                        None => self.filters.remove(&col),
+                    };
+                    self.filter_editor = None;
+                    self.apply_filters();
+                }
+                Err(e) => editor.error = Some(e),
+            }
+        } else if clear {
+            let col = editor.col;
+            self.filter_editor = None;
+            self.clear_filter(col);
+        } else if cancel || !window_open {
+            self.filter_editor = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(kind: FilterKind) -> FilterEditor {
+        FilterEditor {
+            col: 0,
+            kind,
+            text: String::new(),
+            min: String::new(),
+            max: String::new(),
+            column_type: ColumnType::Number,
+            values: vec![("a".into(), 2, true), ("b".into(), 1, true)],
+            truncated: false,
+            value_search: String::new(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn builds_the_filter_described_by_the_window() {
+        let mut values = editor(FilterKind::Values);
+        assert!(values.build().unwrap().is_none());
+        values.values[1].2 = false;
+        let filter = values.build().unwrap().unwrap();
+        assert!(filter.matches("a") && !filter.matches("b"));
+
+        let mut contains = editor(FilterKind::Contains);
+        assert!(contains.build().unwrap().is_none());
+        contains.text = "x".into();
+        assert!(contains.build().unwrap().unwrap().matches("box"));
+        assert!(editor(FilterKind::Empty).build().unwrap().unwrap().matches(""));
+    }
+
+    #[test]
+    fn range_bounds_are_read_as_the_column_type() {
+        let mut between = editor(FilterKind::Between);
+        assert!(between.build().unwrap().is_none());
+        between.column_type = ColumnType::Date;
+        between.min = "2024-01-01".into();
+        let filter = between.build().unwrap().unwrap();
+        assert!(filter.matches("2024-06-30") && !filter.matches("2023-12-31"));
+        between.max = "soon".into();
+        assert_eq!(between.build().unwrap_err(), "Maximum is not a date: soon");
+    }
+
+    #[test]
+    fn lists_numbers_before_text() {
+        let mut values = vec!["b", "10", "A", "2", "a"];
+        values.sort_by(|a, b| compare_listed(a, b));
+        assert_eq!(values, ["2", "10", "A", "a", "b"]);
+    }
+}
@@ -0,0 +1,985 @@
+// Virtualized spreadsheet grid.
+//
+// The grid is painted directly instead of being built from widgets: every frame
+// only the rows and columns that intersect the viewport are laid out, so the cost
+// of drawing depends on the window size rather than on the size of the sheet.
+// The vertical scroll position is kept in f64 because row offsets in files with
+// millions of rows are beyond what f32 can represent to the pixel.
+//
+// When a filter is active the grid shows only some rows. Layout works in
+// display rows (positions on screen, top to bottom) and maps them to data rows
+// through the filter's row list; everything outside the layout, such as the
+// selection and the cell being edited, uses data rows.
+
+use eframe::egui;
+use std::ops::Range;
+use std::rc::Rc;
+
+use gridview_core::history::CellChange;
+use gridview_core::types::ColumnType;
+use crate::copy_as::{self, CopyAs};
+use crate::{Selection, SpreadsheetApp};
+
+pub const ROW_HEIGHT: f32 = 25.0;
+const MIN_COLUMN_WIDTH: f32 = 30.0;
+const MIN_ROW_HEADER_WIDTH: f32 = 50.0;
+const SCROLLBAR_WIDTH: f32 = 10.0;
+const MIN_THUMB_LENGTH: f32 = 20.0;
+const RESIZE_HANDLE_WIDTH: f32 = 6.0;
+const HEADER_HIGHLIGHT: egui::Color32 = egui::Color32::from_rgb(100, 150, 200);
+
+#[derive(Default)]
+pub struct GridState {
+    // Scroll position of the body in content pixels
+    scroll_top: f64,
+    scroll_left: f32,
+    // Cell to bring into view on the next frame
+    pub scroll_to: Option<(usize, usize)>,
+    // Row/column that was right-clicked, used by the open context menu
+    context_cell: (usize, usize),
+}
+
+// Structural changes requested while drawing, applied once drawing is done
+#[derive(Default)]
+struct GridActions {
+    commit_edit: bool,
+    insert_row_at: Option<usize>,
+    insert_col_at: Option<usize>,
+    delete_row: Option<usize>,
+    delete_col: Option<usize>,
+    clear_cell: Option<(usize, usize)>,
+    sort: Option<(usize, bool)>,
+    sort_dialog: Option<usize>,
+    edit_filter: Option<usize>,
+    // Column and its new type, None to infer it again
+    set_type: Option<(usize, Option<ColumnType>)>,
+    clear_filter: Option<usize>,
+    copy_as: Option<(Selection, CopyAs)>,
+}
+
+// Screen geometry of the grid for the current frame
+struct GridLayout {
+    // Displayed rows, which is fewer than the sheet has while filtering
+    num_rows: usize,
+    // Data row shown at each display row, None when every row is shown
+    row_map: Option<Rc<Vec<usize>>>,
+    num_cols: usize,
+    frozen_rows: usize,
+    // Column letters
+    header_rect: egui::Rect,
+    // Pinned rows below the column letters (when Freeze Top Row is on)
+    frozen_rect: egui::Rect,
+    // Scrolling rows
+    body_rect: egui::Rect,
+    // Row numbers, spanning frozen and body rows
+    row_header_rect: egui::Rect,
+    // Left edge of each column relative to the first column, plus the total width
+    col_left: Vec<f32>,
+    scroll_top: f64,
+    scroll_left: f32,
+}
+
+impl GridLayout {
+    fn data_row(&self, display_row: usize) -> usize {
+        self.row_map.as_ref().map_or(display_row, |rows| rows[display_row])
+    }
+
+    // Display position of a data row, None if it is filtered out
+    fn display_row(&self, data_row: usize) -> Option<usize> {
+        match &self.row_map {
+            Some(rows) => rows.binary_search(&data_row).ok(),
+            None => (data_row < self.num_rows).then_some(data_row),
+        }
+    }
+
+    fn body_rows(&self) -> usize {
+        self.num_rows - self.frozen_rows
+    }
+
+    fn content_height(&self) -> f64 {
+        self.body_rows() as f64 * ROW_HEIGHT as f64
+    }
+
+    fn content_width(&self) -> f32 {
+        *self.col_left.last().unwrap_or(&0.0)
+    }
+
+    fn max_scroll_top(&self) -> f64 {
+        (self.content_height() - self.body_rect.height() as f64).max(0.0)
+    }
+
+    fn max_scroll_left(&self) -> f32 {
+        (self.content_width() - self.body_rect.width()).max(0.0)
+    }
+
+    fn col_x(&self, col: usize) -> f32 {
+        self.body_rect.left() + self.col_left[col] - self.scroll_left
+    }
+
+    fn row_y(&self, row: usize) -> f32 {
+        if row < self.frozen_rows {
+            self.frozen_rect.top() + row as f32 * ROW_HEIGHT
+        } else {
+            let offset = (row - self.frozen_rows) as f64 * ROW_HEIGHT as f64 - self.scroll_top;
+            self.body_rect.top() + offset as f32
+        }
+    }
+
+    fn cell_rect(&self, row: usize, col: usize) -> egui::Rect {
+        egui::Rect::from_min_max(
+            egui::pos2(self.col_x(col), self.row_y(row)),
+            egui::pos2(self.col_x(col + 1), self.row_y(row) + ROW_HEIGHT),
+        )
+    }
+
+    // Body rows that intersect the viewport
+    fn visible_rows(&self) -> Range<usize> {
+        let row_height = ROW_HEIGHT as f64;
+        let first = (self.scroll_top / row_height).floor() as usize;
+        let last = ((self.scroll_top + self.body_rect.height() as f64) / row_height).ceil() as usize;
+        (self.frozen_rows + first).min(self.num_rows)..(self.frozen_rows + last).min(self.num_rows)
+    }
+
+    fn visible_cols(&self) -> Range<usize> {
+        let left = self.scroll_left;
+        let right = left + self.body_rect.width();
+        let first = self.col_left.partition_point(|&x| x <= left).saturating_sub(1);
+        let last = self.col_left.partition_point(|&x| x < right);
+        first.min(self.num_cols)..last.min(self.num_cols)
+    }
+
+    // Column under an x position, clamped to the first/last column
+    fn col_at(&self, x: f32) -> Option<usize> {
+        if self.num_cols == 0 {
+            return None;
+        }
+        let content_x = x - self.body_rect.left() + self.scroll_left;
+        let col = self.col_left.partition_point(|&left| left <= content_x).saturating_sub(1);
+        Some(col.min(self.num_cols - 1))
+    }
+
+    // Data row under a y position, clamped to the first/last displayed row
+    fn row_at(&self, y: f32) -> Option<usize> {
+        if self.num_rows == 0 {
+            return None;
+        }
+        if y < self.body_rect.top() && self.frozen_rows > 0 {
+            let row = ((y - self.frozen_rect.top()) / ROW_HEIGHT).floor().max(0.0) as usize;
+            return Some(self.data_row(row.min(self.frozen_rows - 1)));
+        }
+        let content_y = (y - self.body_rect.top()) as f64 + self.scroll_top;
+        let row = self.frozen_rows + (content_y / ROW_HEIGHT as f64).floor().max(0.0) as usize;
+        Some(self.data_row(row.min(self.num_rows - 1)))
+    }
+
+    fn cell_at(&self, pos: egui::Pos2) -> Option<(usize, usize)> {
+        Some((self.row_at(pos.y)?, self.col_at(pos.x)?))
+    }
+}
+
+fn is_in_selection(selection: &Selection, row: usize, col: usize) -> bool {
+    match selection {
+        Selection::None => false,
+        Selection::CellRange { start, end } => {
+            let (r1, c1) = *start;
+            let (r2, c2) = *end;
+            let (min_r, max_r) = if r1 <= r2 { (r1, r2) } else { (r2, r1) };
+            let (min_c, max_c) = if c1 <= c2 { (c1, c2) } else { (c2, c1) };
+            row >= min_r && row <= max_r && col >= min_c && col <= max_c
+        }
+        Selection::Column(c) => col == *c,
+        Selection::Row(r) => row == *r,
+    }
+}
+
+impl SpreadsheetApp {
+    pub fn show_grid(&mut self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+        let id = ui.id().with("grid");
+
+        let mut layout = self.grid_layout(rect);
+        let mut actions = GridActions::default();
+        let previous_editing_cell = self.editing_cell;
+
+        self.handle_grid_scrolling(ui, &mut layout);
+
+        // Formula results for the cells about to be drawn
+        let drawn_rows = (0..layout.frozen_rows).chain(layout.visible_rows()).map(|row| layout.data_row(row));
+        self.doc.calc.prepare(&self.doc.sheet, drawn_rows, layout.visible_cols());
+
+        self.paint_cells(ui, &layout);
+        self.handle_cell_interaction(ui, id, &layout, &mut actions);
+        self.show_cell_editor(ui, &layout);
+        self.show_column_header(ui, id, &mut layout, &mut actions);
+        self.show_row_header(ui, id, &layout, &mut actions);
+
+        // Corner above the row numbers
+        let corner = egui::Rect::from_min_max(rect.min, egui::pos2(layout.row_header_rect.right(), layout.header_rect.bottom()));
+        ui.painter().rect_filled(corner, 0.0, ui.visuals().panel_fill);
+
+        self.show_scrollbars(ui, id, &mut layout);
+
+        // Save current edit if user clicked away (use the PREVIOUS editing cell)
+        if actions.commit_edit
+            && let Some((edit_row, edit_col)) = previous_editing_cell
+        {
+            self.commit_cell_edit(edit_row, edit_col);
+        }
+
+        // Process pending operations after rendering
+        if let Some((col_idx, ascending)) = actions.sort {
+            self.sort_by_column(col_idx, ascending);
+        }
+        if let Some(col_idx) = actions.sort_dialog {
+            self.open_sort_dialog(Some(col_idx));
+        }
+        if let Some((col_idx, column_type)) = actions.set_type {
+            self.set_column_type(col_idx, column_type);
+        }
+        if let Some(col_idx) = actions.edit_filter {
+            self.open_filter_editor(col_idx);
+        }
+        if let Some(col_idx) = actions.clear_filter {
+            self.clear_filter(col_idx);
+        }
+        if let Some(col_idx) = actions.insert_col_at {
+            self.insert_column_at(col_idx);
+        }
+        if let Some(row_idx) = actions.insert_row_at {
+            self.insert_row_at(row_idx);
+        }
+        if let Some(col_idx) = actions.delete_col {
+            self.delete_column(col_idx);
+        }
+        if let Some(row_idx) = actions.delete_row {
+            self.delete_row(row_idx);
+        }
+        match actions.copy_as {
+            Some((selection, CopyAs::Format(format))) => self.copy_selection_as(&selection, format),
+            Some((selection, CopyAs::Window)) => self.open_copy_as_dialog(selection),
+            None => {}
+        }
+        if let Some((row_idx, col_idx)) = actions.clear_cell {
+            let label = format!("Clear {}", Self::cell_name(row_idx, col_idx));
+            self.edit_cells(label, vec![CellChange { row: row_idx, col: col_idx, text: String::new() }]);
+        }
+
+        // Clear drag state when mouse released
+        if ui.input(|i| i.pointer.primary_released()) {
+            self.drag_start = None;
+        }
+    }
+
+    fn grid_layout(&self, rect: egui::Rect) -> GridLayout {
+        let row_map = self.visible_rows.clone();
+        let num_rows = row_map.as_ref().map_or(self.doc.sheet.num_rows(), |rows| rows.len());
+        let num_cols = self.doc.sheet.num_cols();
+        let frozen_rows = if self.freeze_top_row && num_rows > 1 { 1 } else { 0 };
+
+        // Widen the row number column for large files
+        let digits = self.doc.sheet.num_rows().max(1).to_string().len() as f32;
+        let row_header_width = (digits * 8.0 + 20.0).max(MIN_ROW_HEADER_WIDTH);
+
+        let mut col_left = Vec::with_capacity(num_cols + 1);
+        let mut x = 0.0;
+        col_left.push(x);
+        for col_idx in 0..num_cols {
+            x += self.get_column_width(col_idx);
+            col_left.push(x);
+        }
+
+        let cells_left = rect.left() + row_header_width;
+        let cells_right = (rect.right() - SCROLLBAR_WIDTH).max(cells_left);
+        let header_bottom = rect.top() + ROW_HEIGHT;
+        let frozen_bottom = header_bottom + frozen_rows as f32 * ROW_HEIGHT;
+        let body_bottom = (rect.bottom() - SCROLLBAR_WIDTH).max(frozen_bottom);
+
+        GridLayout {
+            num_rows,
+            row_map,
+            num_cols,
+            frozen_rows,
+            header_rect: egui::Rect::from_x_y_ranges(cells_left..=cells_right, rect.top()..=header_bottom),
+            frozen_rect: egui::Rect::from_x_y_ranges(cells_left..=cells_right, header_bottom..=frozen_bottom),
+            body_rect: egui::Rect::from_x_y_ranges(cells_left..=cells_right, frozen_bottom..=body_bottom),
+            row_header_rect: egui::Rect::from_x_y_ranges(rect.left()..=cells_left, header_bottom..=body_bottom),
+            col_left,
+            scroll_top: self.grid.scroll_top,
+            scroll_left: self.grid.scroll_left,
+        }
+    }
+
+    fn handle_grid_scrolling(&mut self, ui: &egui::Ui, layout: &mut GridLayout) {
+        let grid_rect = layout.header_rect.union(layout.body_rect).union(layout.row_header_rect);
+
+        // Mouse wheel / trackpad
+        if ui.is_enabled() && ui.rect_contains_pointer(grid_rect) {
+            let delta = ui.input(|i| i.smooth_scroll_delta);
+            layout.scroll_top -= delta.y as f64;
+            layout.scroll_left -= delta.x;
+        }
+
+        // Keep the dragged selection moving when the pointer leaves the body
+        if self.drag_start.is_some()
+            && ui.input(|i| i.pointer.primary_down())
+            && let Some(pos) = ui.input(|i| i.pointer.latest_pos())
+        {
+            if pos.y > layout.body_rect.bottom() {
+                layout.scroll_top += ROW_HEIGHT as f64;
+            } else if pos.y < layout.body_rect.top() {
+                layout.scroll_top -= ROW_HEIGHT as f64;
+            }
+            if pos.x > layout.body_rect.right() {
+                layout.scroll_left += ROW_HEIGHT;
+            } else if pos.x < layout.body_rect.left() {
+                layout.scroll_left -= ROW_HEIGHT;
+            }
+        }
+
+        // Bring a cell into view (keyboard navigation, search results)
+        if let Some((data_row, col)) = self.grid.scroll_to.take() {
+            if let Some(row) = layout.display_row(data_row)
+                && row >= layout.frozen_rows
+            {
+                let top = (row - layout.frozen_rows) as f64 * ROW_HEIGHT as f64;
+                let bottom = top + ROW_HEIGHT as f64;
+                if top < layout.scroll_top {
+                    layout.scroll_top = top;
+                } else if bottom > layout.scroll_top + layout.body_rect.height() as f64 {
+                    layout.scroll_top = bottom - layout.body_rect.height() as f64;
+                }
+            }
+            if col < layout.num_cols {
+                let left = layout.col_left[col];
+                let right = layout.col_left[col + 1];
+                if left < layout.scroll_left {
+                    layout.scroll_left = left;
+                } else if right > layout.scroll_left + layout.body_rect.width() {
+                    layout.scroll_left = (right - layout.body_rect.width()).min(left);
+                }
+            }
+        }
+
+        self.clamp_scroll(layout);
+    }
+
+    fn clamp_scroll(&mut self, layout: &mut GridLayout) {
+        layout.scroll_top = layout.scroll_top.clamp(0.0, layout.max_scroll_top());
+        layout.scroll_left = layout.scroll_left.clamp(0.0, layout.max_scroll_left());
+        self.grid.scroll_top = layout.scroll_top;
+        self.grid.scroll_left = layout.scroll_left;
+    }
+
+    fn paint_cells(&self, ui: &egui::Ui, layout: &GridLayout) {
+        let visuals = ui.visuals();
+        let grid_color = visuals.widgets.noninteractive.bg_stroke.color;
+        let text_color = visuals.text_color();
+        let cols = layout.visible_cols();
+        let column_types: Vec<ColumnType> = cols.clone().map(|col_idx| self.column_type(col_idx)).collect();
+
+        let regions = [
+            (layout.frozen_rect, 0..layout.frozen_rows),
+            (layout.body_rect, layout.visible_rows()),
+        ];
+
+        for (region, rows) in regions {
+            if rows.is_empty() {
+                continue;
+            }
+            let painter = ui.painter_at(region);
+
+            for display_row in rows.clone() {
+                let row_idx = layout.data_row(display_row);
+                let is_frozen_header = display_row < layout.frozen_rows || (self.freeze_top_row && row_idx == 0);
+                let row_top = layout.row_y(display_row);
+
+                // Striped rows, counted from the first scrolling row like the table used to
+                if !is_frozen_header && (display_row - layout.frozen_rows) % 2 == 1 {
+                    let stripe = egui::Rect::from_x_y_ranges(region.x_range(), row_top..=row_top + ROW_HEIGHT);
+                    painter.rect_filled(stripe, 0.0, visuals.faint_bg_color);
+                }
+
+                for (col_idx, &column_type) in cols.clone().zip(&column_types) {
+                    let rect = layout.cell_rect(display_row, col_idx);
+                    let is_editing = self.editing_cell == Some((row_idx, col_idx));
+                    let is_selected = is_in_selection(&self.selection, row_idx, col_idx);
+
+                    // Check if cell is in search results (kept in row-major order)
+                    let is_search_match = self.search_results.binary_search(&(row_idx, col_idx)).is_ok();
+                    let is_current_search_result = self.search_results.get(self.current_search_result) == Some(&(row_idx, col_idx));
+                    let is_duplicate = self.is_duplicate_cell(row_idx, col_idx);
+
+                    // Draw cell background with priority: frozen header > current search result > search match > selected > duplicate > normal
+                    // Use different colors for dark vs light mode
+                    let bg_color = if is_frozen_header {
+                        if self.dark_mode {
+                            egui::Color32::from_rgb(50, 50, 60) // Slightly lighter than background for dark mode
+                        } else {
+                            egui::Color32::from_rgb(230, 230, 240) // Slightly darker than background for light mode
+                        }
+                    } else if is_current_search_result {
+                        if self.dark_mode {
+                            egui::Color32::from_rgb(180, 100, 0) // Dark orange for dark mode
+                        } else {
+                            egui::Color32::from_rgb(255, 200, 100) // Light orange for light mode
+                        }
+                    } else if is_search_match {
+                        if self.dark_mode {
+                            egui::Color32::from_rgb(120, 100, 0) // Dark gold for dark mode
+                        } else {
+                            egui::Color32::from_rgb(255, 255, 150) // Light yellow for light mode
+                        }
+                    } else if is_selected {
+                        if self.dark_mode {
+                            egui::Color32::from_rgb(60, 90, 120) // Dark blue for dark mode
+                        } else {
+                            egui::Color32::from_rgb(180, 210, 240) // Light blue for light mode
+                        }
+                    } else if is_duplicate {
+                        if self.dark_mode {
+                            egui::Color32::from_rgb(90, 50, 110) // Dark purple for dark mode
+                        } else {
+                            egui::Color32::from_rgb(230, 205, 245) // Light purple for light mode
+                        }
+                    } else {
+                        egui::Color32::TRANSPARENT
+                    };
+
+                    if bg_color != egui::Color32::TRANSPARENT {
+                        painter.rect_filled(rect, 0.0, bg_color);
+                    }
+
+                    // Draw cell border (blue if editing, normal grid color otherwise)
+                    if is_editing {
+                        painter.rect_stroke(
+                            rect,
+                            0.0,
+                            egui::Stroke::new(2.0, egui::Color32::from_rgb(66, 133, 244)),
+                            egui::epaint::StrokeKind::Inside,
+                        );
+                        continue;
+                    }
+                    painter.rect_stroke(rect, 0.0, egui::Stroke::new(0.5, grid_color), egui::epaint::StrokeKind::Inside);
+
+                    let cell_val = self.doc.calc.display(&self.doc.sheet, row_idx, col_idx);
+                    if cell_val.is_empty() {
+                        continue;
+                    }
+
+                    // Use bold font for frozen header row
+                    let font_id = if is_frozen_header {
+                        egui::FontId::proportional(14.0)
+                    } else {
+                        egui::FontId::default()
+                    };
+
+                    // Numbers line up on the right; cells that don't fit their
+                    // column's type are shown in the error color
+                    let (anchor, align) = if column_type.is_numeric() && !is_frozen_header {
+                        (rect.right_center() - egui::vec2(4.0, 0.0), egui::Align2::RIGHT_CENTER)
+                    } else {
+                        (rect.left_center() + egui::vec2(4.0, 0.0), egui::Align2::LEFT_CENTER)
+                    };
+                    let color = if !is_frozen_header && !column_type.is_valid(&cell_val) {
+                        visuals.error_fg_color
+                    } else {
+                        text_color
+                    };
+
+                    // Draw the text with clipping to prevent overflow
+                    let text_rect = rect.shrink2(egui::vec2(4.0, 0.0)).intersect(region);
+                    painter.with_clip_rect(text_rect).text(anchor, align, cell_val.as_ref(), font_id, color);
+                }
+            }
+        }
+    }
+
+    fn handle_cell_interaction(&mut self, ui: &mut egui::Ui, id: egui::Id, layout: &GridLayout, actions: &mut GridActions) {
+        let cells_rect = layout.frozen_rect.union(layout.body_rect);
+        let response = ui.interact(cells_rect, id.with("cells"), egui::Sense::click_and_drag());
+        let pointer = ui.input(|i| i.pointer.interact_pos());
+        let hovered_cell = pointer.filter(|pos| cells_rect.contains(*pos)).and_then(|pos| layout.cell_at(pos));
+
+        // Start drag selection
+        if response.is_pointer_button_down_on()
+            && ui.input(|i| i.pointer.primary_pressed())
+            && let Some(cell_id) = hovered_cell
+        {
+            actions.commit_edit = self.editing_cell.is_some();
+            self.drag_start = Some(cell_id);
+            self.selection = Selection::CellRange { start: cell_id, end: cell_id };
+            self.editing_cell = None;
+        }
+
+        // Double-click to edit
+        if response.double_clicked()
+            && !self.read_only
+            && let Some(cell_id) = hovered_cell
+        {
+            self.editing_cell = Some(cell_id);
+            self.edit_buffer = self.doc.sheet.get(cell_id.0, cell_id.1).to_string();
+            self.selection = Selection::None;
+            self.drag_start = None;
+        }
+
+        // Update selection while dragging, following the pointer outside the grid too
+        if let Some(start) = self.drag_start
+            && ui.input(|i| i.pointer.primary_down())
+        {
+            if let Some(end) = pointer.and_then(|pos| layout.cell_at(pos)) {
+                self.selection = Selection::CellRange { start, end };
+            }
+            // Request continuous repaints while dragging for smooth selection updates
+            ui.ctx().request_repaint();
+        }
+
+        // Clear drag state when opening context menu (right-click)
+        if response.secondary_clicked() {
+            self.drag_start = None;
+            if let Some(cell_id) = hovered_cell {
+                self.grid.context_cell = cell_id;
+            }
+        }
+
+        response.context_menu(|ui| {
+            let cell_id = self.grid.context_cell;
+            if ui.button("Cut").clicked() {
+                self.cut_selection();
+                ui.close();
+            }
+            if ui.button("Copy").clicked() {
+                self.copy_selection();
+                ui.close();
+            }
+            if let Some(picked) = copy_as::copy_as_menu(ui) {
+                actions.copy_as = Some((self.selection.clone(), picked));
+            }
+            if ui.button("Paste").clicked() {
+                if let Ok(text) = self.clipboard.get_text() {
+                    self.paste_text(&text);
+                }
+                ui.close();
+            }
+            ui.separator();
+            if ui.button("Clear").clicked() {
+                actions.clear_cell = Some(cell_id);
+                ui.close();
+            }
+        });
+    }
+
+    fn show_cell_editor(&mut self, ui: &mut egui::Ui, layout: &GridLayout) {
+        let Some((row_idx, col_idx)) = self.editing_cell else {
+            return;
+        };
+        let Some(display_row) = layout.display_row(row_idx) else {
+            return;
+        };
+        if col_idx >= layout.num_cols {
+            return;
+        }
+
+        let visible = display_row < layout.frozen_rows || layout.visible_rows().contains(&display_row);
+        let region = if display_row < layout.frozen_rows { layout.frozen_rect } else { layout.body_rect };
+        let rect = layout.cell_rect(display_row, col_idx);
+        if !visible || !region.intersects(rect) {
+            return;
+        }
+
+        // Show text edit without frame, just cursor
+        let edit_rect = rect.shrink2(egui::vec2(4.0, 2.0));
+        let mut child_ui = ui.new_child(
+            egui::UiBuilder::new()
+                .max_rect(edit_rect)
+                .layout(egui::Layout::left_to_right(egui::Align::Center)),
+        );
+        child_ui.set_clip_rect(region.intersect(rect));
+
+        let text_edit = egui::TextEdit::singleline(&mut self.edit_buffer)
+            .frame(false)
+            .desired_width(edit_rect.width());
+        let edit_response = child_ui.add(text_edit);
+
+        // Check if Enter was pressed to move down
+        let enter_pressed = ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+        if edit_response.lost_focus() || enter_pressed {
+            self.commit_cell_edit(row_idx, col_idx);
+            self.editing_cell = None;
+        }
+
+        edit_response.request_focus();
+    }
+
+    // Store the edit buffer in a cell as one undoable step
+    pub(crate) fn commit_cell_edit(&mut self, row_idx: usize, col_idx: usize) {
+        if self.doc.sheet.contains(row_idx, col_idx) {
+            let label = format!("Edit {}", Self::cell_name(row_idx, col_idx));
+            let change = CellChange { row: row_idx, col: col_idx, text: self.edit_buffer.clone() };
+            self.edit_cells(label, vec![change]);
+        }
+    }
+
+    fn show_column_header(&mut self, ui: &mut egui::Ui, id: egui::Id, layout: &mut GridLayout, actions: &mut GridActions) {
+        let header = layout.header_rect;
+        let painter = ui.painter_at(header);
+        painter.rect_filled(header, 0.0, ui.visuals().panel_fill);
+
+        let grid_color = ui.visuals().widgets.noninteractive.bg_stroke.color;
+        let text_color = ui.visuals().text_color();
+
+        for col_idx in layout.visible_cols() {
+            let rect = egui::Rect::from_x_y_ranges(layout.col_x(col_idx)..=layout.col_x(col_idx + 1), header.y_range());
+
+            if matches!(&self.selection, Selection::Column(c) if *c == col_idx) {
+                painter.rect_filled(rect, 0.0, HEADER_HIGHLIGHT);
+            }
+            painter.vline(rect.right(), rect.y_range(), egui::Stroke::new(0.5, grid_color));
+
+            // Draw column letter with sort indicator
+            let mut col_text = Self::col_index_to_letter(col_idx);
+            if self.sorted_column == Some(col_idx) {
+                col_text.push(' ');
+                col_text.push(if self.sort_ascending { '^' } else { 'v' });
+            }
+            painter.text(rect.center(), egui::Align2::CENTER_CENTER, col_text, egui::FontId::default(), text_color);
+
+            // Column type in small print on the left
+            let tag = self.column_type(col_idx).tag();
+            if !tag.is_empty() {
+                painter.text(
+                    rect.left_center() + egui::vec2(4.0, 0.0),
+                    egui::Align2::LEFT_CENTER,
+                    tag,
+                    egui::FontId::proportional(10.0),
+                    ui.visuals().weak_text_color(),
+                );
+            }
+
+            // Small downward triangle on filtered columns
+            if self.filters.contains_key(&col_idx) {
+                let center = egui::pos2(rect.right() - 10.0, rect.center().y);
+                let points = vec![
+                    center + egui::vec2(-4.0, -3.0),
+                    center + egui::vec2(4.0, -3.0),
+                    center + egui::vec2(0.0, 3.0),
+                ];
+                painter.add(egui::Shape::convex_polygon(points, ui.visuals().selection.bg_fill, egui::Stroke::NONE));
+            }
+        }
+
+        let response = ui.interact(header, id.with("column_header"), egui::Sense::click());
+        let hovered_col = ui
+            .input(|i| i.pointer.interact_pos())
+            .filter(|pos| header.contains(*pos))
+            .and_then(|pos| layout.col_at(pos.x));
+
+        if response.clicked()
+            && let Some(col_idx) = hovered_col
+        {
+            self.selection = Selection::Column(col_idx);
+            self.editing_cell = None;
+        }
+        if response.secondary_clicked()
+            && let Some(col_idx) = hovered_col
+        {
+            self.grid.context_cell.1 = col_idx;
+        }
+
+        response.context_menu(|ui| {
+            let col_idx = self.grid.context_cell.1;
+            if ui.button("Sort Ascending").clicked() {
+                actions.sort = Some((col_idx, true));
+                ui.close();
+            }
+            if ui.button("Sort Descending").clicked() {
+                actions.sort = Some((col_idx, false));
+                ui.close();
+            }
+            if ui.button("Sort...").clicked() {
+                actions.sort_dialog = Some(col_idx);
+                ui.close();
+            }
+            ui.separator();
+            ui.menu_button("Column Type", |ui| {
+                let current = self.column_type(col_idx);
+                for column_type in ColumnType::ALL {
+                    if ui.radio(current == column_type, column_type.label()).clicked() {
+                        actions.set_type = Some((col_idx, Some(column_type)));
+                        ui.close();
+                    }
+                }
+                ui.separator();
+                if ui.button("Detect Automatically").clicked() {
+                    actions.set_type = Some((col_idx, None));
+                    ui.close();
+                }
+            });
+            if ui.button("Filter...").clicked() {
+                actions.edit_filter = Some(col_idx);
+                ui.close();
+            }
+            if ui.add_enabled(self.filters.contains_key(&col_idx), egui::Button::new("Clear Filter")).clicked() {
+                actions.clear_filter = Some(col_idx);
+                ui.close();
+            }
+            ui.separator();
+            if let Some(picked) = copy_as::copy_as_menu(ui) {
+                actions.copy_as = Some((Selection::Column(col_idx), picked));
+            }
+            ui.separator();
+            if ui.button("Insert Column Left").clicked() {
+                actions.insert_col_at = Some(col_idx);
+                ui.close();
+            }
+            if ui.button("Insert Column Right").clicked() {
+                actions.insert_col_at = Some(col_idx + 1);
+                ui.close();
+            }
+            ui.separator();
+            if ui.button("Delete Column").clicked() {
+                actions.delete_col = Some(col_idx);
+                ui.close();
+            }
+        });
+
+        // Drag column borders to resize
+        for col_idx in layout.visible_cols() {
+            let x = layout.col_x(col_idx + 1);
+            if x < header.left() || x > header.right() {
+                continue;
+            }
+            let handle = egui::Rect::from_center_size(
+                egui::pos2(x, header.center().y),
+                egui::vec2(RESIZE_HANDLE_WIDTH, header.height()),
+            );
+            let handle_response = ui
+                .interact(handle, id.with(("resize", col_idx)), egui::Sense::drag())
+                .on_hover_cursor(egui::CursorIcon::ResizeHorizontal);
+            if handle_response.dragged() {
+                let width = (self.get_column_width(col_idx) + handle_response.drag_delta().x).max(MIN_COLUMN_WIDTH);
+                self.column_widths.insert(col_idx, width);
+            }
+            if handle_response.dragged() || handle_response.hovered() {
+                ui.painter().vline(x, header.y_range(), ui.visuals().widgets.active.fg_stroke);
+            }
+        }
+    }
+
+    fn show_row_header(&mut self, ui: &mut egui::Ui, id: egui::Id, layout: &GridLayout, actions: &mut GridActions) {
+        let area = layout.row_header_rect;
+        let painter = ui.painter_at(area);
+        painter.rect_filled(area, 0.0, ui.visuals().panel_fill);
+
+        let grid_color = ui.visuals().widgets.noninteractive.bg_stroke.color;
+        let text_color = ui.visuals().text_color();
+
+        let rows = (0..layout.frozen_rows).chain(layout.visible_rows());
+        for display_row in rows {
+            let row_idx = layout.data_row(display_row);
+            let top = layout.row_y(display_row);
+            let rect = egui::Rect::from_x_y_ranges(area.x_range(), top..=top + ROW_HEIGHT);
+            // Frozen rows are drawn over the scrolling ones
+            let clip = if display_row < layout.frozen_rows {
+                area
+            } else {
+                egui::Rect::from_x_y_ranges(area.x_range(), layout.body_rect.y_range())
+            };
+            let painter = painter.with_clip_rect(clip);
+
+            if matches!(&self.selection, Selection::Row(r) if *r == row_idx) {
+                painter.rect_filled(rect, 0.0, HEADER_HIGHLIGHT);
+            }
+            painter.hline(rect.x_range(), rect.bottom(), egui::Stroke::new(0.5, grid_color));
+
+            // Draw row number
+            painter.text(rect.center(), egui::Align2::CENTER_CENTER, (row_idx + 1).to_string(), egui::FontId::default(), text_color);
+        }
+
+        let response = ui.interact(area, id.with("row_header"), egui::Sense::click());
+        let hovered_row = ui
+            .input(|i| i.pointer.interact_pos())
+            .filter(|pos| area.contains(*pos))
+            .and_then(|pos| layout.row_at(pos.y));
+
+        if response.clicked()
+            && let Some(row_idx) = hovered_row
+        {
+            self.selection = Selection::Row(row_idx);
+            self.editing_cell = None;
+        }
+        if response.secondary_clicked()
+            && let Some(row_idx) = hovered_row
+        {
+            self.grid.context_cell.0 = row_idx;
+        }
+
+        response.context_menu(|ui| {
+            let row_idx = self.grid.context_cell.0;
+            if let Some(picked) = copy_as::copy_as_menu(ui) {
+                actions.copy_as = Some((Selection::Row(row_idx), picked));
+            }
+            ui.separator();
+            if ui.button("Insert Row Above").clicked() {
+                actions.insert_row_at = Some(row_idx);
+                ui.close();
+            }
+            if ui.button("Insert Row Below").clicked() {
+                actions.insert_row_at = Some(row_idx + 1);
+                ui.close();
+            }
+            ui.separator();
+            if ui.button("Delete Row").clicked() {
+                actions.delete_row = Some(row_idx);
+                ui.close();
+            }
+        });
+    }
+
+    fn show_scrollbars(&mut self, ui: &mut egui::Ui, id: egui::Id, layout: &mut GridLayout) {
+        let body = layout.body_rect;
+
+        let vertical_track = egui::Rect::from_x_y_ranges(body.right()..=body.right() + SCROLLBAR_WIDTH, body.y_range());
+        layout.scroll_top = scrollbar(
+            ui,
+            id.with("vscroll"),
+            vertical_track,
+            true,
+            layout.scroll_top,
+            layout.content_height(),
+            body.height() as f64,
+        );
+
+        let horizontal_track = egui::Rect::from_x_y_ranges(body.x_range(), body.bottom()..=body.bottom() + SCROLLBAR_WIDTH);
+        layout.scroll_left = scrollbar(
+            ui,
+            id.with("hscroll"),
+            horizontal_track,
+            false,
+            layout.scroll_left as f64,
+            layout.content_width() as f64,
+            body.width() as f64,
+        ) as f32;
+
+        self.clamp_scroll(layout);
+    }
+}
+
+// Draw a scrollbar in `track` and return the updated offset. Dragging moves the
+// thumb; clicking the track pages towards the pointer.
+fn scrollbar(
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    track: egui::Rect,
+    vertical: bool,
+    offset: f64,
+    content: f64,
+    viewport: f64,
+) -> f64 {
+    if content <= viewport || viewport <= 0.0 {
+        return 0.0;
+    }
+
+    let axis = |v: egui::Vec2| if vertical { v.y } else { v.x };
+    let track_len = axis(track.size()) as f64;
+    let thumb_len = (track_len * viewport / content).clamp(MIN_THUMB_LENGTH as f64, track_len);
+    let max_offset = content - viewport;
+    let travel = (track_len - thumb_len).max(1.0);
+
+    let response = ui.interact(track, id, egui::Sense::click_and_drag());
+    let mut offset = offset;
+    if response.dragged() {
+        offset += axis(response.drag_delta()) as f64 * max_offset / travel;
+    } else if response.clicked()
+        && let Some(pos) = response.interact_pointer_pos()
+    {
+        let thumb_start = travel * offset / max_offset;
+        let pointer = axis(pos - track.min) as f64;
+        if pointer < thumb_start {
+            offset -= viewport;
+        } else if pointer > thumb_start + thumb_len {
+            offset += viewport;
+        }
+    }
+    let offset = offset.clamp(0.0, max_offset);
+
+    let thumb_start = (travel * offset / max_offset) as f32;
+    let thumb = if vertical {
+        egui::Rect::from_min_size(track.min + egui::vec2(2.0, thumb_start), egui::vec2(track.width() - 4.0, thumb_len as f32))
+    } else {
+        egui::Rect::from_min_size(track.min + egui::vec2(thumb_start, 2.0), egui::vec2(thumb_len as f32, track.height() - 4.0))
+    };
+
+    let visuals = ui.visuals();
+    let thumb_color = if response.dragged() {
+        visuals.widgets.active.bg_fill
+    } else if response.hovered() {
+        visuals.widgets.hovered.bg_fill
+    } else {
+        visuals.widgets.inactive.bg_fill
+    };
+    ui.painter().rect_filled(track, 0.0, visuals.extreme_bg_color);
+    ui.painter().rect_filled(thumb, 3.0, thumb_color);
+
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Five columns 100 wide, a 400 x 250 body (ten rows) below one frozen row
+    fn layout(num_rows: usize, row_map: Option<Vec<usize>>) -> GridLayout {
+        let rect = |left: f32, top: f32, right: f32, bottom: f32| egui::Rect::from_x_y_ranges(left..=right, top..=bottom);
+        GridLayout {
+            num_rows: row_map.as_ref().map_or(num_rows, Vec::len),
+            row_map: row_map.map(Rc::new),
+            num_cols: 5,
+            frozen_rows: 1,
+            header_rect: rect(50.0, 0.0, 450.0, 25.0),
+            frozen_rect: rect(50.0, 25.0, 450.0, 50.0),
+            body_rect: rect(50.0, 50.0, 450.0, 300.0),
+            row_header_rect: rect(0.0, 25.0, 50.0, 300.0),
+            col_left: vec![0.0, 100.0, 200.0, 300.0, 400.0, 500.0],
+            scroll_top: 0.0,
+            scroll_left: 0.0,
+        }
+    }
+
+    #[test]
+    fn only_rows_and_columns_in_view_are_visible() {
+        let mut layout = layout(1_000_000, None);
+        assert_eq!(layout.visible_rows(), 1..11);
+        assert_eq!(layout.visible_cols(), 0..4);
+
+        layout.scroll_top = 2510.0;
+        layout.scroll_left = 150.0;
+        assert_eq!(layout.visible_rows(), 101..112);
+        assert_eq!(layout.visible_cols(), 1..5);
+        assert_eq!(layout.row_y(101), 40.0);
+
+        layout.scroll_top = layout.max_scroll_top();
+        assert_eq!(layout.visible_rows().end, 1_000_000);
+        assert_eq!(layout.max_scroll_left(), 100.0);
+    }
+
+    #[test]
+    fn maps_positions_to_cells() {
+        let mut layout = layout(100, None);
+        layout.scroll_top = 250.0;
+        assert_eq!(layout.cell_at(egui::pos2(60.0, 30.0)), Some((0, 0)));
+        assert_eq!(layout.cell_at(egui::pos2(260.0, 60.0)), Some((11, 2)));
+        // Past the edges clamps to the last row and column
+        assert_eq!(layout.cell_at(egui::pos2(900.0, 9000.0)), Some((99, 4)));
+    }
+
+    #[test]
+    fn filtered_rows_map_to_data_rows() {
+        let layout = layout(0, Some(vec![0, 4, 7, 20]));
+        assert_eq!(layout.visible_rows(), 1..4);
+        assert_eq!(layout.data_row(2), 7);
+        assert_eq!(layout.display_row(20), Some(3));
+        assert_eq!(layout.display_row(5), None);
+        assert_eq!(layout.row_at(80.0), Some(7));
+    }
+}
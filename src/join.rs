@@ -0,0 +1,341 @@
+// Merging columns from another file into the active document, matching rows by
+// a key column on each side like a database join or a spreadsheet lookup.
+//
+// The other file can be one of the open tabs or a file read just for this. The
+// window picks the key columns, the join type and the columns to add, then
+// adds them after the last column as one undoable step and says how many rows
+// matched and which keys the other file has more than once.
+
+use std::path::PathBuf;
+
+use eframe::egui;
+
+use gridview_core::join::{Join, JoinKind, JoinReport};
+use gridview_core::Document;
+
+use crate::SpreadsheetApp;
+
+// Where the added columns come from
+enum LookupSource {
+    Tab(usize),
+    // A file that isn't open in a tab
+    File { path: PathBuf, doc: Box<Document> },
+}
+
+// State of the Merge Columns window
+pub struct JoinDialog {
+    source: Option<LookupSource>,
+    key: usize,
+    other_key: usize,
+    kind: JoinKind,
+    // Which columns of the other document to add
+    columns: Vec<bool>,
+    error: Option<String>,
+    // Set once the columns are added, to show what matched
+    report: Option<JoinReport>,
+}
+
+impl JoinDialog {
+    // Keep the other document's tab while tabs are opened and closed
+    pub(crate) fn tab_inserted(&mut self, index: usize) {
+        if let Some(LookupSource::Tab(tab)) = &mut self.source
+            && *tab >= index
+        {
+            *tab += 1;
+        }
+    }
+
+    // Returns false if the other document was the tab closed
+    pub(crate) fn tab_removed(&mut self, index: usize) -> bool {
+        match &mut self.source {
+            Some(LookupSource::Tab(tab)) if *tab == index => false,
+            Some(LookupSource::Tab(tab)) => {
+                if *tab > index {
+                    *tab -= 1;
+                }
+                true
+            }
+            _ => true,
+        }
+    }
+}
+
+impl SpreadsheetApp {
+    // Open tabs other than the active one that columns can come from
+    fn lookup_tabs(&self) -> Vec<usize> {
+        (0..self.tabs.len())
+            .filter(|&index| index != self.active_tab && !self.tab_is_comparison(index))
+            .collect()
+    }
+
+    pub(crate) fn open_join_dialog(&mut self) {
+        if self.compare.is_some() {
+            return;
+        }
+        if let Some((row, col)) = self.editing_cell.take() {
+            self.commit_cell_edit(row, col);
+        }
+        let mut dialog = JoinDialog {
+            source: None,
+            key: 0,
+            other_key: 0,
+            kind: JoinKind::Left,
+            columns: Vec::new(),
+            error: None,
+            report: None,
+        };
+        if let Some(&index) = self.lookup_tabs().first() {
+            self.set_lookup_source(&mut dialog, LookupSource::Tab(index));
+        }
+        self.join_dialog = Some(dialog);
+    }
+
+    fn lookup_document<'a>(&'a self, source: &'a LookupSource) -> (&'a Document, bool) {
+        match source {
+            LookupSource::Tab(index) => (self.tab_document(*index), self.tab_has_header(*index)),
+            LookupSource::File { doc, .. } => (doc, doc.dialect.has_headers),
+        }
+    }
+
+    // Use another document and guess the key columns: the first column whose
+    // header both documents have, or the first columns. The columns ticked to
+    // be added are the ones whose header this document doesn't have yet.
+    fn set_lookup_source(&self, dialog: &mut JoinDialog, source: LookupSource) {
+        let (other, other_header) = self.lookup_document(&source);
+        let headers = self.freeze_top_row && other_header;
+        let (key, other_key) = if headers {
+            (0..self.doc.sheet.num_cols())
+                .find_map(|col| {
+                    let name = self.doc.sheet.get(0, col);
+                    let other_col = other.find_column(name, true).filter(|&other_col| other.sheet.get(0, other_col) == name);
+                    other_col.filter(|_| !name.is_empty()).map(|other_col| (col, other_col))
+                })
+                .unwrap_or((0, 0))
+        } else {
+            (0, 0)
+        };
+        dialog.key = key;
+        dialog.other_key = other_key;
+        dialog.columns = (0..other.sheet.num_cols())
+            .map(|col| col != other_key && !(headers && self.doc.sheet.row(0).any(|name| name == other.sheet.get(0, col))))
+            .collect();
+        dialog.error = None;
+        dialog.source = Some(source);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn pick_lookup_file(&self, dialog: &mut JoinDialog) {
+        let Some(path) = rfd::FileDialog::new().add_filter("Spreadsheets", crate::OPEN_EXTENSIONS).pick_file() else {
+            return;
+        };
+        match crate::loader::read_document_file(&path) {
+            Ok(doc) => self.set_lookup_source(dialog, LookupSource::File { path, doc: Box::new(doc) }),
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path.display(), e);
+                dialog.error = Some(format!("Could not read {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    fn lookup_source_name(&self, source: &LookupSource) -> String {
+        match source {
+            LookupSource::Tab(index) => self.tab_name(*index),
+            LookupSource::File { path, .. } => {
+                path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+            }
+        }
+    }
+
+    pub(crate) fn show_join_dialog(&mut self, ctx: &egui::Context) {
+        let Some(mut dialog) = self.join_dialog.take() else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        let mut new_source = None;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut browse = false;
+
+        egui::Window::new("Merge Columns from File")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                if let Some(report) = &dialog.report {
+                    let removed = if dialog.kind == JoinKind::Inner { " and were removed" } else { "" };
+                    ui.label(format!("{} rows matched, {} rows had no match{}.", report.matched, report.unmatched, removed));
+                    if report.unused > 0 {
+                        let outcome = match dialog.kind {
+                            JoinKind::FullOuter => "added as new rows",
+                            _ => "not used",
+                        };
+                        ui.label(format!("{} rows of the other file matched no row and were {}.", report.unused, outcome));
+                    }
+                    if report.duplicate_count > 0 {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("{} keys appear more than once in the other file; their first row was used:", report.duplicate_count),
+                        );
+                        let mut keys = report.duplicate_keys.join(", ");
+                        if report.duplicate_count > report.duplicate_keys.len() {
+                            keys.push_str(", …");
+                        }
+                        ui.label(keys);
+                    }
+                    ui.add_space(10.0);
+                    if ui.button("Close").clicked() {
+                        cancelled = true;
+                    }
+                    return;
+                }
+
+                let tabs = self.lookup_tabs();
+                egui::Grid::new("join_options")
+                    .num_columns(2)
+                    .spacing([10.0, 6.0])
+                    .show(ui, |ui| {
+                        ui.label("Other file:");
+                        ui.horizontal(|ui| {
+                            let selected = dialog.source.as_ref().map(|source| self.lookup_source_name(source)).unwrap_or_default();
+                            egui::ComboBox::from_id_salt("join_source")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    for &index in &tabs {
+                                        let current = matches!(dialog.source, Some(LookupSource::Tab(tab)) if tab == index);
+                                        if ui.selectable_label(current, self.tab_name(index)).clicked() && !current {
+                                            new_source = Some(LookupSource::Tab(index));
+                                        }
+                                    }
+                                });
+                            #[cfg(not(target_arch = "wasm32"))]
+                            if ui.button("Open File...").clicked() {
+                                browse = true;
+                            }
+                        });
+                        ui.end_row();
+
+                        let Some(source) = &dialog.source else {
+                            return;
+                        };
+                        let (other, other_header) = self.lookup_document(source);
+
+                        ui.label("Key column here:");
+                        egui::ComboBox::from_id_salt("join_key")
+                            .selected_text(Self::column_label(&self.doc, self.freeze_top_row, dialog.key))
+                            .show_ui(ui, |ui| {
+                                for col in 0..self.doc.sheet.num_cols() {
+                                    ui.selectable_value(&mut dialog.key, col, Self::column_label(&self.doc, self.freeze_top_row, col));
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Key column there:");
+                        egui::ComboBox::from_id_salt("join_other_key")
+                            .selected_text(Self::column_label(other, other_header, dialog.other_key))
+                            .show_ui(ui, |ui| {
+                                for col in 0..other.sheet.num_cols() {
+                                    ui.selectable_value(&mut dialog.other_key, col, Self::column_label(other, other_header, col));
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Join:");
+                        ui.vertical(|ui| {
+                            for kind in JoinKind::ALL {
+                                ui.radio_value(&mut dialog.kind, kind, kind.label());
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Columns to add:");
+                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            for (col, add) in dialog.columns.iter_mut().enumerate() {
+                                ui.checkbox(add, Self::column_label(other, other_header, col));
+                            }
+                        });
+                        ui.end_row();
+                    });
+
+                if tabs.is_empty() && dialog.source.is_none() {
+                    ui.label("Open the other file in a tab first.");
+                }
+                if let Some(error) = &dialog.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    let ready = dialog.source.is_some() && dialog.columns.contains(&true) && self.doc.sheet.num_cols() > 0;
+                    if ui.add_enabled(ready, egui::Button::new("Merge")).clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if let Some(source) = new_source {
+            self.set_lookup_source(&mut dialog, source);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if browse {
+            self.pick_lookup_file(&mut dialog);
+        }
+        if confirmed {
+            self.merge_columns(&mut dialog);
+        }
+        if !cancelled {
+            self.join_dialog = Some(dialog);
+        }
+    }
+
+    // Add the chosen columns as one step in the undo history
+    fn merge_columns(&mut self, dialog: &mut JoinDialog) {
+        let Some(source) = &dialog.source else {
+            return;
+        };
+        let (other, other_header) = self.lookup_document(source);
+        let join = Join {
+            key: dialog.key,
+            other_key: dialog.other_key,
+            columns: (0..dialog.columns.len()).filter(|&col| dialog.columns[col]).collect(),
+            kind: dialog.kind,
+            header: self.freeze_top_row,
+            other_header,
+        };
+        let (edits, report) = join.edits(&self.doc.sheet, &other.sheet);
+
+        let first_new = self.doc.sheet.num_cols();
+        self.apply_operation("Merge Columns", edits);
+        for col in first_new..first_new + join.columns.len() {
+            self.set_column_type(col, None);
+        }
+        dialog.report = Some(report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialog_follows_the_other_tab() {
+        let mut dialog = JoinDialog {
+            source: Some(LookupSource::Tab(2)),
+            key: 0,
+            other_key: 0,
+            kind: JoinKind::Left,
+            columns: Vec::new(),
+            error: None,
+            report: None,
+        };
+        dialog.tab_inserted(1);
+        assert!(matches!(dialog.source, Some(LookupSource::Tab(3))));
+        assert!(dialog.tab_removed(0));
+        assert!(matches!(dialog.source, Some(LookupSource::Tab(2))));
+        assert!(dialog.tab_removed(4));
+        assert!(!dialog.tab_removed(2));
+    }
+}
@@ -0,0 +1,489 @@
+// Loading files without blocking the UI.
+//
+// Native builds read and parse on a worker thread. The browser has no threads,
+// so the WASM build parses from an async task that yields to the event loop
+// between chunks of rows. Either way the worker only touches the shared
+// `LoadProgress`, which `update` polls every frame like `AsyncFileResult`, and
+// the open document is left alone until the new one is complete.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use gridview_core::dialect::{CsvDialect, CsvParser, ParsedCsv};
+use gridview_core::encoding::TextEncoding;
+use gridview_core::columnar::{self, ColumnarFormat};
+use gridview_core::json;
+#[cfg(not(target_arch = "wasm32"))]
+use gridview_core::sqlite::{self, Database, Source, SqlTable};
+use gridview_core::xlsx::{self, Workbook};
+use gridview_core::{Document, Sheet};
+
+// Rows parsed between progress updates and cancellation checks
+const CHUNK_ROWS: usize = 10_000;
+
+#[cfg(not(target_arch = "wasm32"))]
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+pub enum LoadResult {
+    // Raw file contents, ready for the import dialog
+    Read(Vec<u8>),
+    Parsed(ParsedCsv),
+    // An opened workbook, ready for the user to pick a worksheet
+    Workbook(Workbook),
+    Sheet(Sheet),
+    // A file read whole into a document, such as JSON or Parquet
+    Document(Box<Document>),
+    // Rows read from a database table or query
+    #[cfg(not(target_arch = "wasm32"))]
+    Table(SqlTable),
+    Failed(String),
+}
+
+#[derive(Default)]
+pub struct LoadProgress {
+    pub bytes_done: usize,
+    pub bytes_total: usize,
+    pub rows: usize,
+    pub cancelled: bool,
+    pub result: Option<LoadResult>,
+}
+
+// A worksheet picked from a workbook, or a table or query read from a database
+#[derive(Clone)]
+pub struct SheetSource {
+    pub name: String,
+    // Whether the file holds more than this sheet, which saving over it would
+    // lose. Always set for databases, so the tab names the table.
+    pub other_sheets: bool,
+}
+
+// A file being read or parsed in the background
+pub struct BackgroundLoad {
+    pub path: PathBuf,
+    // Dialect and encoding confirmed in the import dialog; None while the raw
+    // file is still being read
+    pub format: Option<(CsvDialect, TextEncoding)>,
+    // Worksheet being read, for workbooks, or table for databases
+    pub sheet: Option<SheetSource>,
+    progress: Arc<Mutex<LoadProgress>>,
+}
+
+impl BackgroundLoad {
+    // Read a file from disk in chunks
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read(path: PathBuf) -> Self {
+        let progress = Arc::new(Mutex::new(LoadProgress::default()));
+        let shared = progress.clone();
+        let file_path = path.clone();
+
+        std::thread::spawn(move || {
+            let result = match read_file(&file_path, &shared) {
+                Ok(Some(bytes)) if xlsx::is_workbook_path(&file_path) => match Workbook::from_bytes(bytes) {
+                    Ok(workbook) => LoadResult::Workbook(workbook),
+                    Err(e) => LoadResult::Failed(e.to_string()),
+                },
+                Ok(Some(bytes)) if is_document_path(&file_path) => parse_document(bytes, &file_path),
+                Ok(Some(bytes)) => LoadResult::Read(bytes),
+                // Cancelled
+                Ok(None) => return,
+                Err(e) => LoadResult::Failed(e.to_string()),
+            };
+            if let Ok(mut guard) = shared.lock() {
+                guard.result = Some(result);
+            }
+        });
+
+        Self {
+            path,
+            format: None,
+            sheet: None,
+            progress,
+        }
+    }
+
+    // Decode and parse the whole file with the dialect chosen in the import dialog
+    pub fn parse(bytes: Vec<u8>, path: PathBuf, dialect: CsvDialect, encoding: TextEncoding) -> Self {
+        let progress = Arc::new(Mutex::new(LoadProgress {
+            bytes_total: bytes.len(),
+            ..Default::default()
+        }));
+        let shared = progress.clone();
+        let job_dialect = dialect.clone();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            std::thread::spawn(move || {
+                let mut job = ParseJob::new(bytes, &job_dialect, &encoding);
+                while job.step(&shared) {}
+            });
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(async move {
+                let mut job = ParseJob::new(bytes, &job_dialect, &encoding);
+                while job.step(&shared) {
+                    yield_to_browser().await;
+                }
+            });
+        }
+
+        Self {
+            path,
+            format: Some((dialect, encoding)),
+            sheet: None,
+            progress,
+        }
+    }
+
+    // Read one worksheet of a workbook. Cells come out of the workbook in one
+    // go, so there is no progress to report until it is done.
+    pub fn read_sheet(mut workbook: Workbook, path: PathBuf, sheet: SheetSource) -> Self {
+        let progress = Arc::new(Mutex::new(LoadProgress::default()));
+        let shared = progress.clone();
+        let name = sheet.name.clone();
+        let job = move || {
+            let result = match workbook.read_sheet(&name) {
+                Ok(cells) => LoadResult::Sheet(cells),
+                Err(e) => LoadResult::Failed(e.to_string()),
+            };
+            if let Ok(mut guard) = shared.lock()
+                && !guard.cancelled
+            {
+                guard.result = Some(result);
+            }
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            std::thread::spawn(job);
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(async move {
+                // Paint the progress window before blocking on the read
+                yield_to_browser().await;
+                job();
+            });
+        }
+
+        Self {
+            path,
+            format: None,
+            sheet: Some(sheet),
+            progress,
+        }
+    }
+
+    // Parse a JSON, Parquet or Arrow file that has already been read, as the
+    // browser hands over whole files. Like a worksheet, there is no progress to
+    // report.
+    pub fn read_document(bytes: Vec<u8>, path: PathBuf) -> Self {
+        let progress = Arc::new(Mutex::new(LoadProgress {
+            bytes_total: bytes.len(),
+            ..Default::default()
+        }));
+        let shared = progress.clone();
+        let file_path = path.clone();
+        let job = move || {
+            let result = parse_document(bytes, &file_path);
+            if let Ok(mut guard) = shared.lock()
+                && !guard.cancelled
+            {
+                guard.result = Some(result);
+            }
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            std::thread::spawn(job);
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            wasm_bindgen_futures::spawn_local(async move {
+                yield_to_browser().await;
+                job();
+            });
+        }
+
+        Self {
+            path,
+            format: None,
+            sheet: None,
+            progress,
+        }
+    }
+
+    // Read a table, or the rows of a query, from a database. Like a worksheet,
+    // there is no progress to report until it is done.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_table(path: PathBuf, source: Source) -> Self {
+        let progress = Arc::new(Mutex::new(LoadProgress::default()));
+        let shared = progress.clone();
+        let file_path = path.clone();
+        let name = match &source {
+            Source::Table(name) => name.clone(),
+            Source::Query(_) => "Query".to_string(),
+        };
+
+        std::thread::spawn(move || {
+            let result = match Database::open(&file_path).and_then(|database| database.read(&source)) {
+                Ok(table) => LoadResult::Table(table),
+                Err(e) => LoadResult::Failed(e.to_string()),
+            };
+            if let Ok(mut guard) = shared.lock()
+                && !guard.cancelled
+            {
+                guard.result = Some(result);
+            }
+        });
+
+        Self {
+            path,
+            format: None,
+            sheet: Some(SheetSource { name, other_sheets: true }),
+            progress,
+        }
+    }
+
+    // Stop the worker. It notices at its next chunk and discards what it has.
+    pub fn cancel(&self) {
+        if let Ok(mut guard) = self.progress.lock() {
+            guard.cancelled = true;
+        }
+    }
+
+    pub fn take_result(&self) -> Option<LoadResult> {
+        self.progress.lock().ok().and_then(|mut guard| guard.result.take())
+    }
+
+    // Fraction done and a line describing it for the progress bar
+    pub fn status(&self) -> (f32, String) {
+        if let Some(sheet) = &self.sheet {
+            #[cfg(not(target_arch = "wasm32"))]
+            if sqlite::is_sqlite_path(&self.path) {
+                return (0.0, format!("Reading \"{}\"", sheet.name));
+            }
+            return (0.0, format!("Worksheet \"{}\"", sheet.name));
+        }
+        let Ok(guard) = self.progress.lock() else {
+            return (0.0, String::new());
+        };
+        let fraction = if guard.bytes_total > 0 {
+            guard.bytes_done as f32 / guard.bytes_total as f32
+        } else {
+            0.0
+        };
+        let mut text = format!("{} of {}", format_bytes(guard.bytes_done), format_bytes(guard.bytes_total));
+        if self.format.is_some() {
+            text.push_str(&format!(", {} rows", guard.rows));
+        }
+        (fraction, text)
+    }
+}
+
+// Files that are read straight into a document, without the import dialog
+pub fn is_document_path(path: &Path) -> bool {
+    json::is_json_path(path) || columnar::is_columnar_path(path)
+}
+
+// Read a whole file into a document on the spot, for files used alongside the
+// open one: a CSV, JSON, Parquet or Arrow file, or the first worksheet of a
+// workbook
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_document_file(path: &Path) -> Result<Document, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    if json::is_json_path(path) {
+        return Document::from_json(&bytes).map_err(|e| e.to_string());
+    }
+    if let Some(format) = ColumnarFormat::for_path(path) {
+        return Document::from_columnar(bytes, format).map_err(|e| e.to_string());
+    }
+    if !xlsx::is_workbook_path(path) {
+        return Document::from_bytes(&bytes).map_err(|e| e.to_string());
+    }
+    let mut workbook = Workbook::from_bytes(bytes).map_err(|e| e.to_string())?;
+    let Some(name) = workbook.sheet_names().into_iter().next() else {
+        return Err("The workbook has no worksheets".to_string());
+    };
+    let sheet = workbook.read_sheet(&name).map_err(|e| e.to_string())?;
+    Ok(Document::from_sheet(sheet))
+}
+
+fn parse_document(bytes: Vec<u8>, path: &Path) -> LoadResult {
+    let doc = match ColumnarFormat::for_path(path) {
+        Some(format) => Document::from_columnar(bytes, format).map_err(|e| e.to_string()),
+        None => Document::from_json(&bytes).map_err(|e| e.to_string()),
+    };
+    match doc {
+        Ok(doc) => LoadResult::Document(Box::new(doc)),
+        Err(e) => LoadResult::Failed(e),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_file(path: &PathBuf, progress: &Mutex<LoadProgress>) -> std::io::Result<Option<Vec<u8>>> {
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)?;
+    let total = file.metadata().map(|m| m.len() as usize).unwrap_or(0);
+    if let Ok(mut guard) = progress.lock() {
+        guard.bytes_total = total;
+    }
+
+    let mut bytes = Vec::with_capacity(total);
+    let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut chunk)?;
+        if n == 0 {
+            return Ok(Some(bytes));
+        }
+        bytes.extend_from_slice(&chunk[..n]);
+
+        let Ok(mut guard) = progress.lock() else {
+            return Ok(None);
+        };
+        if guard.cancelled {
+            return Ok(None);
+        }
+        guard.bytes_done = bytes.len();
+        guard.bytes_total = guard.bytes_total.max(bytes.len());
+    }
+}
+
+// Parsing state carried between chunks
+struct ParseJob {
+    parser: CsvParser<Vec<u8>>,
+    parsed: ParsedCsv,
+    // Size of the raw file, used to scale progress through the decoded text
+    raw_len: usize,
+}
+
+impl ParseJob {
+    // Takes the raw bytes so they are gone before parsing starts: a large
+    // file is then only held once, as decoded text
+    fn new(bytes: Vec<u8>, dialect: &CsvDialect, encoding: &TextEncoding) -> Self {
+        let raw_len = bytes.len();
+        let text = encoding.decode_owned(bytes);
+        Self {
+            parser: CsvParser::new(text.into_bytes(), dialect),
+            parsed: ParsedCsv::default(),
+            raw_len,
+        }
+    }
+
+    // Parse the next chunk and publish progress. Returns false when the job is
+    // finished or has been cancelled.
+    fn step(&mut self, progress: &Mutex<LoadProgress>) -> bool {
+        let read = self.parser.read_rows(&mut self.parsed, CHUNK_ROWS);
+
+        let Ok(mut guard) = progress.lock() else {
+            return false;
+        };
+        if guard.cancelled {
+            return false;
+        }
+        let more = match read {
+            Ok(more) => more,
+            Err(e) => {
+                guard.result = Some(LoadResult::Failed(e.to_string()));
+                return false;
+            }
+        };
+
+        let decoded_len = self.parser.bytes().len().max(1);
+        guard.bytes_done = (self.parser.position() as f64 / decoded_len as f64 * self.raw_len as f64) as usize;
+        guard.rows = self.parsed.rows.num_rows();
+        if !more {
+            self.parsed.rows.shrink_to_fit();
+            guard.bytes_done = guard.bytes_total;
+            guard.result = Some(LoadResult::Parsed(std::mem::take(&mut self.parsed)));
+        }
+        more
+    }
+}
+
+// Let the browser handle input and paint a frame before the next chunk
+#[cfg(target_arch = "wasm32")]
+async fn yield_to_browser() {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        if let Some(window) = web_sys::window() {
+            let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 0);
+        }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(rows: usize) -> Vec<u8> {
+        (0..rows).map(|row| format!("{},x\n", row)).collect::<String>().into_bytes()
+    }
+
+    #[test]
+    fn parses_in_chunks_and_reports_progress() {
+        let bytes = numbers(CHUNK_ROWS * 2 + 5);
+        let total = bytes.len();
+        let progress = Mutex::new(LoadProgress { bytes_total: total, ..Default::default() });
+        let mut job = ParseJob::new(bytes, &CsvDialect::default(), &TextEncoding::default());
+        assert!(job.step(&progress));
+        {
+            let guard = progress.lock().unwrap();
+            assert_eq!(guard.rows, CHUNK_ROWS);
+            assert!(guard.bytes_done > 0 && guard.bytes_done < total);
+            assert!(guard.result.is_none());
+        }
+        while job.step(&progress) {}
+        let guard = progress.lock().unwrap();
+        assert_eq!(guard.bytes_done, total);
+        let Some(LoadResult::Parsed(parsed)) = &guard.result else {
+            panic!("no rows parsed");
+        };
+        assert_eq!(parsed.rows.num_rows(), CHUNK_ROWS * 2 + 5);
+        assert_eq!(parsed.rows.get(CHUNK_ROWS, 0), CHUNK_ROWS.to_string());
+    }
+
+    #[test]
+    fn cancelling_discards_the_rows() {
+        let bytes = numbers(CHUNK_ROWS * 3);
+        let progress = Mutex::new(LoadProgress::default());
+        let mut job = ParseJob::new(bytes, &CsvDialect::default(), &TextEncoding::default());
+        assert!(job.step(&progress));
+        progress.lock().unwrap().cancelled = true;
+        assert!(!job.step(&progress));
+        assert!(progress.lock().unwrap().result.is_none());
+    }
+
+    #[test]
+    fn parses_on_a_worker_thread() {
+        let load = BackgroundLoad::parse(numbers(3), PathBuf::from("n.csv"), CsvDialect::default(), TextEncoding::default());
+        let result = loop {
+            if let Some(result) = load.take_result() {
+                break result;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        };
+        assert!(matches!(result, LoadResult::Parsed(parsed) if parsed.rows.num_rows() == 3));
+        assert_eq!(load.status().1, "12 B of 12 B, 3 rows");
+        assert_eq!(format_bytes(3 * 1024 * 1024 / 2), "1.5 MB");
+    }
+}
@@ -1,7 +1,49 @@
 use eframe::egui;
-use egui_extras::{TableBuilder, Column};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
+mod compare;
+mod copy_as;
+#[cfg(not(target_arch = "wasm32"))]
+mod database;
+mod duplicates;
+mod filter;
+mod grid;
+mod join;
+mod loader;
+#[cfg(not(target_arch = "wasm32"))]
+mod query;
+mod search;
+mod sort;
+mod tabs;
+mod types;
+
+use gridview_core::columnar::{ColumnarFormat, ColumnarOptions};
+use gridview_core::dialect::{self, CsvDialect, LineTerminator, ParsedCsv, QuoteStyle};
+use gridview_core::encoding::{self, TextEncoding};
+use gridview_core::filter::ColumnFilter;
+use gridview_core::history::{CellChange, Edit};
+use gridview_core::json::{JsonLayout, JsonOptions};
+#[cfg(not(target_arch = "wasm32"))]
+use gridview_core::sqlite::{self, TableLink};
+use gridview_core::xlsx::{self, XlsxOptions};
+use gridview_core::{clipboard, ColumnType, Document, SaveOptions, Sheet};
+use compare::{CompareDialog, CompareView};
+use copy_as::CopyAsDialog;
+#[cfg(not(target_arch = "wasm32"))]
+use database::{DatabaseDialog, ExportTableDialog};
+use duplicates::{DuplicateDialog, DuplicateHighlight};
+use filter::FilterEditor;
+use grid::GridState;
+use join::JoinDialog;
+use loader::{BackgroundLoad, LoadResult, SheetSource};
+#[cfg(not(target_arch = "wasm32"))]
+use query::QueryPanel;
+use sort::SortDialog;
+use tabs::DocumentTab;
 
 // WASM-specific imports
 #[cfg(target_arch = "wasm32")]
@@ -71,11 +113,169 @@ enum Selection {
     Row(usize),
 }
 
+// File extensions accepted by the open dialog and drag-and-drop
+const OPEN_EXTENSIONS: &[&str] = &["csv", "tsv", "txt", xlsx::EXTENSION, "json", "ndjson", "jsonl", "parquet", "arrow", "feather"];
+
+// Extensions offered when saving as delimited text
+const DELIMITED_EXTENSIONS: &[&str] = &["csv", "tsv", "txt"];
+
+// Rows shown in the import dialog preview
+const IMPORT_PREVIEW_ROWS: usize = 20;
+
+// A file that has been read but not yet loaded into the grid, waiting for the
+// user to confirm the guessed dialect in the import dialog
+struct PendingImport {
+    bytes: Vec<u8>,
+    path: PathBuf,
+    encoding: TextEncoding,
+    // Start of the file decoded to UTF-8, used for sniffing and the preview.
+    // The whole file is only decoded once the import is confirmed.
+    sample: String,
+    dialect: CsvDialect,
+    custom_delimiter: String,
+    preview: ParsedCsv,
+}
+
+impl PendingImport {
+    fn new(bytes: Vec<u8>, path: PathBuf) -> Self {
+        let encoding = TextEncoding::detect(&bytes[..bytes.len().min(dialect::SNIFF_SAMPLE_SIZE)]);
+        let mut import = Self {
+            bytes,
+            path,
+            encoding,
+            sample: String::new(),
+            dialect: CsvDialect::default(),
+            custom_delimiter: String::new(),
+            preview: ParsedCsv::default(),
+        };
+        import.decode();
+        import
+    }
+
+    // Decode with the current encoding and guess the dialect again, since a
+    // wrong encoding makes the earlier guess meaningless
+    fn decode(&mut self) {
+        (self.sample, self.dialect) = dialect::sniff_sample(&self.bytes, &self.encoding);
+        self.refresh_preview();
+    }
+
+    fn refresh_preview(&mut self) {
+        // The sample is far too small to fill a column
+        self.preview = dialect::read_csv(self.sample.as_bytes(), &self.dialect, Some(IMPORT_PREVIEW_ROWS)).unwrap_or_default();
+    }
+}
+
+// A workbook with several worksheets, waiting for the user to pick one
+struct PendingWorkbook {
+    workbook: xlsx::Workbook,
+    path: PathBuf,
+    sheets: Vec<String>,
+    selected: usize,
+}
+
+// File formats Save As can write
+#[derive(Clone, Copy, PartialEq)]
+enum SaveFormat {
+    Delimited,
+    Workbook,
+    Json(JsonLayout),
+    Columnar(ColumnarFormat),
+}
+
+impl SaveFormat {
+    const ALL: [SaveFormat; 6] = [
+        SaveFormat::Delimited,
+        SaveFormat::Workbook,
+        SaveFormat::Json(JsonLayout::Array),
+        SaveFormat::Json(JsonLayout::Lines),
+        SaveFormat::Columnar(ColumnarFormat::Parquet),
+        SaveFormat::Columnar(ColumnarFormat::Arrow),
+    ];
+
+    // Format a file is written in, going by its extension
+    fn for_path(path: &Path) -> Self {
+        if xlsx::is_workbook_path(path) {
+            SaveFormat::Workbook
+        } else if let Some(layout) = JsonLayout::for_path(path) {
+            SaveFormat::Json(layout)
+        } else if let Some(format) = ColumnarFormat::for_path(path) {
+            SaveFormat::Columnar(format)
+        } else {
+            SaveFormat::Delimited
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SaveFormat::Delimited => "Delimited text",
+            SaveFormat::Workbook => "Excel workbook (.xlsx)",
+            SaveFormat::Json(JsonLayout::Array) => "JSON array (.json)",
+            SaveFormat::Json(JsonLayout::Lines) => "Newline-delimited JSON (.ndjson)",
+            SaveFormat::Columnar(ColumnarFormat::Parquet) => "Parquet (.parquet)",
+            SaveFormat::Columnar(ColumnarFormat::Arrow) => "Arrow IPC (.arrow)",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            SaveFormat::Delimited => "csv",
+            SaveFormat::Workbook => xlsx::EXTENSION,
+            SaveFormat::Json(layout) => layout.extension(),
+            SaveFormat::Columnar(format) => format.extension(),
+        }
+    }
+
+    // Whether formulas can be saved as formulas; the other formats hold
+    // computed values
+    fn keeps_formulas(self) -> bool {
+        matches!(self, SaveFormat::Delimited | SaveFormat::Workbook)
+    }
+}
+
+// Format options chosen in the Save As dialog before picking a destination
+struct SaveAsDialog {
+    format: SaveFormat,
+    dialect: CsvDialect,
+    encoding: TextEncoding,
+    save_values: bool,
+    save_normalized: bool,
+    save_nested: bool,
+    // Type of each column, which Parquet and Arrow files are written with
+    column_types: Vec<ColumnType>,
+    custom_delimiter: String,
+}
+
+// Delimiter combo box plus a one-character field for anything not in the list.
+// Returns true when the delimiter changed.
+fn delimiter_picker(ui: &mut egui::Ui, id_salt: &str, delimiter: &mut u8, custom: &mut String) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt(id_salt)
+            .selected_text(dialect::delimiter_label(*delimiter))
+            .show_ui(ui, |ui| {
+                for (value, name) in dialect::DELIMITERS {
+                    changed |= ui.selectable_value(delimiter, *value, *name).changed();
+                }
+            });
+        ui.label("Other:");
+        let response = ui.add(egui::TextEdit::singleline(custom)
+            .desired_width(24.0)
+            .char_limit(1));
+        if response.changed()
+            && let [b] = custom.as_bytes()
+        {
+            *delimiter = *b;
+            changed = true;
+        }
+    });
+    changed
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum PendingAction {
     None,
-    NewFile,
-    OpenFile,
+    // Close the active tab, which has unsaved changes
+    CloseTab,
     Exit,
 }
 
@@ -96,6 +296,15 @@ fn load_icon() -> Option<egui::IconData> {
 // Native entry point
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result<()> {
+    // A command runs without a window; anything else is a file to open
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(first) = args.first()
+        && cli::is_command(first)
+    {
+        std::process::exit(cli::run(&args));
+    }
+    let open_path = args.first().map(PathBuf::from);
+
     let mut viewport = egui::ViewportBuilder::default()
         .with_inner_size([1200.0, 800.0]);
 
@@ -111,7 +320,13 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "GridView",
         options,
-        Box::new(|_cc| Ok(Box::new(SpreadsheetApp::default()))),
+        Box::new(|_cc| {
+            let mut app = SpreadsheetApp::default();
+            if let Some(path) = open_path {
+                app.load_csv(path);
+            }
+            Ok(Box::new(app))
+        }),
     )
 }
 
@@ -167,7 +382,12 @@ fn main() {
 }
 
 struct SpreadsheetApp {
-    data: Vec<Vec<String>>,
+    // The open file: cells, formulas, format, column types and undo history
+    doc: Document,
+    // Every open document in tab order. The active one's state is in the
+    // fields of this struct and its slot holds a placeholder.
+    tabs: Vec<DocumentTab>,
+    active_tab: usize,
     file_path: Option<PathBuf>,
     editing_cell: Option<(usize, usize)>,
     edit_buffer: String,
@@ -176,12 +396,11 @@ struct SpreadsheetApp {
     selection: Selection,
     drag_start: Option<(usize, usize)>,
     clipboard: ClipboardContext,
-    undo_stack: Vec<Vec<Vec<String>>>,
-    redo_stack: Vec<Vec<Vec<String>>>,
     pending_action: PendingAction,
     has_unsaved_changes: bool,
+    // Whether edits are refused, for query results
+    read_only: bool,
     allowed_to_close: bool,
-    table_id_salt: u64, // Change this to reset table state
     dark_mode: bool,
     // Search functionality
     search_window_open: bool,
@@ -189,10 +408,57 @@ struct SpreadsheetApp {
     search_case_sensitive: bool,
     search_results: Vec<(usize, usize)>,
     current_search_result: usize,
+    search_regex: bool,
+    search_whole_cell: bool,
+    // Selection the search is limited to, captured when the option is ticked
+    search_scope: Option<Selection>,
+    search_error: Option<String>,
+    search_focus_pending: bool,
+    replace_query: String,
+    // Number of matching cells Replace All would change
+    replace_preview: usize,
     // Sort tracking
     sorted_column: Option<usize>,
     sort_ascending: bool,
+    sort_dialog: Option<SortDialog>,
+    compare_dialog: Option<CompareDialog>,
+    join_dialog: Option<JoinDialog>,
+    duplicate_dialog: Option<DuplicateDialog>,
+    duplicate_highlight: Option<DuplicateHighlight>,
+    // Comparison shown in place of the grid, for tabs that compare two documents
+    compare: Option<CompareView>,
     freeze_top_row: bool,
+    // Column filters and the data rows they let through, ascending; None
+    // while no filter is set
+    filters: BTreeMap<usize, ColumnFilter>,
+    visible_rows: Option<Rc<Vec<usize>>>,
+    filter_editor: Option<FilterEditor>,
+    pending_import: Option<PendingImport>,
+    pending_workbook: Option<PendingWorkbook>,
+    // Worksheet the document was read from, for files opened from a workbook
+    sheet_source: Option<SheetSource>,
+    // Database table the document was read from, for writing edits back
+    #[cfg(not(target_arch = "wasm32"))]
+    table_link: Option<TableLink>,
+    #[cfg(not(target_arch = "wasm32"))]
+    database_dialog: Option<DatabaseDialog>,
+    #[cfg(not(target_arch = "wasm32"))]
+    export_table_dialog: Option<ExportTableDialog>,
+    #[cfg(not(target_arch = "wasm32"))]
+    query_panel: QueryPanel,
+    copy_as_dialog: Option<CopyAsDialog>,
+    save_as: Option<SaveAsDialog>,
+    // Whether saving writes formula results instead of the formulas
+    save_values: bool,
+    // Whether saving writes typed cells in plain form
+    save_normalized: bool,
+    // Whether saving as JSON turns dotted column names into nested objects
+    save_nested: bool,
+    import_problems_open: bool,
+    history_open: bool,
+    grid: GridState,
+    // File being read or parsed in the background
+    loading: Option<BackgroundLoad>,
     #[cfg(target_arch = "wasm32")]
     async_file_loading: Arc<Mutex<AsyncFileResult>>,
 }
@@ -200,7 +466,9 @@ struct SpreadsheetApp {
 impl Default for SpreadsheetApp {
     fn default() -> Self {
         Self {
-            data: vec![vec![String::new(); 10]; 20],
+            doc: Document::new(20, 10),
+            tabs: vec![DocumentTab::default()],
+            active_tab: 0,
             file_path: None,
             editing_cell: None,
             edit_buffer: String::new(),
@@ -209,21 +477,55 @@ impl Default for SpreadsheetApp {
             selection: Selection::None,
             drag_start: None,
             clipboard: ClipboardContext::new().unwrap(),
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
             pending_action: PendingAction::None,
             has_unsaved_changes: false,
+            read_only: false,
             allowed_to_close: false,
-            table_id_salt: 0,
             dark_mode: true, // Default to dark mode
             search_window_open: false,
             search_query: String::new(),
             search_case_sensitive: false,
             search_results: Vec::new(),
             current_search_result: 0,
+            search_regex: false,
+            search_whole_cell: false,
+            search_scope: None,
+            search_error: None,
+            search_focus_pending: false,
+            replace_query: String::new(),
+            replace_preview: 0,
             sorted_column: None,
             sort_ascending: true,
+            sort_dialog: None,
+            compare_dialog: None,
+            join_dialog: None,
+            duplicate_dialog: None,
+            duplicate_highlight: None,
+            compare: None,
             freeze_top_row: false,
+            filters: BTreeMap::new(),
+            visible_rows: None,
+            filter_editor: None,
+            pending_import: None,
+            pending_workbook: None,
+            sheet_source: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            table_link: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            database_dialog: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            export_table_dialog: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            query_panel: QueryPanel::default(),
+            copy_as_dialog: None,
+            save_as: None,
+            save_values: false,
+            save_normalized: false,
+            save_nested: false,
+            import_problems_open: false,
+            history_open: false,
+            grid: GridState::default(),
+            loading: None,
             #[cfg(target_arch = "wasm32")]
             async_file_loading: Arc::new(Mutex::new(AsyncFileResult::default())),
         }
@@ -244,86 +546,53 @@ impl SpreadsheetApp {
         result
     }
 
-    fn normalize_data(&mut self) {
-        let max_cols = self.data.iter().map(|r| r.len()).max().unwrap_or(0);
-        for row in &mut self.data {
-            if row.len() < max_cols {
-                row.resize(max_cols, String::new());
-            }
-        }
+    // Column letter, with the header name when there is one
+    fn column_label(doc: &Document, header: bool, col: usize) -> String {
+        let letter = Self::col_index_to_letter(col);
+        let name = doc.sheet.get(0, col);
+        if header && !name.is_empty() { format!("{} ({})", letter, name) } else { letter }
+    }
+
+    // A1-style name of a cell
+    fn cell_name(row_idx: usize, col_idx: usize) -> String {
+        format!("{}{}", Self::col_index_to_letter(col_idx), row_idx + 1)
     }
 
     fn get_column_width(&self, col_idx: usize) -> f32 {
         *self.column_widths.get(&col_idx).unwrap_or(&self.default_column_width)
     }
 
-    fn clear_selection(&mut self) {
-        match &self.selection {
-            Selection::None => {}
-            Selection::CellRange { start, end } => {
-                let (r1, c1) = *start;
-                let (r2, c2) = *end;
-                let (min_r, max_r) = if r1 <= r2 { (r1, r2) } else { (r2, r1) };
-                let (min_c, max_c) = if c1 <= c2 { (c1, c2) } else { (c2, c1) };
-                for row_idx in min_r..=max_r {
-                    if row_idx < self.data.len() {
-                        for col_idx in min_c..=max_c {
-                            if col_idx < self.data[row_idx].len() {
-                                self.data[row_idx][col_idx].clear();
-                            }
-                        }
-                    }
-                }
-            }
-            Selection::Column(col_idx) => {
-                for row in &mut self.data {
-                    if *col_idx < row.len() {
-                        row[*col_idx].clear();
-                    }
-                }
-            }
-            Selection::Row(row_idx) => {
-                if *row_idx < self.data.len() {
-                    for cell in &mut self.data[*row_idx] {
-                        cell.clear();
-                    }
-                }
+    // Empty the selected cells as one undoable step
+    fn clear_selection(&mut self, label: &str) {
+        let Some((rows, cols)) = self.selection_ranges(&self.selection) else {
+            return;
+        };
+
+        // Rows hidden by a filter are left alone
+        let mut changes = Vec::new();
+        for row in self.visible_rows_in(rows) {
+            for col in cols.clone() {
+                changes.push(CellChange { row, col, text: String::new() });
             }
         }
+        self.edit_cells(label, changes);
     }
 
     #[allow(dead_code)]
     fn load_csv(&mut self, path: PathBuf) {
         #[cfg(not(target_arch = "wasm32"))]
         {
-            match csv::Reader::from_path(&path) {
-                Ok(mut reader) => {
-                    let mut data = Vec::new();
-
-                    // Add headers as first row
-                    if let Ok(headers) = reader.headers() {
-                        let header_row: Vec<String> = headers.iter().map(|s| s.to_string()).collect();
-                        data.push(header_row);
-                    }
-
-                    // Add data rows
-                    for result in reader.records() {
-                        if let Ok(record) = result {
-                            let row: Vec<String> = record.iter().map(|s| s.to_string()).collect();
-                            data.push(row);
-                        }
-                    }
-
-                    self.data = data;
-                    // Normalize immediately to ensure rectangular structure
-                    self.normalize_data();
-                    self.file_path = Some(path);
-                    self.has_unsaved_changes = false;
-                }
-                Err(e) => {
-                    eprintln!("Error loading CSV: {}", e);
-                }
+            // A database has its tables listed to pick one from
+            if sqlite::is_sqlite_path(&path) {
+                self.open_database(path);
+                return;
             }
+            // A file that is already open is shown rather than opened twice
+            if let Some(index) = self.tab_with_path(&path) {
+                self.switch_tab(index);
+                return;
+            }
+            self.start_loading(BackgroundLoad::read(path));
         }
         #[cfg(target_arch = "wasm32")]
         {
@@ -334,40 +603,157 @@ impl SpreadsheetApp {
 
     #[allow(dead_code)]
     fn load_csv_from_bytes(&mut self, bytes: &[u8], filename: String) {
-        let mut reader = csv::Reader::from_reader(bytes);
-        let mut data = Vec::new();
+        let path = PathBuf::from(filename);
+        if loader::is_document_path(&path) {
+            self.start_loading(BackgroundLoad::read_document(bytes.to_vec(), path));
+            return;
+        }
+        if !xlsx::is_workbook_path(&path) {
+            self.pending_import = Some(PendingImport::new(bytes.to_vec(), path));
+            return;
+        }
+        match xlsx::Workbook::from_bytes(bytes.to_vec()) {
+            Ok(workbook) => self.open_workbook(workbook, path),
+            Err(e) => eprintln!("Error loading workbook: {}", e),
+        }
+    }
 
-        // Add headers as first row
-        if let Ok(headers) = reader.headers() {
-            let header_row: Vec<String> = headers.iter().map(|s| s.to_string()).collect();
-            data.push(header_row);
+    // Open a workbook's only worksheet, or ask which one to open
+    fn open_workbook(&mut self, workbook: xlsx::Workbook, path: PathBuf) {
+        let sheets = workbook.sheet_names();
+        let pending = PendingWorkbook { workbook, path, sheets, selected: 0 };
+        match pending.sheets.len() {
+            0 => eprintln!("Error loading workbook: it has no worksheets"),
+            1 => self.finish_workbook(pending),
+            _ => self.pending_workbook = Some(pending),
         }
+    }
 
-        // Add data rows
-        for result in reader.records() {
-            if let Ok(record) = result {
-                let row: Vec<String> = record.iter().map(|s| s.to_string()).collect();
-                data.push(row);
-            }
+    // Load the worksheet picked from a workbook
+    fn finish_workbook(&mut self, pending: PendingWorkbook) {
+        let source = SheetSource {
+            name: pending.sheets[pending.selected].clone(),
+            other_sheets: pending.sheets.len() > 1,
+        };
+        self.start_loading(BackgroundLoad::read_sheet(pending.workbook, pending.path, source));
+    }
+
+    // Load the file waiting in the import dialog using the chosen dialect
+    fn finish_import(&mut self) {
+        if let Some(import) = self.pending_import.take() {
+            self.start_loading(BackgroundLoad::parse(import.bytes, import.path, import.dialect, import.encoding));
         }
+    }
 
-        self.data = data;
-        // Normalize immediately to ensure rectangular structure
-        self.normalize_data();
-        self.file_path = Some(PathBuf::from(filename));
-        self.has_unsaved_changes = false;
+    fn start_loading(&mut self, load: BackgroundLoad) {
+        if let Some(previous) = self.loading.replace(load) {
+            previous.cancel();
+        }
+    }
+
+    // Pick up the result of a background load once it is done
+    fn poll_loading(&mut self, ctx: &egui::Context) {
+        let Some(load) = &self.loading else {
+            return;
+        };
+        let Some(result) = load.take_result() else {
+            // Keep the progress bar moving
+            ctx.request_repaint_after(std::time::Duration::from_millis(50));
+            return;
+        };
+        let Some(mut load) = self.loading.take() else {
+            return;
+        };
+
+        match result {
+            LoadResult::Read(bytes) => {
+                self.pending_import = Some(PendingImport::new(bytes, load.path));
+            }
+            LoadResult::Workbook(workbook) => {
+                self.open_workbook(workbook, load.path);
+            }
+            LoadResult::Parsed(parsed) => {
+                let Some((dialect, encoding)) = load.format.take() else {
+                    return;
+                };
+                self.finish_loading(Document::from_parsed(parsed, dialect, encoding), load);
+            }
+            LoadResult::Sheet(sheet) => {
+                self.finish_loading(Document::from_sheet(sheet), load);
+            }
+            LoadResult::Document(doc) => {
+                self.finish_loading(*doc, load);
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            LoadResult::Table(table) => {
+                let (doc, link) = table.into_document();
+                self.finish_loading(doc, load);
+                self.table_link = link;
+            }
+            LoadResult::Failed(e) => {
+                eprintln!("Error loading file: {}", e);
+            }
+        }
     }
 
-    fn save_csv(&self, path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    // Replace the open document with one that has finished loading
+    fn finish_loading(&mut self, doc: Document, load: BackgroundLoad) {
+        if self.active_tab_in_use() {
+            self.new_tab();
+        }
+        self.doc = doc;
+        self.save_values = false;
+        self.save_normalized = false;
+        self.save_nested = false;
+        self.import_problems_open = !self.doc.problems.is_empty();
+        self.freeze_top_row = self.doc.dialect.has_headers;
+        self.file_path = Some(load.path);
+        self.sheet_source = load.sheet;
         #[cfg(not(target_arch = "wasm32"))]
         {
-            let mut writer = csv::Writer::from_path(path)?;
+            self.table_link = None;
+        }
+        self.has_unsaved_changes = false;
+        self.read_only = false;
+        self.grid = GridState::default();
+        self.clear_all_filters();
+    }
 
-            for row in &self.data {
-                writer.write_record(row)?;
+    // Save to the current file. Saving over a workbook would drop its other
+    // worksheets, so that goes through Save As instead. A table read from a
+    // database has its edits written back to it, and views and queries, which
+    // have nowhere to write back to, go through Save As too.
+    fn save(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        if sqlite::is_sqlite_path(&path) {
+            if self.table_link.is_some() {
+                self.save_to_table(&path);
+            } else {
+                self.open_save_as_dialog();
             }
+            return;
+        }
+        if xlsx::is_workbook_path(&path) && self.sheet_source.as_ref().is_some_and(|sheet| sheet.other_sheets) {
+            self.open_save_as_dialog();
+            return;
+        }
+        if let Err(e) = self.save_file(&path) {
+            eprintln!("Error saving file: {}", e);
+        } else {
+            self.has_unsaved_changes = false;
+        }
+    }
 
-            writer.flush()?;
+    // Write the document as a workbook, JSON or delimited text, going by the
+    // file's extension
+    fn save_file(&mut self, path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let bytes = self.save_to_bytes(SaveFormat::for_path(path))?;
+            std::fs::write(path, bytes)?;
         }
         #[cfg(target_arch = "wasm32")]
         {
@@ -377,153 +763,347 @@ impl SpreadsheetApp {
         Ok(())
     }
 
-    #[allow(dead_code)]
-    fn save_csv_to_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut writer = csv::Writer::from_writer(Vec::new());
+    fn save_csv_to_bytes(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (bytes, unmappable) = self.doc.to_bytes(SaveOptions {
+            values: self.save_values,
+            plain: self.save_normalized,
+            header: self.freeze_top_row,
+        })?;
+        if unmappable > 0 {
+            eprintln!(
+                "Warning: {} character(s) can't be represented in {} and were saved as '?'",
+                unmappable,
+                self.doc.encoding.charset.label()
+            );
+        }
+        Ok(bytes)
+    }
 
-        for row in &self.data {
-            writer.write_record(row)?;
+    fn save_to_bytes(&mut self, format: SaveFormat) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match format {
+            SaveFormat::Delimited => self.save_csv_to_bytes(),
+            SaveFormat::Workbook => self.save_xlsx_to_bytes(),
+            SaveFormat::Json(layout) => Ok(self.save_json_to_bytes(layout)),
+            SaveFormat::Columnar(format) => {
+                Ok(self.doc.to_columnar(&ColumnarOptions { format, header: self.freeze_top_row })?)
+            }
         }
+    }
 
-        writer.flush()?;
-        Ok(writer.into_inner()?)
+    fn save_json_to_bytes(&mut self, layout: JsonLayout) -> Vec<u8> {
+        self.doc.to_json(&JsonOptions { layout, header: self.freeze_top_row, nest: self.save_nested })
     }
 
-    fn add_row(&mut self) {
-        let cols = self.data.first().map(|r| r.len()).unwrap_or(10);
-        self.data.push(vec![String::new(); cols]);
-        self.has_unsaved_changes = true;
+    fn save_xlsx_to_bytes(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let options = XlsxOptions {
+            sheet_name: self.sheet_source.as_ref().map(|sheet| sheet.name.clone()).unwrap_or_default(),
+            values: self.save_values,
+            header: self.freeze_top_row,
+            column_widths: (0..self.doc.sheet.num_cols()).map(|col| self.get_column_width(col)).collect(),
+        };
+        Ok(self.doc.to_xlsx(&options)?)
     }
 
-    fn add_column(&mut self) {
-        if self.data.is_empty() {
-            self.data.push(vec![String::new()]);
-        } else {
-            for row in &mut self.data {
-                row.push(String::new());
+    // Pick a file to open. It gets a tab of its own unless the active tab
+    // is an untouched new file.
+    fn open_file_dialog(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("Spreadsheets", OPEN_EXTENSIONS)
+                .pick_file()
+            {
+                self.load_csv(path);
             }
         }
-        self.has_unsaved_changes = true;
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.trigger_open_file();
+        }
     }
 
-    fn insert_row_at(&mut self, row_idx: usize) {
-        let cols = self.data.first().map(|r| r.len()).unwrap_or(10);
-        self.data.insert(row_idx, vec![String::new(); cols]);
-        self.has_unsaved_changes = true;
-
-        // Adjust editing cell index if after inserted row
-        if let Some((editing_row, editing_col)) = self.editing_cell {
-            if editing_row >= row_idx {
-                self.editing_cell = Some((editing_row + 1, editing_col));
-            }
+    fn open_save_as_dialog(&mut self) {
+        // A comparison is saved as its merged result
+        if self.compare.is_some() {
+            self.open_merged_result();
         }
+        self.save_as = Some(SaveAsDialog {
+            format: self.file_path.as_deref().map(SaveFormat::for_path).unwrap_or(SaveFormat::Delimited),
+            dialect: self.doc.dialect.clone(),
+            encoding: self.doc.encoding,
+            save_values: self.save_values,
+            save_normalized: self.save_normalized,
+            save_nested: self.save_nested,
+            column_types: (0..self.doc.sheet.num_cols()).map(|col| self.doc.column_type(col)).collect(),
+            custom_delimiter: String::new(),
+        });
     }
 
-    fn insert_column_at(&mut self, col_idx: usize) {
-        if self.data.is_empty() {
-            self.data.push(vec![String::new()]);
-        } else {
-            for row in &mut self.data {
-                row.insert(col_idx, String::new());
-            }
+    // Save with the format chosen in the Save As dialog. The new format only
+    // sticks if the file is actually written.
+    fn finish_save_as(&mut self, options: SaveAsDialog) {
+        let format = options.format;
+        let previous = (
+            std::mem::replace(&mut self.doc.dialect, options.dialect),
+            self.doc.encoding,
+            self.save_values,
+            self.save_normalized,
+            self.save_nested,
+            self.doc.column_types.clone(),
+        );
+        self.doc.encoding = options.encoding;
+        self.save_values = options.save_values;
+        self.save_normalized = options.save_normalized;
+        self.save_nested = options.save_nested;
+        if matches!(format, SaveFormat::Columnar(_)) {
+            self.doc.column_types = options.column_types.into_iter().enumerate().collect();
         }
-        self.has_unsaved_changes = true;
 
-        // Adjust editing cell index if after inserted column
-        if let Some((editing_row, editing_col)) = self.editing_cell {
-            if editing_col >= col_idx {
-                self.editing_cell = Some((editing_row, editing_col + 1));
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let dialog = match format {
+                SaveFormat::Delimited => rfd::FileDialog::new()
+                    .add_filter("CSV", &["csv"])
+                    .add_filter("Delimited text", DELIMITED_EXTENSIONS),
+                SaveFormat::Workbook => rfd::FileDialog::new().add_filter("Excel workbook", &[xlsx::EXTENSION]),
+                SaveFormat::Json(JsonLayout::Array) => rfd::FileDialog::new().add_filter("JSON", &["json"]),
+                SaveFormat::Json(JsonLayout::Lines) => {
+                    rfd::FileDialog::new().add_filter("Newline-delimited JSON", &["ndjson", "jsonl"])
+                }
+                SaveFormat::Columnar(ColumnarFormat::Parquet) => rfd::FileDialog::new().add_filter("Parquet", &["parquet"]),
+                SaveFormat::Columnar(ColumnarFormat::Arrow) => {
+                    rfd::FileDialog::new().add_filter("Arrow IPC", &["arrow", "feather"])
+                }
+            };
+            let saved = dialog
+                .save_file()
+                .map(|mut path| {
+                    // The format is the one chosen in the dialog, whatever the name typed
+                    if SaveFormat::for_path(&path) != format {
+                        path.set_extension(format.extension());
+                    }
+                    match self.save_file(&path) {
+                        Ok(()) => {
+                            self.file_path = Some(path);
+                            self.has_unsaved_changes = false;
+                            // A saved query result is a file like any other
+                            self.read_only = false;
+                            // The new file holds only this worksheet, and
+                            // is no longer tied to a database table
+                            if let Some(sheet) = self.sheet_source.as_mut() {
+                                sheet.other_sheets = false;
+                            }
+                            self.table_link = None;
+                            true
+                        }
+                        Err(e) => {
+                            eprintln!("Error saving file: {}", e);
+                            false
+                        }
+                    }
+                })
+                .unwrap_or(false);
+
+            if !saved {
+                (self.doc.dialect, self.doc.encoding, self.save_values, self.save_normalized, self.save_nested, self.doc.column_types) =
+                    previous;
             }
         }
-
-        // Shift column widths for columns at or after the inserted one
-        let mut new_widths = HashMap::new();
-        for (&idx, &width) in &self.column_widths {
-            if idx >= col_idx {
-                new_widths.insert(idx + 1, width);
-            } else {
-                new_widths.insert(idx, width);
+        #[cfg(target_arch = "wasm32")]
+        {
+            let filename = format!("spreadsheet.{}", format.extension());
+            match self.save_to_bytes(format) {
+                Ok(bytes) => {
+                    self.download_file(&bytes, &filename);
+                    self.has_unsaved_changes = false;
+                }
+                Err(e) => {
+                    log::error!("Error saving file: {}", e);
+                    (self.doc.dialect, self.doc.encoding, self.save_values, self.save_normalized, self.save_nested, self.doc.column_types) =
+                        previous;
+                }
             }
         }
-        self.column_widths = new_widths;
+    }
+
+    fn add_row(&mut self) {
+        let cols = if self.doc.sheet.is_empty() { 10 } else { self.doc.sheet.num_cols() };
+        self.apply_operation("Add row", vec![Edit::Resize(self.doc.sheet.num_rows() + 1, cols, Vec::new())]);
+    }
+
+    fn add_column(&mut self) {
+        let size = (self.doc.sheet.num_rows().max(1), self.doc.sheet.num_cols() + 1);
+        self.apply_operation("Add column", vec![Edit::Resize(size.0, size.1, Vec::new())]);
+    }
+
+    fn insert_row_at(&mut self, row_idx: usize) {
+        let mut edits = Vec::new();
+        if self.doc.sheet.is_empty() {
+            edits.push(Edit::Resize(0, 10, Vec::new()));
+        }
+        edits.push(Edit::InsertRow(row_idx, Vec::new()));
+        self.apply_operation(format!("Insert row {}", row_idx + 1), edits);
+    }
+
+    fn insert_column_at(&mut self, col_idx: usize) {
+        let mut edits = vec![Edit::InsertColumn(col_idx, None, None)];
+        if self.doc.sheet.is_empty() {
+            edits.push(Edit::Resize(1, self.doc.sheet.num_cols() + 1, Vec::new()));
+        }
+        self.apply_operation(format!("Insert column {}", Self::col_index_to_letter(col_idx)), edits);
     }
 
     fn delete_row(&mut self, row_idx: usize) {
-        if row_idx < self.data.len() {
-            self.data.remove(row_idx);
-            self.has_unsaved_changes = true;
-            // Clear editing state if we're editing the deleted row
-            if let Some((editing_row, _)) = self.editing_cell {
-                if editing_row == row_idx {
-                    self.editing_cell = None;
-                } else if editing_row > row_idx {
-                    // Adjust editing cell index if after deleted row
-                    self.editing_cell = Some((editing_row - 1, self.editing_cell.unwrap().1));
-                }
-            }
+        if row_idx < self.doc.sheet.num_rows() {
+            self.apply_operation(format!("Delete row {}", row_idx + 1), vec![Edit::RemoveRow(row_idx)]);
         }
     }
 
     fn delete_column(&mut self, col_idx: usize) {
-        for row in &mut self.data {
-            if col_idx < row.len() {
-                row.remove(col_idx);
-            }
-        }
-        self.has_unsaved_changes = true;
-        // Clear editing state if we're editing the deleted column
-        if let Some((editing_row, editing_col)) = self.editing_cell {
-            if editing_col == col_idx {
-                self.editing_cell = None;
-            } else if editing_col > col_idx {
-                // Adjust editing cell index if after deleted column
-                self.editing_cell = Some((editing_row, editing_col - 1));
-            }
+        if col_idx < self.doc.sheet.num_cols() {
+            let label = format!("Delete column {}", Self::col_index_to_letter(col_idx));
+            self.apply_operation(label, vec![Edit::RemoveColumn(col_idx)]);
         }
-        // Remove column width setting
-        self.column_widths.remove(&col_idx);
-        // Shift column widths for columns after the deleted one
-        let mut new_widths = HashMap::new();
-        for (&idx, &width) in &self.column_widths {
-            if idx > col_idx {
-                new_widths.insert(idx - 1, width);
-            } else {
-                new_widths.insert(idx, width);
-            }
+    }
+
+    // Set cells as one undoable step, skipping cells that already hold the text
+    fn edit_cells(&mut self, label: impl Into<String>, changes: Vec<CellChange>) {
+        let changes: Vec<CellChange> = changes
+            .into_iter()
+            .filter(|change| self.doc.sheet.get(change.row, change.col) != change.text)
+            .collect();
+        if !changes.is_empty() {
+            self.apply_operation(label, vec![Edit::SetCells(changes)]);
         }
-        self.column_widths = new_widths;
     }
 
-    fn save_undo_state(&mut self) {
-        self.undo_stack.push(self.data.clone());
-        self.redo_stack.clear();
+    // Apply edits to the sheet as one step in the undo history
+    fn apply_operation(&mut self, label: impl Into<String>, edits: Vec<Edit>) {
+        if edits.is_empty() || self.read_only {
+            return;
+        }
+        self.edit_document(|doc, before_edit| doc.apply_operation_with(label, edits, before_edit));
         self.has_unsaved_changes = true;
-        // Limit undo stack to 50 entries
-        if self.undo_stack.len() > 50 {
-            self.undo_stack.remove(0);
+    }
+
+    // Run a document step that applies edits, keeping the rest of the app in
+    // step with each one. The document is taken out of the app meanwhile, so
+    // the edits can update the app's own state.
+    fn edit_document<R>(&mut self, step: impl FnOnce(&mut Document, &mut dyn FnMut(&Edit, &Sheet)) -> R) -> R {
+        let mut doc = std::mem::take(&mut self.doc);
+        // Rows that moved have to be matched against the filters again, as do
+        // all rows once a filtered column is gone
+        let mut refilter = false;
+        let result = step(&mut doc, &mut |edit, sheet| {
+            refilter |= match edit {
+                Edit::ReorderRows(_) => true,
+                Edit::RemoveColumn(col_idx) => self.filters.contains_key(col_idx),
+                _ => false,
+            };
+            self.adjust_indices_for(edit, sheet);
+        });
+        self.doc = doc;
+        if refilter {
+            self.apply_filters();
+        }
+        self.refresh_duplicate_highlight();
+        result
+    }
+
+    // Keep state that refers to rows and columns by index in step with the
+    // sheet, given an edit about to be applied to it
+    fn adjust_indices_for(&mut self, edit: &Edit, sheet: &Sheet) {
+        self.adjust_filters_for(edit, sheet);
+        self.adjust_duplicate_highlight_for(edit);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(link) = &mut self.table_link {
+            link.adjust_for(edit, sheet);
+        }
+        match *edit {
+            Edit::InsertRow(row_idx, _) => {
+                // Adjust editing cell index if after inserted row
+                if let Some((editing_row, editing_col)) = self.editing_cell
+                    && editing_row >= row_idx
+                {
+                    self.editing_cell = Some((editing_row + 1, editing_col));
+                }
+            }
+            Edit::RemoveRow(row_idx) => {
+                // Clear editing state if we're editing the deleted row
+                if let Some((editing_row, editing_col)) = self.editing_cell {
+                    if editing_row == row_idx {
+                        self.editing_cell = None;
+                    } else if editing_row > row_idx {
+                        self.editing_cell = Some((editing_row - 1, editing_col));
+                    }
+                }
+            }
+            Edit::InsertColumn(col_idx, ..) => {
+                // Adjust editing cell index if after inserted column
+                if let Some((editing_row, editing_col)) = self.editing_cell
+                    && editing_col >= col_idx
+                {
+                    self.editing_cell = Some((editing_row, editing_col + 1));
+                }
+                // Shift column widths for columns at or after the inserted one
+                self.column_widths = self
+                    .column_widths
+                    .iter()
+                    .map(|(&idx, &width)| (if idx >= col_idx { idx + 1 } else { idx }, width))
+                    .collect();
+            }
+            Edit::RemoveColumn(col_idx) => {
+                // Clear editing state if we're editing the deleted column
+                if let Some((editing_row, editing_col)) = self.editing_cell {
+                    if editing_col == col_idx {
+                        self.editing_cell = None;
+                    } else if editing_col > col_idx {
+                        self.editing_cell = Some((editing_row, editing_col - 1));
+                    }
+                }
+                // Drop the deleted column's width and shift the ones after it
+                self.column_widths.remove(&col_idx);
+                self.column_widths = self
+                    .column_widths
+                    .iter()
+                    .map(|(&idx, &width)| (if idx > col_idx { idx - 1 } else { idx }, width))
+                    .collect();
+            }
+            Edit::SetCells(_) | Edit::ReorderRows(_) | Edit::Resize(..) => {}
         }
     }
 
     fn undo(&mut self) {
-        if let Some(prev_state) = self.undo_stack.pop() {
-            self.redo_stack.push(self.data.clone());
-            self.data = prev_state;
-            self.has_unsaved_changes = true;
-            // Clear sort indicator since data state changed
-            self.sorted_column = None;
+        if self.edit_document(|doc, before_edit| doc.undo_with(before_edit)) {
+            self.after_history_step();
         }
     }
 
     fn redo(&mut self) {
-        if let Some(next_state) = self.redo_stack.pop() {
-            self.undo_stack.push(self.data.clone());
-            self.data = next_state;
-            self.has_unsaved_changes = true;
-            // Clear sort indicator since data state changed
-            self.sorted_column = None;
+        if self.edit_document(|doc, before_edit| doc.redo_with(before_edit)) {
+            self.after_history_step();
         }
     }
 
+    // Undo or redo until `applied` operations are in effect
+    fn jump_to_history(&mut self, applied: usize) {
+        while self.doc.history.undo_count() > applied {
+            self.undo();
+        }
+        while self.doc.history.undo_count() < applied && self.doc.history.redo_count() > 0 {
+            self.redo();
+        }
+    }
+
+    fn after_history_step(&mut self) {
+        self.has_unsaved_changes = true;
+        self.editing_cell = None;
+        // Clear sort indicator since data state changed
+        self.sorted_column = None;
+        // Problems refer to rows as they were imported, which may no longer match
+        self.doc.problems.clear();
+    }
+
     fn copy_selection(&mut self) {
         let text = self.get_selection_as_text();
         if !text.is_empty() {
@@ -532,11 +1112,10 @@ impl SpreadsheetApp {
     }
 
     fn cut_selection(&mut self) {
-        self.save_undo_state();
         let text = self.get_selection_as_text();
         if !text.is_empty() {
             let _ = self.clipboard.set_text(text);
-            self.clear_selection();
+            self.clear_selection("Cut");
         }
     }
 
@@ -549,36 +1128,16 @@ impl SpreadsheetApp {
                 let (min_r, max_r) = if r1 <= r2 { (r1, r2) } else { (r2, r1) };
                 let (min_c, max_c) = if c1 <= c2 { (c1, c2) } else { (c2, c1) };
 
-                let mut rows = Vec::new();
-                for row_idx in min_r..=max_r {
-                    if row_idx < self.data.len() {
-                        let mut cells = Vec::new();
-                        for col_idx in min_c..=max_c {
-                            if col_idx < self.data[row_idx].len() {
-                                cells.push(self.data[row_idx][col_idx].clone());
-                            } else {
-                                cells.push(String::new());
-                            }
-                        }
-                        rows.push(cells.join("\t"));
-                    }
-                }
-                rows.join("\n")
+                // Only the rows shown are copied, as with a filtered range in other spreadsheets
+                clipboard::copy_text(&self.doc.sheet, self.visible_rows_in(min_r..max_r + 1), min_c..max_c + 1)
             }
             Selection::Column(col_idx) => {
-                let mut cells = Vec::new();
-                for row in &self.data {
-                    if *col_idx < row.len() {
-                        cells.push(row[*col_idx].clone());
-                    } else {
-                        cells.push(String::new());
-                    }
-                }
-                cells.join("\n")
+                let rows = self.visible_rows_in(0..self.doc.sheet.num_rows());
+                clipboard::copy_text(&self.doc.sheet, rows, *col_idx..*col_idx + 1)
             }
             Selection::Row(row_idx) => {
-                if *row_idx < self.data.len() {
-                    self.data[*row_idx].join("\t")
+                if *row_idx < self.doc.sheet.num_rows() {
+                    clipboard::copy_text(&self.doc.sheet, [*row_idx], 0..self.doc.sheet.num_cols())
                 } else {
                     String::new()
                 }
@@ -595,165 +1154,35 @@ impl SpreadsheetApp {
             Selection::None => (0, 0),
         };
 
-        let lines: Vec<&str> = text.lines().collect();
-
-        // Calculate max columns needed
-        let max_cols_needed = self.data.iter().map(|r| r.len()).max().unwrap_or(10);
-
-        for (row_offset, line) in lines.iter().enumerate() {
-            let row_idx = start_row + row_offset;
-            let cells: Vec<&str> = line.split('\t').collect();
-
-            // Ensure we have enough rows
-            while row_idx >= self.data.len() {
-                self.data.push(vec![String::new(); max_cols_needed]);
-            }
-
-            for (col_offset, cell_text) in cells.iter().enumerate() {
-                let col_idx = start_col + col_offset;
-
-                // Ensure we have enough columns
-                while col_idx >= self.data[row_idx].len() {
-                    self.data[row_idx].push(String::new());
-                }
+        // Lines go to successive shown rows, then to new rows at the end
+        let rows = self.visible_rows_in(start_row..self.doc.sheet.num_rows());
+        let edits = clipboard::paste_edits(&self.doc.sheet, rows, start_col, text);
+        self.apply_operation("Paste", edits);
+    }
 
-                self.data[row_idx][col_idx] = cell_text.to_string();
-            }
+    // Name for the document where a file or table is named after it: the
+    // worksheet or table it came from, or the file
+    fn document_name(&self) -> String {
+        match (&self.sheet_source, &self.file_path) {
+            (Some(sheet), _) => sheet.name.clone(),
+            (None, Some(path)) => path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+            (None, None) => "data".to_string(),
         }
-
-        // Normalize to ensure all rows have the same length
-        self.normalize_data();
     }
 
     fn select_all(&mut self) {
-        if !self.data.is_empty() {
-            let max_cols = self.data.iter().map(|row| row.len()).max().unwrap_or(0);
+        if !self.doc.sheet.is_empty() {
+            let max_cols = self.doc.sheet.num_cols();
             if max_cols > 0 {
                 self.selection = Selection::CellRange {
                     start: (0, 0),
-                    end: (self.data.len() - 1, max_cols - 1),
+                    end: (self.doc.sheet.num_rows() - 1, max_cols - 1),
                 };
                 self.editing_cell = None;
             }
         }
     }
 
-    fn perform_search(&mut self) {
-        self.search_results.clear();
-        self.current_search_result = 0;
-
-        if self.search_query.is_empty() {
-            return;
-        }
-
-        let query = if self.search_case_sensitive {
-            self.search_query.clone()
-        } else {
-            self.search_query.to_lowercase()
-        };
-
-        for (row_idx, row) in self.data.iter().enumerate() {
-            for (col_idx, cell) in row.iter().enumerate() {
-                let cell_text = if self.search_case_sensitive {
-                    cell.clone()
-                } else {
-                    cell.to_lowercase()
-                };
-
-                if cell_text.contains(&query) {
-                    self.search_results.push((row_idx, col_idx));
-                }
-            }
-        }
-    }
-
-    fn go_to_next_search_result(&mut self) {
-        if !self.search_results.is_empty() {
-            self.current_search_result = (self.current_search_result + 1) % self.search_results.len();
-            let (row, col) = self.search_results[self.current_search_result];
-            self.selection = Selection::CellRange {
-                start: (row, col),
-                end: (row, col),
-            };
-            self.editing_cell = None;
-        }
-    }
-
-    fn go_to_prev_search_result(&mut self) {
-        if !self.search_results.is_empty() {
-            if self.current_search_result == 0 {
-                self.current_search_result = self.search_results.len() - 1;
-            } else {
-                self.current_search_result -= 1;
-            }
-            let (row, col) = self.search_results[self.current_search_result];
-            self.selection = Selection::CellRange {
-                start: (row, col),
-                end: (row, col),
-            };
-            self.editing_cell = None;
-        }
-    }
-
-    fn sort_by_column(&mut self, col_idx: usize, ascending: bool) {
-        if self.data.is_empty() {
-            return;
-        }
-
-        self.save_undo_state();
-
-        if self.freeze_top_row && self.data.len() > 1 {
-            // Sort only data rows (skip first row which is the header)
-            let mut header = self.data[0].clone();
-            let mut data_rows: Vec<Vec<String>> = self.data[1..].to_vec();
-
-            data_rows.sort_by(|a, b| {
-                let a_val = a.get(col_idx).map(|s| s.as_str()).unwrap_or("");
-                let b_val = b.get(col_idx).map(|s| s.as_str()).unwrap_or("");
-
-                // Try to parse as numbers first
-                let cmp = match (a_val.parse::<f64>(), b_val.parse::<f64>()) {
-                    (Ok(a_num), Ok(b_num)) => a_num.partial_cmp(&b_num).unwrap_or(std::cmp::Ordering::Equal),
-                    _ => a_val.cmp(b_val),
-                };
-
-                if ascending {
-                    cmp
-                } else {
-                    cmp.reverse()
-                }
-            });
-
-            // Reconstruct with header at top
-            self.data.clear();
-            self.data.push(header);
-            self.data.extend(data_rows);
-        } else {
-            // Sort all data (no frozen row)
-            self.data.sort_by(|a, b| {
-                let a_val = a.get(col_idx).map(|s| s.as_str()).unwrap_or("");
-                let b_val = b.get(col_idx).map(|s| s.as_str()).unwrap_or("");
-
-                // Try to parse as numbers first
-                let cmp = match (a_val.parse::<f64>(), b_val.parse::<f64>()) {
-                    (Ok(a_num), Ok(b_num)) => a_num.partial_cmp(&b_num).unwrap_or(std::cmp::Ordering::Equal),
-                    _ => a_val.cmp(b_val),
-                };
-
-                if ascending {
-                    cmp
-                } else {
-                    cmp.reverse()
-                }
-            });
-        }
-
-        // Track which column is sorted
-        self.sorted_column = Some(col_idx);
-        self.sort_ascending = ascending;
-        self.has_unsaved_changes = true;
-    }
-
     #[cfg(target_arch = "wasm32")]
     fn download_file(&self, data: &[u8], filename: &str) {
         use wasm_bindgen::JsCast;
@@ -787,7 +1216,7 @@ impl SpreadsheetApp {
         #[cfg(not(target_arch = "wasm32"))]
         {
             if let Some(path) = rfd::FileDialog::new()
-                .add_filter("CSV", &["csv"])
+                .add_filter("Spreadsheets", OPEN_EXTENSIONS)
                 .pick_file()
             {
                 self.load_csv(path);
@@ -802,7 +1231,7 @@ impl SpreadsheetApp {
             wasm_bindgen_futures::spawn_local(async move {
                 // rfd::AsyncFileDialog works perfectly in WASM
                 let file = rfd::AsyncFileDialog::new()
-                    .add_filter("CSV", &["csv"])
+                    .add_filter("Spreadsheets", OPEN_EXTENSIONS)
                     .pick_file()
                     .await;
 
@@ -819,6 +1248,568 @@ impl SpreadsheetApp {
             });
         }
     }
+
+    fn show_loading_progress(&mut self, ctx: &egui::Context) {
+        let Some(load) = &self.loading else {
+            return;
+        };
+
+        let (fraction, progress_text) = load.status();
+        let stage = if load.format.is_some() { "Parsing" } else { "Reading" };
+        let file_name = load.path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+
+        egui::Window::new("Opening File")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("{} {}...", stage, file_name));
+                // A worksheet is read in one go, with no progress to report
+                ui.add(
+                    egui::ProgressBar::new(fraction)
+                        .text(progress_text)
+                        .desired_width(320.0)
+                        .animate(load.sheet.is_some()),
+                );
+                ui.add_space(10.0);
+                if ui.button("Cancel").clicked() {
+                    cancelled = true;
+                }
+            });
+
+        // The document that was open before stays as it is
+        if cancelled && let Some(load) = self.loading.take() {
+            load.cancel();
+        }
+    }
+
+    fn show_import_dialog(&mut self, ctx: &egui::Context) {
+        let Some(import) = self.pending_import.as_mut() else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        let mut changed = false;
+
+        let filename = import.path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("Untitled")
+            .to_string();
+
+        egui::Window::new("Import")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(640.0)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("File: {}", filename));
+                ui.add_space(5.0);
+
+                egui::Grid::new("import_options")
+                    .num_columns(2)
+                    .spacing([10.0, 6.0])
+                    .show(ui, |ui| {
+                        ui.label("Encoding:");
+                        let mut encoding_changed = false;
+                        let mut charsets = encoding::CHARSETS.to_vec();
+                        if !charsets.contains(&import.encoding.charset) {
+                            charsets.push(import.encoding.charset);
+                        }
+                        egui::ComboBox::from_id_salt("import_encoding")
+                            .selected_text(import.encoding.charset.label())
+                            .show_ui(ui, |ui| {
+                                for charset in charsets {
+                                    encoding_changed |= ui.selectable_value(&mut import.encoding.charset, charset, charset.label()).changed();
+                                }
+                            });
+                        if encoding_changed {
+                            import.encoding.bom = import.encoding.charset.supports_bom()
+                                && import.bytes.starts_with(import.encoding.charset.bom());
+                            import.decode();
+                        }
+                        ui.end_row();
+
+                        ui.label("Delimiter:");
+                        changed |= delimiter_picker(ui, "import_delimiter", &mut import.dialect.delimiter, &mut import.custom_delimiter);
+                        ui.end_row();
+
+                        ui.label("Quote:");
+                        let quote_label = match import.dialect.quote {
+                            Some(b'"') => "Double quote (\")",
+                            Some(b'\'') => "Single quote (')",
+                            _ => "None",
+                        };
+                        egui::ComboBox::from_id_salt("import_quote")
+                            .selected_text(quote_label)
+                            .show_ui(ui, |ui| {
+                                changed |= ui.selectable_value(&mut import.dialect.quote, Some(b'"'), "Double quote (\")").changed();
+                                changed |= ui.selectable_value(&mut import.dialect.quote, Some(b'\''), "Single quote (')").changed();
+                                changed |= ui.selectable_value(&mut import.dialect.quote, None, "None").changed();
+                            });
+                        ui.end_row();
+
+                        ui.label("Escape:");
+                        let escape_label = match import.dialect.escape {
+                            Some(b'\\') => "Backslash (\\)",
+                            _ => "Doubled quotes",
+                        };
+                        ui.add_enabled_ui(import.dialect.quote.is_some(), |ui| {
+                            egui::ComboBox::from_id_salt("import_escape")
+                                .selected_text(escape_label)
+                                .show_ui(ui, |ui| {
+                                    changed |= ui.selectable_value(&mut import.dialect.escape, None, "Doubled quotes").changed();
+                                    changed |= ui.selectable_value(&mut import.dialect.escape, Some(b'\\'), "Backslash (\\)").changed();
+                                });
+                        });
+                        ui.end_row();
+
+                        ui.label("Header:");
+                        ui.checkbox(&mut import.dialect.has_headers, "First row is a header");
+                        ui.end_row();
+
+                        ui.label("Parsing:");
+                        changed |= ui.checkbox(&mut import.dialect.flexible, "Flexible (allow rows with a different number of fields)").changed();
+                        ui.end_row();
+                    });
+
+                ui.separator();
+                ui.label(format!("Preview (first {} rows):", import.preview.rows.num_rows()));
+                if !import.preview.problems.is_empty() {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!(
+                            "{} of these rows could not be parsed cleanly and will be listed under Import Problems",
+                            import.preview.problems.len()
+                        ),
+                    );
+                }
+
+                egui::ScrollArea::both()
+                    .max_height(300.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        egui::Grid::new("import_preview")
+                            .striped(true)
+                            .spacing([12.0, 4.0])
+                            .show(ui, |ui| {
+                                for row_idx in 0..import.preview.rows.num_rows() {
+                                    for cell in import.preview.rows.row(row_idx) {
+                                        // Keep very long values from blowing up the preview
+                                        let text: String = if cell.chars().count() > 40 {
+                                            cell.chars().take(40).chain(std::iter::once('…')).collect()
+                                        } else {
+                                            cell.to_string()
+                                        };
+                                        if row_idx == 0 && import.dialect.has_headers {
+                                            ui.strong(text);
+                                        } else {
+                                            ui.label(text);
+                                        }
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Import").clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if changed {
+            import.refresh_preview();
+        }
+
+        if confirmed {
+            self.finish_import();
+        } else if cancelled {
+            self.pending_import = None;
+        }
+    }
+
+    // Pick which worksheet of a workbook to open
+    fn show_sheet_dialog(&mut self, ctx: &egui::Context) {
+        let Some(pending) = self.pending_workbook.as_mut() else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        let filename = pending.path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+        egui::Window::new("Open Worksheet")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("{} has {} worksheets:", filename, pending.sheets.len()));
+                ui.add_space(4.0);
+                egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    for (index, name) in pending.sheets.iter().enumerate() {
+                        let response = ui.selectable_value(&mut pending.selected, index, name);
+                        if response.double_clicked() {
+                            confirmed = true;
+                        }
+                    }
+                });
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Open").clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            if let Some(pending) = self.pending_workbook.take() {
+                self.finish_workbook(pending);
+            }
+        } else if cancelled {
+            self.pending_workbook = None;
+        }
+    }
+
+    fn show_save_as_dialog(&mut self, ctx: &egui::Context) {
+        let Some(options) = self.save_as.as_mut() else {
+            return;
+        };
+
+        let has_formulas = self.doc.calc.has_formulas();
+        let has_typed_columns = self.doc.column_types.values().any(|&column_type| column_type != ColumnType::Text);
+        let mut confirmed = false;
+        let mut cancelled = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+
+        egui::Window::new("Save As")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("save_as_options")
+                    .num_columns(2)
+                    .spacing([10.0, 6.0])
+                    .show(ui, |ui| {
+                        ui.label("Format:");
+                        egui::ComboBox::from_id_salt("save_as_format")
+                            .selected_text(options.format.label())
+                            .show_ui(ui, |ui| {
+                                for format in SaveFormat::ALL {
+                                    ui.selectable_value(&mut options.format, format, format.label());
+                                }
+                            });
+                        ui.end_row();
+
+                        // Workbooks, JSON and columnar files keep their own cell types, so only text needs these
+                        if options.format == SaveFormat::Delimited {
+                            ui.label("Delimiter:");
+                            delimiter_picker(ui, "save_as_delimiter", &mut options.dialect.delimiter, &mut options.custom_delimiter);
+                            ui.end_row();
+
+                            ui.label("Quoting:");
+                            let quoting_label = if options.dialect.quote.is_none() {
+                                "Never"
+                            } else {
+                                options.dialect.quote_style.label()
+                            };
+                            egui::ComboBox::from_id_salt("save_as_quoting")
+                                .selected_text(quoting_label)
+                                .show_ui(ui, |ui| {
+                                    for style in QuoteStyle::ALL {
+                                        let selected = options.dialect.quote.is_some() && options.dialect.quote_style == style;
+                                        if ui.selectable_label(selected, style.label()).clicked() {
+                                            options.dialect.quote_style = style;
+                                            options.dialect.quote.get_or_insert(b'"');
+                                        }
+                                    }
+                                    if ui.selectable_label(options.dialect.quote.is_none(), "Never").clicked() {
+                                        options.dialect.quote = None;
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("Line endings:");
+                            egui::ComboBox::from_id_salt("save_as_terminator")
+                                .selected_text(options.dialect.terminator.label())
+                                .show_ui(ui, |ui| {
+                                    for terminator in LineTerminator::ALL {
+                                        ui.selectable_value(&mut options.dialect.terminator, terminator, terminator.label());
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("");
+                            ui.checkbox(&mut options.dialect.trailing_newline, "End file with a line break");
+                            ui.end_row();
+
+                            ui.label("Encoding:");
+                            let mut charsets = encoding::CHARSETS.to_vec();
+                            if !charsets.contains(&options.encoding.charset) {
+                                charsets.push(options.encoding.charset);
+                            }
+                            egui::ComboBox::from_id_salt("save_as_encoding")
+                                .selected_text(options.encoding.charset.label())
+                                .show_ui(ui, |ui| {
+                                    for charset in charsets {
+                                        ui.selectable_value(&mut options.encoding.charset, charset, charset.label());
+                                    }
+                                });
+                            options.encoding.bom &= options.encoding.charset.supports_bom();
+                            ui.end_row();
+
+                            ui.label("");
+                            ui.add_enabled(
+                                options.encoding.charset.supports_bom(),
+                                egui::Checkbox::new(&mut options.encoding.bom, "Write byte order mark"),
+                            );
+                            ui.end_row();
+                        }
+
+                        if matches!(options.format, SaveFormat::Json(_)) {
+                            ui.label("Column names:");
+                            ui.checkbox(&mut options.save_nested, "Nest dotted names into objects")
+                                .on_hover_text("Columns such as address.city are written as {\"address\": {\"city\": ...}}");
+                            ui.end_row();
+                        }
+
+                        if has_formulas && options.format.keeps_formulas() {
+                            ui.label("Formulas:");
+                            ui.horizontal(|ui| {
+                                ui.radio_value(&mut options.save_values, false, "Save formulas");
+                                ui.radio_value(&mut options.save_values, true, "Save computed values");
+                            });
+                            ui.end_row();
+                        }
+
+                        if has_typed_columns && options.format == SaveFormat::Delimited {
+                            ui.label("Typed columns:");
+                            ui.checkbox(&mut options.save_normalized, "Write plain values")
+                                .on_hover_text("Numbers without separators or currency symbols (1234.5), dates as 2024-03-01, booleans as TRUE/FALSE");
+                            ui.end_row();
+                        }
+
+                        // The schema of the file follows the column types
+                        if matches!(options.format, SaveFormat::Columnar(_)) && !options.column_types.is_empty() {
+                            ui.label("Column types:")
+                                .on_hover_text("Numbers are written as integers or floats, dates as dates and booleans as booleans. A column with cells that don't fit its type is written as text.");
+                            egui::ScrollArea::vertical().id_salt("save_as_types").max_height(200.0).show(ui, |ui| {
+                                egui::Grid::new("save_as_types_grid").num_columns(2).show(ui, |ui| {
+                                    for (col, column_type) in options.column_types.iter_mut().enumerate() {
+                                        ui.label(Self::column_label(&self.doc, self.freeze_top_row, col));
+                                        egui::ComboBox::from_id_salt(("save_as_type", col))
+                                            .selected_text(column_type.label())
+                                            .show_ui(ui, |ui| {
+                                                for ty in ColumnType::ALL {
+                                                    ui.selectable_value(column_type, ty, ty.label());
+                                                }
+                                            });
+                                        ui.end_row();
+                                    }
+                                });
+                            });
+                            ui.end_row();
+                        }
+                    });
+
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let save_label = "Choose Location...";
+                    #[cfg(target_arch = "wasm32")]
+                    let save_label = "Download";
+                    if ui.button(save_label).clicked() {
+                        confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancelled = true;
+                    }
+                });
+            });
+
+        if confirmed {
+            if let Some(options) = self.save_as.take() {
+                self.finish_save_as(options);
+            }
+        } else if cancelled {
+            self.save_as = None;
+        }
+    }
+
+    // List of undoable operations. Clicking one undoes or redoes up to it.
+    fn show_history_panel(&mut self, ctx: &egui::Context) {
+        if !self.history_open {
+            return;
+        }
+
+        let applied = self.doc.history.undo_count();
+        let mut jump_to: Option<usize> = None;
+
+        let mut window_open = true;
+        egui::Window::new("History")
+            .open(&mut window_open)
+            .collapsible(true)
+            .resizable(true)
+            .default_width(260.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        let first = if self.doc.history.is_trimmed() { "Oldest kept state" } else { "Original" };
+                        let labels = std::iter::once(first)
+                            .chain(self.doc.history.done_labels())
+                            .chain(self.doc.history.undone_labels());
+                        // Entry `idx` is the state with `idx` operations applied
+                        for (idx, label) in labels.enumerate() {
+                            let text = if idx > applied {
+                                egui::RichText::new(label).weak()
+                            } else {
+                                egui::RichText::new(label)
+                            };
+                            if ui.selectable_label(idx == applied, text).clicked() && idx != applied {
+                                jump_to = Some(idx);
+                            }
+                        }
+                    });
+            });
+
+        if !window_open {
+            self.history_open = false;
+        }
+        if let Some(idx) = jump_to {
+            self.jump_to_history(idx);
+        }
+    }
+
+    fn show_import_problems(&mut self, ctx: &egui::Context) {
+        if !self.import_problems_open || self.doc.problems.is_empty() {
+            return;
+        }
+
+        // Actions are applied after the window is drawn: (problem index, action)
+        enum ProblemAction {
+            GoTo,
+            Keep,
+            Fix,
+            Drop,
+        }
+        let mut action: Option<(usize, ProblemAction)> = None;
+        let mut keep_all = false;
+        let mut drop_all = false;
+
+        let mut window_open = true;
+        egui::Window::new("Import Problems")
+            .open(&mut window_open)
+            .collapsible(true)
+            .resizable(true)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} row{} could not be parsed cleanly. They were loaded as-is; keep, fix or drop each one.",
+                    self.doc.problems.len(),
+                    if self.doc.problems.len() == 1 { "" } else { "s" }
+                ));
+
+                ui.horizontal(|ui| {
+                    if ui.button("Keep All").clicked() {
+                        keep_all = true;
+                    }
+                    if ui.button("Drop All").clicked() {
+                        drop_all = true;
+                    }
+                });
+
+                ui.separator();
+
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        for (idx, problem) in self.doc.problems.iter_mut().enumerate() {
+                            ui.push_id(idx, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.strong(format!("Line {}", problem.line));
+                                    ui.label(format!("(row {})", problem.row + 1));
+                                });
+                                ui.colored_label(ui.visuals().warn_fg_color, &problem.message);
+                                ui.add(egui::TextEdit::singleline(&mut problem.raw)
+                                    .font(egui::TextStyle::Monospace)
+                                    .desired_width(f32::INFINITY));
+                                ui.horizontal(|ui| {
+                                    if ui.button("Go to Row").clicked() {
+                                        action = Some((idx, ProblemAction::GoTo));
+                                    }
+                                    if ui.button("Keep").clicked() {
+                                        action = Some((idx, ProblemAction::Keep));
+                                    }
+                                    if ui.button("Apply Fix").on_hover_text("Re-parse the edited text and replace the row").clicked() {
+                                        action = Some((idx, ProblemAction::Fix));
+                                    }
+                                    if ui.button("Drop").clicked() {
+                                        action = Some((idx, ProblemAction::Drop));
+                                    }
+                                });
+                                ui.separator();
+                            });
+                        }
+                    });
+            });
+
+        if !window_open {
+            self.import_problems_open = false;
+        }
+
+        if keep_all {
+            self.doc.problems.clear();
+        } else if drop_all {
+            // Delete from the bottom up so earlier row indices stay valid
+            let mut rows: Vec<usize> = self.doc.problems.iter().map(|p| p.row).collect();
+            rows.sort_unstable();
+            rows.dedup();
+            let edits = rows.into_iter().rev().map(Edit::RemoveRow).collect();
+            self.apply_operation("Drop problem rows", edits);
+            self.doc.problems.clear();
+        } else if let Some((idx, action)) = action {
+            let row_idx = self.doc.problems[idx].row;
+            match action {
+                ProblemAction::GoTo => {
+                    self.selection = Selection::Row(row_idx);
+                    self.editing_cell = None;
+                    self.grid.scroll_to = Some((row_idx, 0));
+                }
+                ProblemAction::Keep => {
+                    self.doc.problems.remove(idx);
+                }
+                ProblemAction::Fix => {
+                    let fields = dialect::parse_record(&self.doc.problems[idx].raw, &self.doc.dialect);
+                    let mut edits = Vec::new();
+                    if fields.len() > self.doc.sheet.num_cols() {
+                        edits.push(Edit::Resize(self.doc.sheet.num_rows(), fields.len(), Vec::new()));
+                    }
+                    let changes = (0..fields.len().max(self.doc.sheet.num_cols()))
+                        .map(|col| CellChange { row: row_idx, col, text: fields.get(col).cloned().unwrap_or_default() })
+                        .filter(|change| self.doc.sheet.get(change.row, change.col) != change.text)
+                        .collect();
+                    edits.push(Edit::SetCells(changes));
+                    self.apply_operation(format!("Fix row {}", row_idx + 1), edits);
+                    self.doc.problems.remove(idx);
+                }
+                ProblemAction::Drop => {
+                    self.delete_row(row_idx);
+                }
+            }
+        }
+    }
 }
 
 impl eframe::App for SpreadsheetApp {
@@ -844,6 +1835,9 @@ impl eframe::App for SpreadsheetApp {
             }
         }
 
+        // Pick up files read or parsed in the background
+        self.poll_loading(ctx);
+
         // Handle drag-and-drop files
         ctx.input(|i| {
             if !i.raw.dropped_files.is_empty() {
@@ -852,8 +1846,10 @@ impl eframe::App for SpreadsheetApp {
                     #[cfg(not(target_arch = "wasm32"))]
                     {
                         if let Some(path) = &file.path {
-                            // Only load CSV files
-                            if path.extension().and_then(|s| s.to_str()) == Some("csv") {
+                            // Only load files we can open
+                            if path.extension().and_then(|s| s.to_str()).is_some_and(|ext| OPEN_EXTENSIONS.contains(&ext))
+                                || sqlite::is_sqlite_path(path)
+                            {
                                 self.load_csv(path.clone());
                             }
                         }
@@ -864,8 +1860,8 @@ impl eframe::App for SpreadsheetApp {
                     {
                         if let Some(bytes) = &file.bytes {
                             let filename = file.name.clone();
-                            // Only load CSV files
-                            if filename.ends_with(".csv") {
+                            // Only load files we can open
+                            if OPEN_EXTENSIONS.iter().any(|ext| filename.ends_with(&format!(".{}", ext))) {
                                 self.load_csv_from_bytes(bytes, filename);
                             }
                         }
@@ -878,7 +1874,7 @@ impl eframe::App for SpreadsheetApp {
         if ctx.input(|i| i.viewport().close_requested()) {
             if self.allowed_to_close {
                 // User confirmed exit - allow window to close
-            } else if self.has_unsaved_changes {
+            } else if self.any_unsaved_changes() {
                 // Prevent close and show confirmation modal
                 ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
                 self.pending_action = PendingAction::Exit;
@@ -916,75 +1912,52 @@ impl eframe::App for SpreadsheetApp {
         }
 
         // Handle keyboard input - check shortcuts early before any UI
-        let not_editing = self.editing_cell.is_none();
+        let not_editing = self.editing_cell.is_none()
+            && self.pending_import.is_none()
+            && self.pending_workbook.is_none()
+            && self.save_as.is_none()
+            && self.copy_as_dialog.is_none()
+            && self.join_dialog.is_none()
+            && self.duplicate_dialog.is_none()
+            && self.loading.is_none()
+            // Typing in a text field of a window, such as the fix for an
+            // import problem, isn't meant for the grid
+            && !ctx.wants_keyboard_input();
+        // Shortcuts would change the document the SQLite windows work on
+        #[cfg(not(target_arch = "wasm32"))]
+        let not_editing = not_editing && self.database_dialog.is_none() && self.export_table_dialog.is_none();
 
         // File operation shortcuts (Cmd/Ctrl + S/N/O/Shift+S)
         if not_editing {
-            // Cmd+N - New File
+            // Cmd+N - New File, in a new tab
             if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::N)) {
-                if self.has_unsaved_changes {
-                    self.pending_action = PendingAction::NewFile;
-                } else {
-                    self.data = vec![vec![String::new(); 10]; 20];
-                    self.file_path = None;
-                }
+                self.new_tab();
             }
 
-            // Cmd+O - Open File
+            // Cmd+O - Open File, in a new tab unless this one is an untouched new file
             if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::O)) {
-                if self.has_unsaved_changes {
-                    self.pending_action = PendingAction::OpenFile;
-                } else {
-                    #[cfg(not(target_arch = "wasm32"))]
-                    {
-                        if let Some(path) = rfd::FileDialog::new()
-                            .add_filter("CSV", &["csv"])
-                            .pick_file()
-                        {
-                            self.load_csv(path);
-                        }
-                    }
-                    #[cfg(target_arch = "wasm32")]
-                    {
-                        self.trigger_open_file();
-                    }
-                }
+                self.open_file_dialog();
+            }
+
+            // Cmd+W - Close Tab
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::W)) {
+                self.close_tab(self.active_tab);
+            }
+
+            // Ctrl+Tab / Ctrl+Shift+Tab - Next / Previous Tab
+            if ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL.plus(egui::Modifiers::SHIFT), egui::Key::Tab)) {
+                self.switch_tab((self.active_tab + self.tabs.len() - 1) % self.tabs.len());
+            } else if ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::Tab)) {
+                self.switch_tab((self.active_tab + 1) % self.tabs.len());
             }
 
             // Cmd+Shift+S - Save As
             if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND.plus(egui::Modifiers::SHIFT), egui::Key::S)) {
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    if let Some(path) = rfd::FileDialog::new()
-                        .add_filter("CSV", &["csv"])
-                        .save_file()
-                    {
-                        if let Err(e) = self.save_csv(&path) {
-                            eprintln!("Error saving CSV: {}", e);
-                        } else {
-                            self.file_path = Some(path);
-                            self.has_unsaved_changes = false;
-                        }
-                    }
-                }
-                #[cfg(target_arch = "wasm32")]
-                {
-                    // WASM: Trigger download
-                    if let Ok(bytes) = self.save_csv_to_bytes() {
-                        self.download_file(&bytes, "spreadsheet.csv");
-                        self.has_unsaved_changes = false;
-                    }
-                }
+                self.open_save_as_dialog();
             }
             // Cmd+S - Save (must come after Cmd+Shift+S check)
             else if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::S)) {
-                if let Some(ref path) = self.file_path {
-                    if let Err(e) = self.save_csv(path) {
-                        eprintln!("Error saving CSV: {}", e);
-                    } else {
-                        self.has_unsaved_changes = false;
-                    }
-                }
+                self.save();
             }
         }
 
@@ -1022,11 +1995,12 @@ impl eframe::App for SpreadsheetApp {
         if do_copy {
             self.copy_selection();
         }
-        if do_paste {
-            if let Some(text) = paste_text {
-                self.save_undo_state();
-                self.paste_text(&text);
-            }
+        // A comparison has no cells to paste into
+        if do_paste
+            && self.compare.is_none()
+            && let Some(text) = paste_text
+        {
+            self.paste_text(&text);
         }
         if do_cut {
             self.cut_selection();
@@ -1036,13 +2010,18 @@ impl eframe::App for SpreadsheetApp {
         if not_editing && !self.search_window_open && ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::A)) {
             self.select_all();
         }
-        if not_editing && ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::F)) {
-            self.search_window_open = true;
+        if not_editing
+            && ctx.input_mut(|i| {
+                i.consume_key(egui::Modifiers::COMMAND, egui::Key::F) || i.consume_key(egui::Modifiers::COMMAND, egui::Key::H)
+            })
+        {
+            self.open_search_window();
         }
-        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Y)) {
+        // Undo and redo would change the sheet under an open window
+        if not_editing && ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Y)) {
             self.redo();
         }
-        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z)) {
+        if not_editing && ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z)) {
             self.undo();
         }
 
@@ -1053,9 +2032,8 @@ impl eframe::App for SpreadsheetApp {
 
         ctx.input(|i| {
             if i.key_pressed(egui::Key::Delete) || i.key_pressed(egui::Key::Backspace) {
-                if self.editing_cell.is_none() {
-                    self.save_undo_state();
-                    self.clear_selection();
+                if not_editing {
+                    self.clear_selection("Clear");
                 }
             }
             if i.key_pressed(egui::Key::Escape) {
@@ -1075,7 +2053,7 @@ impl eframe::App for SpreadsheetApp {
             }
 
             // Handle arrow keys when cell is selected (not editing)
-            if self.editing_cell.is_none() {
+            if not_editing {
                 extend_selection = i.modifiers.shift;
 
                 if i.key_pressed(egui::Key::ArrowUp) {
@@ -1090,7 +2068,7 @@ impl eframe::App for SpreadsheetApp {
             }
 
             // Start editing on text input when single cell is selected (but not when search window is open)
-            if self.editing_cell.is_none() && !self.search_window_open {
+            if not_editing && !self.search_window_open && !self.read_only {
                 if let Selection::CellRange { start, end } = &self.selection {
                     if start == end {
                         // Single cell selected, check for text input
@@ -1120,76 +2098,68 @@ impl eframe::App for SpreadsheetApp {
             egui::MenuBar::new().ui(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("New").clicked() {
-                        if self.has_unsaved_changes {
-                            self.pending_action = PendingAction::NewFile;
-                        } else {
-                            // No unsaved changes, create new file directly
-                            self.data = vec![vec![String::new(); 10]; 20];
-                            self.file_path = None;
-                        }
+                        self.new_tab();
                         ui.close();
                     }
 
                     if ui.button("Open CSV").clicked() {
-                        if self.has_unsaved_changes {
-                            self.pending_action = PendingAction::OpenFile;
-                        } else {
-                            // No unsaved changes, open file directly
-                            #[cfg(not(target_arch = "wasm32"))]
-                            {
-                                if let Some(path) = rfd::FileDialog::new()
-                                    .add_filter("CSV", &["csv"])
-                                    .pick_file()
-                                {
-                                    self.load_csv(path);
-                                }
-                            }
-                            #[cfg(target_arch = "wasm32")]
-                            {
-                                self.trigger_open_file();
-                            }
-                        }
+                        self.open_file_dialog();
                         ui.close();
                     }
 
                     if ui.button("Save").clicked() {
-                        if let Some(ref path) = self.file_path {
-                            if let Err(e) = self.save_csv(path) {
-                                eprintln!("Error saving CSV: {}", e);
-                            } else {
-                                self.has_unsaved_changes = false;
-                            }
-                        }
+                        self.save();
                         ui.close();
                     }
 
                     if ui.button("Save As...").clicked() {
-                        #[cfg(not(target_arch = "wasm32"))]
-                        {
-                            if let Some(path) = rfd::FileDialog::new()
-                                .add_filter("CSV", &["csv"])
-                                .save_file()
-                            {
-                                if let Err(e) = self.save_csv(&path) {
-                                    eprintln!("Error saving CSV: {}", e);
-                                } else {
-                                    self.file_path = Some(path);
-                                    self.has_unsaved_changes = false;
-                                }
-                            }
+                        self.open_save_as_dialog();
+                        ui.close();
+                    }
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        ui.separator();
+
+                        if ui.button("Open SQLite...").clicked() {
+                            self.pick_database();
+                            ui.close();
                         }
-                        #[cfg(target_arch = "wasm32")]
-                        {
-                            if let Ok(bytes) = self.save_csv_to_bytes() {
-                                self.download_file(&bytes, "spreadsheet.csv");
-                                self.has_unsaved_changes = false;
-                            }
+
+                        if ui.button("Export to SQLite...").clicked() {
+                            self.open_export_table_dialog();
+                            ui.close();
                         }
+                    }
+
+                    ui.separator();
+
+                    if ui.add_enabled(self.can_compare(), egui::Button::new("Compare Documents...")).clicked() {
+                        self.open_compare_dialog();
+                        ui.close();
+                    }
+
+                    ui.separator();
+
+                    if ui.button("Close Tab").clicked() {
+                        self.close_tab(self.active_tab);
                         ui.close();
                     }
                 });
 
                 ui.menu_button("Edit", |ui| {
+                    if ui.add_enabled(self.doc.history.undo_count() > 0, egui::Button::new("Undo")).clicked() {
+                        self.undo();
+                        ui.close();
+                    }
+
+                    if ui.add_enabled(self.doc.history.redo_count() > 0, egui::Button::new("Redo")).clicked() {
+                        self.redo();
+                        ui.close();
+                    }
+
+                    ui.separator();
+
                     if ui.button("Cut").clicked() {
                         self.cut_selection();
                         ui.close();
@@ -1200,9 +2170,13 @@ impl eframe::App for SpreadsheetApp {
                         ui.close();
                     }
 
+                    if ui.add_enabled(self.selection != Selection::None, egui::Button::new("Copy As...")).clicked() {
+                        self.open_copy_as_dialog(self.selection.clone());
+                        ui.close();
+                    }
+
                     if ui.button("Paste").clicked() {
                         if let Ok(text) = self.clipboard.get_text() {
-                            self.save_undo_state();
                             self.paste_text(&text);
                         }
                         ui.close();
@@ -1210,6 +2184,23 @@ impl eframe::App for SpreadsheetApp {
 
                     ui.separator();
 
+                    if ui.button("Find & Replace...").clicked() {
+                        self.open_search_window();
+                        ui.close();
+                    }
+
+                    if ui.button("Sort...").clicked() {
+                        let col = match self.selection {
+                            Selection::Column(col_idx) => Some(col_idx),
+                            Selection::CellRange { start, .. } => Some(start.1),
+                            _ => None,
+                        };
+                        self.open_sort_dialog(col);
+                        ui.close();
+                    }
+
+                    ui.separator();
+
                     if ui.button("Add Row").clicked() {
                         self.add_row();
                         ui.close();
@@ -1221,435 +2212,135 @@ impl eframe::App for SpreadsheetApp {
                     }
                 });
 
-                ui.menu_button("View", |ui| {
-                    if ui.checkbox(&mut self.freeze_top_row, "Freeze Top Row").clicked() {
+                ui.menu_button("Data", |ui| {
+                    if ui.add_enabled(self.compare.is_none(), egui::Button::new("Merge Columns from File...")).clicked() {
+                        self.open_join_dialog();
                         ui.close();
                     }
 
                     ui.separator();
 
-                    if ui.button("Reset Column Widths").clicked() {
-                        self.column_widths.clear();
-                        self.table_id_salt += 1; // Change table ID to reset egui's internal state
+                    if ui.add_enabled(self.compare.is_none(), egui::Button::new("Remove Duplicates...")).clicked() {
+                        self.open_duplicate_dialog();
                         ui.close();
                     }
 
-                    ui.separator();
-
-                    let theme_label = if self.dark_mode { "Light Mode" } else { "Dark Mode" };
-                    if ui.button(theme_label).clicked() {
-                        self.dark_mode = !self.dark_mode;
+                    if ui.add_enabled(self.duplicate_highlight.is_some(), egui::Button::new("Clear Duplicate Highlight")).clicked() {
+                        self.duplicate_highlight = None;
                         ui.close();
                     }
-                });
-            });
-        });
-
-        // Always render the central panel, but disable interaction when modal is open
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let num_rows = self.data.len();
-            let num_cols = self.data.iter().map(|r| r.len()).max().unwrap_or(0);
-            let row_height = 25.0;
-
-            // Wrap everything in add_enabled_ui to disable interaction when modal is open
-            ui.add_enabled_ui(self.pending_action == PendingAction::None, |ui| {
-                // Wrap in ScrollArea for horizontal scrolling
-                egui::ScrollArea::both()
-                    .auto_shrink([false; 2])
-                    .show(ui, |ui| {
-                        // Remove spacing between cells to create continuous grid
-                        ui.style_mut().spacing.item_spacing = egui::vec2(0.0, 0.0);
-
-                        // Track if we should save current edit when clicking away
-                        let mut save_current_edit = false;
-                        let previous_editing_cell = self.editing_cell; // Capture BEFORE we change it
-
-                        // Clone selection for use in closures (before any updates)
-                        let current_selection = self.selection.clone();
-
-                        // Track pending operations
-                        let mut delete_row: Option<usize> = None;
-                        let mut delete_col: Option<usize> = None;
-                        let mut insert_row_at: Option<usize> = None;
-                        let mut insert_col_at: Option<usize> = None;
-                        let mut drag_end_cell: Option<(usize, usize)> = None;
-                        let mut clear_cell: Option<(usize, usize)> = None;
-
-                        let mut table = TableBuilder::new(ui)
-                .id_salt(self.table_id_salt) // Use salt to reset table state
-                .striped(true)
-                .resizable(true)
-                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-                .vscroll(false) // Disable internal scroll since we have outer ScrollArea
-                .column(Column::initial(50.0).at_least(30.0)); // Row index column
-
-            // Add data columns with custom widths
-            for col_idx in 0..num_cols {
-                let width = self.get_column_width(col_idx);
-                table = table.column(Column::initial(width).at_least(30.0).resizable(true));
-            }
-
-            table
-                .header(row_height, |mut header| {
-                    // Corner cell
-                    header.col(|_ui| {});
-
-                    // Column headers
-                    for col_idx in 0..num_cols {
-                        header.col(|ui| {
-                            let is_col_selected = matches!(&current_selection, Selection::Column(c) if *c == col_idx);
-
-                            // Allocate space and draw background if selected
-                            let (rect, response) = ui.allocate_exact_size(
-                                ui.available_size(),
-                                egui::Sense::click()
-                            );
-
-                            if is_col_selected {
-                                ui.painter().rect_filled(rect, 0.0, egui::Color32::from_rgb(100, 150, 200));
-                            }
-
-                            // Draw column letter with sort indicator
-                            let mut col_text = Self::col_index_to_letter(col_idx);
-                            if self.sorted_column == Some(col_idx) {
-                                col_text.push(' ');
-                                col_text.push(if self.sort_ascending { '^' } else { 'v' });
-                            }
-                            ui.painter().text(
-                                rect.center(),
-                                egui::Align2::CENTER_CENTER,
-                                col_text,
-                                egui::FontId::default(),
-                                ui.visuals().text_color()
-                            );
 
-                            if response.clicked() {
-                                self.selection = Selection::Column(col_idx);
-                                self.editing_cell = None;
-                            }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        ui.separator();
 
-                            response.context_menu(|ui| {
-                                if ui.button("Sort Ascending").clicked() {
-                                    self.sort_by_column(col_idx, true);
-                                    ui.close();
-                                }
-                                if ui.button("Sort Descending").clicked() {
-                                    self.sort_by_column(col_idx, false);
-                                    ui.close();
-                                }
-                                ui.separator();
-                                if ui.button("Insert Column Left").clicked() {
-                                    insert_col_at = Some(col_idx);
-                                    ui.close();
-                                }
-                                if ui.button("Insert Column Right").clicked() {
-                                    insert_col_at = Some(col_idx + 1);
-                                    ui.close();
-                                }
-                                ui.separator();
-                                if ui.button("Delete Column").clicked() {
-                                    delete_col = Some(col_idx);
-                                    ui.close();
-                                }
-                            });
-                        });
+                        if ui.button("SQL Query...").clicked() {
+                            self.open_query_panel();
+                            ui.close();
+                        }
                     }
-                })
-                .body(|body| {
-                    body.rows(row_height, num_rows, |mut row| {
-                        let row_idx = row.index();
-                        let is_row_selected = matches!(&current_selection, Selection::Row(r) if *r == row_idx);
-
-                        // Row number
-                        row.col(|ui| {
-                            // Allocate space and draw background if selected
-                            let (rect, response) = ui.allocate_exact_size(
-                                ui.available_size(),
-                                egui::Sense::click()
-                            );
-
-                            if is_row_selected {
-                                ui.painter().rect_filled(rect, 0.0, egui::Color32::from_rgb(100, 150, 200));
-                            }
-
-                            // Draw row number
-                            ui.painter().text(
-                                rect.center(),
-                                egui::Align2::CENTER_CENTER,
-                                (row_idx + 1).to_string(),
-                                egui::FontId::default(),
-                                ui.visuals().text_color()
-                            );
-
-                            if response.clicked() {
-                                self.selection = Selection::Row(row_idx);
-                                self.editing_cell = None;
-                            }
-
-                            response.context_menu(|ui| {
-                                if ui.button("Insert Row Above").clicked() {
-                                    insert_row_at = Some(row_idx);
-                                    ui.close();
-                                }
-                                if ui.button("Insert Row Below").clicked() {
-                                    insert_row_at = Some(row_idx + 1);
-                                    ui.close();
-                                }
-                                ui.separator();
-                                if ui.button("Delete Row").clicked() {
-                                    delete_row = Some(row_idx);
-                                    ui.close();
-                                }
-                            });
-                        });
-
-                        // Data cells
-                        for col_idx in 0..num_cols {
-                            row.col(|ui| {
-                                let cell_id = (row_idx, col_idx);
-                                let is_editing = self.editing_cell == Some(cell_id);
-
-                                // Calculate is_selected inline without calling self methods
-                                let is_selected = match &current_selection {
-                                    Selection::None => false,
-                                    Selection::CellRange { start, end } => {
-                                        let (r1, c1) = *start;
-                                        let (r2, c2) = *end;
-                                        let (min_r, max_r) = if r1 <= r2 { (r1, r2) } else { (r2, r1) };
-                                        let (min_c, max_c) = if c1 <= c2 { (c1, c2) } else { (c2, c1) };
-                                        row_idx >= min_r && row_idx <= max_r && col_idx >= min_c && col_idx <= max_c
-                                    }
-                                    Selection::Column(c) => col_idx == *c,
-                                    Selection::Row(r) => row_idx == *r,
-                                };
-
-                                // Check if cell is in search results
-                                let is_search_match = self.search_results.contains(&(row_idx, col_idx));
-                                let is_current_search_result = !self.search_results.is_empty()
-                                    && self.search_results.get(self.current_search_result) == Some(&(row_idx, col_idx));
-
-                                if let Some(row_data) = self.data.get_mut(row_idx) {
-                                    if col_idx >= row_data.len() {
-                                        return; // Skip if column doesn't exist yet
-                                    }
-                                    let cell_val = &mut row_data[col_idx];
-                                        // Create an interactive area that fills the cell
-                                        let (rect, response) = ui.allocate_exact_size(
-                                            ui.available_size(),
-                                            egui::Sense::click_and_drag()
-                                        );
-
-                                        // Check if this is the frozen header row
-                                        let is_frozen_header = self.freeze_top_row && row_idx == 0;
-
-                                        // Draw cell background with priority: frozen header > current search result > search match > selected > normal
-                                        // Use different colors for dark vs light mode
-                                        let bg_color = if is_frozen_header {
-                                            if self.dark_mode {
-                                                egui::Color32::from_rgb(50, 50, 60) // Slightly lighter than background for dark mode
-                                            } else {
-                                                egui::Color32::from_rgb(230, 230, 240) // Slightly darker than background for light mode
-                                            }
-                                        } else if is_current_search_result {
-                                            if self.dark_mode {
-                                                egui::Color32::from_rgb(180, 100, 0) // Dark orange for dark mode
-                                            } else {
-                                                egui::Color32::from_rgb(255, 200, 100) // Light orange for light mode
-                                            }
-                                        } else if is_search_match {
-                                            if self.dark_mode {
-                                                egui::Color32::from_rgb(120, 100, 0) // Dark gold for dark mode
-                                            } else {
-                                                egui::Color32::from_rgb(255, 255, 150) // Light yellow for light mode
-                                            }
-                                        } else if is_selected {
-                                            if self.dark_mode {
-                                                egui::Color32::from_rgb(60, 90, 120) // Dark blue for dark mode
-                                            } else {
-                                                egui::Color32::from_rgb(180, 210, 240) // Light blue for light mode
-                                            }
-                                        } else {
-                                            egui::Color32::TRANSPARENT
-                                        };
-
-                                        if bg_color != egui::Color32::TRANSPARENT {
-                                            ui.painter().rect_filled(rect, 0.0, bg_color);
-                                        }
-
-                                        // Draw cell border (blue if editing, normal grid color otherwise)
-                                        let border_color = if is_editing {
-                                            egui::Color32::from_rgb(66, 133, 244) // Blue border when editing
-                                        } else {
-                                            ui.visuals().widgets.noninteractive.bg_stroke.color
-                                        };
-
-                                        let border_width = if is_editing { 2.0 } else { 0.5 };
+                });
 
-                                        ui.painter().rect_stroke(
-                                            rect,
-                                            0.0,
-                                            egui::Stroke::new(border_width, border_color),
-                                            egui::epaint::StrokeKind::Inside
-                                        );
+                ui.menu_button("View", |ui| {
+                    if ui.checkbox(&mut self.freeze_top_row, "Freeze Top Row").clicked() {
+                        // The header row is exempt from filters only while frozen
+                        self.apply_filters();
+                        self.refresh_duplicate_highlight();
+                        ui.close();
+                    }
 
-                                        if is_editing {
-                                            // Show text edit without frame, just cursor
-                                            let edit_rect = rect.shrink2(egui::vec2(4.0, 2.0));
-                                            let mut child_ui = ui.new_child(
-                                                egui::UiBuilder::new()
-                                                    .max_rect(edit_rect)
-                                                    .layout(egui::Layout::left_to_right(egui::Align::Center))
-                                            );
+                    if ui.add_enabled(!self.filters.is_empty(), egui::Button::new("Reapply Filters")).clicked() {
+                        self.apply_filters();
+                        ui.close();
+                    }
 
-                                            let text_edit = egui::TextEdit::singleline(&mut self.edit_buffer)
-                                                .frame(false);
+                    if ui.add_enabled(!self.filters.is_empty(), egui::Button::new("Clear All Filters")).clicked() {
+                        self.clear_all_filters();
+                        ui.close();
+                    }
 
-                                            let edit_response = child_ui.add(text_edit);
+                    if !self.doc.problems.is_empty()
+                        && ui.button(format!("Import Problems ({})", self.doc.problems.len())).clicked()
+                    {
+                        self.import_problems_open = true;
+                        ui.close();
+                    }
 
-                                            // Check if Enter was pressed to move down
-                                            let enter_pressed = ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.checkbox(&mut self.history_open, "History").clicked() {
+                        ui.close();
+                    }
 
-                                            if edit_response.lost_focus() || enter_pressed {
-                                                *cell_val = self.edit_buffer.clone();
-                                                self.has_unsaved_changes = true;
-                                                self.editing_cell = None;
-                                            }
+                    ui.separator();
 
-                                            edit_response.request_focus();
-                                        } else {
-                                            // Draw the text with clipping to prevent overflow
-                                            let text_rect = rect.shrink2(egui::vec2(4.0, 0.0));
-                                            let text_pos = rect.left_center() + egui::vec2(4.0, 0.0);
-
-                                            // Use bold font for frozen header row
-                                            let font_id = if is_frozen_header {
-                                                egui::FontId::proportional(14.0)
-                                            } else {
-                                                egui::FontId::default()
-                                            };
-
-                                            ui.painter().with_clip_rect(text_rect).text(
-                                                text_pos,
-                                                egui::Align2::LEFT_CENTER,
-                                                &*cell_val,
-                                                font_id,
-                                                ui.visuals().text_color()
-                                            );
-
-                                            // Double-click to edit
-                                            if response.double_clicked() {
-                                                save_current_edit = true;
-                                                self.editing_cell = Some(cell_id);
-                                                self.edit_buffer = cell_val.clone();
-                                                self.selection = Selection::None;
-                                                self.drag_start = None;
-                                            }
-                                            // Start drag selection
-                                            else if response.is_pointer_button_down_on() {
-                                                save_current_edit = true;
-                                                self.drag_start = Some(cell_id);
-                                                self.selection = Selection::CellRange { start: cell_id, end: cell_id };
-                                                self.editing_cell = None;
-                                            }
-
-                                            // Track drag end cell for later update (avoid flicker)
-                                            if self.drag_start.is_some() && ui.input(|i| i.pointer.primary_down()) {
-                                                if let Some(pos) = ui.input(|i| i.pointer.hover_pos()) {
-                                                    if rect.contains(pos) {
-                                                        drag_end_cell = Some(cell_id);
-                                                    }
-                                                }
-                                            }
+                    if ui.button("Reset Column Widths").clicked() {
+                        self.column_widths.clear();
+                        ui.close();
+                    }
 
-                                            // Clear drag state when opening context menu (right-click)
-                                            if response.secondary_clicked() {
-                                                self.drag_start = None;
-                                            }
+                    ui.separator();
 
-                                            response.context_menu(|ui| {
-                                                if ui.button("Cut").clicked() {
-                                                    self.cut_selection();
-                                                    ui.close();
-                                                }
-                                                if ui.button("Copy").clicked() {
-                                                    self.copy_selection();
-                                                    ui.close();
-                                                }
-                                                if ui.button("Paste").clicked() {
-                                                    if let Ok(text) = self.clipboard.get_text() {
-                                                        self.save_undo_state();
-                                                        self.paste_text(&text);
-                                                    }
-                                                    ui.close();
-                                                }
-                                                ui.separator();
-                                                if ui.button("Clear").clicked() {
-                                                    clear_cell = Some(cell_id);
-                                                    ui.close();
-                                                }
-                                            });
-                                        }
-                                }
-                            });
-                        }
-                    });
+                    let theme_label = if self.dark_mode { "Light Mode" } else { "Dark Mode" };
+                    if ui.button(theme_label).clicked() {
+                        self.dark_mode = !self.dark_mode;
+                        ui.close();
+                    }
                 });
 
-            // Save current edit if user clicked away (use the PREVIOUS editing cell)
-            if save_current_edit {
-                if let Some((edit_row, edit_col)) = previous_editing_cell {
-                    if let Some(row_data) = self.data.get_mut(edit_row) {
-                        if let Some(edit_cell) = row_data.get_mut(edit_col) {
-                            *edit_cell = self.edit_buffer.clone();
-                            self.has_unsaved_changes = true;
-                        }
-                    }
+                if self.read_only {
+                    ui.separator();
+                    ui.label("Read-only");
                 }
-            }
 
-            // Update selection based on drag AFTER table render
-            if let Some(end_cell) = drag_end_cell {
-                if let Some(start) = self.drag_start {
-                    self.selection = Selection::CellRange { start, end: end_cell };
-                    // Request immediate repaint to show selection update without flicker
-                    ctx.request_repaint();
+                if !self.filters.is_empty() {
+                    ui.separator();
+                    ui.label(format!("Filtered: showing {} of {} rows", self.visible_row_count(), self.doc.sheet.num_rows()));
                 }
-            }
-
-            // Request continuous repaints while dragging for smooth selection updates
-            if self.drag_start.is_some() && ui.input(|i| i.pointer.primary_down()) {
-                ctx.request_repaint();
-            }
 
-            // Process pending operations after UI rendering
-            if let Some(col_idx) = insert_col_at {
-                self.insert_column_at(col_idx);
-            }
-            if let Some(row_idx) = insert_row_at {
-                self.insert_row_at(row_idx);
-            }
-            if let Some(col_idx) = delete_col {
-                self.delete_column(col_idx);
-            }
-            if let Some(row_idx) = delete_row {
-                self.delete_row(row_idx);
-            }
-            if let Some((row_idx, col_idx)) = clear_cell {
-                self.save_undo_state();
-                if let Some(row_data) = self.data.get_mut(row_idx) {
-                    if let Some(cell) = row_data.get_mut(col_idx) {
-                        cell.clear();
-                    }
+                if let Some(summary) = self.duplicate_highlight_summary() {
+                    ui.separator();
+                    ui.label(summary);
                 }
-            }
+            });
+        });
 
-            // Clear drag state when mouse released
-            if ui.input(|i| i.pointer.primary_released()) {
-                self.drag_start = None;
-            }
+        let modal_open = self.pending_action != PendingAction::None
+            || self.pending_import.is_some()
+            || self.pending_workbook.is_some()
+            || self.save_as.is_some()
+            || self.compare_dialog.is_some()
+            || self.join_dialog.is_some()
+            || self.duplicate_dialog.is_some()
+            || self.copy_as_dialog.is_some()
+            || self.loading.is_some();
+        #[cfg(not(target_arch = "wasm32"))]
+        let modal_open = modal_open || self.database_dialog.is_some() || self.export_table_dialog.is_some();
+
+        // Open documents
+        egui::TopBottomPanel::top("tab_bar").show(ctx, |ui| {
+            ui.add_enabled_ui(!modal_open, |ui| {
+                self.show_tab_bar(ui);
+            });
+        });
+
+        // Always render the central panel, but disable interaction when modal is open
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let num_rows = self.doc.sheet.num_rows();
+            let num_cols = self.doc.sheet.num_cols();
+
+            // Wrap everything in add_enabled_ui to disable interaction when modal is open
+            ui.add_enabled_ui(!modal_open, |ui| {
+                if self.compare.is_some() {
+                    self.show_compare_view(ui);
+                } else {
+                    self.show_grid(ui);
+                }
+            });
 
             // Handle cell navigation (Arrow keys/Enter)
-            if let Some((row_delta, col_delta)) = move_selection {
+            if let Some((row_delta, col_delta)) = move_selection
+                && num_rows > 0
+                && num_cols > 0
+            {
                 self.editing_cell = None;
 
                 // Get current position and selection anchor
@@ -1661,7 +2352,7 @@ impl eframe::App for SpreadsheetApp {
                     ((0, 0), (0, 0))
                 };
 
-                let new_row = (current_pos.0 as isize + row_delta).max(0).min((num_rows - 1) as isize) as usize;
+                let new_row = self.step_visible_row(current_pos.0, row_delta);
                 let new_col = (current_pos.1 as isize + col_delta).max(0).min((num_cols - 1) as isize) as usize;
 
                 if extend_selection {
@@ -1677,9 +2368,8 @@ impl eframe::App for SpreadsheetApp {
                         end: (new_row, new_col)
                     };
                 }
+                self.grid.scroll_to = Some((new_row, new_col));
             }
-                    }); // End of ScrollArea
-            }); // End of add_enabled_ui
         });
 
         // Draw unified confirmation modal
@@ -1690,15 +2380,10 @@ impl eframe::App for SpreadsheetApp {
             }
 
             let (title, message, confirm_label) = match &self.pending_action {
-                PendingAction::NewFile => (
-                    "Confirm New File",
-                    "Are you sure you want to create a new file?",
-                    "Yes, create new file"
-                ),
-                PendingAction::OpenFile => (
-                    "Confirm Open File",
-                    "Are you sure you want to open a file?",
-                    "Yes, open file"
+                PendingAction::CloseTab => (
+                    "Confirm Close Tab",
+                    "Are you sure you want to close this tab?",
+                    "Yes, close tab"
                 ),
                 PendingAction::Exit => (
                     "Confirm Exit",
@@ -1720,26 +2405,8 @@ impl eframe::App for SpreadsheetApp {
                     ui.horizontal(|ui| {
                         if ui.button(confirm_label).clicked() {
                             match self.pending_action {
-                                PendingAction::NewFile => {
-                                    self.data = vec![vec![String::new(); 10]; 20];
-                                    self.file_path = None;
-                                    self.has_unsaved_changes = false;
-                                    self.pending_action = PendingAction::None;
-                                }
-                                PendingAction::OpenFile => {
-                                    #[cfg(not(target_arch = "wasm32"))]
-                                    {
-                                        if let Some(path) = rfd::FileDialog::new()
-                                            .add_filter("CSV", &["csv"])
-                                            .pick_file()
-                                        {
-                                            self.load_csv(path);
-                                        }
-                                    }
-                                    #[cfg(target_arch = "wasm32")]
-                                    {
-                                        self.trigger_open_file();
-                                    }
+                                PendingAction::CloseTab => {
+                                    self.remove_tab(self.active_tab);
                                     self.pending_action = PendingAction::None;
                                 }
                                 PendingAction::Exit => {
@@ -1759,74 +2426,36 @@ impl eframe::App for SpreadsheetApp {
                 });
         }
 
-        // Search window
-        if self.search_window_open {
-            // Handle Escape key to close search window
-            if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
-                self.search_window_open = false;
-            }
-
-            let mut window_open = true;
-            egui::Window::new("Search")
-                .open(&mut window_open)
-                .collapsible(false)
-                .resizable(false)
-                .default_width(400.0)
-                .show(ctx, |ui| {
-                    ui.horizontal(|ui| {
-                        ui.label("Find:");
-                        let response = ui.text_edit_singleline(&mut self.search_query);
-
-                        // Auto-focus the text field when window opens
-                        if self.search_window_open {
-                            response.request_focus();
-                        }
-
-                        // Enter key: search if no results yet, otherwise go to next result
-                        if ui.input(|i| i.key_pressed(egui::Key::Enter)) && response.has_focus() {
-                            if self.search_results.is_empty() {
-                                self.perform_search();
-                            } else {
-                                self.go_to_next_search_result();
-                            }
-                        }
-                    });
+        // Find & Replace
+        self.show_search_window(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.show_query_panel(ctx);
+        self.show_filter_editor(ctx);
+        self.show_sort_dialog(ctx);
+        self.show_compare_dialog(ctx);
+        self.show_join_dialog(ctx);
+        self.show_duplicate_dialog(ctx);
+        self.show_copy_as_dialog(ctx);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.show_database_dialog(ctx);
+            self.show_export_table_dialog(ctx);
+        }
 
-                    ui.horizontal(|ui| {
-                        ui.checkbox(&mut self.search_case_sensitive, "Case sensitive");
+        // Rows the parser rejected while importing
+        self.show_import_problems(ctx);
+        self.show_history_panel(ctx);
 
-                        if ui.button("Search").clicked() {
-                            self.perform_search();
-                        }
-                    });
+        // Format options for Save As
+        self.show_save_as_dialog(ctx);
 
-                    ui.separator();
+        // Import dialog for a file that has been read but not loaded yet
+        self.show_import_dialog(ctx);
 
-                    if !self.search_results.is_empty() {
-                        ui.label(format!(
-                            "Found {} match{} (showing {} of {})",
-                            self.search_results.len(),
-                            if self.search_results.len() == 1 { "" } else { "es" },
-                            self.current_search_result + 1,
-                            self.search_results.len()
-                        ));
-
-                        ui.horizontal(|ui| {
-                            if ui.button("Previous").clicked() {
-                                self.go_to_prev_search_result();
-                            }
-                            if ui.button("Next").clicked() {
-                                self.go_to_next_search_result();
-                            }
-                        });
-                    } else if !self.search_query.is_empty() {
-                        ui.label("No matches found");
-                    }
-                });
+        // Worksheet picker for a workbook with several worksheets
+        self.show_sheet_dialog(ctx);
 
-            if !window_open {
-                self.search_window_open = false;
-            }
-        }
+        // Progress of a file being read or parsed
+        self.show_loading_progress(ctx);
     }
 }
@@ -0,0 +1,261 @@
+// The SQL query console. A tab's document is copied into an in-memory SQLite
+// database as the table `t`, with the header row as column names and columns
+// typed by their column types, next to any files registered under table names
+// of their own. A SELECT over them, with WHERE, GROUP BY, ORDER BY and JOINs,
+// opens its rows in a new read-only tab that Save As can write to a file.
+//
+// The database is built again for every query, so edits made since the last
+// one are always included, and registered files are read again too. Native
+// only, like the rest of the SQLite support.
+
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+
+use gridview_core::sqlite::{Database, Source, SqlTable};
+
+use crate::loader::{self, SheetSource};
+use crate::SpreadsheetApp;
+
+// A file registered to be queried alongside `t`
+struct QueryFile {
+    name: String,
+    path: PathBuf,
+}
+
+// State of the SQL Query window, kept while it is closed
+pub struct QueryPanel {
+    open: bool,
+    // Tab whose document is `t`; None for the active tab
+    tab: Option<usize>,
+    sql: String,
+    files: Vec<QueryFile>,
+    error: Option<String>,
+    // Number of results opened so far, to name their tabs
+    results: usize,
+    focus_pending: bool,
+}
+
+impl Default for QueryPanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            tab: None,
+            sql: "SELECT * FROM t".to_string(),
+            files: Vec::new(),
+            error: None,
+            results: 0,
+            focus_pending: false,
+        }
+    }
+}
+
+impl QueryPanel {
+    // Keep pointing at the same tab while tabs are opened and closed
+    pub(crate) fn tab_inserted(&mut self, index: usize) {
+        if let Some(tab) = &mut self.tab
+            && *tab >= index
+        {
+            *tab += 1;
+        }
+    }
+
+    pub(crate) fn tab_removed(&mut self, index: usize) {
+        match self.tab {
+            Some(tab) if tab == index => self.tab = None,
+            Some(tab) if tab > index => self.tab = Some(tab - 1),
+            _ => {}
+        }
+    }
+}
+
+// Table name for a file: its name without the extension, with anything SQL
+// would need quotes for replaced by underscores
+fn table_name(path: &Path) -> String {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut name: String = stem.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+    if !name.starts_with(|c: char| c.is_alphabetic()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+impl SpreadsheetApp {
+    pub(crate) fn open_query_panel(&mut self) {
+        if let Some((row, col)) = self.editing_cell.take() {
+            self.commit_cell_edit(row, col);
+        }
+        let panel = &mut self.query_panel;
+        panel.open = true;
+        panel.tab = Some(self.active_tab);
+        panel.error = None;
+        panel.focus_pending = true;
+    }
+
+    // Tab whose document is `t`
+    fn query_tab(&self) -> usize {
+        self.query_panel
+            .tab
+            .filter(|&index| index < self.tabs.len() && !self.tab_is_comparison(index))
+            .unwrap_or(self.active_tab)
+    }
+
+    fn add_query_file(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("Spreadsheets", crate::OPEN_EXTENSIONS).pick_file() else {
+            return;
+        };
+        let mut name = table_name(&path);
+        // Every table needs a name of its own
+        let taken = |name: &str, files: &[QueryFile]| name == "t" || files.iter().any(|file| file.name == name);
+        if taken(&name, &self.query_panel.files) {
+            let base = name.clone();
+            name = (2..).map(|n| format!("{}_{}", base, n)).find(|name| !taken(name, &self.query_panel.files)).unwrap_or(base);
+        }
+        self.query_panel.files.push(QueryFile { name, path });
+    }
+
+    pub(crate) fn show_query_panel(&mut self, ctx: &egui::Context) {
+        if !self.query_panel.open {
+            return;
+        }
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.query_panel.open = false;
+        }
+
+        let mut window_open = true;
+        let mut run = false;
+        let mut add_file = false;
+        let mut remove_file = None;
+        let mut new_tab = None;
+        let source = self.query_tab();
+        let header = self.tab_has_header(source);
+        let tabs: Vec<(usize, String)> = (0..self.tabs.len())
+            .filter(|&index| !self.tab_is_comparison(index))
+            .map(|index| (index, self.tab_name(index)))
+            .collect();
+        let panel = &mut self.query_panel;
+
+        egui::Window::new("SQL Query")
+            .open(&mut window_open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                egui::Grid::new("query_tables").num_columns(2).spacing([10.0, 6.0]).show(ui, |ui| {
+                    ui.label("Table t:");
+                    let selected = tabs.iter().find(|(index, _)| *index == source).map(|(_, name)| name.clone()).unwrap_or_default();
+                    egui::ComboBox::from_id_salt("query_tab").selected_text(selected).show_ui(ui, |ui| {
+                        for (index, name) in &tabs {
+                            if ui.selectable_label(*index == source, name).clicked() {
+                                new_tab = Some(*index);
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    for (index, file) in panel.files.iter_mut().enumerate() {
+                        ui.add(egui::TextEdit::singleline(&mut file.name).desired_width(100.0));
+                        ui.horizontal(|ui| {
+                            ui.label(file.path.display().to_string());
+                            if ui.small_button("×").on_hover_text("Remove").clicked() {
+                                remove_file = Some(index);
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+                if !header {
+                    ui.weak("The first row of t isn't a header, so its columns are named A, B, C, ...");
+                }
+                if ui.button("Add File...").on_hover_text("Query another file as a table of its own").clicked() {
+                    add_file = true;
+                }
+                ui.separator();
+
+                // Ctrl+Enter runs the query while typing it, rather than
+                // adding a line
+                let editor = ui.make_persistent_id("query_sql");
+                if ui.memory(|memory| memory.has_focus(editor))
+                    && ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter))
+                {
+                    run = true;
+                }
+                let response = ui.add(
+                    egui::TextEdit::multiline(&mut panel.sql)
+                        .id(editor)
+                        .code_editor()
+                        .desired_rows(6)
+                        .desired_width(f32::INFINITY),
+                );
+                if panel.focus_pending {
+                    response.request_focus();
+                    panel.focus_pending = false;
+                }
+                if response.changed() {
+                    panel.error = None;
+                }
+                if let Some(error) = &panel.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!panel.sql.trim().is_empty(), egui::Button::new("Run")).clicked() {
+                        run = true;
+                    }
+                    ui.weak("Ctrl+Enter runs the query. Results open in a new read-only tab.");
+                });
+            });
+
+        if !window_open {
+            panel.open = false;
+        }
+        if let Some(index) = new_tab {
+            panel.tab = Some(index);
+        }
+        if let Some(index) = remove_file {
+            panel.files.remove(index);
+        }
+        if add_file {
+            self.add_query_file();
+        }
+        if run {
+            self.run_query(source);
+        }
+    }
+
+    // Run the query and open its rows in a new tab, or show why it failed
+    fn run_query(&mut self, source: usize) {
+        let sql = self.query_panel.sql.trim().to_string();
+        match self.query_rows(source, &sql) {
+            Ok(table) => {
+                self.query_panel.error = None;
+                self.query_panel.tab = Some(source);
+                self.query_panel.results += 1;
+                let name = format!("Query {}", self.query_panel.results);
+                let (doc, _) = table.into_document();
+                self.new_tab();
+                self.doc = doc;
+                self.freeze_top_row = true;
+                self.sheet_source = Some(SheetSource { name, other_sheets: false });
+                self.read_only = true;
+            }
+            Err(e) => self.query_panel.error = Some(e),
+        }
+    }
+
+    fn query_rows(&mut self, source: usize, sql: &str) -> Result<SqlTable, String> {
+        let mut database = Database::in_memory().map_err(|e| e.to_string())?;
+        let header = self.tab_has_header(source);
+        self.tab_document_mut(source)
+            .export_to_table(&mut database, "t", header, false)
+            .map_err(|e| e.to_string())?;
+        for file in &self.query_panel.files {
+            let mut doc = loader::read_document_file(&file.path).map_err(|e| format!("{}: {}", file.path.display(), e))?;
+            let header = doc.dialect.has_headers;
+            doc.export_to_table(&mut database, file.name.trim(), header, false)
+                .map_err(|e| format!("{}: {}", file.name.trim(), e))?;
+        }
+        database.read(&Source::Query(sql.to_string())).map_err(|e| e.to_string())
+    }
+}
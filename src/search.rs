@@ -0,0 +1,291 @@
+// The Find & Replace window. Matching is done by `gridview_core::search`;
+// this keeps the list of results, steps through them and applies
+// replacements as undoable edits.
+
+use eframe::egui;
+use std::ops::Range;
+
+use gridview_core::history::CellChange;
+use gridview_core::search::{self, Matcher};
+
+use crate::{Selection, SpreadsheetApp};
+
+impl SpreadsheetApp {
+    // Rows and columns covered by a selection, clipped to the sheet
+    pub(crate) fn selection_ranges(&self, selection: &Selection) -> Option<(Range<usize>, Range<usize>)> {
+        let (rows, cols) = match selection {
+            Selection::None => return None,
+            Selection::CellRange { start, end } => {
+                let (r1, c1) = *start;
+                let (r2, c2) = *end;
+                (r1.min(r2)..r1.max(r2) + 1, c1.min(c2)..c1.max(c2) + 1)
+            }
+            Selection::Column(col_idx) => (0..self.doc.sheet.num_rows(), *col_idx..*col_idx + 1),
+            Selection::Row(row_idx) => (*row_idx..*row_idx + 1, 0..self.doc.sheet.num_cols()),
+        };
+        let rows = rows.start.min(self.doc.sheet.num_rows())..rows.end.min(self.doc.sheet.num_rows());
+        let cols = cols.start.min(self.doc.sheet.num_cols())..cols.end.min(self.doc.sheet.num_cols());
+        Some((rows, cols))
+    }
+
+    // Rows and columns searched: the scope picked in the window, or the whole sheet
+    fn search_ranges(&self) -> (Range<usize>, Range<usize>) {
+        self.search_scope
+            .as_ref()
+            .and_then(|scope| self.selection_ranges(scope))
+            .unwrap_or((0..self.doc.sheet.num_rows(), 0..self.doc.sheet.num_cols()))
+    }
+
+    fn search_matcher(&self) -> Result<Matcher, search::PatternError> {
+        Matcher::new(
+            &self.search_query,
+            self.search_case_sensitive,
+            self.search_regex,
+            self.search_whole_cell,
+        )
+    }
+
+    pub(crate) fn perform_search(&mut self) {
+        self.search_results.clear();
+        self.current_search_result = 0;
+        self.search_error = None;
+
+        if self.search_query.is_empty() {
+            self.update_replace_preview();
+            return;
+        }
+        let matcher = match self.search_matcher() {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                self.search_error = Some(e.to_string());
+                self.update_replace_preview();
+                return;
+            }
+        };
+
+        // Results are kept in row-major order so the grid can binary search them.
+        // Rows hidden by a filter are skipped.
+        let (rows, cols) = self.search_ranges();
+        self.search_results = search::find(&self.doc.sheet, self.visible_rows_in(rows), cols, &matcher);
+
+        self.update_replace_preview();
+        self.grid.scroll_to = self.search_results.first().copied();
+    }
+
+    // Count the matching cells that Replace All would change
+    fn update_replace_preview(&mut self) {
+        self.replace_preview = match self.search_matcher() {
+            Ok(matcher) if !self.search_query.is_empty() => self
+                .search_results
+                .iter()
+                .filter(|&&(row, col)| matcher.replace(self.doc.sheet.get(row, col), &self.replace_query).is_some())
+                .count(),
+            _ => 0,
+        };
+    }
+
+    // Replace in the current result, then move on to the next one
+    fn replace_current(&mut self) {
+        let Ok(matcher) = self.search_matcher() else {
+            return;
+        };
+        let Some(&(row, col)) = self.search_results.get(self.current_search_result) else {
+            return;
+        };
+        if let Some(text) = matcher.replace(self.doc.sheet.get(row, col), &self.replace_query) {
+            self.edit_cells(format!("Replace in {}", Self::cell_name(row, col)), vec![CellChange { row, col, text }]);
+        }
+
+        // The cell may no longer match; either way continue from the next result
+        if !matcher.is_match(self.doc.sheet.get(row, col)) {
+            self.search_results.remove(self.current_search_result);
+            if self.current_search_result >= self.search_results.len() {
+                self.current_search_result = 0;
+            }
+        } else {
+            self.current_search_result = (self.current_search_result + 1) % self.search_results.len();
+        }
+        self.update_replace_preview();
+        if let Some(&(row, col)) = self.search_results.get(self.current_search_result) {
+            self.selection = Selection::CellRange { start: (row, col), end: (row, col) };
+            self.grid.scroll_to = Some((row, col));
+        }
+    }
+
+    // Replace in every matching cell as one undoable step
+    fn replace_all(&mut self) {
+        let Ok(matcher) = self.search_matcher() else {
+            return;
+        };
+        let changes = search::replacements(&self.doc.sheet, self.search_results.iter().copied(), &matcher, &self.replace_query);
+        let label = format!("Replace All ({} cell{})", changes.len(), if changes.len() == 1 { "" } else { "s" });
+        self.edit_cells(label, changes);
+        self.perform_search();
+    }
+
+    pub(crate) fn go_to_next_search_result(&mut self) {
+        if !self.search_results.is_empty() {
+            self.current_search_result = (self.current_search_result + 1) % self.search_results.len();
+            self.show_current_search_result();
+        }
+    }
+
+    pub(crate) fn go_to_prev_search_result(&mut self) {
+        if !self.search_results.is_empty() {
+            if self.current_search_result == 0 {
+                self.current_search_result = self.search_results.len() - 1;
+            } else {
+                self.current_search_result -= 1;
+            }
+            self.show_current_search_result();
+        }
+    }
+
+    fn show_current_search_result(&mut self) {
+        let (row, col) = self.search_results[self.current_search_result];
+        self.selection = Selection::CellRange {
+            start: (row, col),
+            end: (row, col),
+        };
+        self.editing_cell = None;
+        self.grid.scroll_to = Some((row, col));
+    }
+
+    pub(crate) fn open_search_window(&mut self) {
+        self.search_window_open = true;
+        self.search_focus_pending = true;
+    }
+
+    pub(crate) fn show_search_window(&mut self, ctx: &egui::Context) {
+        if !self.search_window_open {
+            return;
+        }
+
+        // Handle Escape key to close search window
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.search_window_open = false;
+        }
+
+        let mut window_open = true;
+        let mut options_changed = false;
+        egui::Window::new("Find & Replace")
+            .open(&mut window_open)
+            .collapsible(false)
+            .resizable(false)
+            .default_width(400.0)
+            .show(ctx, |ui| {
+                egui::Grid::new("search_fields").num_columns(2).spacing([10.0, 6.0]).show(ui, |ui| {
+                    ui.label("Find:");
+                    let response = ui.text_edit_singleline(&mut self.search_query);
+
+                    // Focus the text field when the window opens
+                    if self.search_focus_pending {
+                        response.request_focus();
+                        self.search_focus_pending = false;
+                    }
+                    options_changed |= response.changed();
+
+                    // Enter key: search if no results yet, otherwise go to next result
+                    if ui.input(|i| i.key_pressed(egui::Key::Enter)) && response.lost_focus() {
+                        if self.search_results.is_empty() {
+                            self.perform_search();
+                        } else {
+                            self.go_to_next_search_result();
+                        }
+                        response.request_focus();
+                    }
+                    ui.end_row();
+
+                    ui.label("Replace:");
+                    if ui.text_edit_singleline(&mut self.replace_query).changed() {
+                        self.update_replace_preview();
+                    }
+                    ui.end_row();
+                });
+
+                ui.horizontal(|ui| {
+                    options_changed |= ui.checkbox(&mut self.search_case_sensitive, "Case sensitive").changed();
+                    options_changed |= ui.checkbox(&mut self.search_whole_cell, "Whole cell").changed();
+                    options_changed |= ui
+                        .checkbox(&mut self.search_regex, "Regex")
+                        .on_hover_text("Use $1 or ${name} in the replacement for capture groups")
+                        .changed();
+                });
+
+                // Capture the selection as the scope when the box is ticked, so
+                // stepping through results doesn't change it
+                let mut in_selection = self.search_scope.is_some();
+                let has_selection = self.selection != Selection::None;
+                let scope_label = match self.search_scope.as_ref().and_then(|scope| self.selection_ranges(scope)) {
+                    Some((rows, cols)) if !rows.is_empty() && !cols.is_empty() => format!(
+                        "Only in selection ({}:{})",
+                        Self::cell_name(rows.start, cols.start),
+                        Self::cell_name(rows.end - 1, cols.end - 1)
+                    ),
+                    _ => "Only in selection".to_string(),
+                };
+                if ui
+                    .add_enabled(has_selection || in_selection, egui::Checkbox::new(&mut in_selection, scope_label))
+                    .changed()
+                {
+                    self.search_scope = in_selection.then(|| self.selection.clone());
+                    options_changed = true;
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Search").clicked() {
+                        self.perform_search();
+                    }
+                    if ui.add_enabled(!self.search_results.is_empty(), egui::Button::new("Replace")).clicked() {
+                        self.replace_current();
+                    }
+                    if ui.add_enabled(self.replace_preview > 0, egui::Button::new("Replace All")).clicked() {
+                        self.replace_all();
+                    }
+                });
+
+                ui.separator();
+
+                if let Some(error) = &self.search_error {
+                    // Regex errors point at the problem with a caret, which needs a fixed-width font
+                    ui.colored_label(ui.visuals().error_fg_color, egui::RichText::new(error).monospace());
+                } else if !self.search_results.is_empty() {
+                    ui.label(format!(
+                        "Found {} match{} (showing {} of {})",
+                        self.search_results.len(),
+                        if self.search_results.len() == 1 { "" } else { "es" },
+                        self.current_search_result + 1,
+                        self.search_results.len()
+                    ));
+                    ui.label(format!(
+                        "Replace All will change {} cell{}",
+                        self.replace_preview,
+                        if self.replace_preview == 1 { "" } else { "s" }
+                    ));
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Previous").clicked() {
+                            self.go_to_prev_search_result();
+                        }
+                        if ui.button("Next").clicked() {
+                            self.go_to_next_search_result();
+                        }
+                    });
+                } else if !self.search_query.is_empty() {
+                    ui.label("No matches found");
+                }
+            });
+
+        // Results and the preview belong to the old query
+        if options_changed {
+            self.search_results.clear();
+            self.current_search_result = 0;
+            self.search_error = None;
+            self.replace_preview = 0;
+        }
+
+        if !window_open {
+            self.search_window_open = false;
+        }
+    }
+}
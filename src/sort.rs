@@ -0,0 +1,197 @@
+// The Sort window and sorting from the column header menu. Rows are put in
+// order by `gridview_core::sort` and moved as one undoable step.
+
+use eframe::egui;
+
+use gridview_core::history::Edit;
+use gridview_core::sort::{SortKey, SortType, sort_order};
+
+use crate::SpreadsheetApp;
+
+// State of the Sort window
+pub struct SortDialog {
+    keys: Vec<SortKey>,
+}
+
+impl SpreadsheetApp {
+    // A key for a column that sorts it by its type, guessed from the
+    // contents for columns that haven't been typed
+    fn default_sort_key(&self, col: usize, ascending: bool) -> SortKey {
+        let column_type = match self.doc.column_types.get(&col) {
+            Some(&column_type) => column_type,
+            None => self.infer_column_type(col),
+        };
+        SortKey {
+            col,
+            ascending,
+            sort_type: SortType::for_column(column_type),
+            empties_last: true,
+        }
+    }
+
+    pub(crate) fn sort_by_column(&mut self, col_idx: usize, ascending: bool) {
+        let key = self.default_sort_key(col_idx, ascending);
+        self.sort_rows(&[key]);
+    }
+
+    // Reorder the rows by the keys, in priority order, as one undoable step
+    pub(crate) fn sort_rows(&mut self, keys: &[SortKey]) {
+        if self.doc.sheet.is_empty() || keys.is_empty() {
+            return;
+        }
+        let order = sort_order(&self.doc.sheet, &mut self.doc.calc, keys, self.freeze_top_row);
+
+        let names: Vec<String> = keys.iter().map(|key| Self::col_index_to_letter(key.col)).collect();
+        let label = match keys {
+            [key] => format!(
+                "Sort by column {} {}",
+                names[0],
+                if key.ascending { "ascending" } else { "descending" }
+            ),
+            _ => format!("Sort by columns {}", names.join(", ")),
+        };
+        self.apply_operation(label, vec![Edit::ReorderRows(order)]);
+
+        // Track which column is sorted
+        self.sorted_column = Some(keys[0].col);
+        self.sort_ascending = keys[0].ascending;
+    }
+
+    pub(crate) fn open_sort_dialog(&mut self, col: Option<usize>) {
+        if self.doc.sheet.num_cols() == 0 {
+            return;
+        }
+        let col = col
+            .or(self.sorted_column)
+            .unwrap_or(0)
+            .min(self.doc.sheet.num_cols() - 1);
+        let ascending = self.sorted_column != Some(col) || self.sort_ascending;
+        self.sort_dialog = Some(SortDialog { keys: vec![self.default_sort_key(col, ascending)] });
+    }
+
+    // Column names for the pickers, with the header text when the top row is frozen
+    fn sort_column_name(&self, col: usize) -> String {
+        let letter = Self::col_index_to_letter(col);
+        let header = self.doc.sheet.get(0, col);
+        if self.freeze_top_row && !header.is_empty() {
+            format!("{}: {}", letter, header)
+        } else {
+            letter
+        }
+    }
+
+    pub(crate) fn show_sort_dialog(&mut self, ctx: &egui::Context) {
+        if self.sort_dialog.is_none() {
+            return;
+        }
+        let names: Vec<String> = (0..self.doc.sheet.num_cols()).map(|col| self.sort_column_name(col)).collect();
+        let Some(dialog) = &mut self.sort_dialog else {
+            return;
+        };
+
+        let mut window_open = true;
+        let mut sort = false;
+        let mut cancel = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        let mut changed_col = None;
+        egui::Window::new("Sort")
+            .open(&mut window_open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut remove = None;
+                let mut move_up = None;
+                egui::Grid::new("sort_keys").num_columns(6).spacing([8.0, 6.0]).show(ui, |ui| {
+                    let count = dialog.keys.len();
+                    for (i, key) in dialog.keys.iter_mut().enumerate() {
+                        ui.label(if i == 0 { "Sort by" } else { "Then by" });
+
+                        let name = names.get(key.col).map_or("", String::as_str);
+                        egui::ComboBox::from_id_salt(("sort_col", i))
+                            .selected_text(name)
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for (col, name) in names.iter().enumerate() {
+                                    if ui.selectable_value(&mut key.col, col, name).changed() {
+                                        changed_col = Some(i);
+                                    }
+                                }
+                            });
+
+                        egui::ComboBox::from_id_salt(("sort_type", i))
+                            .selected_text(key.sort_type.label())
+                            .show_ui(ui, |ui| {
+                                for sort_type in SortType::ALL {
+                                    ui.selectable_value(&mut key.sort_type, sort_type, sort_type.label());
+                                }
+                            });
+
+                        egui::ComboBox::from_id_salt(("sort_order", i))
+                            .selected_text(if key.ascending { "Ascending" } else { "Descending" })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut key.ascending, true, "Ascending");
+                                ui.selectable_value(&mut key.ascending, false, "Descending");
+                            });
+
+                        egui::ComboBox::from_id_salt(("sort_empties", i))
+                            .selected_text(if key.empties_last { "Empty cells last" } else { "Empty cells first" })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut key.empties_last, false, "Empty cells first");
+                                ui.selectable_value(&mut key.empties_last, true, "Empty cells last");
+                            });
+
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(i > 0, egui::Button::new("^")).on_hover_text("Move up").clicked() {
+                                move_up = Some(i);
+                            }
+                            if ui.add_enabled(count > 1, egui::Button::new("x")).on_hover_text("Remove").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = move_up {
+                    dialog.keys.swap(i - 1, i);
+                }
+                if let Some(i) = remove {
+                    dialog.keys.remove(i);
+                }
+
+                if ui.button("Add Key").clicked()
+                    && let Some(last) = dialog.keys.last()
+                {
+                    let col = (last.col + 1).min(names.len() - 1);
+                    dialog.keys.push(SortKey { col, ..last.clone() });
+                    changed_col = Some(dialog.keys.len() - 1);
+                }
+
+                if self.freeze_top_row {
+                    ui.label(egui::RichText::new("The frozen top row stays in place").weak());
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    sort = ui.button("Sort").clicked();
+                    cancel |= ui.button("Cancel").clicked();
+                });
+            });
+
+        // Guess the type again for a newly picked column
+        if let Some(i) = changed_col
+            && let Some(col) = self.sort_dialog.as_ref().map(|dialog| dialog.keys[i].col)
+        {
+            let detected = self.default_sort_key(col, true).sort_type;
+            if let Some(dialog) = &mut self.sort_dialog {
+                dialog.keys[i].sort_type = detected;
+            }
+        }
+
+        if sort {
+            if let Some(dialog) = self.sort_dialog.take() {
+                self.sort_rows(&dialog.keys);
+            }
+        } else if cancel || !window_open {
+            self.sort_dialog = None;
+        }
+    }
+}
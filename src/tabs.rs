@@ -0,0 +1,338 @@
+// Tabs of open documents. The active document lives in the app's own fields,
+// the way it did before there were tabs, and every other tab is parked in a
+// DocumentTab with what belongs to that file: cells and undo history, path,
+// the database table it was read from, dirty and read-only flags, selection,
+// column widths, sort, filters, highlighted duplicates, search results and
+// scroll position. Switching tabs swaps the two. The clipboard, search text
+// and window settings are shared, so cells copied in one tab paste into
+// another. A tab can also hold a comparison of two other documents instead of
+// a sheet to edit, or the read-only result of a query.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use eframe::egui;
+
+use gridview_core::Document;
+use gridview_core::filter::ColumnFilter;
+#[cfg(not(target_arch = "wasm32"))]
+use gridview_core::sqlite::TableLink;
+
+use crate::compare::CompareView;
+use crate::duplicates::DuplicateHighlight;
+use crate::grid::GridState;
+use crate::loader::SheetSource;
+use crate::{PendingAction, Selection, SpreadsheetApp};
+
+pub struct DocumentTab {
+    doc: Document,
+    file_path: Option<PathBuf>,
+    sheet_source: Option<SheetSource>,
+    #[cfg(not(target_arch = "wasm32"))]
+    table_link: Option<TableLink>,
+    has_unsaved_changes: bool,
+    read_only: bool,
+    column_widths: HashMap<usize, f32>,
+    selection: Selection,
+    sorted_column: Option<usize>,
+    sort_ascending: bool,
+    freeze_top_row: bool,
+    filters: BTreeMap<usize, ColumnFilter>,
+    visible_rows: Option<Rc<Vec<usize>>>,
+    duplicate_highlight: Option<DuplicateHighlight>,
+    search_results: Vec<(usize, usize)>,
+    current_search_result: usize,
+    search_scope: Option<Selection>,
+    replace_preview: usize,
+    save_values: bool,
+    save_normalized: bool,
+    save_nested: bool,
+    import_problems_open: bool,
+    grid: GridState,
+    compare: Option<CompareView>,
+}
+
+// A new, empty document
+impl Default for DocumentTab {
+    fn default() -> Self {
+        Self {
+            doc: Document::new(20, 10),
+            file_path: None,
+            sheet_source: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            table_link: None,
+            has_unsaved_changes: false,
+            read_only: false,
+            column_widths: HashMap::new(),
+            selection: Selection::None,
+            sorted_column: None,
+            sort_ascending: true,
+            freeze_top_row: false,
+            filters: BTreeMap::new(),
+            visible_rows: None,
+            duplicate_highlight: None,
+            search_results: Vec::new(),
+            current_search_result: 0,
+            search_scope: None,
+            replace_preview: 0,
+            save_values: false,
+            save_normalized: false,
+            save_nested: false,
+            import_problems_open: false,
+            grid: GridState::default(),
+            compare: None,
+        }
+    }
+}
+
+// Name shown on a tab: the file name, with the worksheet for one sheet of a
+// larger workbook. Query results that haven't been saved go by their own name.
+fn tab_title(path: Option<&Path>, sheet: Option<&SheetSource>, compare: Option<&CompareView>) -> String {
+    if let Some(compare) = compare {
+        return compare.title();
+    }
+    let name = match (path.and_then(|path| path.file_name()), sheet) {
+        (Some(name), _) => name.to_string_lossy().into_owned(),
+        (None, Some(sheet)) => return sheet.name.clone(),
+        (None, None) => "Untitled".to_string(),
+    };
+    match sheet {
+        Some(sheet) if sheet.other_sheets => format!("{} [{}]", name, sheet.name),
+        _ => name,
+    }
+}
+
+impl SpreadsheetApp {
+    // Exchange the active document's state with a parked tab
+    fn swap_tab(&mut self, tab: &mut DocumentTab) {
+        std::mem::swap(&mut self.doc, &mut tab.doc);
+        std::mem::swap(&mut self.file_path, &mut tab.file_path);
+        std::mem::swap(&mut self.sheet_source, &mut tab.sheet_source);
+        #[cfg(not(target_arch = "wasm32"))]
+        std::mem::swap(&mut self.table_link, &mut tab.table_link);
+        std::mem::swap(&mut self.has_unsaved_changes, &mut tab.has_unsaved_changes);
+        std::mem::swap(&mut self.read_only, &mut tab.read_only);
+        std::mem::swap(&mut self.column_widths, &mut tab.column_widths);
+        std::mem::swap(&mut self.selection, &mut tab.selection);
+        std::mem::swap(&mut self.sorted_column, &mut tab.sorted_column);
+        std::mem::swap(&mut self.sort_ascending, &mut tab.sort_ascending);
+        std::mem::swap(&mut self.freeze_top_row, &mut tab.freeze_top_row);
+        std::mem::swap(&mut self.filters, &mut tab.filters);
+        std::mem::swap(&mut self.visible_rows, &mut tab.visible_rows);
+        std::mem::swap(&mut self.duplicate_highlight, &mut tab.duplicate_highlight);
+        std::mem::swap(&mut self.search_results, &mut tab.search_results);
+        std::mem::swap(&mut self.current_search_result, &mut tab.current_search_result);
+        std::mem::swap(&mut self.search_scope, &mut tab.search_scope);
+        std::mem::swap(&mut self.replace_preview, &mut tab.replace_preview);
+        std::mem::swap(&mut self.save_values, &mut tab.save_values);
+        std::mem::swap(&mut self.save_normalized, &mut tab.save_normalized);
+        std::mem::swap(&mut self.save_nested, &mut tab.save_nested);
+        std::mem::swap(&mut self.import_problems_open, &mut tab.import_problems_open);
+        std::mem::swap(&mut self.grid, &mut tab.grid);
+        std::mem::swap(&mut self.compare, &mut tab.compare);
+    }
+
+    // Document of a tab, active or not
+    pub(crate) fn tab_document(&self, index: usize) -> &Document {
+        if index == self.active_tab { &self.doc } else { &self.tabs[index].doc }
+    }
+
+    // Whether a tab's first row is a header
+    pub(crate) fn tab_has_header(&self, index: usize) -> bool {
+        if index == self.active_tab { self.freeze_top_row } else { self.tabs[index].freeze_top_row }
+    }
+
+    pub(crate) fn tab_document_mut(&mut self, index: usize) -> &mut Document {
+        if index == self.active_tab { &mut self.doc } else { &mut self.tabs[index].doc }
+    }
+
+    pub(crate) fn tab_is_comparison(&self, index: usize) -> bool {
+        if index == self.active_tab { self.compare.is_some() } else { self.tabs[index].compare.is_some() }
+    }
+
+    pub(crate) fn tab_name(&self, index: usize) -> String {
+        if index == self.active_tab {
+            tab_title(self.file_path.as_deref(), self.sheet_source.as_ref(), self.compare.as_ref())
+        } else {
+            let tab = &self.tabs[index];
+            tab_title(tab.file_path.as_deref(), tab.sheet_source.as_ref(), tab.compare.as_ref())
+        }
+    }
+
+    // Finish what was going on in the active tab before it is parked. Windows
+    // that point at its columns are closed.
+    fn leave_tab(&mut self) {
+        if let Some((row, col)) = self.editing_cell.take() {
+            self.commit_cell_edit(row, col);
+        }
+        self.drag_start = None;
+        self.sort_dialog = None;
+        self.filter_editor = None;
+        self.join_dialog = None;
+        self.duplicate_dialog = None;
+        self.copy_as_dialog = None;
+    }
+
+    pub(crate) fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return;
+        }
+        self.leave_tab();
+        // The active tab's slot holds an empty placeholder while it is active
+        let mut parked = std::mem::take(&mut self.tabs[index]);
+        self.swap_tab(&mut parked);
+        self.tabs[self.active_tab] = parked;
+        self.active_tab = index;
+    }
+
+    // Open an empty document in a new tab next to the active one
+    pub(crate) fn new_tab(&mut self) {
+        self.tabs.insert(self.active_tab + 1, DocumentTab::default());
+        self.tab_inserted(self.active_tab + 1);
+        self.switch_tab(self.active_tab + 1);
+    }
+
+    // Whether a file opened now should get a tab of its own rather than
+    // replace the active document, which it does only for an untouched new one
+    pub(crate) fn active_tab_in_use(&self) -> bool {
+        self.file_path.is_some() || self.has_unsaved_changes || self.read_only || self.compare.is_some()
+    }
+
+    pub(crate) fn tab_with_path(&self, path: &Path) -> Option<usize> {
+        (0..self.tabs.len()).find(|&index| {
+            let tab_path = if index == self.active_tab { self.file_path.as_deref() } else { self.tabs[index].file_path.as_deref() };
+            tab_path == Some(path)
+        })
+    }
+
+    // Tab editing a table of a database, so it isn't opened twice
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn tab_with_table(&self, path: &Path, table: &str) -> Option<usize> {
+        (0..self.tabs.len()).find(|&index| {
+            let (tab_path, link) = if index == self.active_tab {
+                (self.file_path.as_deref(), self.table_link.as_ref())
+            } else {
+                (self.tabs[index].file_path.as_deref(), self.tabs[index].table_link.as_ref())
+            };
+            tab_path == Some(path) && link.is_some_and(|link| link.table == table)
+        })
+    }
+
+    pub(crate) fn any_unsaved_changes(&self) -> bool {
+        self.has_unsaved_changes
+            || self.tabs.iter().enumerate().any(|(index, tab)| index != self.active_tab && tab.has_unsaved_changes)
+    }
+
+    // Close a tab, asking first if it has unsaved changes
+    pub(crate) fn close_tab(&mut self, index: usize) {
+        let unsaved = if index == self.active_tab {
+            self.has_unsaved_changes
+        } else {
+            self.tabs.get(index).is_some_and(|tab| tab.has_unsaved_changes)
+        };
+        if unsaved {
+            self.switch_tab(index);
+            self.pending_action = PendingAction::CloseTab;
+        } else {
+            self.remove_tab(index);
+        }
+    }
+
+    // Close a tab without asking. Closing the last one leaves an empty
+    // document.
+    pub(crate) fn remove_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() {
+            return;
+        }
+        if self.tabs.len() == 1 {
+            self.leave_tab();
+            self.swap_tab(&mut DocumentTab::default());
+            return;
+        }
+        if index == self.active_tab {
+            // Show the tab to the right, or to the left for the last one
+            let next = if index + 1 < self.tabs.len() { index + 1 } else { index - 1 };
+            self.switch_tab(next);
+        }
+        self.tabs.remove(index);
+        self.tab_removed(index);
+        if index < self.active_tab {
+            self.active_tab -= 1;
+        }
+    }
+
+    // Windows that refer to tabs by index keep pointing at the same ones
+    fn tab_inserted(&mut self, index: usize) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.query_panel.tab_inserted(index);
+        if let Some(dialog) = &mut self.compare_dialog {
+            dialog.tab_inserted(index);
+        }
+        if let Some(dialog) = &mut self.join_dialog {
+            dialog.tab_inserted(index);
+        }
+    }
+
+    // Windows working on a closed tab are closed with it
+    fn tab_removed(&mut self, index: usize) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.query_panel.tab_removed(index);
+        if let Some(dialog) = &mut self.compare_dialog
+            && !dialog.tab_removed(index)
+        {
+            self.compare_dialog = None;
+        }
+        if let Some(dialog) = &mut self.join_dialog
+            && !dialog.tab_removed(index)
+        {
+            self.join_dialog = None;
+        }
+    }
+
+    pub(crate) fn show_tab_bar(&mut self, ui: &mut egui::Ui) {
+        let mut switch_to = None;
+        let mut close = None;
+
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for index in 0..self.tabs.len() {
+                    let (path, unsaved) = if index == self.active_tab {
+                        (self.file_path.as_deref(), self.has_unsaved_changes)
+                    } else {
+                        let tab = &self.tabs[index];
+                        (tab.file_path.as_deref(), tab.has_unsaved_changes)
+                    };
+                    let title = self.tab_name(index);
+                    let label = if unsaved { format!("{} *", title) } else { title };
+
+                    let mut response = ui.selectable_label(index == self.active_tab, label);
+                    if let Some(path) = path {
+                        response = response.on_hover_text(path.display().to_string());
+                    }
+                    if response.clicked() {
+                        switch_to = Some(index);
+                    }
+                    // Middle-click closes a tab, as in browsers
+                    if response.middle_clicked() {
+                        close = Some(index);
+                    }
+                    if ui.small_button("×").on_hover_text("Close tab").clicked() {
+                        close = Some(index);
+                    }
+                    ui.separator();
+                }
+                if ui.small_button("+").on_hover_text("New tab").clicked() {
+                    self.new_tab();
+                }
+            });
+        });
+
+        if let Some(index) = switch_to {
+            self.switch_tab(index);
+        }
+        if let Some(index) = close {
+            self.close_tab(index);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+// Column types in the app: the type of each column is kept by index
+// alongside the column widths, inferred when a file is opened and changed from
+// the column header menu.
+
+use gridview_core::ColumnType;
+
+use crate::SpreadsheetApp;
+
+impl SpreadsheetApp {
+    pub(crate) fn column_type(&self, col: usize) -> ColumnType {
+        self.doc.column_type(col)
+    }
+
+    // Type a column's cells suggest, ignoring a frozen header row
+    pub(crate) fn infer_column_type(&self, col: usize) -> ColumnType {
+        let first = if self.freeze_top_row { 1 } else { 0 };
+        ColumnType::infer(self.doc.sheet.column(col).skip(first))
+    }
+
+    // Set a column's type, or infer it again for None
+    pub(crate) fn set_column_type(&mut self, col: usize, column_type: Option<ColumnType>) {
+        let column_type = column_type.unwrap_or_else(|| self.infer_column_type(col));
+        self.doc.column_types.insert(col, column_type);
+    }
+}